@@ -0,0 +1,114 @@
+#[cfg(feature = "cuda")]
+use burn::backend::cuda::{Cuda, CudaDevice};
+
+#[cfg(feature = "wgpu")]
+use burn::backend::wgpu::{Wgpu, WgpuDevice};
+
+use burn::{
+    module::Module,
+    record::{NoStdTrainingRecorder, Recorder},
+};
+use clap::Parser;
+use ruky::{
+    epd::run_suite,
+    eval::AzEval,
+    mcts::Mcts,
+    nn::{AlphaZeroNet, AlphaZeroNetRecord},
+    search::SearchBudget,
+    tensor_decoder::AzDecoder,
+    tensor_encoder::AzEncoder,
+    Ruky,
+};
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+
+#[cfg(feature = "cuda")]
+type Backend = Cuda;
+#[cfg(feature = "wgpu")]
+type Backend = Wgpu;
+
+// Runs a suite of EPD positions, e.g. WAC or STS, and prints the score.
+fn main() {
+    let args = Args::parse();
+    let ruky = Ruky::new();
+
+    let epds = match fs::read_to_string(&args.suite) {
+        Ok(text) => match ruky.read_epds(&text) {
+            Ok(epds) => epds,
+            Err(err) => {
+                eprintln!("Unable to parse suite {:?}: {}", args.suite, err);
+                std::process::exit(1);
+            }
+        },
+        Err(err) => {
+            eprintln!("Unable to read suite {:?}: {}", args.suite, err);
+            std::process::exit(1);
+        }
+    };
+
+    #[cfg(feature = "cuda")]
+    let device = CudaDevice::new(0);
+    #[cfg(feature = "wgpu")]
+    let device = WgpuDevice::DefaultDevice;
+
+    let net = match args.model_path {
+        None => AlphaZeroNet::<Backend>::new(&device),
+        Some(ref model_path) => {
+            let record: AlphaZeroNetRecord<Backend> = NoStdTrainingRecorder::new()
+                .load(model_path.clone(), &device)
+                .expect("Expecting to read model");
+            AlphaZeroNet::<Backend>::new(&device).load_record(record)
+        }
+    };
+    let evaluator = Arc::new(AzEval::create(
+        AzEncoder::new(device),
+        AzDecoder::new(),
+        Arc::new(net),
+    ));
+    let mut mcts = Mcts::create(evaluator, args.nodes);
+
+    let budget = match args.time_ms {
+        Some(time_ms) => SearchBudget::Time(Duration::from_millis(time_ms)),
+        None => SearchBudget::Nodes(args.nodes),
+    };
+    println!("Running {} positions with {:?}...", epds.len(), budget);
+    let suite_result = run_suite(&mut mcts, &epds, budget);
+    for result in suite_result.results.iter() {
+        if args.verbose || !result.solved {
+            println!(
+                "id={} move={} solved={} points={}/{} nodes={} millis={}",
+                result.id.as_deref().unwrap_or("?"),
+                result.san.as_deref().unwrap_or("-"),
+                result.solved,
+                result.points,
+                result.max_points,
+                result.nodes,
+                result.time.as_millis()
+            );
+        }
+    }
+    println!("{}", suite_result);
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// The path to the EPD file with one position per line.
+    suite: PathBuf,
+
+    /// The number of simulations per position.
+    #[arg(short, long, default_value_t = 800)]
+    nodes: usize,
+
+    /// If set, each position is searched for this many milliseconds instead of
+    /// a fixed number of simulations.
+    #[arg(short, long)]
+    time_ms: Option<u64>,
+
+    /// The path to the model. If not set, a new network is used.
+    #[arg(short, long)]
+    model_path: Option<PathBuf>,
+
+    /// Print the result for every position rather than only the unsolved ones.
+    #[arg(short, long)]
+    verbose: bool,
+}
@@ -1,14 +1,77 @@
-use ruky::magics::{compute_bmagics, compute_rmagics, Magics};
-use std::env;
-use std::fs::File;
-use std::io::Write;
+use clap::Parser;
+use rand::RngCore;
+use ruky::magics::{
+    from_black_btables, from_black_rtables, from_bmagics_with_shifts, from_btables,
+    from_rmagics_with_shifts, from_rtables, search_black_bmagics, search_black_rmagics,
+    search_bmagics, search_rmagics, verify_black_bmagics, verify_black_rmagics, verify_bmagics,
+    verify_rmagics, BlackMagicAttacks, MagicAttacks, MagicErr, Magics,
+};
+use std::fmt::Write;
+use std::path::PathBuf;
 use std::thread::spawn;
 
+// Computes bishop and rook magics and writes them as a Rust module, e.g.
+//
+//   cargo run --release --bin gen_magic -- src/gen_magics.rs --seed 7 --tables
+//
+// The module has BMAGICS, BSHIFTS, RMAGICS and RSHIFTS, which can be loaded
+// with from_bmagics_with_shifts and from_rmagics_with_shifts. With --tables,
+// it also has the attack tables, which can be loaded with from_btables and
+// from_rtables.
+//
+// With --black, it has fixed-shift magics instead: BBLACK_MAGICS,
+// BBLACK_OFFSETS and BBLACK_ATTACKS, and the same for rooks, which can be
+// loaded with from_black_btables and from_black_rtables.
+//
+// Every entry is verified before the module is written, so the loaders do not
+// verify the tables again.
 fn main() {
-    println!("Beginning to compute magics...");
+    let args = Args::parse();
+    let seed = args.seed.unwrap_or_else(|| rand::rng().next_u64());
+    println!("Beginning to compute magics with seed {seed}...");
 
-    let t = spawn(compute_bmagics);
-    let r = compute_rmagics();
+    let attempts = args.attempts;
+    let module = if args.black {
+        let (bmagics, rmagics) = search(
+            move || search_black_bmagics(seed, attempts),
+            || search_black_rmagics(seed, attempts),
+        );
+        print_black_info(&bmagics, false);
+        print_black_info(&rmagics, true);
+        if let Err(err) = verify_black(&bmagics, &rmagics) {
+            exit_with_err(&format!("The magics failed verification: {:?}", err));
+        }
+        write_black_module(seed, &bmagics, &rmagics)
+    } else {
+        let reduce_bits = args.reduce_bits;
+        let (bmagics, rmagics) = search(
+            move || search_bmagics(seed, reduce_bits, attempts),
+            || search_rmagics(seed, reduce_bits, attempts),
+        );
+        print_info(&bmagics, false);
+        print_info(&rmagics, true);
+        if let Err(err) = verify(&bmagics, &rmagics, args.tables) {
+            exit_with_err(&format!("The magics failed verification: {:?}", err));
+        }
+        write_module(seed, &bmagics, &rmagics, args.tables)
+    };
+    println!("Verified all magics.");
+
+    if let Some(ref fname) = args.output {
+        if std::fs::write(fname, module).is_err() {
+            exit_with_err("Unable to write magics to file.");
+        }
+        println!("Wrote magics to {}.", fname.display());
+    }
+}
+
+// Runs the bishop search on another thread and the rook search on this one.
+fn search<T: Send + 'static>(
+    bsearch: impl FnOnce() -> Result<T, MagicErr> + Send + 'static,
+    rsearch: impl FnOnce() -> Result<T, MagicErr>,
+) -> (T, T) {
+    let t = spawn(bsearch);
+    let r = rsearch();
 
     let br = t.join();
     if br.is_err() {
@@ -16,50 +79,158 @@ fn main() {
     }
 
     match (br.unwrap(), r) {
-        (Ok(ref bmagic), Ok(ref rmagic)) => process_magics(bmagic, rmagic),
+        (Ok(bmagics), Ok(rmagics)) => (bmagics, rmagics),
         (Ok(_), Err(_)) => exit_with_err("Error computing rook magics."),
         (Err(_), Ok(_)) => exit_with_err("Error computing bishop magics."),
         _ => exit_with_err("Error computing rook and bishop magics."),
     }
 }
 
-fn process_magics(bmagics: &impl Magics, rmagics: &impl Magics) {
-    print_info(bmagics, false);
-    print_info(rmagics, true);
-
-    if let Some(fname) = env::args_os().nth(1) {
-        match File::create(fname) {
-            Err(_) => exit_with_err("Unable to open file for writing."),
-            Ok(mut file) => {
-                fwrite(
-                    &mut file,
-                    "BMAGICS",
-                    bmagics.as_ref().iter().map(|m| m.magic),
-                );
-                fwrite(
-                    &mut file,
-                    "RMAGICS",
-                    rmagics.as_ref().iter().map(|m| m.magic),
-                );
-            }
-        }
+// Loads the magics back in the same way as the generated module would and
+// checks every entry against get_battacks and get_rattacks.
+fn verify(bmagics: &MagicAttacks, rmagics: &MagicAttacks, tables: bool) -> Result<(), MagicErr> {
+    let bloaded = from_bmagics_with_shifts(shifts(bmagics))?;
+    let rloaded = from_rmagics_with_shifts(shifts(rmagics))?;
+    verify_bmagics(&bloaded)?;
+    verify_rmagics(&rloaded)?;
+    if bloaded != *bmagics || rloaded != *rmagics {
+        return Err(MagicErr::Mismatch);
     }
+    if tables {
+        let (offsets, attacks) = flatten(bmagics);
+        let (magics, rshifts): (Vec<_>, Vec<_>) = shifts(bmagics).unzip();
+        verify_bmagics(&from_btables(&magics, &rshifts, &offsets, &attacks)?)?;
+        let (offsets, attacks) = flatten(rmagics);
+        let (magics, rshifts): (Vec<_>, Vec<_>) = shifts(rmagics).unzip();
+        verify_rmagics(&from_rtables(&magics, &rshifts, &offsets, &attacks)?)?;
+    }
+    Ok(())
 }
 
-fn fwrite(ofile: &mut File, name: &str, magics: impl Iterator<Item = u64>) {
-    if writeln!(ofile, "const {}: [u64: 64] = [", name).is_err() {
-        exit_with_err(format!("Unable to write magics for {}", name).as_ref());
+fn verify_black(bmagics: &BlackMagicAttacks, rmagics: &BlackMagicAttacks) -> Result<(), MagicErr> {
+    let bloaded = from_black_btables(bmagics.magics(), bmagics.offsets(), &table(bmagics))?;
+    let rloaded = from_black_rtables(rmagics.magics(), rmagics.offsets(), &table(rmagics))?;
+    verify_black_bmagics(&bloaded)?;
+    verify_black_rmagics(&rloaded)?;
+    if bloaded != *bmagics || rloaded != *rmagics {
+        return Err(MagicErr::Mismatch);
     }
+    Ok(())
+}
 
-    for m in magics {
-        if writeln!(ofile, "{:#x}", m).is_err() {
-            exit_with_err(format!("Unable to write magics for {}", name).as_ref());
+fn table(magics: &BlackMagicAttacks) -> Vec<u64> {
+    magics.table().iter().map(|a| a.u64()).collect()
+}
+
+fn shifts(magics: &MagicAttacks) -> impl ExactSizeIterator<Item = (u64, u8)> + '_ {
+    magics.as_ref().iter().map(|m| (m.magic, m.rshift))
+}
+
+// Returns the offset of the attacks for each square and all the attacks.
+fn flatten(magics: &MagicAttacks) -> (Vec<usize>, Vec<u64>) {
+    let mut offsets = Vec::with_capacity(64);
+    let mut attacks = Vec::new();
+    for m in magics.as_ref() {
+        offsets.push(attacks.len());
+        attacks.extend(m.attacks.iter().map(|a| a.u64()));
+    }
+    (offsets, attacks)
+}
+
+fn write_module(seed: u64, bmagics: &MagicAttacks, rmagics: &MagicAttacks, tables: bool) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Generated by gen_magic with seed {seed}. Do not edit."
+    )
+    .unwrap();
+    for (prefix, magics) in [("B", bmagics), ("R", rmagics)] {
+        writeln!(out).unwrap();
+        write_array(
+            &mut out,
+            "const",
+            &format!("{prefix}MAGICS"),
+            "u64",
+            shifts(magics).map(|(m, _)| format!("{m:#x}")),
+        );
+        write_array(
+            &mut out,
+            "const",
+            &format!("{prefix}SHIFTS"),
+            "u8",
+            shifts(magics).map(|(_, s)| s.to_string()),
+        );
+        if tables {
+            let (offsets, attacks) = flatten(magics);
+            write_array(
+                &mut out,
+                "const",
+                &format!("{prefix}OFFSETS"),
+                "usize",
+                offsets.iter().map(|o| o.to_string()),
+            );
+            write_array(
+                &mut out,
+                "static",
+                &format!("{prefix}ATTACKS"),
+                "u64",
+                attacks.iter().map(|a| format!("{a:#x}")),
+            );
         }
     }
+    out
+}
+
+fn write_black_module(
+    seed: u64,
+    bmagics: &BlackMagicAttacks,
+    rmagics: &BlackMagicAttacks,
+) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Generated by gen_magic --black with seed {seed}. Do not edit."
+    )
+    .unwrap();
+    for (prefix, magics) in [("B", bmagics), ("R", rmagics)] {
+        writeln!(out).unwrap();
+        write_array(
+            &mut out,
+            "const",
+            &format!("{prefix}BLACK_MAGICS"),
+            "u64",
+            magics.magics().iter().map(|m| format!("{m:#x}")),
+        );
+        write_array(
+            &mut out,
+            "const",
+            &format!("{prefix}BLACK_OFFSETS"),
+            "isize",
+            magics.offsets().iter().map(|o| o.to_string()),
+        );
+        write_array(
+            &mut out,
+            "static",
+            &format!("{prefix}BLACK_ATTACKS"),
+            "u64",
+            table(magics).iter().map(|a| format!("{a:#x}")),
+        );
+    }
+    out
+}
 
-    if writeln!(ofile, "];").is_err() {
-        exit_with_err(format!("Unable to write magics for {}", name).as_ref());
+fn write_array(
+    out: &mut String,
+    kind: &str,
+    name: &str,
+    ty: &str,
+    values: impl ExactSizeIterator<Item = String>,
+) {
+    writeln!(out, "pub {kind} {name}: [{ty}; {}] = [", values.len()).unwrap();
+    for v in values {
+        writeln!(out, "    {v},").unwrap();
     }
+    writeln!(out, "];").unwrap();
 }
 
 fn print_info(magics: &impl Magics, for_rook: bool) {
@@ -76,9 +247,121 @@ fn print_info(magics: &impl Magics, for_rook: bool) {
             m.attacks.len()
         );
     }
+    let total: usize = magics.as_ref().iter().map(|m| m.attacks.len()).sum();
+    println!("\ttotal attacks={total}");
 }
 
-fn exit_with_err(msg: &str) {
+fn print_black_info(magics: &BlackMagicAttacks, for_rook: bool) {
+    println!(
+        "{} fixed-shift magics are ...",
+        if for_rook { "Rook" } else { "Bishop" }
+    );
+    for (i, (m, o)) in magics.magics().iter().zip(magics.offsets()).enumerate() {
+        println!("\tsquare {i}: magic={m} offset={o}");
+    }
+    println!("\ttotal attacks={}", magics.table().len());
+}
+
+fn exit_with_err(msg: &str) -> ! {
     eprintln!("{}", msg);
     std::process::exit(1);
 }
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// The path of the Rust module to write. If not set, the magics are only
+    /// printed.
+    output: Option<PathBuf>,
+
+    /// The seed for the search. If not set, a random seed is used and printed.
+    #[arg(short, long)]
+    seed: Option<u64>,
+
+    /// The number of index bits to try to save for each square, which gives
+    /// smaller attack tables. Squares where no such magic is found fall back
+    /// to the full number of bits.
+    #[arg(short, long, default_value_t = 0)]
+    reduce_bits: u8,
+
+    /// The number of candidates to try per square when reducing bits. With
+    /// --black, all of them are tried to find the smallest tables, so use far
+    /// fewer, e.g. 10000.
+    #[arg(short, long, default_value_t = 1_000_000)]
+    attempts: usize,
+
+    /// Also write the precomputed attack tables.
+    #[arg(short, long)]
+    tables: bool,
+
+    /// Search for fixed-shift ("black") magics, which share one attack table,
+    /// instead. The tables are always written.
+    #[arg(short, long)]
+    black: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the values of an array in a generated module.
+    fn array<T: TryFrom<i128>>(module: &str, name: &str) -> Vec<T> {
+        let start = module
+            .lines()
+            .position(|line| line.contains(&format!(" {name}: [")))
+            .unwrap();
+        module
+            .lines()
+            .skip(start + 1)
+            .take_while(|line| *line != "];")
+            .map(|line| {
+                let value = line.trim().trim_end_matches(',');
+                let value = match value.strip_prefix("0x") {
+                    Some(hex) => i128::from_str_radix(hex, 16).unwrap(),
+                    None => value.parse::<i128>().unwrap(),
+                };
+                T::try_from(value).ok().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn emitted_tables() {
+        // Rook searches are slow in debug builds, so both halves of the module
+        // have the same bishop magics.
+        let bmagics = search_bmagics(7, 1, 100).unwrap();
+        let module = write_module(7, &bmagics, &bmagics, true);
+        assert!(module.starts_with("// Generated by gen_magic with seed 7."));
+
+        let loaded = from_bmagics_with_shifts(
+            array::<u64>(&module, "BMAGICS")
+                .into_iter()
+                .zip(array::<u8>(&module, "BSHIFTS")),
+        )
+        .unwrap();
+        assert_eq!(loaded, bmagics);
+        let loaded = from_btables(
+            &array::<u64>(&module, "BMAGICS"),
+            &array::<u8>(&module, "BSHIFTS"),
+            &array::<usize>(&module, "BOFFSETS"),
+            &array::<u64>(&module, "BATTACKS"),
+        )
+        .unwrap();
+        assert!(verify_bmagics(&loaded).is_ok());
+        assert_eq!(loaded, bmagics);
+    }
+
+    #[test]
+    fn emitted_black_tables() {
+        let bmagics = search_black_bmagics(7, 10).unwrap();
+        let module = write_black_module(7, &bmagics, &bmagics);
+        let loaded = from_black_btables(
+            &array::<u64>(&module, "BBLACK_MAGICS"),
+            &array::<isize>(&module, "BBLACK_OFFSETS"),
+            &array::<u64>(&module, "BBLACK_ATTACKS"),
+        )
+        .unwrap();
+        assert!(verify_black_bmagics(&loaded).is_ok());
+        assert_eq!(loaded, bmagics);
+    }
+}
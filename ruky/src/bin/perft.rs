@@ -0,0 +1,99 @@
+use clap::Parser;
+use ruky::{perft::PerftCounts, Piece, PieceMove, Ruky};
+use std::time::Instant;
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+fn main() {
+    let args = Args::parse();
+    let ruky = Ruky::new();
+    let board = match ruky.from_fen(&args.fen) {
+        Ok(board) => board,
+        Err(err) => {
+            eprintln!("Unable to parse FEN: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let now = Instant::now();
+    let total = if args.divide {
+        let mut moves = board.divide(args.depth);
+        moves.sort_by_key(|(piece_move, _)| move_str(piece_move));
+        for (piece_move, counts) in moves.iter() {
+            if args.counts {
+                println!("{}: {}", move_str(piece_move), counts);
+            } else {
+                println!("{}: {}", move_str(piece_move), counts.nodes);
+            }
+        }
+        println!();
+        moves.into_iter().map(|(_, counts)| counts).sum()
+    } else if args.counts {
+        board.perft_counts(args.depth)
+    } else {
+        PerftCounts {
+            nodes: board.perft(args.depth),
+            ..Default::default()
+        }
+    };
+    let dur = now.elapsed();
+
+    if args.counts {
+        println!("{}", total);
+    } else {
+        println!("nodes={}", total.nodes);
+    }
+    println!(
+        "time: millis={} nps={:.0}",
+        dur.as_millis(),
+        total.nodes as f64 / dur.as_secs_f64()
+    );
+}
+
+// Formats a move in the notation used by UCI, e.g. e2e4, a7a8q or N@f3.
+fn move_str(piece_move: &Piece<PieceMove>) -> String {
+    let (from, to) = piece_move.val().from_to();
+    if piece_move.val().is_drop() {
+        let letter = match piece_move {
+            Piece::Queen(_) => 'Q',
+            Piece::Rook(_) => 'R',
+            Piece::Bishop(_) => 'B',
+            Piece::Knight(_) => 'N',
+            _ => 'P',
+        };
+        return format!("{}@{}", letter, to.str().to_lowercase());
+    }
+    let promo = match piece_move.val().promo() {
+        Some(Piece::Queen(_)) => "q",
+        Some(Piece::Rook(_)) => "r",
+        Some(Piece::Bishop(_)) => "b",
+        Some(Piece::Knight(_)) => "n",
+        _ => "",
+    };
+    format!(
+        "{}{}{}",
+        from.str().to_lowercase(),
+        to.str().to_lowercase(),
+        promo
+    )
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// The depth of the tree of moves to count.
+    depth: u8,
+
+    /// The position to start from.
+    #[arg(short, long, default_value_t = START_FEN.to_string())]
+    fen: String,
+
+    /// Print the counts for each move from the starting position.
+    #[arg(short, long)]
+    divide: bool,
+
+    /// Also count captures, en passant, castles, promotions, checks and mates.
+    /// This is slower than only counting nodes.
+    #[arg(short, long)]
+    counts: bool,
+}
@@ -17,6 +17,7 @@ use ruky::{
 };
 use std::{
     fmt::{Display, Formatter},
+    fs,
     path::PathBuf,
     sync::Arc,
     time::{Duration, Instant},
@@ -54,7 +55,25 @@ fn main() {
         Ok(match_result) => {
             let mins = as_mins(&now.elapsed());
             println!("Finished match in {} minutes.", mins);
-            println!("Match results are: {:?}", match_result);
+            println!(
+                "Match results are: {:?} {:?}",
+                match_result.result_player1, match_result.result_player2
+            );
+            if let Some(pgn_path) = args.pgn {
+                let pgn: Result<String, _> = match_result
+                    .pgn_games
+                    .iter()
+                    .map(|game| game.to_pgn().map(|pgn| pgn + "\n"))
+                    .collect();
+                match pgn {
+                    Ok(pgn) => {
+                        if let Err(err) = fs::write(&pgn_path, pgn) {
+                            eprintln!("Unable to write games to {:?}: {}", pgn_path, err);
+                        }
+                    }
+                    Err(err) => eprintln!("Unable to write games as PGN: {}", err),
+                }
+            }
         }
     }
 }
@@ -93,6 +112,10 @@ struct Args {
     /// The path to the model for player 2 when the init strategy is ModelPath.
     #[arg(long)]
     model_path2: Option<PathBuf>,
+
+    /// If set, the games of the match are written to this file in PGN.
+    #[arg(long)]
+    pgn: Option<PathBuf>,
 }
 
 #[derive(Clone, Copy, Debug, ValueEnum)]
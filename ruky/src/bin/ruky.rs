@@ -1,5 +1,6 @@
 use log::LevelFilter;
 use ruky::random_eng::RandomEng;
+use std::path::PathBuf;
 use std::sync::Arc;
 use uzi::conf::Config;
 use uzi::eng::EngController;
@@ -10,6 +11,7 @@ fn main() {
     let mut config = Config::new();
     config.id_name = "Ruky chess engine".into();
     config.id_author = "Omar Serrano".into();
+    config.syzygy_path = Some(PathBuf::from("<empty>"));
     let uzi_out = Arc::new(UziOut::new());
     let eng = RandomEng::new(uzi_out.clone());
     let mut eng_controller = EngController::create(eng, uzi_out, config);
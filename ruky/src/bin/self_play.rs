@@ -128,6 +128,16 @@ fn main() {
         game_stats.avg_move_gen_time_micros(),
         game_stats.max_move_gen_time.as_micros(),
     );
+    let pgn_game = result
+        .pgn_builder()
+        .event("Self-play")
+        .white("Ruky")
+        .black("Ruky")
+        .build();
+    match pgn_game.to_pgn() {
+        Ok(pgn) => println!("======== PGN ========\n{}", pgn),
+        Err(err) => eprintln!("Unable to write the game as PGN: {}", err),
+    }
 }
 
 fn as_mins(dur: &Duration) -> f32 {
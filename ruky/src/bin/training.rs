@@ -7,6 +7,7 @@ use burn::backend::wgpu::{Wgpu, WgpuDevice};
 use clap::Parser;
 use log::LevelFilter;
 use ruky::trainer::TrainerBuilder;
+use ruky::variant::Variant;
 use ruky::Ruky;
 use std::time::{Duration, Instant};
 
@@ -26,7 +27,7 @@ fn main() {
 
     let trainer = TrainerBuilder::<Backend>::new()
         .device(device)
-        .board(ruky.new_board())
+        .board(ruky.variant_board(args.variant))
         .num_games(args.training_games)
         .match_games(args.match_games)
         .num_sessions(args.sessions)
@@ -34,6 +35,7 @@ fn main() {
         .training_batch_size(args.training_batch_size)
         .training_percent(args.training_percent)
         .num_epochs(args.epochs)
+        .chess960(args.chess960)
         .build()
         .expect("Expecting a trainer.");
 
@@ -55,6 +57,10 @@ fn as_mins(dur: &Duration) -> f32 {
     dur.as_secs_f32() / 60.0
 }
 
+fn parse_variant(name: &str) -> Result<Variant, String> {
+    Variant::from_name(name).ok_or_else(|| format!("unknown variant {name}"))
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -91,4 +97,14 @@ struct Args {
     /// The number of epochs to use for training.
     #[arg(short, long, default_value_t = 75)]
     epochs: usize,
+
+    /// If set, the games of self-play start from random Chess960 positions to
+    /// increase the diversity of openings.
+    #[arg(long)]
+    chess960: bool,
+
+    /// The variant to self-play, e.g. standard, three-check, king-of-the-hill
+    /// or atomic.
+    #[arg(long, default_value = "standard", value_parser = parse_variant)]
+    variant: Variant,
 }
@@ -543,6 +543,97 @@ pub const RANK_8: BitBoard = BitBoard {
     bits: 0xffu64 << 56,
 };
 
+// Square colors, where a1 is a dark square.
+pub const LIGHT_SQUARES: BitBoard = BitBoard {
+    bits: 0x55aa55aa55aa55aau64,
+};
+pub const DARK_SQUARES: BitBoard = BitBoard {
+    bits: !0x55aa55aa55aa55aau64,
+};
+
+// The four center squares, i.e. d4, e4, d5 and e5.
+pub const CENTER: BitBoard = BitBoard {
+    bits: 0x0000001818000000u64,
+};
+
+// Returns the squares strictly between squares a and b if they are on the same
+// rank, file or diagonal, or an empty BitBoard otherwise.
+#[inline]
+pub fn between(a: Sq, b: Sq) -> BitBoard {
+    BitBoard::from(LINES.between[a.as_usize()][b.as_usize()])
+}
+
+// Returns the full rank, file or diagonal going through squares a and b,
+// including both squares, or an empty BitBoard if they are not on one line.
+#[inline]
+pub fn line(a: Sq, b: Sq) -> BitBoard {
+    BitBoard::from(LINES.line[a.as_usize()][b.as_usize()])
+}
+
+// Lookup tables for between and line, indexed by [a][b].
+struct LineTables {
+    between: [[u64; 64]; 64],
+    line: [[u64; 64]; 64],
+}
+
+// The tables are generated at compile time.
+static LINES: LineTables = LineTables::generate();
+
+// The rank and file steps along ranks, files and diagonals.
+const DIRECTIONS: [(i8, i8); 8] = [
+    (0, 1),
+    (0, -1),
+    (1, 0),
+    (-1, 0),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+impl LineTables {
+    const fn generate() -> Self {
+        let mut between = [[0u64; 64]; 64];
+        let mut line = [[0u64; 64]; 64];
+        let mut a = 0;
+        while a < 64 {
+            let mut d = 0;
+            while d < DIRECTIONS.len() {
+                let (dr, dc) = DIRECTIONS[d];
+                // The full line is the ray in this direction, the ray in the
+                // opposite direction and square a itself.
+                let full = ray(a, dr, dc) | ray(a, -dr, -dc) | (1 << a);
+                let (mut r, mut c) = ((a / 8) as i8 + dr, (a % 8) as i8 + dc);
+                let mut squares = 0u64;
+                while r >= 0 && r < 8 && c >= 0 && c < 8 {
+                    let b = (r * 8 + c) as usize;
+                    between[a][b] = squares;
+                    line[a][b] = full;
+                    squares |= 1 << b;
+                    r += dr;
+                    c += dc;
+                }
+                d += 1;
+            }
+            a += 1;
+        }
+        Self { between, line }
+    }
+}
+
+// Returns the squares from square a, exclusive, to the edge of the board, when
+// moving dr ranks and dc files at a time.
+const fn ray(a: usize, dr: i8, dc: i8) -> u64 {
+    let (mut r, mut c) = ((a / 8) as i8 + dr, (a % 8) as i8 + dc);
+    let mut squares = 0u64;
+    while r >= 0 && r < 8 && c >= 0 && c < 8 {
+        squares |= 1 << (r * 8 + c);
+        r += dr;
+        c += dc;
+    }
+    squares
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -828,4 +919,31 @@ mod tests {
             BitBoard::from(&[sq::B5, sq::C6, sq::G2])
         );
     }
+
+    #[test]
+    fn between_squares() {
+        assert_eq!(
+            between(sq::A1, sq::H8),
+            BitBoard::from(&[9, 18, 27, 36, 45, 54])
+        );
+        assert_eq!(between(sq::H8, sq::A1), between(sq::A1, sq::H8));
+        assert_eq!(between(sq::E1, sq::E4), BitBoard::from(&[12, 20]));
+        assert_eq!(between(sq::B1, sq::G1), BitBoard::from(&[2, 3, 4, 5]));
+        assert_eq!(between(sq::E1, sq::E2), BitBoard::new());
+        assert_eq!(between(sq::A1, sq::B3), BitBoard::new());
+        assert_eq!(between(sq::E4, sq::E4), BitBoard::new());
+    }
+
+    #[test]
+    fn lines_through_squares() {
+        assert_eq!(line(sq::B2, sq::D4), line(sq::A1, sq::H8));
+        assert_eq!(line(sq::E2, sq::E7), FILE_E);
+        assert_eq!(line(sq::C3, sq::F3), RANK_3);
+        assert_eq!(
+            line(sq::H1, sq::A8),
+            BitBoard::from(&[7, 14, 21, 28, 35, 42, 49, 56])
+        );
+        assert_eq!(line(sq::A1, sq::B3), BitBoard::new());
+        assert_eq!(line(sq::E4, sq::E4), BitBoard::new());
+    }
 }
@@ -1,14 +1,20 @@
-use crate::bitboard::{BitBoard, RANK_3, RANK_6};
-use crate::magics::ChessMagics;
+use crate::bitboard::{
+    between, BitBoard, DARK_SQUARES, LIGHT_SQUARES, RANK_1, RANK_3, RANK_6, RANK_8,
+};
+use crate::fen::{from_fen, to_fen, to_shredder_fen};
+use crate::magics::{shared_magics, ChessMagics};
+use crate::packed::{to_packed, PackedBoard, PackedErr};
+use crate::perft::PerftCounts;
 use crate::piece::{Color, Piece, Piece::*};
 use crate::piece_move::{PieceMove, PieceMove::*};
 use crate::piece_set::{AttackSquares, PieceSet, PiecesErr, PsBuilder};
+use crate::pocket::Pocket;
+use crate::san::{from_san, to_san, SanErr};
 use crate::sq::Sq;
-use std::{
-    collections::HashMap,
-    hash::{DefaultHasher, Hash, Hasher},
-    sync::Arc,
-};
+use crate::variant::{Variant, HILL, WINNING_CHECKS};
+use crate::zobrist::ZOBRIST;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashMap, sync::Arc};
 
 /// Represents a chess board, and encodes the rules for moving pieces and
 /// determining the current game state, e.g. whether the game is drawn.
@@ -114,6 +120,59 @@ impl Board {
         self.black().has_queen_castle()
     }
 
+    // Returns the squares of the white rooks that can castle on the king side
+    // and the queen side.
+    #[inline]
+    pub fn white_castle_rooks(&self) -> (Option<Sq>, Option<Sq>) {
+        (
+            self.white().king_castle_rook(),
+            self.white().queen_castle_rook(),
+        )
+    }
+
+    // Returns the squares of the black rooks that can castle on the king side
+    // and the queen side.
+    #[inline]
+    pub fn black_castle_rooks(&self) -> (Option<Sq>, Option<Sq>) {
+        (
+            self.black().king_castle_rook(),
+            self.black().queen_castle_rook(),
+        )
+    }
+
+    // Returns true if the game is Chess960.
+    #[inline]
+    pub fn is_chess960(&self) -> bool {
+        self.state.chess960
+    }
+
+    // Returns the variant of chess that is played on the board.
+    #[inline]
+    pub fn variant(&self) -> Variant {
+        self.state.variant
+    }
+
+    // Returns the number of checks given by color, which is only counted in
+    // Three-check.
+    #[inline]
+    pub fn checks_given(&self, color: Color) -> u8 {
+        self.state.checks[color_index(color)]
+    }
+
+    // Returns the pocket of color, which is always empty unless the variant is
+    // Crazyhouse.
+    #[inline]
+    pub fn pocket(&self, color: Color) -> &Pocket {
+        &self.state.pockets[color_index(color)]
+    }
+
+    // Returns the squares of the pieces that were promoted from pawns, which
+    // are only tracked in Crazyhouse.
+    #[inline]
+    pub fn promoted(&self) -> BitBoard {
+        self.state.promoted
+    }
+
     #[inline]
     pub fn white(&self) -> &PieceSet {
         match self.color() {
@@ -164,81 +223,171 @@ impl Board {
         self.state.prev_moves.len()
     }
 
+    // Returns the FEN string for the current position.
+    pub fn to_fen(&self) -> String {
+        to_fen(self)
+    }
+
+    // Returns the position packed into 32 bytes, or a PackedErr if the board
+    // has more than 32 pieces or is a Crazyhouse board.
+    pub fn to_packed(&self) -> Result<PackedBoard, PackedErr> {
+        to_packed(self)
+    }
+
+    // Returns a builder for new boards, which share the chess magics and the
+    // variant with this board.
+    pub fn builder(&self) -> BoardBuilder {
+        let mut builder = BoardBuilder::from(self.magics.clone());
+        builder.set_variant(self.state.variant);
+        builder
+    }
+
+    // Returns the Shredder-FEN string for the current position, where the
+    // castling rights are given by the files of the castling rooks.
+    pub fn to_shredder_fen(&self) -> String {
+        to_shredder_fen(self)
+    }
+
+    // Returns piece_move in Standard Algebraic Notation, e.g. Nbd7 or O-O, or
+    // None if the move is not legal in the current position.
+    pub fn to_san(&self, piece_move: Piece<PieceMove>) -> Option<String> {
+        to_san(self, piece_move)
+    }
+
+    // Returns the legal move that matches a move in Standard Algebraic
+    // Notation, e.g. Nbd7 or O-O, or an error if there is no unique match.
+    pub fn parse_san(&self, san: &str) -> Result<Piece<PieceMove>, SanErr> {
+        from_san(self, san)
+    }
+
     // Returns the current repetition count.
     pub fn rep_count(&self) -> u8 {
         self.state.hash_count.values().max().copied().unwrap_or(0)
     }
 
-    // Updates the game state.
-    fn update_game_state(&mut self, piece_move: Option<PieceMove>) {
-        if piece_move.is_some() && piece_move.unwrap().is_king_capture() {
-            self.state.game_state = GameState::Mate(self.state.color());
-            return;
-        }
+    // Returns the number of times the current position has occurred.
+    fn position_count(&self) -> u8 {
+        self.state
+            .hash_count
+            .get(&self.state.state_hash)
+            .copied()
+            .unwrap_or(0)
+    }
 
-        if self.state.half_move >= 50 || !self.state.is_enough_material() {
-            self.state.game_state = GameState::Draw;
-            return;
+    // Returns the reason for which a draw can be claimed in the current
+    // position, i.e. if the position has occurred three times, or there have
+    // been 50 moves by each side without a pawn move or capture, or None if a
+    // draw cannot be claimed. Note that these draws are not automatic, unlike
+    // fivefold repetition and the 75-move rule.
+    pub fn claimable_draw(&self) -> Option<DrawReason> {
+        if self.is_terminal() {
+            None
+        } else if self.position_count() >= CLAIM_REPETITIONS {
+            Some(DrawReason::Repetition)
+        } else if self.state.half_move >= CLAIM_HALF_MOVES {
+            Some(DrawReason::FiftyMove)
+        } else {
+            None
         }
+    }
 
-        if self.state.hash_count.values().find(|&x| *x >= 3).is_some() {
-            self.state.game_state = GameState::Draw;
-            return;
-        }
+    // Claims a draw if one can be claimed in the current position, which ends
+    // the game. Returns the reason for the draw, or None if a draw cannot be
+    // claimed, in which case the board is not changed.
+    pub fn claim_draw(&mut self) -> Option<DrawReason> {
+        let reason = self.claimable_draw()?;
+        self.state.game_state = GameState::Draw(reason);
+        Some(reason)
+    }
 
-        let moves = self.all_moves();
-        if moves.is_none() {
-            self.state.game_state = GameState::Draw;
+    // Updates the game state. Mate and stalemate take precedence over the
+    // automatic draws, e.g. a move that mates on the 75th move wins the game.
+    // A win by the rules of the variant takes precedence over everything else.
+    fn update_game_state(&mut self, piece_move: Option<PieceMove>) {
+        if piece_move.is_some() && piece_move.unwrap().is_king_capture() {
+            self.state.game_state = GameState::Mate(self.state.color());
             return;
         }
-
-        // We want to verify that we have some move such that we are not in check.
-        for pmv in moves.unwrap() {
-            let mut board = self.clone();
-            board.state.partial_update(pmv, self.magics.as_ref());
-            if !board.state.is_other_in_check() {
-                self.state.game_state = if self.is_check() {
-                    GameState::Check(self.state.color())
-                } else {
-                    GameState::Next(self.state.color())
-                };
-                return;
-            }
+        if let Some(loser) = self.state.variant_loser() {
+            self.state.game_state = GameState::Mate(loser);
+            return;
         }
 
-        self.state.game_state = if self.is_check() {
-            GameState::Mate(self.state.color())
-        } else {
+        let color = self.state.color();
+        let is_check = self.is_check();
+        self.state.game_state = if self.legal_moves().is_empty() {
             // If we don't have moves without check, but are not currently in check, then we
             // are in stalemate.
-            GameState::Draw
+            match is_check {
+                true => GameState::Mate(color),
+                false => GameState::Draw(DrawReason::Stalemate),
+            }
+        } else if !self.state.is_enough_material() {
+            GameState::Draw(DrawReason::InsufficientMaterial)
+        } else if self.position_count() >= AUTO_REPETITIONS {
+            GameState::Draw(DrawReason::Repetition)
+        } else if self.state.half_move >= AUTO_HALF_MOVES {
+            GameState::Draw(DrawReason::FiftyMove)
+        } else if is_check {
+            GameState::Check(color)
+        } else {
+            GameState::Next(color)
         };
     }
 
-    // Updates the board state after a move is made.
-    fn update(&mut self, piece_move: Piece<PieceMove>) {
-        self.state.partial_update(piece_move, self.magics.as_ref());
+    // Applies piece_move to the board in place, and returns an Undo that can be
+    // passed to unmake_move to restore the board to exactly the state it had
+    // before the move. The move is expected to be legal, e.g. one of the moves
+    // returned by next_moves.
+    pub fn make_move(&mut self, piece_move: Piece<PieceMove>) -> Undo {
+        let undo = self.state.partial_update(piece_move, self.magics.as_ref());
         self.update_game_state(Some(piece_move.val()));
         self.state.prev_moves.push(piece_move);
+        undo
+    }
+
+    // Reverts the last move made with make_move, where undo is the value that
+    // make_move returned. Moves must be unmade in the reverse order in which
+    // they were made.
+    pub fn unmake_move(&mut self, undo: Undo) {
+        self.state.prev_moves.pop();
+        self.state.unmake(undo);
+    }
+
+    // Passes the turn to the other player, e.g. for null-move pruning or for
+    // the 0000 move in UCI. The en-passant square is cleared, and the hash and
+    // the clocks are updated as for a quiet move, but the null move is not
+    // added to the previous moves, and the position after it is not counted
+    // for repetitions, so that null moves in a search line never make a draw
+    // by repetition. Returns None if the player to move is in check or the
+    // game is over, since passing is not possible then, and otherwise an Undo
+    // that can be passed to unmake_null_move.
+    pub fn make_null_move(&mut self) -> Option<Undo> {
+        if self.is_check() || self.is_terminal() {
+            return None;
+        }
+        let undo = self.state.null_update(self.magics.as_ref());
+        self.update_game_state(None);
+        Some(undo)
+    }
+
+    // Reverts the last null move made with make_null_move, where undo is the
+    // value that make_null_move returned.
+    pub fn unmake_null_move(&mut self, undo: Undo) {
+        self.state.restore(undo);
     }
 
     // Returns boards representing all the valid positions that are reachable from
     // the current position. If this is a terminal state, then it returns None.
     pub fn next_boards(&self) -> Option<Vec<Board>> {
-        if self.is_terminal() {
-            return None;
-        }
-
         Some(
-            self.all_moves()
-                .expect("Unable to compute any moves in a non-terminal state.")
+            self.next_moves()?
                 .into_iter()
                 .map(|piece_move| {
                     let mut board = self.clone();
-                    board.update(piece_move);
+                    board.make_move(piece_move);
                     board
                 })
-                .filter(|board| !board.state.is_other_in_check())
                 .collect(),
         )
     }
@@ -250,17 +399,7 @@ impl Board {
             return None;
         }
 
-        Some(
-            self.all_moves()
-                .expect("Unable to compute any moves in a non-terminal state.")
-                .into_iter()
-                .filter(|piece_move| {
-                    let mut board = self.clone();
-                    board.update(*piece_move);
-                    !board.state.is_other_in_check()
-                })
-                .collect(),
-        )
+        Some(self.legal_moves())
     }
 
     // Returns true if piece_move is a legal move in the current position, or false
@@ -270,6 +409,69 @@ impl Board {
             .is_some_and(|moves| moves.into_iter().find(|mv| *mv == piece_move).is_some())
     }
 
+    // Returns the static exchange evaluation of piece_move in centipawns, i.e.
+    // the material balance for the side moving next after the sequence of
+    // captures on the destination square, where each side recaptures with its
+    // least valuable piece and may stop capturing when it is not favourable.
+    // Sliders behind other attackers join the exchange once the pieces in front
+    // of them have captured. Pins and promotions during the exchange are not
+    // considered.
+    pub fn see(&self, piece_move: Piece<PieceMove>) -> i32 {
+        let mv = piece_move.val();
+        let (from, to) = mv.from_to();
+        let mut occupied = self.state.all();
+        let mut gains = [0; 32];
+        let mut on_sq = piece_move.value();
+        match mv {
+            PieceMove::Castle { .. } => return 0,
+            PieceMove::Simple { .. } | PieceMove::Drop { .. } => (),
+            PieceMove::Capture { cap, .. } => gains[0] = cap.value(),
+            PieceMove::EnPassant { passant, .. } => {
+                gains[0] = Pawn(()).value();
+                occupied.clear_bit(passant);
+            }
+            PieceMove::Promo { promo, .. } => {
+                gains[0] = promo.value() - on_sq;
+                on_sq = promo.value();
+            }
+            PieceMove::PromoCap { promo, cap, .. } => {
+                gains[0] = cap.value() + promo.value() - on_sq;
+                on_sq = promo.value();
+            }
+        }
+        occupied.clear_bit(from);
+
+        // Each side captures in turn with its least valuable attacker, where
+        // gains[depth] is the balance for the side capturing at depth if the
+        // exchange stops after its capture.
+        let mut depth = 0;
+        for pieces in [&self.state.other, &self.state.mine].into_iter().cycle() {
+            let attackers = self.state.attackers(to, occupied, &self.magics) & occupied;
+            let attacker = match pieces.least_valuable(attackers) {
+                Some(attacker) => attacker,
+                None => break,
+            };
+            depth += 1;
+            gains[depth] = on_sq - gains[depth - 1];
+            on_sq = attacker.value();
+            occupied.clear_bit(attacker.val());
+        }
+
+        // Either side can stop the exchange, so the balance at each depth is the
+        // worse of stopping and letting the opponent recapture.
+        while depth > 0 {
+            gains[depth - 1] = gains[depth - 1].min(-gains[depth]);
+            depth -= 1;
+        }
+        gains[0]
+    }
+
+    // Returns true if the static exchange evaluation of piece_move is at least
+    // threshold.
+    pub fn see_ge(&self, piece_move: Piece<PieceMove>, threshold: i32) -> bool {
+        self.see(piece_move) >= threshold
+    }
+
     // Returns the next board when move piece_move is applied. If the move is
     // illegal or we are in a terminal state, then it returns None.
     pub fn next_from_move(&self, piece_move: Piece<PieceMove>) -> Option<Board> {
@@ -282,7 +484,7 @@ impl Board {
         }
 
         let mut board = self.clone();
-        board.update(piece_move);
+        board.make_move(piece_move);
         Some(board)
     }
 
@@ -298,7 +500,7 @@ impl Board {
             if !board.is_legal_move(*pm) {
                 return None;
             }
-            board.update(*pm);
+            board.make_move(*pm);
         }
         Some(board)
     }
@@ -306,6 +508,9 @@ impl Board {
     // Same as next_from_moves, but moves are simply specified as triplets of (from,
     // to, optional piece), where from is the source square, to is the
     // destination square, and the optional piece is for a pawn promotion.
+    // Castling moves are given as the king capturing its own rook in Chess960,
+    // since e.g. b1c1 may be either a king move or castling, and otherwise may
+    // be given either way. Drops in Crazyhouse are given as (to, to, piece).
     pub fn next_from_rc(&self, moves: &[(u8, u8, Option<Piece<()>>)]) -> Option<Board> {
         let mut board = self.clone();
         for pm in moves {
@@ -319,17 +524,120 @@ impl Board {
                 .iter()
                 .find(|m| {
                     let move_type = m.val();
+                    if let Drop { to } = move_type {
+                        return *pm == (u8::from(to), u8::from(to), Some(m.kind()));
+                    }
                     let from_to = move_type.from_to();
                     let s = u8::from(from_to.0);
                     let d = u8::from(from_to.1);
+                    if let Castle {
+                        king_from,
+                        rook_from,
+                        ..
+                    } = move_type
+                    {
+                        if *pm == (u8::from(king_from), u8::from(rook_from), None) {
+                            return true;
+                        }
+                        if board.is_chess960() {
+                            return false;
+                        }
+                    }
                     *pm == (s, d, move_type.promo())
                 })
                 .copied()?;
-            board.update(next_move);
+            board.make_move(next_move);
         }
         Some(board)
     }
 
+    // Returns the number of leaf nodes in the tree of legal moves of the given
+    // depth from the current position. Unlike next_moves, this ignores the
+    // draw rules, e.g. repetitions, so that the counts only depend on the
+    // move generator.
+    pub fn perft(&self, depth: u8) -> u64 {
+        self.clone().perft_nodes(depth)
+    }
+
+    // Same as perft, but the leaf nodes are also counted by the type of move
+    // leading to them, and whether they are check or mate.
+    pub fn perft_counts(&self, depth: u8) -> PerftCounts {
+        if depth == 0 {
+            return PerftCounts {
+                nodes: 1,
+                ..Default::default()
+            };
+        }
+        let mut board = self.clone();
+        let mut counts = PerftCounts::default();
+        board.perft_add(depth, &mut counts);
+        counts
+    }
+
+    // Returns the perft counts for each legal move from the current position,
+    // where depth includes the move itself. This is useful to find where the
+    // move generator diverges from a reference implementation.
+    pub fn divide(&self, depth: u8) -> Vec<(Piece<PieceMove>, PerftCounts)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        let mut board = self.clone();
+        board
+            .legal_moves()
+            .into_iter()
+            .map(|piece_move| {
+                let undo = board
+                    .state
+                    .partial_update(piece_move, board.magics.as_ref());
+                let mut counts = PerftCounts::default();
+                if depth == 1 {
+                    counts = board.perft_leaf(piece_move);
+                } else {
+                    board.perft_add(depth - 1, &mut counts);
+                }
+                board.state.unmake(undo);
+                (piece_move, counts)
+            })
+            .collect()
+    }
+
+    fn perft_nodes(&mut self, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for piece_move in moves {
+            let undo = self.state.partial_update(piece_move, self.magics.as_ref());
+            nodes += self.perft_nodes(depth - 1);
+            self.state.unmake(undo);
+        }
+        nodes
+    }
+
+    fn perft_add(&mut self, depth: u8, counts: &mut PerftCounts) {
+        for piece_move in self.legal_moves() {
+            let undo = self.state.partial_update(piece_move, self.magics.as_ref());
+            if depth == 1 {
+                *counts += self.perft_leaf(piece_move);
+            } else {
+                self.perft_add(depth - 1, counts);
+            }
+            self.state.unmake(undo);
+        }
+    }
+
+    // Returns the counts for the current position as a leaf node reached by
+    // piece_move.
+    fn perft_leaf(&self, piece_move: Piece<PieceMove>) -> PerftCounts {
+        let is_check = self.state.is_mine_in_check();
+        let is_mate = is_check && self.legal_moves().is_empty();
+        PerftCounts::leaf(piece_move, is_check, is_mate)
+    }
+
     // Returns true if the current position represents a terminal state.
     #[inline]
     pub fn is_terminal(&self) -> bool {
@@ -348,6 +656,38 @@ impl Board {
         self.state.is_mine_in_check()
     }
 
+    // Returns the pieces of the given color that attack square sq.
+    pub fn attackers_to(&self, sq: Sq, color: Color) -> BitBoard {
+        let pieces = if self.state.color() == color {
+            &self.state.mine
+        } else {
+            &self.state.other
+        };
+        self.state.attackers(sq, self.state.all(), &self.magics) & pieces.all()
+    }
+
+    // Returns true if square sq is attacked by any piece of color by.
+    pub fn is_attacked(&self, sq: Sq, by: Color) -> bool {
+        self.attackers_to(sq, by).any()
+    }
+
+    // Returns the pieces giving check to the king of the player moving next.
+    pub fn checkers(&self) -> BitBoard {
+        match self.state.mine.king().first_bit() {
+            Some(king_sq) => self.attackers_to(king_sq, self.state.color().flip()),
+            None => BitBoard::new(),
+        }
+    }
+
+    // Returns the pieces of the player moving next that are pinned to their
+    // king, along with the rays along which they can still move.
+    pub fn pinned(&self) -> Vec<Pin> {
+        match self.state.mine.king().first_bit() {
+            Some(king_sq) => self.state.pins(king_sq, &self.magics).collect(),
+            None => Vec::new(),
+        }
+    }
+
     // Getter for the game state.
     #[inline]
     pub fn game_state(&self) -> GameState {
@@ -362,15 +702,10 @@ impl Board {
             return None;
         }
 
-        let mut moves: Vec<Piece<PieceMove>> = Vec::new();
-
-        self.king_moves(&mut moves);
-        self.queen_moves(&mut moves);
-        self.rook_moves(&mut moves);
-        self.bishop_moves(&mut moves);
-        self.knight_moves(&mut moves);
-        self.pawn_moves(&mut moves);
-
+        let moves = self.gen_moves(&MoveMasks::pseudo_legal(
+            self.state.other_attacks.all(),
+            GenStage::All,
+        ));
         if moves.is_empty() {
             None
         } else {
@@ -378,22 +713,223 @@ impl Board {
         }
     }
 
-    fn king_moves(&self, moves: &mut Vec<Piece<PieceMove>>) {
-        self.simple_moves(King(self.state.mine.king()), moves, |b| b.king_moves());
-        let (king_castle, queen_castle) = self
-            .state
-            .mine
-            .castle(&self.state.other, self.state.other_attacks.all());
-        if let Some(mv) = king_castle {
-            moves.push(mv);
+    // Returns the legal captures, including captures by en passant, and the
+    // promotions. Unlike next_moves, this does not check if the game is over.
+    pub fn captures(&self) -> Vec<Piece<PieceMove>> {
+        self.legal_moves_for(GenStage::Captures)
+    }
+
+    // Returns the pseudo-legal captures and promotions, which may leave the king
+    // in check.
+    pub fn pseudo_captures(&self) -> Vec<Piece<PieceMove>> {
+        self.gen_moves(&MoveMasks::pseudo_legal(
+            self.state.other_attacks.all(),
+            GenStage::Captures,
+        ))
+    }
+
+    // Returns the legal moves that are neither captures nor promotions, which
+    // includes castling. Unlike next_moves, this does not check if the game is
+    // over.
+    pub fn quiets(&self) -> Vec<Piece<PieceMove>> {
+        self.legal_moves_for(GenStage::Quiets)
+    }
+
+    // Returns the pseudo-legal moves that are neither captures nor promotions,
+    // which may leave the king in check.
+    pub fn pseudo_quiets(&self) -> Vec<Piece<PieceMove>> {
+        self.gen_moves(&MoveMasks::pseudo_legal(
+            self.state.other_attacks.all(),
+            GenStage::Quiets,
+        ))
+    }
+
+    // Returns the legal moves if the player moving next is in check, or no moves
+    // otherwise.
+    pub fn evasions(&self) -> Vec<Piece<PieceMove>> {
+        if !self.is_check() {
+            return Vec::new();
+        }
+        self.legal_moves_for(GenStage::All)
+    }
+
+    // Returns the moves that capture or block a single checking piece, and the
+    // king moves to squares that are not attacked, if the player moving next is
+    // in check, or no moves otherwise. The moves may leave the king in check if
+    // the moving piece is pinned.
+    pub fn pseudo_evasions(&self) -> Vec<Piece<PieceMove>> {
+        if !self.is_check() {
+            return Vec::new();
+        }
+        match self.check_masks(GenStage::All) {
+            Some((_, mut masks)) => {
+                masks.check_discovered = false;
+                self.gen_moves(&masks)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // Computes the legal moves directly, without making any of the moves, by
+    // restricting where each piece can move based on the pieces giving check
+    // and the pieces that are pinned to the king.
+    fn legal_moves(&self) -> Vec<Piece<PieceMove>> {
+        self.legal_moves_for(GenStage::All)
+    }
+
+    // Computes the legal moves of the given stage.
+    fn legal_moves_for(&self, stage: GenStage) -> Vec<Piece<PieceMove>> {
+        if self.state.variant == Variant::Atomic {
+            return self.atomic_moves(stage);
+        }
+        let (king_sq, mut masks) = match self.check_masks(stage) {
+            Some(masks) => masks,
+            None => return Vec::new(),
+        };
+
+        // Pinned pieces can only move along the line between the king and the
+        // pinning piece, which includes capturing the pinning piece.
+        for pin in self.state.pins(king_sq, &self.magics) {
+            masks.targets[pin.sq.as_usize()] = masks.targets[pin.sq.as_usize()] & pin.ray;
+        }
+
+        self.gen_moves(&masks)
+    }
+
+    // Computes the legal moves of the given stage in Atomic chess. Explosions
+    // can remove the pieces giving check and the pieces that are pinned, so
+    // the pseudo-legal moves are filtered by playing each move on a copy of
+    // the pieces.
+    fn atomic_moves(&self, stage: GenStage) -> Vec<Piece<PieceMove>> {
+        let state = &self.state;
+        if state.mine.king().none() {
+            return Vec::new();
+        }
+        let magics = self.magics.as_ref();
+        let attacked = state.other_attacked(state.all() & !state.mine.king(), magics);
+        self.gen_moves(&MoveMasks::pseudo_legal(attacked, stage))
+            .into_iter()
+            .filter(|piece_move| state.is_atomic_legal(*piece_move, magics))
+            .collect()
+    }
+
+    // Returns the square of the king moving next, and the masks that only allow
+    // the king to move to squares that are not attacked, and the other pieces
+    // to capture or block a piece giving check, if any. Returns None if there
+    // is no king.
+    fn check_masks(&self, stage: GenStage) -> Option<(Sq, MoveMasks)> {
+        let state = &self.state;
+        let magics = self.magics.as_ref();
+        let king_sq = state.mine.king().first_bit()?;
+        let occupied = state.all();
+
+        // The squares attacked by the other pieces are computed without our king,
+        // so that the king cannot escape a check by moving along the line of
+        // attack of a slider.
+        let attacked = state.other_attacked(occupied & !state.mine.king(), magics);
+        let checkers = state.other_attackers(king_sq, occupied, magics);
+
+        // If there are no checkers, then pieces can move anywhere. If there is a
+        // single checker, then pieces must capture it or block it. If it is a
+        // double check, then only the king can move.
+        let check_mask = match checkers.count() {
+            0 => !BitBoard::new(),
+            1 => {
+                let checker = checkers
+                    .first_bit()
+                    .expect("Checkers should have a bit set.");
+                checkers | between(king_sq, checker)
+            }
+            _ => BitBoard::new(),
+        };
+
+        let mut masks = MoveMasks {
+            targets: [check_mask; 64],
+            drops: check_mask,
+            attacked,
+            check_discovered: true,
+            stage,
+        };
+        masks.targets[king_sq.as_usize()] = !attacked;
+        Some((king_sq, masks))
+    }
+
+    // Generates the moves for all the pieces moving next, restricted to the
+    // squares allowed by masks.
+    fn gen_moves(&self, masks: &MoveMasks) -> Vec<Piece<PieceMove>> {
+        let mut moves: Vec<Piece<PieceMove>> = Vec::new();
+
+        self.king_moves(&mut moves, masks);
+        self.queen_moves(&mut moves, masks);
+        self.rook_moves(&mut moves, masks);
+        self.bishop_moves(&mut moves, masks);
+        self.knight_moves(&mut moves, masks);
+        self.pawn_moves(&mut moves, masks);
+        if self.state.variant == Variant::Crazyhouse {
+            self.drop_moves(&mut moves, masks);
+        }
+
+        moves
+    }
+
+    // Generates the drops of the pieces in the pocket of the player moving next
+    // on the empty squares allowed by masks. Pawns cannot be dropped on the
+    // first or last rank.
+    fn drop_moves(&self, moves: &mut Vec<Piece<PieceMove>>, masks: &MoveMasks) {
+        if !masks.stage.has_quiets() {
+            return;
+        }
+        let empty = self.state.none() & masks.drops;
+        for (piece, _) in self.state.pockets[color_index(self.state.color())].iter() {
+            let targets = match piece {
+                Pawn(_) => empty & !(RANK_1 | RANK_8),
+                _ => empty,
+            };
+            for to in targets.sq_iter() {
+                moves.push(piece.with(Drop { to }));
+            }
+        }
+    }
+
+    fn king_moves(&self, moves: &mut Vec<Piece<PieceMove>>, masks: &MoveMasks) {
+        self.simple_moves(King(self.state.mine.king()), moves, masks, |b| {
+            b.king_moves()
+        });
+        if !masks.stage.has_quiets() {
+            return;
         }
-        if let Some(mv) = queen_castle {
-            moves.push(mv);
+        let (king_castle, queen_castle) = self.state.mine.castle(&self.state.other, masks.attacked);
+        for mv in [king_castle, queen_castle].into_iter().flatten() {
+            if !masks.check_discovered || self.is_castle_legal(mv.val()) {
+                moves.push(mv);
+            }
+        }
+    }
+
+    // Returns true if castling does not leave the king in check. The squares
+    // that the king moves through are never attacked, but in Chess960 the rook
+    // may shield the king from a slider on the first rank before castling,
+    // e.g. a king on d1 and a rook on b1 with an enemy rook on a1.
+    fn is_castle_legal(&self, mv: PieceMove) -> bool {
+        match mv {
+            Castle {
+                king_from,
+                king_to,
+                rook_from,
+                ..
+            } => {
+                let mut occupied = self.state.all();
+                occupied.clear_bit(king_from).clear_bit(rook_from);
+                self.state
+                    .other_attackers(king_to, occupied, self.magics.as_ref())
+                    .none()
+            }
+            _ => true,
         }
     }
 
-    fn queen_moves(&self, moves: &mut Vec<Piece<PieceMove>>) {
-        self.simple_moves(Queen(self.state.mine.queens()), moves, |b| {
+    fn queen_moves(&self, moves: &mut Vec<Piece<PieceMove>>, masks: &MoveMasks) {
+        self.simple_moves(Queen(self.state.mine.queens()), moves, masks, |b| {
             let from = b.first_bit().expect("BitBoard should have a bit set.");
             self.magics
                 .qmagics(from, self.state.all())
@@ -401,8 +937,8 @@ impl Board {
         });
     }
 
-    fn rook_moves(&self, moves: &mut Vec<Piece<PieceMove>>) {
-        self.simple_moves(Rook(self.state.mine.rooks()), moves, |b| {
+    fn rook_moves(&self, moves: &mut Vec<Piece<PieceMove>>, masks: &MoveMasks) {
+        self.simple_moves(Rook(self.state.mine.rooks()), moves, masks, |b| {
             let from = b.first_bit().expect("BitBoard should have a bit set.");
             self.magics
                 .rmagics(from, self.state.all())
@@ -410,8 +946,8 @@ impl Board {
         });
     }
 
-    fn bishop_moves(&self, moves: &mut Vec<Piece<PieceMove>>) {
-        self.simple_moves(Bishop(self.state.mine.bishops()), moves, |b| {
+    fn bishop_moves(&self, moves: &mut Vec<Piece<PieceMove>>, masks: &MoveMasks) {
+        self.simple_moves(Bishop(self.state.mine.bishops()), moves, masks, |b| {
             let from = b.first_bit().expect("BitBoard should have a bit set.");
             self.magics
                 .bmagics(from, self.state.all())
@@ -419,22 +955,24 @@ impl Board {
         });
     }
 
-    fn knight_moves(&self, moves: &mut Vec<Piece<PieceMove>>) {
-        self.simple_moves(Knight(self.state.mine.knights()), moves, |b| {
+    fn knight_moves(&self, moves: &mut Vec<Piece<PieceMove>>, masks: &MoveMasks) {
+        self.simple_moves(Knight(self.state.mine.knights()), moves, masks, |b| {
             b.knight_moves()
         });
     }
 
-    fn pawn_moves(&self, moves: &mut Vec<Piece<PieceMove>>) {
+    fn pawn_moves(&self, moves: &mut Vec<Piece<PieceMove>>, masks: &MoveMasks) {
         if self.state.color().is_white() {
             self.add_pawn_moves(
                 moves,
+                masks,
                 |bits, empty| bits.wp_moves(empty),
                 |s| s.in_last_rank(),
             );
         } else {
             self.add_pawn_moves(
                 moves,
+                masks,
                 |bits, empty| bits.bp_moves(empty),
                 |s| s.in_first_rank(),
             );
@@ -444,6 +982,7 @@ impl Board {
     fn add_pawn_moves(
         &self,
         moves: &mut Vec<Piece<PieceMove>>,
+        masks: &MoveMasks,
         moves_fn: impl Fn(BitBoard, BitBoard) -> (BitBoard, BitBoard),
         is_promo: impl Fn(Sq) -> bool,
     ) {
@@ -453,16 +992,23 @@ impl Board {
 
         for (from, pawn_bit) in pawns.sq_bit_iter() {
             let (forward_moves, all_attacks) = moves_fn(pawn_bit, empty);
+            let target = masks.targets[from.as_usize()];
 
-            for to in forward_moves.sq_iter() {
+            for to in (forward_moves & target).sq_iter() {
                 if is_promo(to) {
-                    add_promo(from, to, moves);
-                } else {
+                    if masks.stage.has_captures() {
+                        add_promo(from, to, moves);
+                    }
+                } else if masks.stage.has_quiets() {
                     moves.push(Pawn(Simple { from, to }));
                 }
             }
 
-            let attacks = all_attacks & other;
+            if !masks.stage.has_captures() {
+                continue;
+            }
+
+            let attacks = all_attacks & other & target;
 
             for to in attacks.sq_iter() {
                 let cap = self
@@ -481,6 +1027,10 @@ impl Board {
             if let Some(passant_cap) = self
                 .state
                 .passant_sq
+                .filter(|ps| {
+                    !masks.check_discovered
+                        || self.state.is_passant_legal(from, *ps, self.magics.as_ref())
+                })
                 .and_then(|ps| ps.by_enpassant(from, all_attacks))
             {
                 moves.push(passant_cap)
@@ -492,14 +1042,21 @@ impl Board {
         &self,
         piece: Piece<BitBoard>,
         moves: &mut Vec<Piece<PieceMove>>,
+        masks: &MoveMasks,
         move_fn: impl Fn(BitBoard) -> BitBoard,
     ) {
         for (from, bit) in piece.val().sq_bit_iter() {
-            let bit_moves = move_fn(bit);
+            let bit_moves = move_fn(bit) & masks.targets[from.as_usize()];
+
+            if masks.stage.has_quiets() {
+                let non_attacks = bit_moves & self.state.none();
+                for to in non_attacks.sq_iter() {
+                    moves.push(piece.with(Simple { from, to }));
+                }
+            }
 
-            let non_attacks = bit_moves & self.state.none();
-            for to in non_attacks.sq_iter() {
-                moves.push(piece.with(Simple { from, to }));
+            if !masks.stage.has_captures() {
+                continue;
             }
 
             let attacks = bit_moves & self.state.other.all();
@@ -539,6 +1096,13 @@ impl std::hash::Hash for Board {
     }
 }
 
+// Displays the board as a FEN string.
+impl std::fmt::Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_fen())
+    }
+}
+
 // For debug, don't include magics.
 impl std::fmt::Debug for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -546,6 +1110,67 @@ impl std::fmt::Debug for Board {
     }
 }
 
+// The serialized form of a Board. The position is stored as FEN, and the
+// game state and history are stored next to it, so claimed draws, repetitions
+// and the last move survive a round trip.
+#[derive(Deserialize, Serialize)]
+struct SerdeBoard {
+    fen: String,
+    chess960: bool,
+    #[serde(default)]
+    variant: Variant,
+    game_state: GameState,
+    prev_moves: Vec<Piece<PieceMove>>,
+    hash_count: Vec<(u64, u8)>,
+}
+
+impl Serialize for Board {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut hash_count: Vec<_> = self
+            .state
+            .hash_count
+            .iter()
+            .map(|(h, c)| (*h, *c))
+            .collect();
+        // Sort the counts so that the same board is always written the same way.
+        hash_count.sort_unstable();
+        SerdeBoard {
+            fen: self.to_fen(),
+            chess960: self.state.chess960,
+            variant: self.state.variant,
+            game_state: self.state.game_state,
+            prev_moves: self.state.prev_moves.clone(),
+            hash_count,
+        }
+        .serialize(serializer)
+    }
+}
+
+// Boards are rebuilt with the shared magics, since the magics aren't
+// serialized. The position is not validated strictly, because it was a valid
+// board when it was written.
+impl<'de> Deserialize<'de> for Board {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = SerdeBoard::deserialize(deserializer)?;
+        let mut builder = BoardBuilder::from(shared_magics());
+        builder
+            .set_lenient(true)
+            .set_chess960(repr.chess960)
+            .set_variant(repr.variant);
+        let mut board = from_fen(&repr.fen, builder).map_err(de::Error::custom)?;
+        board.state.game_state = repr.game_state;
+        board.state.prev_moves = repr.prev_moves;
+        board.state.hash_count = repr.hash_count.into_iter().collect();
+        Ok(board)
+    }
+}
+
 // BoardState holds all the state needed needed to a play a game of regular
 // chess, including the position of the pieces, position of squares that are
 // attacked, the current game state, the number of half moves, the number of
@@ -591,6 +1216,23 @@ struct BoardState {
 
     // A hash-to-count map to be able to identify 3-fold repetition draw.
     hash_count: HashMap<u64, u8>,
+
+    // Whether the game is Chess960, which only changes how castling moves are
+    // written, i.e. as the king capturing its own rook in UCI notation.
+    chess960: bool,
+
+    // The variant of chess, which changes how the game ends, and in Atomic,
+    // what a capture does and which moves are legal.
+    variant: Variant,
+
+    // The number of checks given by white and by black, which is only counted
+    // in Three-check.
+    checks: [u8; 2],
+
+    // The pockets of white and black, and the squares of the pieces that were
+    // promoted from pawns, which are only used in Crazyhouse.
+    pockets: [Pocket; 2],
+    promoted: BitBoard,
 }
 
 impl BoardState {
@@ -611,39 +1253,202 @@ impl BoardState {
 
     // Returns the true if pieces moving next are in check.
     fn is_mine_in_check(&self) -> bool {
-        (self.mine.king() & self.other_attacks.pieces).any()
+        (self.mine.king() & self.other_attacks.pieces).any() && !self.are_kings_connected()
     }
 
     // Returns true if the pieces not moving next are are in check. Technically,
     // this not a a valid state, because a player cannot put themselves in
     // check, which is exactly the motivation for defining this.
     fn is_other_in_check(&self) -> bool {
-        (self.other.king() & self.my_attacks.pieces).any()
+        (self.other.king() & self.my_attacks.pieces).any() && !self.are_kings_connected()
+    }
+
+    // Returns true if the kings are next to each other in Atomic chess, where
+    // neither king can be in check, because capturing the other king would
+    // also explode the own king.
+    fn are_kings_connected(&self) -> bool {
+        self.variant == Variant::Atomic && (self.mine.king().king_moves() & self.other.king()).any()
+    }
+
+    // Returns the color that lost the game by the rules of the variant, if any,
+    // which are only checked after the move and hence only for the pieces that
+    // just moved, or the king that just exploded.
+    fn variant_loser(&self) -> Option<Color> {
+        let mover = self.color().flip();
+        let lost = match self.variant {
+            Variant::Standard => false,
+            Variant::ThreeCheck => self.checks[color_index(mover)] >= WINNING_CHECKS,
+            Variant::KingOfTheHill => (self.other.king() & HILL).any(),
+            Variant::Atomic => self.mine.king().none(),
+            Variant::Crazyhouse => false,
+        };
+        lost.then_some(self.color())
+    }
+
+    // Returns true if piece_move does not explode the king moving next, and does
+    // not leave it in check unless the other king explodes or the kings end up
+    // next to each other. Kings cannot capture in Atomic chess, since that
+    // would explode the king.
+    fn is_atomic_legal(&self, piece_move: Piece<PieceMove>, magics: &ChessMagics) -> bool {
+        let mv = piece_move.val();
+        if piece_move.is_king() && mv.is_capture() {
+            return false;
+        }
+        let (mut mine, mut other) = (*self.mine, *self.other);
+        if mine.apply_move(piece_move).is_err() {
+            return false;
+        }
+        if mv.is_capture() {
+            if other.remove_captured(mv).is_err() {
+                return false;
+            }
+            explode(&mut mine, &mut other, mv.from_to().1);
+        }
+
+        let king_sq = match mine.king().first_bit() {
+            Some(king_sq) => king_sq,
+            None => return false,
+        };
+        if other.king().none() || (mine.king().king_moves() & other.king()).any() {
+            return true;
+        }
+        side_attackers(&other, king_sq, mine.all() | other.all(), magics).none()
     }
 
     // Returns true if there is enough material on either side for a win, or false
     // if neither side can win given the material. The following scenarios are a
     // draw:
     // - only king per side
-    // - bishop or knight vs bishop or knight
+    // - king and knight vs king
+    // - kings and any number of bishops, if all the bishops are on squares of
+    //   the same color, e.g. king and bishop vs king
+    //
+    // In King of the Hill, either king can still walk to the center, in
+    // Three-check, any piece can still give check, and in Crazyhouse, captured
+    // pieces are never lost.
     fn is_enough_material(&self) -> bool {
-        let my_count = self.mine.all().count();
-        let other_count = self.other.all().count();
-
-        if my_count > 2 || other_count > 2 {
+        let (mine, other) = (&self.mine, &self.other);
+        match self.variant {
+            Variant::KingOfTheHill | Variant::Crazyhouse => return true,
+            Variant::ThreeCheck => return (self.all() & !(mine.king() | other.king())).any(),
+            Variant::Standard | Variant::Atomic => (),
+        }
+        let majors = mine.queens() | mine.rooks() | mine.pawns();
+        if (majors | other.queens() | other.rooks() | other.pawns()).any() {
             return true;
         }
 
-        if my_count == 1 && other_count == 1 {
-            return false;
-        } else if my_count == 2 && other_count == 1 {
-            return self.mine.bishops().count() == 0 && self.mine.knights().count() == 0;
-        } else if my_count == 1 && other_count == 2 {
-            return self.other.bishops().count() == 0 && self.other.knights().count() == 0;
+        let knights = mine.knights() | other.knights();
+        let bishops = mine.bishops() | other.bishops();
+        match knights.count() {
+            0 => (bishops & LIGHT_SQUARES).any() && (bishops & DARK_SQUARES).any(),
+            1 => bishops.any(),
+            _ => true,
         }
-        // TODO: king + bishop vs king + bishop is draw if bishops are same color.
+    }
+
+    // Returns true if capturing by en passant with the pawn on from does not
+    // leave the king in check. Since the capture removes two pawns from the
+    // rank, which may expose the king to a slider, this is checked by
+    // recomputing the attacks on the king after the capture.
+    fn is_passant_legal(&self, from: Sq, passant: PassantSq, magics: &ChessMagics) -> bool {
+        let king_sq = match self.mine.king().first_bit() {
+            Some(king_sq) => king_sq,
+            None => return true,
+        };
+        let mut occupied = self.all();
+        occupied
+            .clear_bit(from)
+            .clear_bit(passant.actual)
+            .set_bit(passant.capture);
+        let attackers = self.other_attackers(king_sq, occupied, magics);
+        (attackers & !BitBoard::from(passant.actual)).none()
+    }
+
+    // Returns the pieces not moving next that attack square sq, given the
+    // occupied squares.
+    fn other_attackers(&self, sq: Sq, occupied: BitBoard, magics: &ChessMagics) -> BitBoard {
+        self.attackers(sq, occupied, magics) & self.other.all()
+    }
+
+    // Returns the pieces of either color that attack square sq, given the
+    // occupied squares. The result includes pieces that are not in occupied,
+    // which should be masked out by the caller if needed.
+    fn attackers(&self, sq: Sq, occupied: BitBoard, magics: &ChessMagics) -> BitBoard {
+        let (white, black) = match self.color() {
+            Color::White => (&self.mine, &self.other),
+            Color::Black => (&self.other, &self.mine),
+        };
+        let bit = BitBoard::from(sq);
+        let rooks = magics
+            .rmagics(sq, occupied)
+            .expect("Unable to compute rook magics");
+        let bishops = magics
+            .bmagics(sq, occupied)
+            .expect("Unable to compute bishop magics");
+
+        (bit.king_moves() & (white.king() | black.king()))
+            | (bit.knight_moves() & (white.knights() | black.knights()))
+            | ((bit.bp_left() | bit.bp_right()) & white.pawns())
+            | ((bit.wp_left() | bit.wp_right()) & black.pawns())
+            | (rooks & (white.rooks() | white.queens() | black.rooks() | black.queens()))
+            | (bishops & (white.bishops() | white.queens() | black.bishops() | black.queens()))
+    }
+
+    // Returns the pieces moving next that are pinned to their king at king_sq,
+    // i.e. the pieces that are the only blocker between the king and a slider
+    // not moving next.
+    fn pins<'a>(&'a self, king_sq: Sq, magics: &ChessMagics) -> impl Iterator<Item = Pin> + 'a {
+        let other = &self.other;
+        let snipers = (magics
+            .rmagics(king_sq, other.all())
+            .expect("Unable to compute rook magics")
+            & (other.rooks() | other.queens()))
+            | (magics
+                .bmagics(king_sq, other.all())
+                .expect("Unable to compute bishop magics")
+                & (other.bishops() | other.queens()));
+        let occupied = self.all();
+        snipers
+            .sq_bit_iter()
+            .filter_map(move |(pinner, pinner_bit)| {
+                let line = between(king_sq, pinner);
+                let blockers = line & occupied;
+                if blockers.is_single() && (blockers & self.mine.all()).any() {
+                    Some(Pin {
+                        sq: blockers.first_bit()?,
+                        pinner,
+                        ray: line | pinner_bit,
+                    })
+                } else {
+                    None
+                }
+            })
+    }
 
-        true
+    // Returns all the squares attacked by the pieces not moving next, given the
+    // occupied squares. Unlike AttackSquares, this includes the squares of the
+    // pieces that are defended.
+    fn other_attacked(&self, occupied: BitBoard, magics: &ChessMagics) -> BitBoard {
+        let other = &self.other;
+        let pawns = other.pawns();
+        let mut attacked = if other.color().is_white() {
+            pawns.wp_left() | pawns.wp_right()
+        } else {
+            pawns.bp_left() | pawns.bp_right()
+        };
+        attacked |= other.king().king_moves() | other.knights().knight_moves();
+        for sq in (other.rooks() | other.queens()).sq_iter() {
+            attacked |= magics
+                .rmagics(sq, occupied)
+                .expect("Unable to compute rook magics");
+        }
+        for sq in (other.bishops() | other.queens()).sq_iter() {
+            attacked |= magics
+                .bmagics(sq, occupied)
+                .expect("Unable to compute bishop magics");
+        }
+        attacked
     }
 
     fn update_attacks(&mut self, magics: &ChessMagics) {
@@ -653,12 +1458,28 @@ impl BoardState {
 
     // Handles all of the state update after a move is made, except setting the
     // GameState. Some of the state change is subsequently used to compute the final
-    // game state.
-    fn partial_update(&mut self, piece_move: Piece<PieceMove>, magics: &ChessMagics) {
+    // game state. Returns an Undo to restore the state from before the move.
+    fn partial_update(&mut self, piece_move: Piece<PieceMove>, magics: &ChessMagics) -> Undo {
+        let mut undo = self.undo();
+
         let mv = piece_move.val();
         let is_pawn = piece_move.is_pawn();
         let is_cap = mv.is_capture();
-        let is_castle = mv.is_castle();
+        let color = self.color();
+
+        // Remove the keys for the state that may change with the move, i.e.
+        // castling rights and en passant, and add them back after the move.
+        let mut hash = self.state_hash
+            ^ self.passant_key(magics)
+            ^ self.checks_key()
+            ^ self.pockets_key()
+            ^ ZOBRIST.castling(&self.mine)
+            ^ ZOBRIST.castling(&self.other)
+            ^ ZOBRIST.move_key(color, piece_move);
+
+        if self.variant == Variant::Crazyhouse {
+            self.update_pockets(piece_move);
+        }
 
         self.mine
             .apply_move(piece_move)
@@ -670,6 +1491,10 @@ impl BoardState {
                 self.other
                     .remove_captured(mv)
                     .expect("Unable to remove captured piece.");
+                hash ^= ZOBRIST.capture_key(color.flip(), mv);
+                if self.variant == Variant::Atomic {
+                    explode(&mut self.mine, &mut self.other, mv.from_to().1);
+                }
             }
         } else {
             self.half_move += 1;
@@ -691,35 +1516,279 @@ impl BoardState {
         std::mem::swap(&mut self.mine, &mut self.other);
         self.update_attacks(magics);
 
-        // Update the position hash and hash count.
-        if is_pawn || is_cap || is_castle {
-            self.hash_count.clear();
+        if self.variant == Variant::ThreeCheck && self.is_mine_in_check() {
+            self.checks[color_index(color)] += 1;
         }
-        self.state_hash = self.compute_hash();
+
+        hash ^= ZOBRIST.castling(&self.mine)
+            ^ ZOBRIST.castling(&self.other)
+            ^ ZOBRIST.black_to_move()
+            ^ self.passant_key(magics)
+            ^ self.checks_key()
+            ^ self.pockets_key();
+
+        // An explosion removes pieces that the keys for the move don't cover,
+        // so the hash is computed from scratch.
+        if is_cap && self.variant == Variant::Atomic {
+            hash = self.compute_hash(magics);
+        }
+
+        // Update the position hash and hash count. Positions before a pawn move
+        // or a capture can never occur again, so the counts are cleared, except
+        // in Crazyhouse, where captured pieces can be dropped back.
+        if (is_pawn || is_cap) && self.variant != Variant::Crazyhouse {
+            undo.hash_count = Some(std::mem::take(&mut self.hash_count));
+        }
+        self.state_hash = hash;
         self.hash_count
             .entry(self.state_hash)
             .and_modify(|count| *count += 1)
             .or_insert(1);
-    }
 
-    // Computes the hash of the current board position.
-    fn compute_hash(&self) -> u64 {
-        position_hash(&self.mine, &self.other, &self.passant_sq)
+        undo
     }
-}
 
-// Initializes the BoardState for a new game.
-impl Default for BoardState {
-    fn default() -> Self {
-        let mine = Box::new(PieceSet::init_white());
-        let other = Box::new(PieceSet::init_black());
-        let state_hash = position_hash(&mine, &other, &None);
+    // Passes the turn to the other player without moving, which clears the
+    // en-passant square and advances the clocks like any other quiet move.
+    // The pieces don't change, so the attacks only trade places. The hash count
+    // is left alone. Returns an Undo to restore the state from before the null
+    // move with restore.
+    fn null_update(&mut self, magics: &ChessMagics) -> Undo {
+        let undo = self.undo();
 
-        BoardState {
-            mine,
-            other,
-            my_attacks: AttackSquares {
-                pieces: BitBoard::new(),
+        self.half_move += 1;
+        if self.color().is_black() {
+            self.full_move += 1;
+        }
+
+        self.state_hash ^= self.passant_key(magics) ^ ZOBRIST.black_to_move();
+        self.passant_sq = None;
+        std::mem::swap(&mut self.mine, &mut self.other);
+        std::mem::swap(&mut self.my_attacks, &mut self.other_attacks);
+
+        undo
+    }
+
+    // Returns an Undo with the current state, without the repetition counts.
+    fn undo(&self) -> Undo {
+        Undo {
+            mine: *self.mine,
+            other: *self.other,
+            my_attacks: self.my_attacks,
+            other_attacks: self.other_attacks,
+            game_state: self.game_state,
+            half_move: self.half_move,
+            full_move: self.full_move,
+            passant_sq: self.passant_sq,
+            state_hash: self.state_hash,
+            checks: self.checks,
+            pockets: self.pockets,
+            promoted: self.promoted,
+            hash_count: None,
+        }
+    }
+
+    // Restores the state from before the move that returned undo.
+    fn unmake(&mut self, mut undo: Undo) {
+        match undo.hash_count.take() {
+            Some(hash_count) => self.hash_count = hash_count,
+            None => {
+                if let Some(count) = self.hash_count.get_mut(&self.state_hash) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.hash_count.remove(&self.state_hash);
+                    }
+                }
+            }
+        }
+        self.restore(undo);
+    }
+
+    // Restores the state from before the move that returned undo, except for
+    // the hash count.
+    fn restore(&mut self, undo: Undo) {
+        *self.mine = undo.mine;
+        *self.other = undo.other;
+        self.my_attacks = undo.my_attacks;
+        self.other_attacks = undo.other_attacks;
+        self.game_state = undo.game_state;
+        self.half_move = undo.half_move;
+        self.full_move = undo.full_move;
+        self.passant_sq = undo.passant_sq;
+        self.state_hash = undo.state_hash;
+        self.checks = undo.checks;
+        self.pockets = undo.pockets;
+        self.promoted = undo.promoted;
+    }
+
+    // Updates the pockets and the promoted pieces in Crazyhouse for piece_move
+    // before it is applied. The player moving adds the captured piece to their
+    // pocket, or a pawn if the captured piece was promoted, and removes the
+    // dropped piece from their pocket.
+    fn update_pockets(&mut self, piece_move: Piece<PieceMove>) {
+        let mine = color_index(self.color());
+        let mv = piece_move.val();
+        if let Drop { .. } = mv {
+            assert!(
+                self.pockets[mine].remove(piece_move),
+                "Unable to drop a piece that is not in the pocket."
+            );
+            return;
+        }
+
+        let (from, to) = mv.from_to();
+        match mv {
+            Capture { cap, .. } | PromoCap { cap, .. } => {
+                let cap = if self.promoted.has_bit(to) {
+                    Pawn(())
+                } else {
+                    cap
+                };
+                self.pockets[mine].add(cap);
+            }
+            EnPassant { .. } => {
+                self.pockets[mine].add(Pawn(()));
+            }
+            _ => (),
+        }
+
+        let is_promoted = mv.is_promo() || (self.promoted.has_bit(from) && !mv.is_castle());
+        self.promoted.clear_bit(from).clear_bit(to);
+        if is_promoted {
+            self.promoted.set_bit(to);
+        }
+    }
+
+    // Computes the hash of the current board position from scratch. Only what
+    // is necessary to identify a repetition is included in the hash: the
+    // pieces, the side to move, the castling rights, and the en-passant file if
+    // capture by en passant is legal, as well as the number of checks given in
+    // Three-check.
+    fn compute_hash(&self, magics: &ChessMagics) -> u64 {
+        pieces_hash(&self.mine, &self.other)
+            ^ self.passant_key(magics)
+            ^ self.checks_key()
+            ^ self.pockets_key()
+    }
+
+    // Returns the combined Zobrist key for the pockets of both colors, which is
+    // 0 unless there are pieces in the pockets in Crazyhouse.
+    fn pockets_key(&self) -> u64 {
+        ZOBRIST.pocket(Color::White, &self.pockets[0])
+            ^ ZOBRIST.pocket(Color::Black, &self.pockets[1])
+    }
+
+    // Returns the combined Zobrist key for the checks given by both colors,
+    // which is 0 unless checks were given in Three-check.
+    fn checks_key(&self) -> u64 {
+        ZOBRIST.checks(Color::White, self.checks[0]) ^ ZOBRIST.checks(Color::Black, self.checks[1])
+    }
+
+    // Returns the Zobrist key for en passant if capture by en passant is legal
+    // for the pieces moving next, or 0 otherwise. Positions only repeat if the
+    // same moves are possible, so a pawn that is pinned, or a king that is in
+    // check, means that the en-passant square does not count.
+    fn passant_key(&self, magics: &ChessMagics) -> u64 {
+        match self.passant_sq {
+            Some(passant)
+                if passant
+                    .attackers(&self.mine)
+                    .sq_iter()
+                    .any(|from| self.is_passant_legal(from, passant, magics)) =>
+            {
+                ZOBRIST.passant(passant.capture)
+            }
+            _ => 0,
+        }
+    }
+}
+
+// The state needed to revert a move made with Board::make_move. Everything that
+// a move can change is saved, except for the repetition counts, which are only
+// saved when they are cleared by the move.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Undo {
+    // The pieces that were moving, and the pieces that were not moving.
+    mine: PieceSet,
+    other: PieceSet,
+    my_attacks: AttackSquares,
+    other_attacks: AttackSquares,
+    game_state: GameState,
+    half_move: u16,
+    full_move: u16,
+    passant_sq: Option<PassantSq>,
+    state_hash: u64,
+    checks: [u8; 2],
+    pockets: [Pocket; 2],
+    promoted: BitBoard,
+    // Set if the move cleared the repetition counts.
+    hash_count: Option<HashMap<u64, u8>>,
+}
+
+// The squares where each piece moving next is allowed to move, which is used to
+// generate either pseudo-legal or legal moves with the same code.
+struct MoveMasks {
+    // The allowed destination squares, indexed by the square of the piece.
+    targets: [BitBoard; 64],
+    // The squares where pieces can be dropped in Crazyhouse.
+    drops: BitBoard,
+    // The squares attacked by the other pieces, which the king cannot castle
+    // through.
+    attacked: BitBoard,
+    // Whether to check that a capture by en passant, or castling in Chess960,
+    // does not expose the king to a slider.
+    check_discovered: bool,
+    // The types of moves to generate.
+    stage: GenStage,
+}
+
+impl MoveMasks {
+    // Returns masks that allow every piece to move anywhere.
+    fn pseudo_legal(attacked: BitBoard, stage: GenStage) -> Self {
+        Self {
+            targets: [!BitBoard::new(); 64],
+            drops: !BitBoard::new(),
+            attacked,
+            check_discovered: false,
+            stage,
+        }
+    }
+}
+
+// The types of moves generated in a stage of move generation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum GenStage {
+    // All the moves.
+    All,
+    // Captures, including captures by en passant, and promotions.
+    Captures,
+    // Moves that are neither captures nor promotions, including castling and
+    // drops.
+    Quiets,
+}
+
+impl GenStage {
+    fn has_captures(&self) -> bool {
+        matches!(*self, GenStage::All | GenStage::Captures)
+    }
+
+    fn has_quiets(&self) -> bool {
+        matches!(*self, GenStage::All | GenStage::Quiets)
+    }
+}
+
+// Initializes the BoardState for a new game.
+impl Default for BoardState {
+    fn default() -> Self {
+        let mine = Box::new(PieceSet::init_white());
+        let other = Box::new(PieceSet::init_black());
+        let state_hash = pieces_hash(&mine, &other);
+
+        BoardState {
+            mine,
+            other,
+            my_attacks: AttackSquares {
+                pieces: BitBoard::new(),
                 no_pieces: RANK_3,
             },
             other_attacks: AttackSquares {
@@ -733,25 +1802,72 @@ impl Default for BoardState {
             prev_moves: Vec::new(),
             state_hash,
             hash_count: HashMap::from([(state_hash, 1)]),
+            chess960: false,
+            variant: Variant::Standard,
+            checks: [0, 0],
+            pockets: [Pocket::default(); 2],
+            promoted: BitBoard::new(),
         }
     }
 }
 
-// Only hash what is necessary to uniquely identify 3-fold repetition.
+// The position hash already identifies the position, so there is no need to
+// hash anything else.
 impl std::hash::Hash for BoardState {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.mine.hash(state);
-        self.other.hash(state);
-        self.passant_sq.hash(state);
+        state.write_u64(self.state_hash);
+    }
+}
+
+// Returns the index of color into BoardState::checks.
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
     }
 }
 
-fn position_hash(white: &PieceSet, black: &PieceSet, passant_sq: &Option<PassantSq>) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    white.hash(&mut hasher);
-    black.hash(&mut hasher);
-    passant_sq.hash(&mut hasher);
-    hasher.finish()
+// Removes the pieces exploded by a capture on square to in Atomic chess, i.e.
+// the capturing piece, and every piece other than a pawn next to the square.
+fn explode(mine: &mut PieceSet, other: &mut PieceSet, to: Sq) {
+    let center = BitBoard::from(to);
+    let bits = center | (center.king_moves() & !(mine.pawns() | other.pawns()));
+    mine.explode(bits);
+    other.explode(bits);
+}
+
+// Returns the pieces in pieces that attack square sq, given the occupied
+// squares.
+fn side_attackers(pieces: &PieceSet, sq: Sq, occupied: BitBoard, magics: &ChessMagics) -> BitBoard {
+    let bit = BitBoard::from(sq);
+    let pawns = if pieces.color().is_white() {
+        bit.bp_left() | bit.bp_right()
+    } else {
+        bit.wp_left() | bit.wp_right()
+    };
+    let rooks = magics
+        .rmagics(sq, occupied)
+        .expect("Unable to compute rook magics");
+    let bishops = magics
+        .bmagics(sq, occupied)
+        .expect("Unable to compute bishop magics");
+
+    (bit.king_moves() & pieces.king())
+        | (bit.knight_moves() & pieces.knights())
+        | (pawns & pieces.pawns())
+        | (rooks & (pieces.rooks() | pieces.queens()))
+        | (bishops & (pieces.bishops() | pieces.queens()))
+}
+
+// Computes the Zobrist hash of the pieces and the side to move, where mine
+// represents the pieces moving next. This includes the castling rights, but not
+// en passant, which is added by BoardState::compute_hash.
+fn pieces_hash(mine: &PieceSet, other: &PieceSet) -> u64 {
+    let mut hash = ZOBRIST.pieces(mine) ^ ZOBRIST.pieces(other);
+    if mine.color().is_black() {
+        hash ^= ZOBRIST.black_to_move();
+    }
+    hash
 }
 
 // A helper class to build chess boards.
@@ -765,7 +1881,18 @@ pub struct BoardBuilder {
     color: Color,
     half_move: u16,
     full_move: u16,
-    passant_sq: Option<PassantSq>,
+    // The en-passant target square, which is validated when building.
+    passant: Option<Sq>,
+    chess960: bool,
+    variant: Variant,
+    // The checks given by white and by black in Three-check.
+    checks: [u8; 2],
+    // The pockets of white and black, and the promoted pieces in Crazyhouse.
+    pockets: [Pocket; 2],
+    promoted: BitBoard,
+    // If set, the position is only checked for what is needed to play from it,
+    // and invalid en-passant squares and full move numbers are ignored.
+    lenient: bool,
 }
 
 impl BoardBuilder {
@@ -813,6 +1940,14 @@ impl BoardBuilder {
         self
     }
 
+    // Adds the castling right for the white rook on sq, which must be on the
+    // first rank. This is used for Chess960, where the castling rook is not
+    // necessarily the outermost rook.
+    pub fn white_castle_rook(&mut self, sq: Sq) -> &mut Self {
+        self.white_builder.add_castle_rook(sq.rc().1);
+        self
+    }
+
     ////////////////////////////
     // Setters for black pieces.
     ////////////////////////////
@@ -857,6 +1992,13 @@ impl BoardBuilder {
         self
     }
 
+    // Adds the castling right for the black rook on sq, which must be on the
+    // eighth rank.
+    pub fn black_castle_rook(&mut self, sq: Sq) -> &mut Self {
+        self.black_builder.add_castle_rook(sq.rc().1);
+        self
+    }
+
     ////////////////////////////
     // Setters for shared state.
     ////////////////////////////
@@ -877,30 +2019,99 @@ impl BoardBuilder {
     }
 
     pub fn set_passant(&mut self, target: Sq) -> &mut Self {
-        self.passant_sq = PassantSq::from_target(target);
+        self.passant = Some(target);
+        self
+    }
+
+    pub fn set_chess960(&mut self, chess960: bool) -> &mut Self {
+        self.chess960 = chess960;
         self
     }
 
-    // TODO:
-    // - need to check that passant squares are valid.
-    // - need to check that full_move is at least 1.
+    pub fn set_variant(&mut self, variant: Variant) -> &mut Self {
+        self.variant = variant;
+        self
+    }
+
+    // Sets the number of checks given by color, which is ignored unless the
+    // variant is Three-check.
+    pub fn set_checks(&mut self, color: Color, checks: u8) -> &mut Self {
+        self.checks[color_index(color)] = checks;
+        self
+    }
+
+    // Adds a piece to the pocket of color, which is ignored unless the variant
+    // is Crazyhouse.
+    pub fn add_to_pocket(&mut self, color: Color, piece: Piece<()>) -> &mut Self {
+        self.pockets[color_index(color)].add(piece);
+        self
+    }
+
+    // Marks the piece on sq as promoted from a pawn, so that it goes back to
+    // the pocket as a pawn when it is captured. This is ignored unless the
+    // variant is Crazyhouse.
+    pub fn set_promoted(&mut self, sq: Sq) -> &mut Self {
+        self.promoted.set_bit(sq);
+        self
+    }
+
+    // Disables the checks for positions that cannot occur in a game, e.g. pawns
+    // on the back ranks or the side not to move being in check, which is useful
+    // for test positions.
+    pub fn set_lenient(&mut self, lenient: bool) -> &mut Self {
+        self.lenient = lenient;
+        self
+    }
+
+    // Builds the board, and returns an error if the position is not valid. Unless
+    // the builder is lenient, this also checks that:
+    // - there are no pawns on the first or last rank
+    // - the side not to move is not in check
+    // - the en-passant square is behind a pawn that just moved two squares
+    // - the full move number is at least 1
     pub fn build(&mut self) -> Result<Board, PiecesErr> {
+        // Pieces can be dropped back on the board in Crazyhouse, so there can
+        // be more pieces of a type than in standard chess.
+        let is_crazyhouse = self.variant == Variant::Crazyhouse;
+        let (mut white, mut black) = (self.white_builder, self.black_builder);
+        white.set_drops(is_crazyhouse);
+        black.set_drops(is_crazyhouse);
+        // The king of the side to move is missing once it has exploded in
+        // Atomic chess, e.g. when a finished game is loaded.
+        let kingless = self.variant == Variant::Atomic;
+        if self.color.is_white() {
+            white.set_kingless(kingless);
+        } else {
+            black.set_kingless(kingless);
+        }
         let (mine, other) = if self.color.is_white() {
-            (
-                Box::new(self.white_builder.build()?),
-                Box::new(self.black_builder.build()?),
-            )
+            (Box::new(white.build()?), Box::new(black.build()?))
         } else {
-            (
-                Box::new(self.black_builder.build()?),
-                Box::new(self.white_builder.build()?),
-            )
+            (Box::new(black.build()?), Box::new(white.build()?))
+        };
+
+        if (mine.all() & other.all()).any() {
+            return Err(PiecesErr::OverlappingPieces);
+        }
+        if !self.lenient && ((mine.pawns() | other.pawns()) & (RANK_1 | RANK_8)).any() {
+            return Err(PiecesErr::PawnsOnBackRank);
+        }
+        let passant_sq = self.passant_sq(&mine, &other)?;
+        let full_move = match self.full_move {
+            0 if !self.lenient => return Err(PiecesErr::InvalidFullMove),
+            full_move => full_move.max(1),
         };
 
         let my_attacks = mine.attacks(&other, self.magics.as_ref());
         let other_attacks = other.attacks(&mine, self.magics.as_ref());
 
-        let state_hash = position_hash(&mine, &other, &self.passant_sq);
+        // Only pieces that a pawn can promote to can be promoted.
+        let promotable = (mine.all() | other.all())
+            & !(mine.king() | other.king() | mine.pawns() | other.pawns());
+
+        // Castling rights that are only possible in Chess960 make it a Chess960
+        // game, even if it was not set explicitly.
+        let chess960 = self.chess960 || !mine.has_std_castle() || !other.has_std_castle();
 
         let mut board = Board {
             state: Box::new(BoardState {
@@ -910,19 +2121,70 @@ impl BoardBuilder {
                 other_attacks,
                 game_state: GameState::Next(self.color),
                 half_move: self.half_move,
-                full_move: self.full_move,
-                passant_sq: self.passant_sq,
+                full_move,
+                passant_sq,
                 prev_moves: Vec::new(),
-                state_hash,
-                hash_count: HashMap::from([(state_hash, 1)]),
+                state_hash: 0,
+                hash_count: HashMap::new(),
+                chess960,
+                variant: self.variant,
+                checks: match self.variant {
+                    Variant::ThreeCheck => self.checks,
+                    _ => [0, 0],
+                },
+                pockets: match self.variant {
+                    Variant::Crazyhouse => self.pockets,
+                    _ => [Pocket::default(); 2],
+                },
+                promoted: match self.variant {
+                    Variant::Crazyhouse => self.promoted & promotable,
+                    _ => BitBoard::new(),
+                },
             }),
             magics: self.magics.clone(),
         };
+        let state_hash = board.state.compute_hash(self.magics.as_ref());
+        board.state.state_hash = state_hash;
+        board.state.hash_count.insert(state_hash, 1);
+        if !self.lenient && board.state.is_other_in_check() {
+            return Err(PiecesErr::OpponentInCheck);
+        }
 
         board.update_game_state(None);
 
         Ok(board)
     }
+
+    // Returns the en-passant square, if the target square is on the rank behind
+    // a pawn of the other color that could have just moved two squares, i.e. the
+    // target square and the square the pawn moved from are empty. Otherwise,
+    // it returns an error, or no en-passant square if the builder is lenient.
+    fn passant_sq(
+        &self,
+        mine: &PieceSet,
+        other: &PieceSet,
+    ) -> Result<Option<PassantSq>, PiecesErr> {
+        let target = match self.passant {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+        let (target_row, from_row) = if self.color.is_white() {
+            (5, 6)
+        } else {
+            (2, 1)
+        };
+        let passant_sq = PassantSq::from_target(target).filter(|ps| {
+            let from = Sq::from_rc(from_row, target.rc().1).expect("File should be valid.");
+            target.rc().0 == target_row
+                && other.pawns().has_bit(ps.actual)
+                && !(mine.all() | other.all()).has_bit(target)
+                && !(mine.all() | other.all()).has_bit(from)
+        });
+        match passant_sq {
+            None if !self.lenient => Err(PiecesErr::InvalidPassant),
+            passant_sq => Ok(passant_sq),
+        }
+    }
 }
 
 // Converts ChessMagics into a BoardBuilder.
@@ -935,18 +2197,48 @@ impl From<Arc<ChessMagics>> for BoardBuilder {
             color: Color::White,
             half_move: 0,
             full_move: 1,
-            passant_sq: None,
+            passant: None,
+            chess960: false,
+            variant: Variant::Standard,
+            checks: [0, 0],
+            pockets: [Pocket::default(); 2],
+            promoted: BitBoard::new(),
+            lenient: false,
         }
     }
 }
 
+// The number of half moves without a pawn move or capture after which a draw
+// can be claimed, and after which the game is drawn automatically.
+const CLAIM_HALF_MOVES: u16 = 100;
+const AUTO_HALF_MOVES: u16 = 150;
+
+// The number of times a position has to occur before a draw can be claimed,
+// and before the game is drawn automatically.
+const CLAIM_REPETITIONS: u8 = 3;
+const AUTO_REPETITIONS: u8 = 5;
+
 // Represents the current game state. Mate and Draw are final game state.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum GameState {
     Next(Color),
     Check(Color),
     Mate(Color),
-    Draw,
+    Draw(DrawReason),
+}
+
+// Represents the reason for a draw.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum DrawReason {
+    // The player to move has no legal moves, but is not in check.
+    Stalemate,
+    // Neither player has enough material to mate.
+    InsufficientMaterial,
+    // The same position occurred five times, or three times if claimed.
+    Repetition,
+    // There were 75 moves by each player without a pawn move or capture, or 50
+    // moves if claimed.
+    FiftyMove,
 }
 
 impl GameState {
@@ -958,10 +2250,33 @@ impl GameState {
 
     // Returns true if this is mate.
     pub fn is_mate(&self) -> bool {
-        !matches!(*self, GameState::Mate(_))
+        matches!(*self, GameState::Mate(_))
+    }
+
+    // Returns true if this is a draw.
+    pub fn is_draw(&self) -> bool {
+        matches!(*self, GameState::Draw(_))
+    }
+
+    // Returns the reason for the draw if this is a draw, or None otherwise.
+    pub fn draw_reason(&self) -> Option<DrawReason> {
+        match *self {
+            GameState::Draw(reason) => Some(reason),
+            _ => None,
+        }
     }
 }
 
+// A piece pinned to its king by a slider, where ray contains the squares
+// between the king and the pinning piece, and the square of the pinning piece.
+// The pinned piece can only move to squares in ray.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Pin {
+    pub sq: Sq,
+    pub pinner: Sq,
+    pub ray: BitBoard,
+}
+
 // A struct to represent the position where en-passant capture is possible.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct PassantSq {
@@ -1012,6 +2327,18 @@ impl PassantSq {
         }
     }
 
+    // Returns the pawns in pieces that attack the capture square, i.e. the pawns
+    // that would be able to capture by en passant if it is legal.
+    fn attackers(&self, pieces: &PieceSet) -> BitBoard {
+        let capture = BitBoard::from(self.capture);
+        let attackers = if pieces.color().is_white() {
+            capture.bp_left() | capture.bp_right()
+        } else {
+            capture.wp_left() | capture.wp_right()
+        };
+        attackers & pieces.pawns()
+    }
+
     // Builds a PassantSq from the target square if the target square is a valid
     // square, otherwise returns None.
     fn from_target(target: Sq) -> Option<PassantSq> {
@@ -1083,6 +2410,7 @@ fn add_promo_with_cap(from: Sq, to: Sq, cap: Piece<()>, moves: &mut Vec<Piece<Pi
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fen::from_fen;
     use crate::sq;
     use lazy_static::lazy_static;
     use std::collections::HashSet;
@@ -1110,21 +2438,22 @@ mod tests {
     #[test]
     fn moves_from_init() {
         let board = Board::from(MAGICS.clone());
+        let masks = MoveMasks::pseudo_legal(board.state.other_attacks.all(), GenStage::All);
         let mut moves: Vec<Piece<PieceMove>> = vec![];
 
-        board.king_moves(&mut moves);
+        board.king_moves(&mut moves, &masks);
         assert!(moves.is_empty());
 
-        board.queen_moves(&mut moves);
+        board.queen_moves(&mut moves, &masks);
         assert!(moves.is_empty());
 
-        board.rook_moves(&mut moves);
+        board.rook_moves(&mut moves, &masks);
         assert!(moves.is_empty());
 
-        board.bishop_moves(&mut moves);
+        board.bishop_moves(&mut moves, &masks);
         assert!(moves.is_empty());
 
-        board.knight_moves(&mut moves);
+        board.knight_moves(&mut moves, &masks);
         assert_eq!(
             HashSet::from_iter(moves.clone()),
             HashSet::from([
@@ -1148,7 +2477,7 @@ mod tests {
         );
 
         moves.clear();
-        board.pawn_moves(&mut moves);
+        board.pawn_moves(&mut moves, &masks);
         assert_eq!(
             HashSet::from_iter(moves),
             HashSet::from([
@@ -1291,7 +2620,10 @@ mod tests {
         assert!(board.is_ok());
 
         let board = board.unwrap();
-        assert_eq!(board.game_state(), GameState::Draw);
+        assert_eq!(
+            board.game_state(),
+            GameState::Draw(DrawReason::InsufficientMaterial)
+        );
 
         // Knight + king vs king.
         let mut builder = BoardBuilder::from(MAGICS.clone());
@@ -1306,6 +2638,911 @@ mod tests {
         assert!(board.is_ok());
 
         let board = board.unwrap();
-        assert_eq!(board.game_state(), GameState::Draw);
+        assert_eq!(
+            board.game_state(),
+            GameState::Draw(DrawReason::InsufficientMaterial)
+        );
+
+        // Bishops on squares of the same color.
+        let board = from_fen(
+            "8/8/1b1k4/8/5b2/2K5/5B2/8 w - - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            board.game_state(),
+            GameState::Draw(DrawReason::InsufficientMaterial)
+        );
+
+        // Bishops on squares of different colors, or a knight and a bishop, can
+        // still mate.
+        for fen in [
+            "8/8/1b1k4/8/8/2K5/4B3/8 w - - 0 1",
+            "8/8/1b1k4/8/8/2K5/4N3/8 w - - 0 1",
+            "8/8/3k4/8/8/2K5/4N3/4N3 w - - 0 1",
+        ] {
+            let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+            assert_eq!(board.game_state(), GameState::Next(Color::White), "{fen}");
+        }
+    }
+
+    #[test]
+    fn overlapping_pieces() {
+        let mut builder = BoardBuilder::from(MAGICS.clone());
+        builder
+            .white_king(sq::E1)
+            .white_queen(sq::E1)
+            .black_king(sq::E8);
+        assert_eq!(builder.build(), Err(PiecesErr::OverlappingPieces));
+
+        let mut builder = BoardBuilder::from(MAGICS.clone());
+        builder
+            .white_king(sq::E1)
+            .white_queen(sq::D4)
+            .black_king(sq::E8)
+            .black_knight(sq::D4);
+        assert_eq!(builder.build(), Err(PiecesErr::OverlappingPieces));
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 99 80";
+        let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+        assert_eq!(board.claimable_draw(), None);
+
+        let mut next = board.next_from_rc(&[(0, 1, None)]).unwrap();
+        assert_eq!(next.game_state(), GameState::Next(Color::Black));
+        assert_eq!(next.claimable_draw(), Some(DrawReason::FiftyMove));
+        assert_eq!(next.claim_draw(), Some(DrawReason::FiftyMove));
+        assert_eq!(next.game_state(), GameState::Draw(DrawReason::FiftyMove));
+        assert!(next.is_terminal());
+
+        // The draw is automatic after 75 moves.
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 149 105";
+        let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+        let next = board.next_from_rc(&[(0, 1, None)]).unwrap();
+        assert_eq!(next.game_state(), GameState::Draw(DrawReason::FiftyMove));
+
+        // Unless the last move mates.
+        let fen = "k7/8/1K6/8/8/8/8/7R w - - 149 105";
+        let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+        let next = board.next_from_rc(&[(7, 63, None)]).unwrap();
+        assert_eq!(next.game_state(), GameState::Mate(Color::Black));
+    }
+
+    #[test]
+    fn repetitions_with_castling_and_passant() {
+        // Castling does not clear the repetition counts, but positions before it
+        // cannot repeat because the castling rights are lost.
+        let mut board = from_fen(
+            "4k3/8/8/8/8/8/8/4K2R w K - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        board.make_move(King(Castle {
+            king_from: sq::E1,
+            king_to: sq::G1,
+            rook_from: sq::H1,
+            rook_to: sq::F1,
+        }));
+        assert_eq!(board.state.hash_count.len(), 2);
+
+        // The en-passant square does not count for repetitions if the capture
+        // is illegal, because the pawn is pinned.
+        let pinned = from_fen(
+            "8/8/8/KPp4r/8/8/8/7k w - c6 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        let no_passant = from_fen(
+            "8/8/8/KPp4r/8/8/8/7k w - - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        assert_eq!(pinned.state_hash(), no_passant.state_hash());
+    }
+
+    #[test]
+    fn attack_queries() {
+        let board = from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            board.attackers_to(sq::D5, Color::Black),
+            BitBoard::from(&[sq::B6, sq::E6, sq::F6])
+        );
+        assert_eq!(
+            board.attackers_to(sq::D5, Color::White),
+            BitBoard::from(&[sq::C3, sq::E4])
+        );
+        assert!(board.is_attacked(sq::G2, Color::Black));
+        assert!(!board.is_attacked(sq::D1, Color::Black));
+        assert!(board.checkers().none());
+        assert!(board.pinned().is_empty());
+
+        // Double check by the knight and the rook.
+        let board = from_fen(
+            "4k3/8/3N4/8/8/8/8/4RK2 b - - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        assert_eq!(board.checkers(), BitBoard::from(&[sq::D6, sq::E1]));
+    }
+
+    #[test]
+    fn pinned_pieces() {
+        let board = from_fen(
+            "4k3/4r3/8/1b6/8/3P4/4R3/r2NK3 w - - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        let pins: HashSet<Pin> = board.pinned().into_iter().collect();
+        assert_eq!(
+            pins,
+            HashSet::from([
+                Pin {
+                    sq: sq::E2,
+                    pinner: sq::E7,
+                    ray: BitBoard::from(&[sq::E2, sq::E3, sq::E4, sq::E5, sq::E6, sq::E7]),
+                },
+                Pin {
+                    sq: sq::D1,
+                    pinner: sq::A1,
+                    ray: BitBoard::from(&[sq::D1, sq::C1, sq::B1, sq::A1]),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn static_exchange_evaluation() {
+        let see = |fen: &str, from: Sq, to: Sq| {
+            let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+            let mv = board
+                .next_moves()
+                .unwrap()
+                .into_iter()
+                .find(|mv| {
+                    mv.val().from_to() == (from, to)
+                        && mv.val().promo().is_none_or(|p| p.is_queen())
+                })
+                .unwrap();
+            board.see(mv)
+        };
+
+        // Undefended pawn.
+        assert_eq!(
+            see(
+                "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+                sq::E1,
+                sq::E5
+            ),
+            100
+        );
+        // The knight takes a pawn and is recaptured, and the x-ray attackers
+        // behind the rook and the bishop do not make the exchange favourable.
+        let fen = "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1";
+        assert_eq!(see(fen, sq::D3, sq::E5), -200);
+        // Pawn takes a defended knight.
+        assert_eq!(
+            see("4k3/8/3p4/4n3/3P4/8/8/4K3 w - - 0 1", sq::D4, sq::E5),
+            200
+        );
+        // A quiet move to a square attacked by a pawn loses the piece.
+        assert_eq!(
+            see("4k3/8/8/8/5p2/8/8/2B1K3 w - - 0 1", sq::C1, sq::E3),
+            -300
+        );
+        // The king can only recapture if the square is not defended, which may
+        // be by an x-ray attacker.
+        assert_eq!(
+            see("8/8/3k4/3p4/8/8/3R4/4K3 w - - 0 1", sq::D2, sq::D5),
+            -400
+        );
+        assert_eq!(
+            see("8/8/3k4/3p4/8/8/3R4/3QK3 w - - 0 1", sq::D2, sq::D5),
+            100
+        );
+        // En passant and promotions.
+        assert_eq!(
+            see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", sq::E5, sq::D6),
+            100
+        );
+        assert_eq!(see("3rk3/4P3/8/8/8/8/8/4K3 w - - 0 1", sq::E7, sq::D8), 400);
+        assert_eq!(see("4k3/2P5/8/8/8/8/8/4K3 w - - 0 1", sq::C7, sq::C8), 800);
+
+        let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+        let mv = Knight(Capture {
+            from: sq::D3,
+            to: sq::E5,
+            cap: Pawn(()),
+        });
+        assert!(board.see_ge(mv, -200));
+        assert!(!board.see_ge(mv, 0));
+    }
+
+    #[test]
+    fn unmake_move_restores_board() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r1bqkbnr/pppppppp/2n5/4Q~3/8/8/PPPP1PPP/RNB1KBNR[Ppn] b KQkq - 0 4",
+        ] {
+            let mut board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+            let original = board.clone();
+            for pm in board.next_moves().unwrap() {
+                let undo = board.make_move(pm);
+                assert_eq!(board, original.next_from_move(pm).unwrap());
+                for next_pm in board.next_moves().unwrap_or_default() {
+                    let next_undo = board.make_move(next_pm);
+                    board.unmake_move(next_undo);
+                }
+                board.unmake_move(undo);
+                assert_eq!(board, original);
+                assert_eq!(board.state.my_attacks, original.state.my_attacks);
+                assert_eq!(board.state.hash_count, original.state.hash_count);
+            }
+        }
+    }
+
+    #[test]
+    fn null_move_passes_the_turn() {
+        let mut board = from_fen(
+            "rnbqkbnr/pppp1ppp/8/8/3pP3/8/PPP2PPP/RNBQKBNR b KQkq e3 0 3",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        let original = board.clone();
+        let undo = board.make_null_move().unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/8/3pP3/8/PPP2PPP/RNBQKBNR w KQkq - 1 4"
+        );
+        let rebuilt = from_fen(&board.to_fen(), BoardBuilder::from(MAGICS.clone())).unwrap();
+        assert_eq!(board.state_hash(), rebuilt.state_hash());
+        assert_eq!(board.state.my_attacks, rebuilt.state.my_attacks);
+        assert_eq!(board.last_move(), original.last_move());
+
+        board.unmake_null_move(undo);
+        assert_eq!(board, original);
+        assert_eq!(board.state.hash_count, original.state.hash_count);
+
+        // Passing is not possible in check.
+        let mut board = from_fen(
+            "4k3/8/8/8/8/8/8/4R1K1 b - - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        assert_eq!(board.make_null_move(), None);
+    }
+
+    #[test]
+    fn null_moves_are_not_repetitions() {
+        let mut board = Board::from(MAGICS.clone());
+        let original = board.clone();
+        let mut undos = Vec::new();
+        // Passing back and forth returns to the starting position, which would
+        // be a fivefold repetition if the null moves were counted.
+        for _ in 0..8 {
+            undos.push(board.make_null_move().unwrap());
+        }
+        assert_eq!(board.state_hash(), original.state_hash());
+        assert_eq!(board.position_count(), 1);
+        assert_eq!(board.state.hash_count, original.state.hash_count);
+        assert_eq!(board.claimable_draw(), None);
+        assert!(!board.is_terminal());
+
+        while let Some(undo) = undos.pop() {
+            board.unmake_null_move(undo);
+        }
+        assert_eq!(board, original);
+        assert_eq!(board.state.hash_count, original.state.hash_count);
+    }
+
+    #[test]
+    fn unmake_move_restores_repetitions() {
+        let mut board = Board::from(MAGICS.clone());
+        let moves = [
+            Knight(Simple {
+                from: sq::G1,
+                to: sq::F3,
+            }),
+            Knight(Simple {
+                from: sq::G8,
+                to: sq::F6,
+            }),
+            Knight(Simple {
+                from: sq::F3,
+                to: sq::G1,
+            }),
+            Knight(Simple {
+                from: sq::F6,
+                to: sq::G8,
+            }),
+        ];
+        let mut undos = Vec::new();
+        for _ in 0..2 {
+            for pm in moves {
+                undos.push(board.make_move(pm));
+            }
+        }
+        assert_eq!(board.rep_count(), 3);
+        assert_eq!(board.game_state(), GameState::Next(Color::White));
+        assert_eq!(board.claimable_draw(), Some(DrawReason::Repetition));
+
+        board.unmake_move(undos.pop().unwrap());
+        assert_eq!(board.rep_count(), 2);
+        assert_eq!(board.game_state(), GameState::Next(Color::Black));
+        assert_eq!(board.claimable_draw(), None);
+        while let Some(undo) = undos.pop() {
+            board.unmake_move(undo);
+        }
+        assert_eq!(board, Board::from(MAGICS.clone()));
+    }
+
+    // Returns the legal moves from a FEN position as a set.
+    fn legal_move_set(fen: &str) -> HashSet<Piece<PieceMove>> {
+        let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+        board.next_moves().unwrap_or_default().into_iter().collect()
+    }
+
+    // Returns the moves that are castling moves.
+    fn castles(moves: &HashSet<Piece<PieceMove>>) -> HashSet<Piece<PieceMove>> {
+        moves
+            .iter()
+            .filter(|pm| pm.val().is_castle())
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn legal_moves_match_filtered_moves() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "rnb1kbnr/pppp1ppp/8/4p3/5P1q/8/PPPPP1PP/RNBQKBNR w KQkq - 1 3",
+            "6k1/8/8/8/8/8/8/rR1K3R w BH - 0 1",
+        ] {
+            let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+            let mut expected = HashSet::new();
+            for pm in board.all_moves().unwrap() {
+                let mut next = board.clone();
+                next.state.partial_update(pm, MAGICS.as_ref());
+                if !next.state.is_other_in_check() {
+                    expected.insert(pm);
+                }
+            }
+            assert_eq!(legal_move_set(fen), expected, "{fen}");
+        }
+    }
+
+    #[test]
+    fn staged_moves_partition_all_moves() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/8/KPp4r/8/8/8/7k w - c6 0 1",
+        ] {
+            let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+            let captures: HashSet<_> = board.captures().into_iter().collect();
+            let quiets: HashSet<_> = board.quiets().into_iter().collect();
+            assert!(captures
+                .iter()
+                .all(|mv| mv.val().is_capture() || mv.val().is_promo()));
+            assert!(captures.is_disjoint(&quiets), "{fen}");
+            let legal: HashSet<_> = captures.union(&quiets).copied().collect();
+            assert_eq!(legal, legal_move_set(fen), "{fen}");
+
+            let captures: HashSet<_> = board.pseudo_captures().into_iter().collect();
+            let quiets: HashSet<_> = board.pseudo_quiets().into_iter().collect();
+            assert!(captures.is_disjoint(&quiets), "{fen}");
+            let all: HashSet<_> = captures.union(&quiets).copied().collect();
+            assert_eq!(
+                all,
+                board.all_moves().unwrap().into_iter().collect(),
+                "{fen}"
+            );
+
+            if !board.is_check() {
+                assert!(board.evasions().is_empty(), "{fen}");
+                assert!(board.pseudo_evasions().is_empty(), "{fen}");
+            }
+        }
+    }
+
+    #[test]
+    fn evasions_when_in_check() {
+        for fen in [
+            "rnb1kbnr/pppp1ppp/8/4p3/5P1q/8/PPPPP1PP/RNBQKBNR w KQkq - 1 3",
+            "4k3/8/3N4/8/8/8/8/4RK2 b - - 0 1",
+            "4k3/8/8/8/1b6/8/3N4/r3K3 w - - 0 1",
+        ] {
+            let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+            let evasions: HashSet<_> = board.evasions().into_iter().collect();
+            assert_eq!(evasions, legal_move_set(fen), "{fen}");
+
+            // The pseudo-legal evasions include the moves of pinned pieces.
+            let pseudo: HashSet<_> = board.pseudo_evasions().into_iter().collect();
+            assert!(pseudo.is_superset(&evasions), "{fen}");
+            let legal: HashSet<_> = pseudo
+                .into_iter()
+                .filter(|mv| board.is_legal_move(*mv))
+                .collect();
+            assert_eq!(legal, evasions, "{fen}");
+        }
+    }
+
+    #[test]
+    fn double_check_only_allows_king_moves() {
+        let moves = legal_move_set("4r1k1/8/8/8/8/3n4/8/R3K3 w Q - 0 1");
+        assert_eq!(
+            moves,
+            HashSet::from([
+                King(Simple {
+                    from: sq::E1,
+                    to: sq::D1,
+                }),
+                King(Simple {
+                    from: sq::E1,
+                    to: sq::D2,
+                }),
+                King(Simple {
+                    from: sq::E1,
+                    to: sq::F1,
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn pinned_piece_moves_along_pin() {
+        let moves = legal_move_set("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1");
+        let rook_moves: HashSet<_> = moves.into_iter().filter(|pm| pm.is_rook()).collect();
+        assert_eq!(rook_moves.len(), 5);
+        assert!(rook_moves.iter().all(|pm| pm.val().from_to().1.rc().1 == 4));
+    }
+
+    #[test]
+    fn passant_with_discovered_check_is_illegal() {
+        let moves = legal_move_set("8/8/8/KPp4r/8/8/8/7k w - c6 0 1");
+        assert!(!moves.iter().any(|pm| matches!(pm.val(), EnPassant { .. })));
+
+        // Without the rook the capture is legal.
+        let moves = legal_move_set("8/8/8/KPp5/8/8/8/7k w - c6 0 1");
+        assert!(moves.contains(&Pawn(EnPassant {
+            from: sq::B5,
+            to: sq::C6,
+            passant: sq::C5,
+        })));
+    }
+
+    #[test]
+    fn passant_can_capture_checking_pawn() {
+        let moves = legal_move_set("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1");
+        assert!(moves.contains(&Pawn(EnPassant {
+            from: sq::E4,
+            to: sq::D3,
+            passant: sq::D4,
+        })));
+    }
+
+    #[test]
+    fn no_castling_through_attacked_squares() {
+        let king_side = King(Castle {
+            king_from: sq::E1,
+            king_to: sq::G1,
+            rook_from: sq::H1,
+            rook_to: sq::F1,
+        });
+        let queen_side = King(Castle {
+            king_from: sq::E1,
+            king_to: sq::C1,
+            rook_from: sq::A1,
+            rook_to: sq::D1,
+        });
+
+        let moves = legal_move_set("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert_eq!(castles(&moves), HashSet::from([king_side, queen_side]));
+
+        // The king cannot pass through f1.
+        let moves = legal_move_set("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert_eq!(castles(&moves), HashSet::from([queen_side]));
+
+        // The rook can pass through an attacked b1.
+        let moves = legal_move_set("1r2k3/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert_eq!(castles(&moves), HashSet::from([king_side, queen_side]));
+
+        // No castling out of check.
+        let moves = legal_move_set("4k3/8/8/8/4r3/8/8/R3K2R w KQ - 0 1");
+        assert!(castles(&moves).is_empty());
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // The rook on b1 shields c1 from the rook on a1 before castling.
+        let moves = legal_move_set("6k1/8/8/8/8/8/8/rR1K3R w BH - 0 1");
+        assert_eq!(
+            castles(&moves),
+            HashSet::from([King(Castle {
+                king_from: sq::D1,
+                king_to: sq::G1,
+                rook_from: sq::H1,
+                rook_to: sq::F1,
+            })])
+        );
+
+        // The king does not move when castling from g1.
+        let moves = legal_move_set("6k1/8/8/8/8/8/8/6KR w H - 0 1");
+        assert_eq!(
+            castles(&moves),
+            HashSet::from([King(Castle {
+                king_from: sq::G1,
+                king_to: sq::G1,
+                rook_from: sq::H1,
+                rook_to: sq::F1,
+            })])
+        );
+
+        // The king and rook swap squares.
+        let moves = legal_move_set("7k/8/8/8/8/8/8/2RK4 w C - 0 1");
+        assert_eq!(
+            castles(&moves),
+            HashSet::from([King(Castle {
+                king_from: sq::D1,
+                king_to: sq::C1,
+                rook_from: sq::C1,
+                rook_to: sq::D1,
+            })])
+        );
+    }
+
+    #[test]
+    fn chess960_moves_from_rc() {
+        let board = from_fen(
+            "4k3/8/8/8/8/8/8/RK6 w A - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        assert!(board.is_chess960());
+
+        // b1c1 is a king move, and castling is given as the king capturing the
+        // rook.
+        let next = board.next_from_rc(&[(1, 2, None)]).unwrap();
+        assert_eq!(next.to_fen(), "4k3/8/8/8/8/8/8/R1K5 b - - 1 1");
+        let next = board.next_from_rc(&[(1, 0, None)]).unwrap();
+        assert_eq!(next.to_fen(), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+
+        // In standard chess castling can be given either way.
+        let board = Board::from(MAGICS.clone())
+            .next_from_rc(&[
+                (6, 21, None),
+                (62, 45, None),
+                (12, 28, None),
+                (52, 36, None),
+            ])
+            .unwrap()
+            .next_from_rc(&[(5, 26, None), (61, 34, None)])
+            .unwrap();
+        assert!(!board.is_chess960());
+        let king_to = board.next_from_rc(&[(4, 6, None)]).unwrap();
+        let king_rook = board.next_from_rc(&[(4, 7, None)]).unwrap();
+        assert_eq!(king_to, king_rook);
+        assert!(king_to.white_king().has_bit(sq::G1));
+    }
+
+    #[test]
+    fn incremental_hash_matches_full_hash() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+            assert_eq!(
+                board.state_hash(),
+                board.state.compute_hash(MAGICS.as_ref())
+            );
+            for next in board.next_boards().unwrap() {
+                assert_eq!(next.state_hash(), next.state.compute_hash(MAGICS.as_ref()));
+                for next in next.next_boards().unwrap_or_default() {
+                    assert_eq!(next.state_hash(), next.state.compute_hash(MAGICS.as_ref()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hash_is_same_for_transpositions() {
+        let board = Board::from(MAGICS.clone());
+        let first = board
+            .next_from_rc(&[(6, 21, None), (62, 45, None), (1, 18, None), (57, 42, None)])
+            .unwrap();
+        let second = board
+            .next_from_rc(&[(1, 18, None), (57, 42, None), (6, 21, None), (62, 45, None)])
+            .unwrap();
+        assert_eq!(first.state_hash(), second.state_hash());
+        assert_ne!(first.state_hash(), board.state_hash());
+    }
+
+    #[test]
+    fn hash_ignores_passant_without_capture() {
+        let board = Board::from(MAGICS.clone())
+            .next_from_rc(&[(12, 28, None)])
+            .unwrap();
+        assert!(board.passant().is_some());
+        let fen_board = from_fen(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        assert_eq!(board.state_hash(), fen_board.state_hash());
+
+        // Same pawn structure, but now black can capture by en passant.
+        let with_capture = from_fen(
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        let without_capture = from_fen(
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        assert_ne!(with_capture.state_hash(), without_capture.state_hash());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        // Shuffle the knights so that the start position repeats, and then
+        // claim the draw.
+        let mut board = Board::from(MAGICS.clone());
+        for _ in 0..2 {
+            board = board
+                .next_from_rc(&[(6, 21, None), (62, 45, None), (21, 6, None), (45, 62, None)])
+                .unwrap();
+        }
+        assert_eq!(board.claim_draw(), Some(DrawReason::Repetition));
+
+        let json = serde_json::to_string(&board).unwrap();
+        let loaded: Board = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, board);
+        assert_eq!(loaded.game_state(), GameState::Draw(DrawReason::Repetition));
+        assert_eq!(loaded.last_move(), board.last_move());
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+
+        // Chess960 boards stay Chess960 boards, even in the standard position.
+        let mut builder = BoardBuilder::from(MAGICS.clone());
+        builder.set_chess960(true);
+        let board = from_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            builder,
+        )
+        .unwrap();
+        let loaded: Board = serde_json::from_str(&serde_json::to_string(&board).unwrap()).unwrap();
+        assert!(loaded.is_chess960());
+        assert_eq!(loaded, board);
+
+        let pm = King(Castle {
+            king_from: sq::E1,
+            king_to: sq::G1,
+            rook_from: sq::H1,
+            rook_to: sq::F1,
+        });
+        let json = serde_json::to_string(&pm).unwrap();
+        assert_eq!(serde_json::from_str::<Piece<PieceMove>>(&json).unwrap(), pm);
+        assert!(serde_json::from_str::<Sq>("64").is_err());
+        assert!(serde_json::from_str::<Board>(r#"{"fen":"8/8 w - - 0 1"}"#).is_err());
+    }
+
+    #[test]
+    fn serde_finished_atomic_game() {
+        // Rxd8 explodes the black king, which ends the game.
+        let board = variant_board(Variant::Atomic, "3qk3/8/8/8/8/8/8/3RK3 w - - 0 1");
+        let board = board.next_from_rc(&[(3, 59, None)]).unwrap();
+        assert!(board.state.mine.king().none());
+        assert_eq!(board.game_state(), GameState::Mate(Color::Black));
+
+        let json = serde_json::to_string(&board).unwrap();
+        let loaded: Board = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, board);
+        assert_eq!(loaded.game_state(), GameState::Mate(Color::Black));
+
+        // Only the king of the side to move can be missing, and only in Atomic.
+        assert!(from_fen(
+            "8/8/8/8/8/8/8/4K3 w - - 0 1",
+            BoardBuilder::from(MAGICS.clone())
+        )
+        .is_err());
+        let mut builder = BoardBuilder::from(MAGICS.clone());
+        builder.set_variant(Variant::Atomic);
+        assert!(from_fen("8/8/8/8/8/8/8/4K3 w - - 0 1", builder).is_err());
+    }
+
+    // Returns a board for the variant from a FEN string, which is not checked
+    // strictly, so that positions with adjacent kings can be built in Atomic.
+    fn variant_board(variant: Variant, fen: &str) -> Board {
+        let mut builder = BoardBuilder::from(MAGICS.clone());
+        builder.set_variant(variant);
+        from_fen(fen, builder).unwrap()
+    }
+
+    #[test]
+    fn three_check_wins() {
+        let board = variant_board(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/R3K3 w - - 1+3 0 1");
+        assert_eq!(board.variant(), Variant::ThreeCheck);
+        assert_eq!(board.checks_given(Color::White), 2);
+        assert_eq!(board.checks_given(Color::Black), 0);
+
+        // The third check wins, even though it is not mate.
+        let mut next = board.clone();
+        let pm = Rook(Simple {
+            from: sq::A1,
+            to: sq::A8,
+        });
+        let undo = next.make_move(pm);
+        assert_eq!(next.checks_given(Color::White), 3);
+        assert_eq!(next.game_state(), GameState::Mate(Color::Black));
+        assert_eq!(next.to_fen(), "R3k3/8/8/8/8/8/8/4K3 b - - 0+3 1 1");
+        assert_eq!(
+            next.state_hash(),
+            variant_board(Variant::ThreeCheck, &next.to_fen()).state_hash()
+        );
+        next.unmake_move(undo);
+        assert_eq!(next, board);
+
+        // A move that does not give check is not counted.
+        let next = board.next_from_rc(&[(0, 8, None)]).unwrap();
+        assert_eq!(next.checks_given(Color::White), 2);
+        assert_eq!(next.game_state(), GameState::Next(Color::Black));
+        assert_ne!(next.state_hash(), Board::from(MAGICS.clone()).state_hash());
+
+        // Any piece can still give check.
+        let board = variant_board(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/3NK3 w - - 3+3 0 1");
+        assert_eq!(board.game_state(), GameState::Next(Color::White));
+        let board = variant_board(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/4K3 w - - 3+3 0 1");
+        assert_eq!(
+            board.game_state(),
+            GameState::Draw(DrawReason::InsufficientMaterial)
+        );
+    }
+
+    #[test]
+    fn king_of_the_hill_wins() {
+        let fen = "4k3/8/8/8/8/3K4/8/8 w - - 0 1";
+        let board = variant_board(Variant::KingOfTheHill, fen);
+        assert_eq!(board.game_state(), GameState::Next(Color::White));
+        let next = board.next_from_rc(&[(19, 27, None)]).unwrap();
+        assert_eq!(next.game_state(), GameState::Mate(Color::Black));
+        let next = board.next_from_rc(&[(19, 18, None)]).unwrap();
+        assert_eq!(next.game_state(), GameState::Next(Color::Black));
+
+        // The same position is already a draw in standard chess.
+        let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+        assert_eq!(
+            board.game_state(),
+            GameState::Draw(DrawReason::InsufficientMaterial)
+        );
+    }
+
+    #[test]
+    fn atomic_explosions() {
+        // Pawns next to the capture survive, the other pieces explode.
+        let board = variant_board(Variant::Atomic, "4k3/8/8/2p1b3/3n4/2P5/8/4K3 w - - 0 1");
+        let next = board.next_from_rc(&[(18, 27, None)]).unwrap();
+        assert_eq!(next.to_fen(), "4k3/8/8/2p5/8/8/8/4K3 b - - 0 1");
+        assert_eq!(next.game_state(), GameState::Next(Color::Black));
+        assert_eq!(
+            next.state_hash(),
+            variant_board(Variant::Atomic, &next.to_fen()).state_hash()
+        );
+
+        // Exploding the king wins the game.
+        let board = variant_board(Variant::Atomic, "3qk3/8/8/8/8/8/8/3RK3 w - - 0 1");
+        let mut next = board.clone();
+        let undo = next.make_move(Rook(Capture {
+            from: sq::D1,
+            to: sq::D8,
+            cap: Queen(()),
+        }));
+        assert_eq!(next.game_state(), GameState::Mate(Color::Black));
+        assert!(next.black_king().none());
+        next.unmake_move(undo);
+        assert_eq!(next, board);
+
+        // Exploded rooks lose their castling rights.
+        let board = variant_board(Variant::Atomic, "4k3/8/8/8/8/4N3/6n1/4K2R w K - 0 1");
+        let next = board.next_from_rc(&[(20, 14, None)]).unwrap();
+        assert!(!next.has_wk_castle());
+    }
+
+    #[test]
+    fn atomic_legal_moves() {
+        // Kings cannot capture.
+        let board = variant_board(Variant::Atomic, "4k3/p7/8/8/8/8/4n3/4K3 w - - 0 1");
+        let moves = board.next_moves().unwrap();
+        assert!(moves.iter().all(|pm| !pm.val().is_capture()));
+
+        // Captures next to the own king are not legal.
+        let board = variant_board(Variant::Atomic, "4k3/8/8/8/8/8/3n4/3RK3 w - - 0 1");
+        assert_eq!(board.next_from_rc(&[(3, 11, None)]), None);
+
+        // Connected kings cannot be in check, so the king may move along the
+        // rank that the queen attacks, as long as it stays next to the other
+        // king.
+        let board = variant_board(Variant::Atomic, "8/8/8/8/8/4k3/q2K4/8 w - - 0 1");
+        assert!(!board.is_check());
+        assert_eq!(board.next_from_rc(&[(11, 10, None)]), None);
+        assert!(board.next_from_rc(&[(11, 12, None)]).is_some());
+
+        // Capturing a piece next to the piece giving check also removes the
+        // check, since the checker explodes.
+        let board = variant_board(Variant::Atomic, "4k3/8/8/8/1n6/2qN4/8/K7 w - - 0 1");
+        assert!(board.is_check());
+        let next = board.next_from_rc(&[(19, 25, None)]).unwrap();
+        assert_eq!(next.to_fen(), "4k3/8/8/8/8/8/8/K7 b - - 0 1");
+    }
+
+    #[test]
+    fn crazyhouse_captures_and_drops() {
+        let board = variant_board(
+            Variant::Crazyhouse,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        );
+        let board = board
+            .next_from_rc(&[(12, 28, None), (51, 35, None), (28, 35, None)])
+            .unwrap();
+        assert_eq!(board.pocket(Color::White).count(Pawn(())), 1);
+        let board = board.next_from_rc(&[(59, 35, None)]).unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR[Pp] w KQkq - 0 3"
+        );
+
+        // Pawns cannot be dropped on the first or last rank.
+        let drops: Vec<_> = board
+            .next_moves()
+            .unwrap()
+            .into_iter()
+            .filter(|pm| pm.val().is_drop())
+            .collect();
+        assert_eq!(drops.len(), 33);
+        assert!(drops
+            .iter()
+            .all(|pm| !(RANK_1 | RANK_8).has_bit(pm.val().from_to().1)));
+
+        let board = board.next_from_rc(&[(28, 28, Some(Pawn(())))]).unwrap();
+        assert!(board.pocket(Color::White).is_empty());
+        assert_eq!(
+            board.to_fen(),
+            "rnb1kbnr/ppp1pppp/8/3q4/4P3/8/PPPP1PPP/RNBQKBNR[p] b KQkq - 0 3"
+        );
+        let rebuilt = from_fen(&board.to_fen(), BoardBuilder::from(MAGICS.clone())).unwrap();
+        assert_eq!(board.state_hash(), rebuilt.state_hash());
+
+        // A promoted piece goes back to the pocket as a pawn.
+        let board = variant_board(Variant::Crazyhouse, "k2r4/8/8/8/8/8/8/K2Q~4[] b - - 0 1");
+        let board = board.next_from_rc(&[(59, 3, None)]).unwrap();
+        assert_eq!(board.pocket(Color::Black).count(Pawn(())), 1);
+        assert_eq!(board.pocket(Color::Black).count(Queen(())), 0);
+        assert_eq!(board.promoted(), BitBoard::new());
+
+        // In check, pieces can only be dropped to block the check.
+        let board = variant_board(Variant::Crazyhouse, "k7/8/8/8/8/8/8/K6r[N] w - - 0 1");
+        let drops = board
+            .next_moves()
+            .unwrap()
+            .into_iter()
+            .filter(|pm| pm.val().is_drop())
+            .fold(BitBoard::new(), |bits, pm| {
+                bits | BitBoard::from(pm.val().from_to().1)
+            });
+        assert_eq!(drops, RANK_1 & !BitBoard::from(&[sq::A1, sq::H1]));
     }
 }
@@ -0,0 +1,209 @@
+/// This module contains code to generate the 960 starting positions of
+/// Chess960, also known as Fischer Random Chess. In Chess960, the pieces on
+/// the first rank are shuffled such that the bishops are on squares of
+/// opposite colors, and the king is between the two rooks, and the pieces of
+/// black mirror the pieces of white.
+///
+/// The positions are numbered from 0 to 959 using the Scharnagl numbering,
+/// where position 518 is the starting position of standard chess. For more
+/// background, see https://www.chessprogramming.org/Chess960.
+use crate::board::{Board, BoardBuilder};
+use crate::piece::{Piece, Piece::*};
+use crate::sq::Sq;
+use rand::Rng;
+
+/// The number of starting positions in Chess960.
+pub const NUM_POSITIONS: u16 = 960;
+
+/// The number of the starting position of standard chess.
+pub const STANDARD_POSITION: u16 = 518;
+
+// The files of the two knights among the five squares that are empty after
+// placing the bishops and the queen, indexed by the knight part of the number.
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// `back_rank` returns the pieces on the first rank for the Chess960 starting
+/// position with number n, from the a file to the h file, or None if n is not
+/// less than 960.
+pub fn back_rank(n: u16) -> Option<[Piece<()>; 8]> {
+    if n >= NUM_POSITIONS {
+        return None;
+    }
+    let mut rank: [Option<Piece<()>>; 8] = [None; 8];
+    let mut n = n as usize;
+
+    // The bishop on the light squares, i.e. b, d, f or h, and then the bishop on
+    // the dark squares, i.e. a, c, e or g.
+    rank[2 * (n % 4) + 1] = Some(Bishop(()));
+    n /= 4;
+    rank[2 * (n % 4)] = Some(Bishop(()));
+    n /= 4;
+
+    // The queen on one of the six empty squares.
+    let queen = empty_files(&rank)[n % 6];
+    rank[queen] = Some(Queen(()));
+    n /= 6;
+
+    // The knights on two of the five empty squares.
+    let empty = empty_files(&rank);
+    let (first, second) = KNIGHTS[n];
+    rank[empty[first]] = Some(Knight(()));
+    rank[empty[second]] = Some(Knight(()));
+
+    // The rooks and the king on the three remaining squares, with the king in
+    // the middle.
+    for (file, piece) in empty_files(&rank)
+        .into_iter()
+        .zip([Rook(()), King(()), Rook(())])
+    {
+        rank[file] = Some(piece);
+    }
+
+    Some(rank.map(|piece| piece.expect("All the files should have a piece.")))
+}
+
+/// `from_chess960` constructs the board for the Chess960 starting position
+/// with number n, with castling rights for all the rooks.
+///
+/// # Arguments
+///
+/// * `n` - The number of the starting position, which is less than 960.
+/// * `builder` - A board builder for building the board.
+///
+/// Returns the board, or None if n is not a valid number.
+pub(crate) fn from_chess960(n: u16, mut builder: BoardBuilder) -> Option<Board> {
+    let rank = back_rank(n)?;
+    for (file, piece) in rank.into_iter().enumerate() {
+        let file = file as u8;
+        let (white, black) = (sq_at(0, file), sq_at(7, file));
+        match piece {
+            King(_) => builder.white_king(white).black_king(black),
+            Queen(_) => builder.white_queen(white).black_queen(black),
+            Rook(_) => builder
+                .white_rook(white)
+                .black_rook(black)
+                .white_castle_rook(white)
+                .black_castle_rook(black),
+            Bishop(_) => builder.white_bishop(white).black_bishop(black),
+            Knight(_) => builder.white_knight(white).black_knight(black),
+            Pawn(_) => panic!("There are no pawns on the first rank."),
+        };
+        builder
+            .white_pawn(sq_at(1, file))
+            .black_pawn(sq_at(6, file));
+    }
+    Some(
+        builder
+            .set_chess960(true)
+            .build()
+            .expect("Chess960 boards should be valid."),
+    )
+}
+
+/// `random_chess960` returns a random number of a Chess960 starting position.
+pub fn random_chess960() -> u16 {
+    rand::rng().random_range(0..NUM_POSITIONS)
+}
+
+// Returns the files that do not have a piece yet.
+fn empty_files(rank: &[Option<Piece<()>>; 8]) -> Vec<usize> {
+    (0..8).filter(|file| rank[*file].is_none()).collect()
+}
+
+#[inline]
+fn sq_at(row: u8, file: u8) -> Sq {
+    Sq::from_rc(row, file).expect("Square should be valid.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magics::ChessMagics;
+    use lazy_static::lazy_static;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    lazy_static! {
+        static ref MAGICS: Arc<ChessMagics> = Arc::new(
+            ChessMagics::from_precomputed().expect("Unable to compute magics for unit test.")
+        );
+    }
+
+    #[test]
+    fn standard_position() {
+        let board = from_chess960(STANDARD_POSITION, BoardBuilder::from(MAGICS.clone())).unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert_eq!(
+            board.to_shredder_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+        assert!(board.is_chess960());
+        assert_eq!(board.next_moves().unwrap().len(), 20);
+    }
+
+    #[test]
+    fn numbered_positions() {
+        for (n, fen) in [
+            (
+                0,
+                "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1",
+            ),
+            (
+                1,
+                "bqnbnrkr/pppppppp/8/8/8/8/PPPPPPPP/BQNBNRKR w KQkq - 0 1",
+            ),
+            (
+                959,
+                "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1",
+            ),
+        ] {
+            let board = from_chess960(n, BoardBuilder::from(MAGICS.clone())).unwrap();
+            assert_eq!(board.to_fen(), fen);
+        }
+        assert!(from_chess960(NUM_POSITIONS, BoardBuilder::from(MAGICS.clone())).is_none());
+    }
+
+    #[test]
+    fn all_positions_are_distinct_and_valid() {
+        let mut ranks = HashSet::new();
+        for n in 0..NUM_POSITIONS {
+            let rank = back_rank(n).unwrap();
+            let files = |target: Piece<()>| -> Vec<usize> {
+                (0..8).filter(|file| rank[*file] == target).collect()
+            };
+            let (bishops, rooks, king) = (files(Bishop(())), files(Rook(())), files(King(())));
+            assert_eq!(bishops.len(), 2);
+            assert_ne!(bishops[0] % 2, bishops[1] % 2);
+            assert_eq!(rooks.len(), 2);
+            assert!(rooks[0] < king[0] && king[0] < rooks[1]);
+            assert_eq!(files(Queen(())).len(), 1);
+            assert_eq!(files(Knight(())).len(), 2);
+            ranks.insert(rank);
+        }
+        assert_eq!(ranks.len(), NUM_POSITIONS as usize);
+    }
+
+    #[test]
+    fn random_positions() {
+        for _ in 0..10 {
+            let n = random_chess960();
+            assert!(n < NUM_POSITIONS);
+            let board = from_chess960(n, BoardBuilder::from(MAGICS.clone())).unwrap();
+            assert!(board.next_moves().unwrap().len() >= 18);
+        }
+    }
+}
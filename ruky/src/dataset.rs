@@ -2,7 +2,7 @@ use crate::board::Board;
 use crate::ecmv::to_index;
 use crate::game::{GameResult, GameWinner};
 use crate::piece::Color;
-use crate::tensor_encoder::{AzEncoder, TensorEncoder};
+use crate::tensor_encoder::{AzEncoder, NetShape, TensorEncoder};
 use burn::{
     data::{dataloader::batcher::Batcher, dataset::Dataset},
     prelude::{Backend, Tensor, TensorData},
@@ -19,7 +19,7 @@ pub(crate) struct GamePosition {
     // necessary game state, e.g. color to move next.
     pub boards: Vec<Board>,
     // The unique code representing the chosen index, i.e. a value in
-    // [0, 8x8x73).
+    // [0, 8x8x78).
     pub move_index: usize,
     // If game winner is White, and player to move is black, then the value for
     // the current move is -1. If white is next to move, then value is 1, and if
@@ -108,7 +108,11 @@ impl<B: Backend> GamesBatcher<B> {
 
 impl<B: Backend> Batcher<B, GamePosition, GamesBatch<B>> for GamesBatcher<B> {
     fn batch(&self, games: Vec<GamePosition>, device: &B::Device) -> GamesBatch<B> {
-        let encoder = AzEncoder::new(device.clone());
+        // The games of a dataset are all played in the same variant.
+        let shape = games.first().map_or(NetShape::Standard, |game| {
+            NetShape::for_variant(game.boards[0].variant())
+        });
+        let encoder = AzEncoder::with_shape(device.clone(), shape);
 
         let n = games.len();
         let mut inputs = Vec::with_capacity(n);
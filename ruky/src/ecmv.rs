@@ -2,8 +2,16 @@
 
 use crate::piece::Piece;
 use crate::piece_move::PieceMove;
+use crate::pocket::POCKET_PIECES;
 use std::cmp::max;
 
+// The number of move codes per square: 56 queen moves, 8 knight moves, 9
+// underpromotions and 5 drops in Crazyhouse.
+pub(crate) const N_MOVE_CODES: usize = 78;
+// The number of move codes per square without drops, which is all standard
+// chess needs.
+pub(crate) const N_STD_MOVE_CODES: usize = 73;
+
 pub(crate) fn to_index(piece_move: Piece<PieceMove>) -> usize {
     EcMove::from(piece_move).index()
 }
@@ -21,7 +29,7 @@ impl EcMove {
         self.row * 8 + self.col
     }
 
-    // Maps EcMove to a number in [0, 8x8x73).
+    // Maps EcMove to a number in [0, 8x8x78).
     pub fn index(&self) -> usize {
         (self.row as usize + 1) * (self.col as usize + 1) * self.code as usize - 1
     }
@@ -31,6 +39,18 @@ impl From<Piece<PieceMove>> for EcMove {
     fn from(piece_move: Piece<PieceMove>) -> EcMove {
         let pm = piece_move.val();
         let (from_sq, to_sq) = pm.from_to();
+
+        // Drops are encoded at the destination square, since there is no
+        // source square.
+        if pm.is_drop() {
+            let (row, col) = to_sq.rc();
+            return Self {
+                row,
+                col,
+                code: 74 + encode_drop(piece_move.kind()),
+            };
+        }
+
         let (from_row, from_col) = from_sq.rc();
         let (to_row, to_col) = to_sq.rc();
         let row_diff = to_row as i8 - from_row as i8;
@@ -95,6 +115,14 @@ fn encode_under_promo(col_diff: i8, promo: Piece<()>) -> u8 {
     }
 }
 
+// Converts the piece of a drop to a number in the range [0, 4].
+fn encode_drop(piece: Piece<()>) -> u8 {
+    POCKET_PIECES
+        .iter()
+        .position(|p| *p == piece)
+        .unwrap_or_else(|| panic!("piece={:?} is not valid for a drop.", piece)) as u8
+}
+
 // Converts a queen move to a number in the range [1, 56].
 fn encode_queen_move(row_diff: i8, col_diff: i8) -> u8 {
     let direction = Direction::from_row_col_diff(row_diff, col_diff).to_u8();
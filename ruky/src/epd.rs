@@ -0,0 +1,462 @@
+/// This module contains code to parse EPD strings, i.e. strings in Extended
+/// Position Description, and to run a Search over suites of EPD positions.
+/// An EPD string consists of the first 4 fields of a FEN string, followed by
+/// operations separated by a semicolon. Each operation has an opcode and zero
+/// or more operands, e.g.:
+///
+/// * [r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - bm Nf5;
+///   id "WAC.009";]
+///
+/// The common opcodes used by test suites are:
+///
+/// * bm - the best moves in SAN.
+/// * am - the moves to avoid in SAN.
+/// * id - the id of the position.
+/// * c0 to c9 - comments, where STS suites use c7 for moves and c8 for the
+///   points of each move.
+/// * hmvc and fmvn - the half move clock and full move number.
+///
+/// For more background, see https://www.chessprogramming.org/Extended_Position_Description.
+use crate::board::{Board, BoardBuilder};
+use crate::fen::{from_fen, FenErr};
+use crate::piece::Piece;
+use crate::piece_move::PieceMove;
+use crate::san::SanErr;
+use crate::search::{Search, SearchBudget};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+const NUM_FIELDS: usize = 4;
+
+// A position parsed from an EPD string.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Epd {
+    pub board: Board,
+    // The operations in the order they appear, where quotes are removed from
+    // the operands.
+    pub ops: Vec<(String, Vec<String>)>,
+    // The moves of the bm operation.
+    pub best_moves: Vec<Piece<PieceMove>>,
+    // The moves of the am operation.
+    pub avoid_moves: Vec<Piece<PieceMove>>,
+    // The moves and their points from the c7 and c8 operations of STS suites.
+    pub move_points: Vec<(Piece<PieceMove>, u32)>,
+}
+
+impl Epd {
+    // Returns the operands of an operation, or None if the position does not
+    // have the operation.
+    pub fn op(&self, opcode: &str) -> Option<&[String]> {
+        self.ops
+            .iter()
+            .find(|(name, _)| name == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    // Returns the id of the position.
+    pub fn id(&self) -> Option<&str> {
+        self.op("id")
+            .and_then(|operands| operands.first())
+            .map(|id| id.as_str())
+    }
+
+    // Returns true if the move is one of the best moves and not one of the moves
+    // to avoid. A position without best moves is solved by any move that is not
+    // avoided.
+    pub fn is_solved_by(&self, piece_move: Piece<PieceMove>) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&piece_move))
+            && !self.avoid_moves.contains(&piece_move)
+    }
+
+    // Returns the points for a move. These are the STS points if set, or else 1
+    // if the position is solved by the move.
+    pub fn points(&self, piece_move: Piece<PieceMove>) -> u32 {
+        if self.move_points.is_empty() {
+            return self.is_solved_by(piece_move) as u32;
+        }
+        self.move_points
+            .iter()
+            .find(|(pm, _)| *pm == piece_move)
+            .map_or(0, |(_, points)| *points)
+    }
+
+    // Returns the maximum points for the position.
+    pub fn max_points(&self) -> u32 {
+        self.move_points
+            .iter()
+            .map(|(_, points)| *points)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+/// `from_epd` constructs an Epd from an EPD string.
+///
+/// # Arguments
+///
+/// * `epd` - The EPD string.
+/// * `builder` - A board builder for building the board.
+///
+/// Returns a Result with an Epd or an EpdErr if there is an error parsing the
+/// string, building the board or parsing the moves of the operations.
+pub(crate) fn from_epd(epd: &str, builder: BoardBuilder) -> Result<Epd, EpdErr> {
+    let mut rest = epd.trim();
+    let mut fields = Vec::with_capacity(NUM_FIELDS);
+    while fields.len() < NUM_FIELDS {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Err(EpdErr::NotEnoughFields);
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    let ops = parse_ops(rest)?;
+    let op = |opcode: &str| {
+        ops.iter()
+            .find(|(name, _)| name == opcode)
+            .and_then(|(_, operands)| operands.first())
+    };
+    let half_move = op("hmvc").map_or("0", |s| s.as_str());
+    let full_move = op("fmvn").map_or("1", |s| s.as_str());
+    let fen = format!("{} {} {}", fields.join(" "), half_move, full_move);
+    let board = from_fen(&fen, builder)?;
+
+    let parse_moves = |opcode: &str| -> Result<Vec<Piece<PieceMove>>, EpdErr> {
+        let operands = ops
+            .iter()
+            .find(|(name, _)| name == opcode)
+            .map_or(&[][..], |(_, operands)| operands.as_slice());
+        operands
+            .iter()
+            .flat_map(|operand| operand.split_whitespace())
+            .map(|san| {
+                board
+                    .parse_san(san)
+                    .map_err(|err| EpdErr::BadMove(opcode.to_string(), err))
+            })
+            .collect()
+    };
+    let best_moves = parse_moves("bm")?;
+    let avoid_moves = parse_moves("am")?;
+
+    let mut move_points = Vec::new();
+    if let (Some(moves), Some(points)) = (op("c7"), op("c8")) {
+        let moves = moves.split_whitespace();
+        let points = points.split_whitespace();
+        for (san, points) in moves.zip(points) {
+            let piece_move = board
+                .parse_san(san)
+                .map_err(|err| EpdErr::BadMove("c7".to_string(), err))?;
+            let points = points
+                .parse::<u32>()
+                .map_err(|_| EpdErr::BadOp(format!("c8 {points}")))?;
+            move_points.push((piece_move, points));
+        }
+    }
+
+    Ok(Epd {
+        board,
+        ops,
+        best_moves,
+        avoid_moves,
+        move_points,
+    })
+}
+
+// Parses the operations, e.g. [bm Nf5; id "WAC.009";]. The last operation may
+// omit the semicolon.
+fn parse_ops(ops: &str) -> Result<Vec<(String, Vec<String>)>, EpdErr> {
+    let mut parsed = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut token = String::new();
+    let mut in_quotes = false;
+    let mut quoted = false;
+
+    for c in ops.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                quoted = true;
+            }
+            _ if in_quotes => token.push(c),
+            ';' | ' ' | '\t' => {
+                if !token.is_empty() || quoted {
+                    tokens.push(std::mem::take(&mut token));
+                    quoted = false;
+                }
+                if c == ';' {
+                    parsed.push(to_op(std::mem::take(&mut tokens))?);
+                }
+            }
+            _ => token.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(EpdErr::BadOp(ops.trim().to_string()));
+    }
+    if !token.is_empty() || quoted {
+        tokens.push(token);
+    }
+    if !tokens.is_empty() {
+        parsed.push(to_op(tokens)?);
+    }
+    Ok(parsed)
+}
+
+fn to_op(mut tokens: Vec<String>) -> Result<(String, Vec<String>), EpdErr> {
+    if tokens.is_empty() {
+        return Err(EpdErr::BadOp(";".to_string()));
+    }
+    let opcode = tokens.remove(0);
+    if !opcode.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Err(EpdErr::BadOp(opcode));
+    }
+    Ok((opcode, tokens))
+}
+
+// The result of searching a single EPD position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpdResult {
+    pub id: Option<String>,
+    // The move found by the search, or None if the search failed.
+    pub best_move: Option<Piece<PieceMove>>,
+    // The best move in SAN.
+    pub san: Option<String>,
+    pub solved: bool,
+    pub points: u32,
+    pub max_points: u32,
+    pub nodes: u32,
+    pub time: Duration,
+}
+
+// The results of running a suite of EPD positions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SuiteResult {
+    pub results: Vec<EpdResult>,
+}
+
+impl SuiteResult {
+    pub fn solved(&self) -> usize {
+        self.results.iter().filter(|result| result.solved).count()
+    }
+
+    pub fn points(&self) -> u32 {
+        self.results.iter().map(|result| result.points).sum()
+    }
+
+    pub fn max_points(&self) -> u32 {
+        self.results.iter().map(|result| result.max_points).sum()
+    }
+
+    pub fn time(&self) -> Duration {
+        self.results.iter().map(|result| result.time).sum()
+    }
+}
+
+impl Display for SuiteResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "solved={}/{} points={}/{} time={:.3}s",
+            self.solved(),
+            self.results.len(),
+            self.points(),
+            self.max_points(),
+            self.time().as_secs_f32()
+        )
+    }
+}
+
+// Searches each position with the given budget and scores the moves found
+// against the bm and am operations, or the STS points if set.
+pub fn run_suite<S: Search>(search: &mut S, epds: &[Epd], budget: SearchBudget) -> SuiteResult {
+    search.set_budget(budget);
+    let mut suite_result = SuiteResult::default();
+    for epd in epds.iter() {
+        let search_result = search.search_board(&epd.board).ok();
+        let best_move = search_result.as_ref().map(|result| result.best_move());
+        let points = best_move.map_or(0, |pm| epd.points(pm));
+        suite_result.results.push(EpdResult {
+            id: epd.id().map(|id| id.to_string()),
+            best_move,
+            san: best_move.and_then(|pm| epd.board.to_san(pm)),
+            solved: best_move.is_some_and(|pm| epd.is_solved_by(pm)),
+            points,
+            max_points: epd.max_points(),
+            nodes: search_result
+                .as_ref()
+                .map_or(0, |result| result.nodes_visited),
+            time: search_result.map_or(Duration::ZERO, |result| result.total_search_time),
+        });
+    }
+    suite_result
+}
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum EpdErr {
+    #[error("not enough fields")]
+    NotEnoughFields,
+    #[error("operation {0} is not valid")]
+    BadOp(String),
+    #[error("move in {0} is not valid: {1}")]
+    BadMove(String, SanErr),
+    #[error("line {0}: {1}")]
+    BadLine(usize, Box<EpdErr>),
+    #[error(transparent)]
+    BadFen(#[from] FenErr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::err::RukyErr;
+    use crate::piece::Piece::*;
+    use crate::piece_move::PieceMove::*;
+    use crate::random_search::RandomSearch;
+    use crate::ruky::Ruky;
+    use crate::search::SearchResult;
+    use crate::sq;
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        static ref RUKY: Ruky = Ruky::new();
+    }
+
+    const WAC009: &str =
+        "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - bm Nf5; id \"WAC.009\";";
+
+    #[test]
+    fn parse_epd() {
+        let epd = RUKY.from_epd(WAC009).unwrap();
+        assert_eq!(
+            epd.board.to_fen(),
+            "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - 0 1"
+        );
+        assert_eq!(epd.id(), Some("WAC.009"));
+        assert_eq!(
+            epd.best_moves,
+            vec![Knight(Simple {
+                from: sq::D4,
+                to: sq::F5,
+            })]
+        );
+        assert!(epd.avoid_moves.is_empty());
+        assert_eq!(epd.op("c0"), None);
+        assert_eq!(epd.max_points(), 1);
+    }
+
+    #[test]
+    fn parse_ops() {
+        let epd = RUKY
+            .from_epd(
+                "4k3/8/8/8/8/8/4P3/4K3 b - - am Kd7 Ke7; c0 \"a comment; with a semicolon\"; \
+                 hmvc 3; fmvn 40; noop; id \"\"",
+            )
+            .unwrap();
+        assert_eq!(epd.board.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 b - - 3 40");
+        assert_eq!(epd.avoid_moves.len(), 2);
+        assert_eq!(
+            epd.op("c0"),
+            Some(&["a comment; with a semicolon".to_string()][..])
+        );
+        assert_eq!(epd.op("noop"), Some(&[][..]));
+        assert_eq!(epd.id(), Some(""));
+        assert!(epd.is_solved_by(King(Simple {
+            from: sq::E8,
+            to: sq::F8,
+        })));
+        assert!(!epd.is_solved_by(King(Simple {
+            from: sq::E8,
+            to: sq::D7,
+        })));
+    }
+
+    #[test]
+    fn sts_points() {
+        let epd = RUKY
+            .from_epd(
+                "4k3/8/8/8/8/8/4P3/4K3 w - - bm e4; id \"STS.001\"; c7 \"e4 e3 Kd2\"; \
+                 c8 \"10 5 2\";",
+            )
+            .unwrap();
+        assert_eq!(epd.max_points(), 10);
+        let e3 = Pawn(Simple {
+            from: sq::E2,
+            to: sq::E3,
+        });
+        assert_eq!(epd.points(e3), 5);
+        assert!(!epd.is_solved_by(e3));
+        assert_eq!(
+            epd.points(King(Simple {
+                from: sq::E1,
+                to: sq::F1,
+            })),
+            0
+        );
+    }
+
+    #[test]
+    fn epd_errors() {
+        assert_eq!(
+            RUKY.from_epd("4k3/8/8/8/8/8/4P3/4K3 w -"),
+            Err(EpdErr::NotEnoughFields)
+        );
+        assert_eq!(
+            RUKY.from_epd("4k3/8/8/8/8/8/4P3/4K3 w - - id \"WAC"),
+            Err(EpdErr::BadOp("id \"WAC".to_string()))
+        );
+        assert!(matches!(
+            RUKY.from_epd("4k3/8/8/8/8/8/4P3/4K3 w - - bm e5;"),
+            Err(EpdErr::BadMove(_, SanErr::NoMatch(_)))
+        ));
+        assert_eq!(
+            RUKY.from_epd("4k3/8/8/8/8/8/4P3/4K3 x - - bm e4;"),
+            Err(EpdErr::BadFen(FenErr::BadColor("x".to_string())))
+        );
+        assert!(matches!(
+            RUKY.read_epds(&format!("{WAC009}\n\n4k3 w - -\n")),
+            Err(EpdErr::BadLine(3, _))
+        ));
+    }
+
+    // A Search that fails for every position.
+    struct FailSearch;
+
+    impl Search for FailSearch {
+        fn search_board(&mut self, _board: &Board) -> Result<SearchResult, RukyErr> {
+            Err(RukyErr::SearchErr)
+        }
+    }
+
+    #[test]
+    fn run_suites() {
+        // Black only has one legal move in the first position.
+        let epds = RUKY
+            .read_epds(
+                "7k/8/6K1/8/8/8/8/R7 b - - bm Kg8; id \"forced\";\n\
+                 # A comment line.\n\
+                 7k/8/6K1/8/8/8/8/R7 b - - am Kg8; id \"avoid\";\n",
+            )
+            .unwrap();
+        assert_eq!(epds.len(), 2);
+
+        let suite_result = run_suite(&mut RandomSearch::new(), &epds, SearchBudget::Nodes(1));
+        assert_eq!(suite_result.solved(), 1);
+        assert_eq!(suite_result.points(), 1);
+        assert_eq!(suite_result.max_points(), 2);
+        let result = &suite_result.results[0];
+        assert_eq!(result.id.as_deref(), Some("forced"));
+        assert_eq!(result.san.as_deref(), Some("Kg8"));
+        assert!(result.solved);
+
+        let suite_result = run_suite(&mut FailSearch, &epds, SearchBudget::Nodes(1));
+        assert_eq!(suite_result.solved(), 0);
+        assert_eq!(suite_result.results[0].best_move, None);
+        assert_eq!(
+            suite_result.to_string(),
+            "solved=0/2 points=0/2 time=0.000s"
+        );
+    }
+}
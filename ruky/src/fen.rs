@@ -1,4 +1,4 @@
-/// This module contains code to parse FEN strings, i.e. strings in
+/// This module contains code to parse and write FEN strings, i.e. strings in
 /// Forsyth-Edwards Notation, which is used to encode the state of a chess
 /// position in a one-line ascii string. A fen string consists of 6 fields
 /// separated by a whitespace:
@@ -19,11 +19,28 @@
 /// * [rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2] after 1.
 ///   e4 c5 2. Nf3.
 ///
+/// For Chess960, the castling rights may also be given by the files of the
+/// castling rooks, as in Shredder-FEN, e.g. [HAha], or only for rooks that are
+/// not the outermost rook on their side of the king, as in X-FEN.
+///
+/// For Three-check, the checks that each side still needs to give to win are
+/// written after the en-passant square, e.g. [... KQkq - 3+2 0 1] after white
+/// gave one check. A FEN string with this field is always read as Three-check.
+///
+/// For Crazyhouse, the pockets are written in brackets after the piece
+/// placement, with uppercase letters for white, e.g.
+/// [rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR[Pp] w KQkq - 0 3] after 1. e4
+/// d5 2. exd5 Qxd5, and promoted pieces are marked with a ~ after their letter,
+/// e.g. Q~. A FEN string with pockets is always read as Crazyhouse.
+///
 /// For more background, see https://www.chessprogramming.org/Forsyth-Edwards_Notation.
+use crate::bitboard::BitBoard;
 use crate::board::{Board, BoardBuilder};
-use crate::piece::Color;
-use crate::piece_set::PiecesErr;
+use crate::piece::{Color, Piece, Piece::*};
+use crate::piece_set::{PieceSet, PiecesErr};
+use crate::pocket::POCKET_PIECES;
 use crate::sq::Sq;
+use crate::variant::{Variant, WINNING_CHECKS};
 
 const NUM_FIELDS: usize = 6;
 
@@ -37,16 +54,20 @@ const NUM_FIELDS: usize = 6;
 /// Returns a Result with a Board or a FenErr if there is an error either
 /// parsing the string or building the board.
 pub(crate) fn from_fen(fen: &str, mut builder: BoardBuilder) -> Result<Board, FenErr> {
-    let split_iter = fen.trim().split(' ');
-    let num_fields = split_iter.clone().count();
+    let mut fields: Vec<&str> = fen.trim().split(' ').collect();
+
+    // The checks field of Three-check comes after the en-passant square.
+    if fields.len() == NUM_FIELDS + 1 && fields[4].contains('+') {
+        parse_checks(fields.remove(4), &mut builder)?;
+    }
 
-    if num_fields < NUM_FIELDS {
+    if fields.len() < NUM_FIELDS {
         return Err(FenErr::NotEnoughFields);
-    } else if num_fields > NUM_FIELDS {
+    } else if fields.len() > NUM_FIELDS {
         return Err(FenErr::TooManyFields);
     }
 
-    for (i, field) in split_iter.enumerate() {
+    for (i, field) in fields.into_iter().enumerate() {
         match i {
             0 => parse_pieces(field, &mut builder)?,
             1 => {
@@ -62,7 +83,7 @@ pub(crate) fn from_fen(fen: &str, mut builder: BoardBuilder) -> Result<Board, Fe
                 let half_move = field
                     .parse::<u16>()
                     .map_err(|_| FenErr::BadHalfMove(field.to_string()))?;
-                if half_move > 50 {
+                if half_move > 150 {
                     return Err(FenErr::BadHalfMove(field.to_string()));
                 }
                 builder.set_half_move(half_move);
@@ -71,7 +92,7 @@ pub(crate) fn from_fen(fen: &str, mut builder: BoardBuilder) -> Result<Board, Fe
                 let full_move = field
                     .parse::<u16>()
                     .map_err(|_| FenErr::BadFullMove(field.to_string()))?;
-                builder.set_full_move(std::cmp::max(full_move, 1));
+                builder.set_full_move(full_move);
             }
             _ => panic!("Should never get here."),
         };
@@ -80,6 +101,164 @@ pub(crate) fn from_fen(fen: &str, mut builder: BoardBuilder) -> Result<Board, Fe
     builder.build().map_err(From::<PiecesErr>::from)
 }
 
+/// `to_fen` writes the state of a Board as a fen string.
+///
+/// # Arguments
+///
+/// * `board` - The board to write.
+///
+/// Returns the fen string, which can be parsed with `from_fen` to get back a
+/// board with the same position, side to move, castling rights, en-passant
+/// square and move counters.
+pub(crate) fn to_fen(board: &Board) -> String {
+    write_fen(board, false)
+}
+
+/// `to_shredder_fen` writes the state of a Board as a Shredder-FEN string,
+/// which is the same as a fen string except that the castling rights are
+/// always given by the files of the castling rooks, e.g. [HAha] for the
+/// starting position.
+pub(crate) fn to_shredder_fen(board: &Board) -> String {
+    write_fen(board, true)
+}
+
+// write_fen writes the fen string, with the castling rights in X-FEN, or in
+// Shredder-FEN if shredder is set.
+fn write_fen(board: &Board, shredder: bool) -> String {
+    let mut fen = String::new();
+    write_pieces(board.white(), board.black(), board.promoted(), &mut fen);
+    if board.variant() == Variant::Crazyhouse {
+        write_pockets(board, &mut fen);
+    }
+
+    fen.push(' ');
+    fen.push(if board.is_white_next() { 'w' } else { 'b' });
+
+    fen.push(' ');
+    let castling_len = fen.len();
+    write_castling(board.white(), shredder, &mut fen);
+    write_castling(board.black(), shredder, &mut fen);
+    if fen.len() == castling_len {
+        fen.push('-');
+    }
+
+    fen.push(' ');
+    match board.passant() {
+        Some(passant) => fen.push_str(&passant.capture.str().to_lowercase()),
+        None => fen.push('-'),
+    }
+
+    if board.variant() == Variant::ThreeCheck {
+        fen.push_str(&format!(
+            " {}+{}",
+            WINNING_CHECKS.saturating_sub(board.checks_given(Color::White)),
+            WINNING_CHECKS.saturating_sub(board.checks_given(Color::Black))
+        ));
+    }
+
+    fen.push_str(&format!(" {} {}", board.half_moves(), board.full_moves()));
+    fen
+}
+
+// write_pieces writes the pieces field of a FEN string, starting from the 8th
+// rank, with a ~ after the promoted pieces.
+fn write_pieces(white: &PieceSet, black: &PieceSet, promoted: BitBoard, fen: &mut String) {
+    for row in (0..8).rev() {
+        let mut empty = 0;
+        for col in 0..8 {
+            let sq = Sq::from_rc(row, col).unwrap();
+            let letter = match (white.find_type(sq), black.find_type(sq)) {
+                (Some(piece), _) => piece_letter(piece).to_ascii_uppercase(),
+                (None, Some(piece)) => piece_letter(piece),
+                (None, None) => {
+                    empty += 1;
+                    continue;
+                }
+            };
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+                empty = 0;
+            }
+            fen.push(letter);
+            if promoted.has_bit(sq) {
+                fen.push('~');
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if row > 0 {
+            fen.push('/');
+        }
+    }
+}
+
+// write_pockets writes the pockets of a Crazyhouse position in brackets, white
+// first, e.g. [QNPqp].
+fn write_pockets(board: &Board, fen: &mut String) {
+    fen.push('[');
+    for color in [Color::White, Color::Black] {
+        for (piece, count) in board.pocket(color).iter() {
+            let letter = match color {
+                Color::White => piece_letter(piece).to_ascii_uppercase(),
+                Color::Black => piece_letter(piece),
+            };
+            (0..count).for_each(|_| fen.push(letter));
+        }
+    }
+    fen.push(']');
+}
+
+// write_castling writes the castling rights for the pieces, king side first.
+// In X-FEN, the castling rights are written as K or Q if the castling rook is
+// the outermost rook on that side of the king, and otherwise as the file of
+// the rook, which is always the case in Shredder-FEN. The letters are
+// uppercase for white.
+fn write_castling(pieces: &PieceSet, shredder: bool, fen: &mut String) {
+    for (rook, king_side) in [
+        (pieces.king_castle_rook(), true),
+        (pieces.queen_castle_rook(), false),
+    ] {
+        let (row, file) = match rook {
+            Some(rook) => rook.rc(),
+            None => continue,
+        };
+        let outermost = pieces
+            .rooks()
+            .sq_iter()
+            .filter(|sq| sq.rc().0 == row)
+            .all(|sq| {
+                if king_side {
+                    sq.rc().1 <= file
+                } else {
+                    sq.rc().1 >= file
+                }
+            });
+        let letter = match (shredder || !outermost, king_side) {
+            (true, _) => (b'a' + file) as char,
+            (false, true) => 'k',
+            (false, false) => 'q',
+        };
+        fen.push(if pieces.color().is_white() {
+            letter.to_ascii_uppercase()
+        } else {
+            letter
+        });
+    }
+}
+
+// Returns the lowercase FEN letter for a piece.
+fn piece_letter<T>(piece: Piece<T>) -> char {
+    match piece {
+        King(_) => 'k',
+        Queen(_) => 'q',
+        Rook(_) => 'r',
+        Bishop(_) => 'b',
+        Knight(_) => 'n',
+        Pawn(_) => 'p',
+    }
+}
+
 // parse_pieces parses the pieces field in a FEN string.
 //
 // @param field The field containing the pieces.
@@ -87,15 +266,37 @@ pub(crate) fn from_fen(fen: &str, mut builder: BoardBuilder) -> Result<Board, Fe
 // @return a Result with a unit or a FenErr if there is an error parsing the
 // pieces field.
 fn parse_pieces(field: &str, builder: &mut BoardBuilder) -> Result<(), FenErr> {
+    // The pockets of Crazyhouse follow the piece placement in brackets.
+    let field = match field.split_once('[') {
+        Some((placement, pockets)) => {
+            parse_pockets(pockets, builder)?;
+            placement
+        }
+        None => field,
+    };
+
     // A counter for the current square.
     let mut s = 0u32;
 
     // Reverse the rows so we can start at 0.
     for row in field.split('/').rev() {
+        // Set if the last letter was a piece, which can be marked as promoted.
+        let mut after_piece = false;
         for letter in row.chars() {
             // Numbers indicate empty squares.
             if ('1'..='8').contains(&letter) {
                 s += letter.to_digit(10).unwrap();
+                after_piece = false;
+                continue;
+            }
+
+            // A ~ marks the piece before it as promoted in Crazyhouse.
+            if letter == '~' {
+                if !after_piece {
+                    return Err(FenErr::BadPiece(letter));
+                }
+                builder.set_promoted(Sq::from(s - 1));
+                after_piece = false;
                 continue;
             }
 
@@ -117,6 +318,7 @@ fn parse_pieces(field: &str, builder: &mut BoardBuilder) -> Result<(), FenErr> {
                 _ => return Err(FenErr::BadPiece(letter)),
             };
             s += 1;
+            after_piece = true;
         }
     }
 
@@ -128,7 +330,36 @@ fn parse_pieces(field: &str, builder: &mut BoardBuilder) -> Result<(), FenErr> {
     Ok(())
 }
 
-// parse_castling parses the castling rights field in a FEN string.
+// parse_pockets parses the pockets of a Crazyhouse position, i.e. the letters
+// of the pieces in the pockets followed by a closing bracket, which are
+// uppercase for white. The variant of the board is set to Crazyhouse.
+//
+// @param field The pockets without the opening bracket.
+// @param builder A board builder to set the pockets.
+// @return a Result with a unit or a FenErr if the pockets are not valid.
+fn parse_pockets(field: &str, builder: &mut BoardBuilder) -> Result<(), FenErr> {
+    let letters = field
+        .strip_suffix(']')
+        .ok_or_else(|| FenErr::BadPocket(field.to_string()))?;
+    builder.set_variant(Variant::Crazyhouse);
+    for letter in letters.chars() {
+        let color = if letter.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let piece = POCKET_PIECES
+            .into_iter()
+            .find(|piece| piece_letter(*piece) == letter.to_ascii_lowercase())
+            .ok_or_else(|| FenErr::BadPocket(field.to_string()))?;
+        builder.add_to_pocket(color, piece);
+    }
+    Ok(())
+}
+
+// parse_castling parses the castling rights field in a FEN string, where the
+// castling rights are given either by K, Q, k and q, or by the files of the
+// castling rooks, which are uppercase for white.
 //
 // @param field The field containing the castling rights.
 // @param builder A board builder to set the castling rights.
@@ -149,6 +380,8 @@ fn parse_castling(field: &str, builder: &mut BoardBuilder) -> Result<(), FenErr>
             'Q' => builder.white_queen_castle(true),
             'k' => builder.black_king_castle(true),
             'q' => builder.black_queen_castle(true),
+            'A'..='H' => builder.white_castle_rook(Sq::from_rc(0, letter as u8 - b'A').unwrap()),
+            'a'..='h' => builder.black_castle_rook(Sq::from_rc(7, letter as u8 - b'a').unwrap()),
             _ => return Err(FenErr::BadCastlingToken(letter)),
         };
     }
@@ -191,6 +424,32 @@ fn parse_passant(field: &str, builder: &mut BoardBuilder) -> Result<(), FenErr>
     }
 }
 
+// parse_checks parses the checks field of a Three-check FEN string, which has
+// the number of checks that white and black still need to give, and sets the
+// variant to Three-check.
+//
+// @param field The field containing the remaining checks, e.g. 3+2.
+// @param builder A board builder to set the checks.
+// @return a Result with a unit or a FenErr if there is an error parsing the
+// checks field.
+fn parse_checks(field: &str, builder: &mut BoardBuilder) -> Result<(), FenErr> {
+    let remaining = |checks: &str| {
+        checks
+            .parse::<u8>()
+            .ok()
+            .filter(|checks| *checks <= WINNING_CHECKS)
+            .ok_or_else(|| FenErr::BadChecks(field.to_string()))
+    };
+    let (white, black) = field
+        .split_once('+')
+        .ok_or_else(|| FenErr::BadChecks(field.to_string()))?;
+    builder
+        .set_variant(Variant::ThreeCheck)
+        .set_checks(Color::White, WINNING_CHECKS - remaining(white)?)
+        .set_checks(Color::Black, WINNING_CHECKS - remaining(black)?);
+    Ok(())
+}
+
 #[derive(thiserror::Error, Clone, Debug, PartialEq)]
 pub enum FenErr {
     // These represent format errors in the FEN string.
@@ -214,6 +473,10 @@ pub enum FenErr {
     BadCastlingToken(char),
     #[error("en-passant square {0} is not valid")]
     BadPassant(String),
+    #[error("remaining checks {0} are not valid")]
+    BadChecks(String),
+    #[error("pocket {0} is not valid")]
+    BadPocket(String),
 
     // These represent logical errors in Board position, and map one-to-one to PiecesErr.
     #[error("pieces need a king")]
@@ -230,6 +493,16 @@ pub enum FenErr {
     TooManyPawns,
     #[error("invalid castling rights")]
     BadCastle,
+    #[error("more than one piece on a square")]
+    OverlappingPieces,
+    #[error("pawns on the first or last rank")]
+    PawnsOnBackRank,
+    #[error("the side not to move is in check")]
+    OpponentInCheck,
+    #[error("invalid en-passant square")]
+    InvalidPassant,
+    #[error("full move number must be at least 1")]
+    InvalidFullMove,
 }
 
 // Conversion from PiecesErr to FenErr.
@@ -243,6 +516,11 @@ impl From<PiecesErr> for FenErr {
             PiecesErr::TooManyKnights => FenErr::TooManyKnights,
             PiecesErr::TooManyPawns => FenErr::TooManyPawns,
             PiecesErr::BadCastle => FenErr::BadCastle,
+            PiecesErr::OverlappingPieces => FenErr::OverlappingPieces,
+            PiecesErr::PawnsOnBackRank => FenErr::PawnsOnBackRank,
+            PiecesErr::OpponentInCheck => FenErr::OpponentInCheck,
+            PiecesErr::InvalidPassant => FenErr::InvalidPassant,
+            PiecesErr::InvalidFullMove => FenErr::InvalidFullMove,
         }
     }
 }
@@ -250,7 +528,6 @@ impl From<PiecesErr> for FenErr {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::bitboard::BitBoard;
     use crate::magics::ChessMagics;
     use crate::sq;
     use lazy_static::lazy_static;
@@ -424,10 +701,10 @@ mod tests {
         );
         assert_eq!(
             from_fen(
-                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 55 1",
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 151 1",
                 BoardBuilder::from(MAGICS.clone())
             ),
-            Err(FenErr::BadHalfMove("55".into()))
+            Err(FenErr::BadHalfMove("151".into()))
         );
     }
 
@@ -452,6 +729,69 @@ mod tests {
         );
     }
 
+    #[test]
+    fn invalid_positions() {
+        for (fen, err) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPP1/RNBQKBNP w Qkq - 0 1",
+                FenErr::PawnsOnBackRank,
+            ),
+            ("4k3/8/8/8/8/8/8/4RK2 w - - 0 1", FenErr::OpponentInCheck),
+            ("8/8/8/8/8/8/8/3Kk3 b - - 0 1", FenErr::OpponentInCheck),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e6 0 1",
+                FenErr::InvalidPassant,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1",
+                FenErr::InvalidPassant,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/4P3/PPP2PPP/RNBQKBNR b KQkq e3 0 1",
+                FenErr::InvalidPassant,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0",
+                FenErr::InvalidFullMove,
+            ),
+        ] {
+            assert_eq!(
+                from_fen(fen, BoardBuilder::from(MAGICS.clone())),
+                Err(err),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn lenient_positions() {
+        let lenient = || {
+            let mut builder = BoardBuilder::from(MAGICS.clone());
+            builder.set_lenient(true);
+            builder
+        };
+        let board = from_fen("4k3/8/8/8/8/8/8/4RK2 w - - 0 1", lenient()).unwrap();
+        assert_eq!(board.white_rooks(), BitBoard::from(sq::E1));
+        let board = from_fen("4k1P1/8/8/8/8/8/8/4K3 w - - 0 1", lenient()).unwrap();
+        assert_eq!(board.white_pawns(), BitBoard::from(sq::G8));
+
+        // Invalid en-passant squares are ignored and the full move number is
+        // at least 1.
+        let board = from_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 0",
+            lenient(),
+        )
+        .unwrap();
+        assert_eq!(board.passant(), None);
+        assert_eq!(board.full_moves(), 1);
+
+        // Positions that cannot be played from are still rejected.
+        assert_eq!(
+            from_fen("4k3/8/8/8/8/8/8/8 w - - 0 1", lenient()),
+            Err(FenErr::NoKing)
+        );
+    }
+
     #[test]
     fn init_position() {
         assert_eq!(
@@ -501,4 +841,179 @@ mod tests {
         assert_eq!(board.black_bishops(), zero);
         assert_eq!(board.black_knights(), zero);
     }
+
+    #[test]
+    fn round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+            "8/5k2/3p4/1p1Pp2p/pP2Pp1P/P4P1K/8/8 b - - 40 50",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/8/8/8/8/8/K6k w - - 0 1",
+        ] {
+            let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+            assert_eq!(board.to_fen(), fen);
+            assert_eq!(board.to_string(), fen);
+            assert_eq!(
+                from_fen(&board.to_fen(), BoardBuilder::from(MAGICS.clone())),
+                Ok(board)
+            );
+        }
+    }
+
+    #[test]
+    fn chess960_round_trip() {
+        for (fen, shredder) in [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9",
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            ),
+            (
+                "1k6/8/8/8/8/8/8/RK2R2R w EQ - 0 1",
+                "1k6/8/8/8/8/8/8/RK2R2R w EA - 0 1",
+            ),
+            (
+                "rk4r1/8/8/8/8/8/8/1K6 b q - 0 1",
+                "rk4r1/8/8/8/8/8/8/1K6 b a - 0 1",
+            ),
+        ] {
+            let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+            assert!(board.is_chess960());
+            assert_eq!(board.to_fen(), fen);
+            assert_eq!(board.to_shredder_fen(), shredder);
+            assert_eq!(
+                from_fen(shredder, BoardBuilder::from(MAGICS.clone())),
+                Ok(board)
+            );
+        }
+
+        // There is no rook to castle with on the a file.
+        assert_eq!(
+            from_fen(
+                "4k3/8/8/8/8/8/8/4K2R w A - 0 1",
+                BoardBuilder::from(MAGICS.clone())
+            ),
+            Err(FenErr::BadCastle)
+        );
+    }
+
+    #[test]
+    fn three_check_round_trip() {
+        let fen = "rnbqkbnr/ppp1pppp/8/1B1p4/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 2+3 1 2";
+        let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+        assert_eq!(board.variant(), Variant::ThreeCheck);
+        assert_eq!(board.checks_given(Color::White), 1);
+        assert_eq!(board.to_fen(), fen);
+
+        // Three-check boards without the checks field start without checks.
+        let mut builder = BoardBuilder::from(MAGICS.clone());
+        builder.set_variant(Variant::ThreeCheck);
+        let board = from_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            builder,
+        )
+        .unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1"
+        );
+
+        for checks in ["4+3", "3+", "a+3", "+"] {
+            assert_eq!(
+                from_fen(
+                    &format!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - {checks} 0 1"),
+                    BoardBuilder::from(MAGICS.clone())
+                ),
+                Err(FenErr::BadChecks(checks.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn crazyhouse_round_trip() {
+        let fen = "rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR[Pp] w KQkq - 0 3";
+        let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+        assert_eq!(board.variant(), Variant::Crazyhouse);
+        assert_eq!(board.pocket(Color::White).count(Pawn(())), 1);
+        assert_eq!(board.pocket(Color::Black).count(Pawn(())), 1);
+        assert_eq!(board.to_fen(), fen);
+
+        // Promoted pieces are marked with a ~, and there can be more pieces of
+        // a type than in standard chess.
+        let fen = "2Q~1k3/8/8/8/8/8/8/NNNNKNN1[NNn] b - - 0 40";
+        let board = from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+        assert_eq!(board.promoted(), BitBoard::from(&[sq::C8]));
+        assert_eq!(board.pocket(Color::White).count(Knight(())), 2);
+        assert_eq!(board.to_fen(), fen);
+
+        let mut builder = BoardBuilder::from(MAGICS.clone());
+        builder.set_variant(Variant::Crazyhouse);
+        let board = from_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            builder,
+        )
+        .unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1"
+        );
+
+        for pockets in ["[K]", "[P", "[x]"] {
+            assert_eq!(
+                from_fen(
+                    &format!("4k3/8/8/8/8/8/8/4K3{pockets} w - - 0 1"),
+                    BoardBuilder::from(MAGICS.clone())
+                ),
+                Err(FenErr::BadPocket(pockets[1..].to_string()))
+            );
+        }
+        assert_eq!(
+            from_fen(
+                "4k3/8/8/8/8/8/8/~4K3[] w - - 0 1",
+                BoardBuilder::from(MAGICS.clone())
+            ),
+            Err(FenErr::BadPiece('~'))
+        );
+    }
+
+    #[test]
+    fn to_fen_after_moves() {
+        let board = Board::from(MAGICS.clone());
+        let board = board.next_from_rc(&[(12, 28, None)]).unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        let board = board.next_from_rc(&[(50, 34, None)]).unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2"
+        );
+        let board = board.next_from_rc(&[(6, 21, None)]).unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+    }
+
+    #[test]
+    fn round_trip_after_moves() {
+        let board = from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            BoardBuilder::from(MAGICS.clone()),
+        )
+        .unwrap();
+        for next in board.next_boards().unwrap() {
+            for next in next.next_boards().unwrap_or_default() {
+                let fen = next.to_fen();
+                let parsed = from_fen(&fen, BoardBuilder::from(MAGICS.clone())).unwrap();
+                assert_eq!(parsed.to_fen(), fen);
+                assert_eq!(parsed.state_hash(), next.state_hash());
+            }
+        }
+    }
 }
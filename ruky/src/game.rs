@@ -1,17 +1,27 @@
 // This module contains components to play games of chess.
 
 use crate::board::{Board, GameState};
+use crate::chess960::{from_chess960, random_chess960};
 use crate::err::RukyErr;
 use crate::eval::AzEval;
 use crate::mcts::{Mcts, SpMcts, SpMctsBuilder};
 use crate::mt_mcts::ParMcts;
 use crate::nn::AlphaZeroNet;
+use crate::pgn::{pgn_date, PgnGame, PgnMove};
 use crate::piece::Color;
 use crate::search::{Search, SearchResult, SpSearch, TreeSize};
 use crate::tensor_decoder::AzDecoder;
-use crate::tensor_encoder::AzEncoder;
+use crate::tensor_encoder::{AzEncoder, NetShape};
 use burn::prelude::{Backend, Device};
-use std::{cmp::max, mem::swap, sync::Arc, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::max,
+    mem::swap,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 // Parallel training game builder.
 #[derive(Clone, Debug)]
@@ -27,6 +37,9 @@ pub struct TrainingGameBuilder<B: Backend> {
     num_workers: Option<usize>,
     // If set, this is used to build the MCTS.
     net: Option<Arc<AlphaZeroNet<B>>>,
+    // If true, each game starts from a random Chess960 position instead of the
+    // board.
+    chess960: bool,
 }
 
 impl<B: Backend> TrainingGameBuilder<B> {
@@ -42,6 +55,7 @@ impl<B: Backend> TrainingGameBuilder<B> {
             batch_size: None,
             num_workers: None,
             net: None,
+            chess960: false,
         }
     }
 
@@ -85,6 +99,11 @@ impl<B: Backend> TrainingGameBuilder<B> {
         self
     }
 
+    pub fn chess960(mut self, chess960: bool) -> Self {
+        self.chess960 = chess960;
+        self
+    }
+
     pub fn net(mut self, net: Arc<AlphaZeroNet<B>>) -> Self {
         self.net.replace(net);
         self
@@ -93,11 +112,16 @@ impl<B: Backend> TrainingGameBuilder<B> {
     pub fn build(self) -> Result<TrainingGame<ParMcts<AzEval<B>>, B>, RukyErr> {
         match (self.board, self.device) {
             (Some(board), Some(device)) => {
-                let encoder = AzEncoder::new(device.clone());
+                let board = match self.chess960 {
+                    true => random_chess960_board(&board),
+                    false => board,
+                };
+                let shape = NetShape::for_variant(board.variant());
+                let encoder = AzEncoder::with_shape(device.clone(), shape);
                 let decoder = AzDecoder::new();
                 let net = self
                     .net
-                    .unwrap_or_else(|| Arc::new(AlphaZeroNet::new(&device)));
+                    .unwrap_or_else(|| Arc::new(AlphaZeroNet::with_shape(&device, shape)));
                 let eval = Arc::new(AzEval::create(encoder, decoder, net.clone()));
                 let mcts = ParMcts::create(
                     eval,
@@ -109,7 +133,7 @@ impl<B: Backend> TrainingGameBuilder<B> {
                     self.batch_size.unwrap_or(16),
                     self.num_workers.unwrap_or(16),
                 );
-                Ok(TrainingGame::create(board, mcts, net, self.max_moves))
+                Ok(TrainingGame::create(board, mcts, net, self.max_moves).chess960(self.chess960))
             }
             (_, _) => Err(RukyErr::PreconditionErr),
         }
@@ -170,9 +194,10 @@ impl<B: Backend> TrGameBuilder<B> {
     pub fn build(self) -> Result<TrainingGame<SpMcts<AzEval<B>>, B>, RukyErr> {
         match (self.board, self.device) {
             (Some(board), Some(device)) => {
-                let encoder = AzEncoder::new(device.clone());
+                let shape = NetShape::for_variant(board.variant());
+                let encoder = AzEncoder::with_shape(device.clone(), shape);
                 let decoder = AzDecoder::new();
-                let net = Arc::new(AlphaZeroNet::new(&device));
+                let net = Arc::new(AlphaZeroNet::with_shape(&device, shape));
                 let eval = Arc::new(AzEval::create(encoder, decoder, net.clone()));
                 let mcts = SpMctsBuilder::new()
                     .eval(eval)
@@ -195,6 +220,8 @@ pub struct TrainingGame<S: SpSearch + TreeSize, B: Backend> {
     wb_search: S,
     pub(crate) net: Arc<AlphaZeroNet<B>>,
     max_moves: usize,
+    // If true, each game starts from a new random Chess960 position.
+    chess960: bool,
 }
 
 impl<S: SpSearch + TreeSize, B: Backend> TrainingGame<S, B> {
@@ -204,14 +231,22 @@ impl<S: SpSearch + TreeSize, B: Backend> TrainingGame<S, B> {
             wb_search,
             net,
             max_moves,
+            chess960: false,
         }
     }
 
+    pub fn chess960(mut self, chess960: bool) -> Self {
+        self.chess960 = chess960;
+        self
+    }
+
     pub fn play(&mut self) -> Result<GameResult, RukyErr> {
         let mut moves = Vec::<SearchResult>::new();
         for _ in 0..self.max_moves {
             let result = self.wb_search.search()?;
             moves.push(result);
+            // Draws by threefold repetition or the 50-move rule are claimed.
+            moves.last_mut().unwrap().best.board.claim_draw();
             let board = moves.last().unwrap().best_board();
             if board.is_terminal() {
                 break;
@@ -228,10 +263,21 @@ impl<S: SpSearch + TreeSize, B: Backend> TrainingGame<S, B> {
     }
 
     pub fn reset(&mut self) {
-        self.wb_search.reset();
+        if self.chess960 {
+            self.board = random_chess960_board(&self.board);
+            self.wb_search.reset_board(&self.board);
+        } else {
+            self.wb_search.reset();
+        }
     }
 }
 
+// Returns a random Chess960 starting position, built with the magics of board.
+fn random_chess960_board(board: &Board) -> Board {
+    from_chess960(random_chess960(), board.builder())
+        .expect("Random Chess960 number should be valid.")
+}
+
 // TODO: make this generic over Search once we have different types of Search.
 pub struct GameBuilder<B: Backend> {
     board: Option<Board>,
@@ -280,9 +326,10 @@ impl<B: Backend> GameBuilder<B> {
     pub fn build(self) -> Result<Game<Mcts<AzEval<B>>>, RukyErr> {
         match (self.board, self.device) {
             (Some(board), Some(device)) => {
-                let encoder = AzEncoder::new(device.clone());
+                let shape = NetShape::for_variant(board.variant());
+                let encoder = AzEncoder::with_shape(device.clone(), shape);
                 let decoder = AzDecoder::new();
-                let net = Arc::new(AlphaZeroNet::new(&device));
+                let net = Arc::new(AlphaZeroNet::with_shape(&device, shape));
                 let evaluator = Arc::new(AzEval::create(encoder, decoder, net));
                 let (mut white_mcts, mut black_mcts) = if self.use_noise {
                     (
@@ -342,6 +389,8 @@ impl<S: Search> Game<S> {
                 false => self.black_search.search_board(next_board)?,
             };
             moves.push(result);
+            // Draws by threefold repetition or the 50-move rule are claimed.
+            moves.last_mut().unwrap().best.board.claim_draw();
             next_board = moves.last().unwrap().best_board();
             if next_board.is_terminal() {
                 break;
@@ -363,7 +412,7 @@ impl<S: Search> Game<S> {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct GameResult {
     pub board: Board,
     pub moves: Vec<SearchResult>,
@@ -388,15 +437,154 @@ impl GameResult {
         }
         game_stats
     }
+
+    // Returns a builder to export this game to PGN.
+    pub fn pgn_builder(&self) -> GamePgnBuilder<'_> {
+        GamePgnBuilder::new(self)
+    }
+
+    // Returns true if the game ended in mate or a draw, rather than reaching the
+    // maximum number of moves.
+    pub fn is_terminated(&self) -> bool {
+        self.moves
+            .last()
+            .is_some_and(|result| result.best_board().is_terminal())
+    }
+}
+
+// Exports a GameResult to PGN with the seven standard tags, the termination
+// and optionally a comment with the search stats for each move.
+#[derive(Clone, Debug)]
+pub struct GamePgnBuilder<'a> {
+    game_result: &'a GameResult,
+    event: String,
+    site: String,
+    date: String,
+    round: String,
+    white: String,
+    black: String,
+    // If set, each move has a comment with the search stats, e.g.
+    // {value=0.512 visits=311 nodes=800 time=1.254s}.
+    annotate: bool,
+}
+
+impl<'a> GamePgnBuilder<'a> {
+    fn new(game_result: &'a GameResult) -> Self {
+        Self {
+            game_result,
+            event: "?".into(),
+            site: "?".into(),
+            date: pgn_date(SystemTime::now()),
+            round: "?".into(),
+            white: "?".into(),
+            black: "?".into(),
+            annotate: true,
+        }
+    }
+
+    pub fn event(mut self, event: &str) -> Self {
+        self.event = event.into();
+        self
+    }
+
+    pub fn site(mut self, site: &str) -> Self {
+        self.site = site.into();
+        self
+    }
+
+    // The date in PGN format, e.g. 2025.01.31. This is today by default.
+    pub fn date(mut self, date: &str) -> Self {
+        self.date = date.into();
+        self
+    }
+
+    pub fn round(mut self, round: usize) -> Self {
+        self.round = round.to_string();
+        self
+    }
+
+    // The name of the white player, e.g. the name of the net.
+    pub fn white(mut self, white: &str) -> Self {
+        self.white = white.into();
+        self
+    }
+
+    // The name of the black player, e.g. the name of the net.
+    pub fn black(mut self, black: &str) -> Self {
+        self.black = black.into();
+        self
+    }
+
+    pub fn annotate(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
+    }
+
+    pub fn build(self) -> PgnGame {
+        let game_result = self.game_result;
+        let result = game_result.winner.pgn_result();
+        let mut pgn_game = PgnGame::new(game_result.board.clone());
+        pgn_game.result = result.to_string();
+        pgn_game
+            .set_tag("Event", &self.event)
+            .set_tag("Site", &self.site)
+            .set_tag("Date", &self.date)
+            .set_tag("Round", &self.round)
+            .set_tag("White", &self.white)
+            .set_tag("Black", &self.black)
+            .set_tag("Result", result);
+        // Games that reach the maximum number of moves are declared a draw.
+        let termination = match game_result.is_terminated() {
+            true => "normal",
+            false => "adjudication",
+        };
+        pgn_game.set_tag("Termination", termination);
+        let fen = game_result.board.to_fen();
+        let variant = game_result.board.variant();
+        if !variant.is_standard() {
+            pgn_game.set_tag("Variant", variant.name());
+        } else if game_result.board.is_chess960() {
+            pgn_game.set_tag("Variant", "Chess960");
+        }
+        if fen != START_FEN {
+            pgn_game.set_tag("SetUp", "1").set_tag("FEN", &fen);
+        }
+
+        for result in game_result.moves.iter() {
+            let mut pgn_move = PgnMove::new(result.best_move());
+            if self.annotate {
+                pgn_move.comments.push(format!(
+                    "value={:.3} visits={} nodes={} time={:.3}s",
+                    result.value,
+                    result.best.visits,
+                    result.nodes_visited,
+                    result.total_search_time.as_secs_f32()
+                ));
+            }
+            pgn_game.moves.push(pgn_move);
+        }
+        pgn_game
+    }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum GameWinner {
     Black,
     White,
     Draw,
 }
 
+impl GameWinner {
+    // Returns the result as written in PGN.
+    pub fn pgn_result(&self) -> &'static str {
+        match *self {
+            GameWinner::White => "1-0",
+            GameWinner::Black => "0-1",
+            GameWinner::Draw => "1/2-1/2",
+        }
+    }
+}
+
 impl From<GameState> for GameWinner {
     fn from(game_state: GameState) -> Self {
         match game_state {
@@ -498,9 +686,20 @@ impl<S: Search> MatchGames<S> {
         let mut match_result = MatchResult::with_names(&self.name_player1, &self.name_player2);
         let mut results_white = &mut match_result.result_player1;
         let mut results_black = &mut match_result.result_player2;
+        let mut name_white = &self.name_player1;
+        let mut name_black = &self.name_player2;
 
-        for _ in 0..self.num_games {
+        for round in 1..=self.num_games {
             let game_result = self.game.play()?;
+            match_result.pgn_games.push(
+                game_result
+                    .pgn_builder()
+                    .event("Match")
+                    .round(round)
+                    .white(name_white)
+                    .black(name_black)
+                    .build(),
+            );
             match game_result.winner {
                 GameWinner::Draw => {
                     results_white.record_white.draws += 1;
@@ -517,6 +716,7 @@ impl<S: Search> MatchGames<S> {
             };
             self.game.flip();
             swap(&mut results_white, &mut results_black);
+            swap(&mut name_white, &mut name_black);
         }
 
         Ok(match_result)
@@ -527,10 +727,12 @@ impl<S: Search> MatchGames<S> {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MatchResult {
     pub result_player1: MatchPlayerResult,
     pub result_player2: MatchPlayerResult,
+    // The games of the match in PGN, in the order they were played.
+    pub pgn_games: Vec<PgnGame>,
 }
 
 impl MatchResult {
@@ -538,6 +740,7 @@ impl MatchResult {
         Self {
             result_player1: MatchPlayerResult::new(name_player1),
             result_player2: MatchPlayerResult::new(name_player2),
+            pgn_games: Vec::new(),
         }
     }
 
@@ -554,7 +757,7 @@ impl MatchResult {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MatchPlayerResult {
     pub name_player: String,
     pub record_white: WinsRecord,
@@ -591,7 +794,7 @@ impl MatchPlayerResult {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
 pub struct WinsRecord {
     pub wins: u64,
     pub losses: u64,
@@ -705,7 +908,8 @@ impl<B: Backend> MatchGamesBuilder<B> {
             return Err(RukyErr::PreconditionErr);
         }
 
-        let encoder = AzEncoder::new(self.device.unwrap());
+        let shape = NetShape::for_variant(self.board.as_ref().unwrap().variant());
+        let encoder = AzEncoder::with_shape(self.device.unwrap(), shape);
         let decoder = AzDecoder::new();
 
         let eval_player1 = Arc::new(AzEval::create(
@@ -751,3 +955,144 @@ impl<B: Backend> MatchGamesBuilder<B> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::DrawReason;
+    use crate::ruky::Ruky;
+    use crate::variant::Variant;
+    use lazy_static::lazy_static;
+    use std::collections::VecDeque;
+
+    lazy_static! {
+        static ref RUKY: Ruky = Ruky::new();
+    }
+
+    // Returns a game from board with the moves in SAN, where the search result
+    // for the i-th move has 100 * (i + 1) nodes visited.
+    fn game_result(board: Board, sans: &[&str], winner: GameWinner) -> GameResult {
+        let mut moves = Vec::new();
+        let mut next = board.clone();
+        for (i, san) in sans.iter().enumerate() {
+            let prev = next.clone();
+            next.make_move(next.parse_san(san).unwrap());
+            let mut result = SearchResult::with_best(prev, next.clone());
+            result.value = 0.25 * i as f32;
+            result.best.visits = 10 * (i as u32 + 1);
+            result.nodes_visited = 100 * (i as u32 + 1);
+            result.total_search_time = Duration::from_millis(1500);
+            moves.push(result);
+        }
+        GameResult {
+            board,
+            moves,
+            winner,
+            total_tree_nodes: 0,
+        }
+    }
+
+    #[test]
+    fn pgn_after_mate() {
+        let game = game_result(
+            RUKY.new_board(),
+            &["f3", "e5", "g4", "Qh4#"],
+            GameWinner::Black,
+        );
+        assert!(game.is_terminated());
+        let pgn_game = game
+            .pgn_builder()
+            .event("Match")
+            .site("Lab")
+            .date("2025.01.31")
+            .round(2)
+            .white("net-a")
+            .black("net-b")
+            .build();
+        assert_eq!(
+            pgn_game.to_pgn().unwrap(),
+            "[Event \"Match\"]\n\
+             [Site \"Lab\"]\n\
+             [Date \"2025.01.31\"]\n\
+             [Round \"2\"]\n\
+             [White \"net-a\"]\n\
+             [Black \"net-b\"]\n\
+             [Result \"0-1\"]\n\
+             [Termination \"normal\"]\n\
+             \n\
+             1. f3 {value=0.000 visits=10 nodes=100 time=1.500s} 1... e5\n\
+             {value=0.250 visits=20 nodes=200 time=1.500s} 2. g4\n\
+             {value=0.500 visits=30 nodes=300 time=1.500s} 2... Qh4#\n\
+             {value=0.750 visits=40 nodes=400 time=1.500s} 0-1\n"
+        );
+    }
+
+    #[test]
+    fn pgn_after_max_moves() {
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        let game = game_result(
+            RUKY.from_variant_fen(Variant::KingOfTheHill, fen).unwrap(),
+            &["Rh7", "Kd8"],
+            GameWinner::Draw,
+        );
+        assert!(!game.is_terminated());
+        let pgn_game = game
+            .pgn_builder()
+            .date("2025.01.31")
+            .annotate(false)
+            .build();
+        assert_eq!(
+            pgn_game.to_pgn().unwrap(),
+            "[Event \"?\"]\n\
+             [Site \"?\"]\n\
+             [Date \"2025.01.31\"]\n\
+             [Round \"?\"]\n\
+             [White \"?\"]\n\
+             [Black \"?\"]\n\
+             [Result \"1/2-1/2\"]\n\
+             [Termination \"adjudication\"]\n\
+             [Variant \"King of the Hill\"]\n\
+             [SetUp \"1\"]\n\
+             [FEN \"4k3/8/8/8/8/8/8/4K2R w K - 0 1\"]\n\
+             \n\
+             1. Rh7 Kd8 1/2-1/2\n"
+        );
+
+        let board = RUKY.chess960_board(0).unwrap();
+        let fen = board.to_fen();
+        let pgn_game = game_result(board, &[], GameWinner::Draw)
+            .pgn_builder()
+            .build();
+        assert_eq!(pgn_game.tag("Variant"), Some("Chess960"));
+        assert_eq!(pgn_game.tag("SetUp"), Some("1"));
+        assert_eq!(pgn_game.tag("FEN"), Some(fen.as_str()));
+    }
+
+    // Plays the given moves in SAN, one per search.
+    #[derive(Debug)]
+    struct ScriptedSearch(VecDeque<&'static str>);
+
+    impl Search for ScriptedSearch {
+        fn search_board(&mut self, board: &Board) -> Result<SearchResult, RukyErr> {
+            let san = self.0.pop_front().ok_or(RukyErr::PreconditionErr)?;
+            let mut next = board.clone();
+            next.make_move(board.parse_san(san).unwrap());
+            Ok(SearchResult::with_best(board.clone(), next))
+        }
+    }
+
+    #[test]
+    fn game_ends_with_threefold_repetition() {
+        let white = ScriptedSearch(["Nf3", "Ng1"].repeat(3).into());
+        let black = ScriptedSearch(["Nf6", "Ng8"].repeat(3).into());
+        let mut game = Game::create(RUKY.new_board(), Box::new(white), Box::new(black), 20);
+        let game_result = game.play().unwrap();
+        assert_eq!(game_result.moves.len(), 8);
+        assert_eq!(game_result.winner, GameWinner::Draw);
+        assert!(game_result.is_terminated());
+        assert_eq!(
+            game_result.moves[7].best_board().game_state(),
+            GameState::Draw(DrawReason::Repetition)
+        );
+    }
+}
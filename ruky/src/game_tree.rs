@@ -0,0 +1,544 @@
+// This module contains a GameTree, which is a record of a game for analysis,
+// where every position can have any number of alternative moves. The first
+// child of a node continues the main line, and the other children are
+// variations. The tree keeps a current node, which is where moves are added,
+// taken back and replayed, and it can be read from and written to PGN.
+
+use crate::board::{Board, Undo};
+use crate::pgn::{PgnGame, PgnMove};
+use crate::piece::Piece;
+use crate::piece_move::PieceMove;
+use crate::san::SanErr;
+use std::fmt::{Display, Formatter};
+
+// The index of the root node, i.e. the starting position.
+pub const ROOT: usize = 0;
+
+// A position in the tree, identified by its index in the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameNode {
+    // The move that leads to this node, which is only None for the root.
+    piece_move: Option<Piece<PieceMove>>,
+    parent: Option<usize>,
+    // The nodes after this one, where the first child is the main line.
+    children: Vec<usize>,
+    // Numeric annotation glyphs for the move.
+    pub nags: Vec<u8>,
+    // The comments that follow the move, or that come before the first move
+    // for the root.
+    pub comments: Vec<String>,
+    // The comments that come before the move, which are written for the first
+    // move of a variation.
+    pub pre_comments: Vec<String>,
+}
+
+impl GameNode {
+    fn new(piece_move: Option<Piece<PieceMove>>, parent: Option<usize>) -> Self {
+        Self {
+            piece_move,
+            parent,
+            children: Vec::new(),
+            nags: Vec::new(),
+            comments: Vec::new(),
+            pre_comments: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn piece_move(&self) -> Option<Piece<PieceMove>> {
+        self.piece_move
+    }
+
+    #[inline]
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    #[inline]
+    pub fn children(&self) -> &[usize] {
+        &self.children
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GameTree {
+    // The tag pairs that are written to PGN.
+    pub tags: Vec<(String, String)>,
+    // The game result, i.e. 1-0, 0-1, 1/2-1/2 or *.
+    pub result: String,
+    // The position at the root.
+    start: Board,
+    nodes: Vec<GameNode>,
+    current: usize,
+    // The position at the current node, and the undos for the moves from the
+    // root to the current node.
+    board: Board,
+    undos: Vec<Undo>,
+    // The nodes that were taken back with undo, most recent last.
+    redos: Vec<usize>,
+}
+
+impl GameTree {
+    // Creates a tree without moves starting from board.
+    pub fn new(board: Board) -> Self {
+        Self {
+            tags: Vec::new(),
+            result: "*".to_string(),
+            start: board.clone(),
+            nodes: vec![GameNode::new(None, None)],
+            current: ROOT,
+            board,
+            undos: Vec::new(),
+            redos: Vec::new(),
+        }
+    }
+
+    // Creates a tree from a game read from PGN, with the variations as
+    // sidelines, e.g. when read with PgnReader::keep_variations. The current
+    // node is the root. Returns an error if any move is not legal.
+    pub fn from_pgn(game: &PgnGame) -> Result<Self, GameTreeErr> {
+        let mut tree = GameTree::new(game.board.clone());
+        tree.tags = game.tags.clone();
+        tree.result = game.result.clone();
+        tree.nodes[ROOT].comments = game.comments.clone();
+        tree.add_line(ROOT, &game.board, &game.moves)?;
+        Ok(tree)
+    }
+
+    // Returns the game as PGN, where the sidelines are written as variations.
+    pub fn to_pgn(&self) -> PgnGame {
+        let mut game = PgnGame::new(self.start.clone());
+        game.tags = self.tags.clone();
+        game.result = self.result.clone();
+        game.comments = self.nodes[ROOT].comments.clone();
+        if let Some(first) = self.nodes[ROOT].children.first() {
+            game.moves = self.line(*first);
+        }
+        game
+    }
+
+    // Returns the position at the root.
+    #[inline]
+    pub fn start_board(&self) -> &Board {
+        &self.start
+    }
+
+    // Returns the position at the current node.
+    #[inline]
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    #[inline]
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Always false, since the tree has at least the root.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, node: usize) -> Option<&GameNode> {
+        self.nodes.get(node)
+    }
+
+    // Returns a node to change its comments and NAGs.
+    pub fn node_mut(&mut self, node: usize) -> Option<&mut GameNode> {
+        self.nodes.get_mut(node)
+    }
+
+    // Returns the nodes from the first move to node, i.e. without the root.
+    pub fn path(&self, node: usize) -> Result<Vec<usize>, GameTreeErr> {
+        let mut path = Vec::new();
+        let mut next = Some(self.check_node(node)?);
+        while let Some(node) = next.filter(|node| *node != ROOT) {
+            path.push(node);
+            next = self.nodes[node].parent;
+        }
+        path.reverse();
+        Ok(path)
+    }
+
+    // Returns the moves from the root to node.
+    pub fn moves_to(&self, node: usize) -> Result<Vec<Piece<PieceMove>>, GameTreeErr> {
+        Ok(self
+            .path(node)?
+            .into_iter()
+            .filter_map(|node| self.nodes[node].piece_move)
+            .collect())
+    }
+
+    // Returns the nodes of the main line, without the root.
+    pub fn main_line(&self) -> Vec<usize> {
+        let mut line = Vec::new();
+        let mut node = ROOT;
+        while let Some(child) = self.nodes[node].children.first() {
+            line.push(*child);
+            node = *child;
+        }
+        line
+    }
+
+    // Returns true if node is on the main line.
+    pub fn is_main_line(&self, node: usize) -> Result<bool, GameTreeErr> {
+        Ok(self
+            .path(node)?
+            .into_iter()
+            .all(|node| self.is_first_child(node)))
+    }
+
+    // Returns the position at node.
+    pub fn board_at(&self, node: usize) -> Result<Board, GameTreeErr> {
+        let mut board = self.start.clone();
+        for piece_move in self.moves_to(node)? {
+            board.make_move(piece_move);
+        }
+        Ok(board)
+    }
+
+    // Plays piece_move from the current node, and makes the resulting node the
+    // current node. If the move was already played from the current node, the
+    // existing node is used, and otherwise the move is added as the main line
+    // if the current node has no children, or else as a variation. Returns the
+    // node of the move, or an error if the move is not legal.
+    pub fn add_move(&mut self, piece_move: Piece<PieceMove>) -> Result<usize, GameTreeErr> {
+        if !self.board.is_legal_move(piece_move) {
+            return Err(GameTreeErr::IllegalMove(format!("{:?}", piece_move)));
+        }
+        let existing = self.nodes[self.current]
+            .children
+            .iter()
+            .find(|child| self.nodes[**child].piece_move == Some(piece_move))
+            .copied();
+        let node = match existing {
+            Some(node) => node,
+            None => self.push_node(self.current, piece_move),
+        };
+        self.redos.clear();
+        self.step(node);
+        Ok(node)
+    }
+
+    // Same as add_move for a move in SAN, e.g. Nf3.
+    pub fn add_san(&mut self, san: &str) -> Result<usize, GameTreeErr> {
+        let piece_move = self.board.parse_san(san)?;
+        self.add_move(piece_move)
+    }
+
+    // Takes back the move of the current node, so that its parent becomes the
+    // current node. Returns false at the root.
+    pub fn undo(&mut self) -> bool {
+        let parent = match self.nodes[self.current].parent {
+            Some(parent) => parent,
+            None => return false,
+        };
+        let undo = self
+            .undos
+            .pop()
+            .expect("There should be an undo for every move from the root.");
+        self.board.unmake_move(undo);
+        self.redos.push(self.current);
+        self.current = parent;
+        true
+    }
+
+    // Replays the last move that was taken back with undo, or if there is none,
+    // the main line move from the current node. Returns false if there is no
+    // move to replay.
+    pub fn redo(&mut self) -> bool {
+        let node = match self.redos.pop() {
+            Some(node) => node,
+            None => match self.nodes[self.current].children.first() {
+                Some(child) => *child,
+                None => return false,
+            },
+        };
+        self.step(node);
+        true
+    }
+
+    // Makes node the current node.
+    pub fn goto(&mut self, node: usize) -> Result<(), GameTreeErr> {
+        let path = self.path(node)?;
+        self.board = self.start.clone();
+        self.undos.clear();
+        self.redos.clear();
+        self.current = ROOT;
+        for node in path {
+            self.step(node);
+        }
+        Ok(())
+    }
+
+    // Moves the variation that starts at node one place up among its siblings,
+    // where the first place is the main line. Does nothing if node is already
+    // first.
+    pub fn promote_variation(&mut self, node: usize) -> Result<(), GameTreeErr> {
+        let parent = self.parent_of(node)?;
+        let siblings = &mut self.nodes[parent].children;
+        let index = siblings
+            .iter()
+            .position(|child| *child == node)
+            .expect("A node should be a child of its parent.");
+        if index > 0 {
+            siblings.swap(index - 1, index);
+        }
+        Ok(())
+    }
+
+    // Makes the line through node the main line, i.e. node and all of its
+    // ancestors become the first child of their parents.
+    pub fn make_main_line(&mut self, node: usize) -> Result<(), GameTreeErr> {
+        for node in self.path(node)? {
+            let parent = self.parent_of(node)?;
+            let siblings = &mut self.nodes[parent].children;
+            siblings.retain(|child| *child != node);
+            siblings.insert(0, node);
+        }
+        Ok(())
+    }
+
+    // Adds the moves of a line from PGN after parent, where board is the
+    // position at parent.
+    fn add_line(
+        &mut self,
+        parent: usize,
+        board: &Board,
+        moves: &[PgnMove],
+    ) -> Result<(), GameTreeErr> {
+        let mut parent = parent;
+        let mut board = board.clone();
+        for pgn_move in moves.iter() {
+            if !board.is_legal_move(pgn_move.piece_move) {
+                return Err(GameTreeErr::IllegalMove(format!(
+                    "{:?}",
+                    pgn_move.piece_move
+                )));
+            }
+            let node = self.push_node(parent, pgn_move.piece_move);
+            self.nodes[node].nags = pgn_move.nags.clone();
+            self.nodes[node].comments = pgn_move.comments.clone();
+            self.nodes[node].pre_comments = pgn_move.pre_comments.clone();
+            for variation in pgn_move.variations.iter() {
+                self.add_line(parent, &board, variation)?;
+            }
+            board.make_move(pgn_move.piece_move);
+            parent = node;
+        }
+        Ok(())
+    }
+
+    // Returns the moves for PGN of the line that starts at node, with the
+    // variations of the moves that are the first child of their parent.
+    fn line(&self, node: usize) -> Vec<PgnMove> {
+        let mut moves = Vec::new();
+        let mut next = Some(node);
+        while let Some(node) = next {
+            let game_node = &self.nodes[node];
+            let mut pgn_move = PgnMove::new(
+                game_node
+                    .piece_move
+                    .expect("Only the root should be without a move."),
+            );
+            pgn_move.nags = game_node.nags.clone();
+            pgn_move.comments = game_node.comments.clone();
+            pgn_move.pre_comments = game_node.pre_comments.clone();
+            if self.is_first_child(node) {
+                let parent = game_node.parent.unwrap_or(ROOT);
+                pgn_move.variations = self.nodes[parent].children[1..]
+                    .iter()
+                    .map(|sibling| self.line(*sibling))
+                    .collect();
+            }
+            moves.push(pgn_move);
+            next = game_node.children.first().copied();
+        }
+        moves
+    }
+
+    fn push_node(&mut self, parent: usize, piece_move: Piece<PieceMove>) -> usize {
+        let node = self.nodes.len();
+        self.nodes
+            .push(GameNode::new(Some(piece_move), Some(parent)));
+        self.nodes[parent].children.push(node);
+        node
+    }
+
+    // Plays the move of node, which must be a child of the current node.
+    fn step(&mut self, node: usize) {
+        let piece_move = self.nodes[node]
+            .piece_move
+            .expect("Only the root should be without a move.");
+        self.undos.push(self.board.make_move(piece_move));
+        self.current = node;
+    }
+
+    fn is_first_child(&self, node: usize) -> bool {
+        match self.nodes[node].parent {
+            Some(parent) => self.nodes[parent].children.first() == Some(&node),
+            None => true,
+        }
+    }
+
+    fn check_node(&self, node: usize) -> Result<usize, GameTreeErr> {
+        match node < self.nodes.len() {
+            true => Ok(node),
+            false => Err(GameTreeErr::NoNode(node)),
+        }
+    }
+
+    // Returns the parent of node, or an error for the root.
+    fn parent_of(&self, node: usize) -> Result<usize, GameTreeErr> {
+        self.nodes[self.check_node(node)?]
+            .parent
+            .ok_or(GameTreeErr::NoNode(node))
+    }
+}
+
+// Writes the tree as PGN.
+impl Display for GameTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_pgn())
+    }
+}
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum GameTreeErr {
+    #[error("node {0} does not exist")]
+    NoNode(usize),
+    #[error("move {0} is not legal")]
+    IllegalMove(String),
+    #[error(transparent)]
+    San(#[from] SanErr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::PgnReader;
+    use crate::ruky::Ruky;
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        static ref RUKY: Ruky = Ruky::new();
+    }
+
+    // Returns a tree with the main line 1. e4 e5 2. Nf3 and the variations
+    // 1... c5 and 2. Bc4.
+    fn tree() -> GameTree {
+        let mut tree = GameTree::new(RUKY.new_board());
+        for san in ["e4", "e5", "Nf3"] {
+            tree.add_san(san).unwrap();
+        }
+        tree.undo();
+        tree.add_san("Bc4").unwrap();
+        tree.goto(1).unwrap();
+        tree.add_san("c5").unwrap();
+        tree
+    }
+
+    #[test]
+    fn add_moves_and_variations() {
+        let tree = tree();
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.main_line(), vec![1, 2, 3]);
+        assert_eq!(tree.node(1).unwrap().children(), &[2, 5]);
+        assert_eq!(tree.node(2).unwrap().children(), &[3, 4]);
+        assert_eq!(tree.current(), 5);
+        assert_eq!(
+            tree.board().to_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2"
+        );
+        assert_eq!(tree.path(4), Ok(vec![1, 2, 4]));
+        assert_eq!(tree.is_main_line(3), Ok(true));
+        assert_eq!(tree.is_main_line(4), Ok(false));
+        assert_eq!(tree.path(6), Err(GameTreeErr::NoNode(6)));
+
+        // Playing a move that is already in the tree goes to its node.
+        let mut tree = tree.clone();
+        tree.goto(ROOT).unwrap();
+        assert_eq!(tree.add_san("e4"), Ok(1));
+        assert_eq!(tree.len(), 6);
+        assert!(matches!(tree.add_san("Ke2"), Err(GameTreeErr::San(_))));
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut tree = tree();
+        tree.goto(3).unwrap();
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert_eq!(tree.current(), 1);
+        assert_eq!(tree.board(), &tree.board_at(1).unwrap());
+        assert!(tree.redo());
+        assert!(tree.redo());
+        assert_eq!(tree.current(), 3);
+        assert!(!tree.redo());
+
+        // Without undone moves, redo follows the main line.
+        tree.goto(ROOT).unwrap();
+        assert!(!tree.undo());
+        assert!(tree.redo());
+        assert_eq!(tree.current(), 1);
+        tree.goto(4).unwrap();
+        assert_eq!(tree.board(), &tree.board_at(4).unwrap());
+        assert_eq!(tree.board().to_fen(), tree.board_at(4).unwrap().to_fen());
+    }
+
+    #[test]
+    fn promote_variations() {
+        let mut tree = tree();
+        tree.promote_variation(4).unwrap();
+        assert_eq!(tree.main_line(), vec![1, 2, 4]);
+        tree.promote_variation(4).unwrap();
+        assert_eq!(tree.node(2).unwrap().children(), &[4, 3]);
+
+        tree.make_main_line(5).unwrap();
+        assert_eq!(tree.main_line(), vec![1, 5]);
+        assert_eq!(tree.node(1).unwrap().children(), &[5, 2]);
+        assert_eq!(tree.promote_variation(ROOT), Err(GameTreeErr::NoNode(ROOT)));
+    }
+
+    #[test]
+    fn pgn_round_trip() {
+        let mut tree = tree();
+        tree.tags
+            .push(("Event".to_string(), "Analysis".to_string()));
+        tree.node_mut(ROOT)
+            .unwrap()
+            .comments
+            .push("start".to_string());
+        tree.node_mut(4).unwrap().nags.push(1);
+        tree.node_mut(5)
+            .unwrap()
+            .comments
+            .push("Sicilian".to_string());
+        tree.node_mut(5)
+            .unwrap()
+            .pre_comments
+            .push("or".to_string());
+        let pgn = tree.to_string();
+        assert_eq!(
+            pgn,
+            "[Event \"Analysis\"]\n\n{start} 1. e4 e5 ({or} 1... c5 {Sicilian}) 2. Nf3 (2. Bc4 $1) *\n"
+        );
+
+        let games = PgnReader::new(RUKY.clone())
+            .keep_variations(true)
+            .read_all(&pgn)
+            .unwrap();
+        let read = GameTree::from_pgn(&games[0]).unwrap();
+        assert_eq!(read.current(), ROOT);
+        assert_eq!(read.len(), tree.len());
+        assert_eq!(read.to_string(), pgn);
+        assert_eq!(read.node(3).unwrap().comments, vec!["Sicilian".to_string()]);
+        assert_eq!(read.node(3).unwrap().pre_comments, vec!["or".to_string()]);
+    }
+}
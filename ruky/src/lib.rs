@@ -2,30 +2,43 @@
 
 pub mod bitboard;
 pub mod board;
+pub mod chess960;
 pub mod dataset;
 mod ecmv;
+pub mod epd;
 pub mod err;
 pub mod eval;
 mod fen;
 pub mod game;
+pub mod game_tree;
 pub mod magics;
 pub mod mcts;
 pub mod mt_mcts;
 pub mod nn;
+pub mod packed;
+pub mod perft;
+pub mod pgn;
 mod piece;
 mod piece_move;
 mod piece_set;
+mod pocket;
 pub mod random_eng;
 pub mod random_search;
+pub mod render;
 pub mod ruky;
+mod san;
 pub mod search;
 mod sq;
+pub mod syzygy;
 pub mod tensor_decoder;
 pub mod tensor_encoder;
 pub mod trainer;
 pub mod tree_search;
+pub mod variant;
+pub mod zobrist;
 
 pub use board::{Board, BoardBuilder};
 pub use piece::Piece;
 pub use piece_move::PieceMove;
+pub use pocket::Pocket;
 pub use ruky::Ruky;
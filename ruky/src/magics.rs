@@ -1,44 +1,133 @@
 use crate::bitboard::BitBoard;
 use crate::sq::Sq;
+use crate::zobrist::splitmix64;
 use rand::RngCore;
+use std::iter::zip;
 use std::ops::Fn;
+use std::sync::{Arc, OnceLock};
 
-// A wrapper around bishop and rook magics to simplify using magics.
+// A wrapper around bishop and rook attack lookups to simplify using magics.
+// The lookups use either magic multiplication, with one table per square or
+// with fixed-shift magics that share a table, or, on x86_64 CPUs with BMI2,
+// the pext instruction, which gives the same attacks.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct ChessMagics {
-    rook: MagicAttacks,
-    bishop: MagicAttacks,
+    sliders: Sliders,
+}
+
+// The backends for slider attack lookups.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Sliders {
+    Magic {
+        rook: MagicAttacks,
+        bishop: MagicAttacks,
+    },
+    Pext {
+        rook: PextAttacks,
+        bishop: PextAttacks,
+    },
+    Black {
+        rook: BlackMagicAttacks,
+        bishop: BlackMagicAttacks,
+    },
 }
 
 impl ChessMagics {
+    // Creates the lookups from the precomputed magics. If the pext feature is
+    // enabled and the CPU supports BMI2, then pext lookups are used instead.
     pub fn from_precomputed() -> Result<Self, MagicErr> {
+        if cfg!(feature = "pext") && is_pext_supported() {
+            return Self::with_pext();
+        }
+        Self::with_magics()
+    }
+
+    // Creates the lookups from the precomputed magics.
+    pub fn with_magics() -> Result<Self, MagicErr> {
+        Ok(Self {
+            sliders: Sliders::Magic {
+                rook: from_rmagics(RMAGICS.into_iter())?,
+                bishop: from_bmagics(BMAGICS.into_iter())?,
+            },
+        })
+    }
+
+    // Creates the lookups using pext, or returns MagicErr::Unsupported if the
+    // CPU does not support BMI2.
+    pub fn with_pext() -> Result<Self, MagicErr> {
         Ok(Self {
-            rook: from_rmagics(RMAGICS.into_iter())?,
-            bishop: from_bmagics(BMAGICS.into_iter())?,
+            sliders: Sliders::Pext {
+                rook: pext_rattacks()?,
+                bishop: pext_battacks()?,
+            },
         })
     }
 
+    // Creates the lookups from the given rook and bishop magics, e.g. the magics
+    // loaded from a module generated by gen_magic.
+    pub fn from_magic_attacks(rook: MagicAttacks, bishop: MagicAttacks) -> Self {
+        Self {
+            sliders: Sliders::Magic { rook, bishop },
+        }
+    }
+
+    // Creates the lookups from the given fixed-shift rook and bishop magics,
+    // e.g. the magics loaded from a module generated by gen_magic --black.
+    pub fn from_black_magics(rook: BlackMagicAttacks, bishop: BlackMagicAttacks) -> Self {
+        Self {
+            sliders: Sliders::Black { rook, bishop },
+        }
+    }
+
+    // Returns true if the lookups use pext.
+    pub fn is_pext(&self) -> bool {
+        matches!(self.sliders, Sliders::Pext { .. })
+    }
+
     pub fn qmagics(&self, sq: Sq, blockers: BitBoard) -> Option<BitBoard> {
-        let rm = self.rook.attacks(sq, blockers)?;
-        let bm = self.bishop.attacks(sq, blockers)?;
+        let rm = self.rmagics(sq, blockers)?;
+        let bm = self.bmagics(sq, blockers)?;
         Some(rm | bm)
     }
 
     pub fn rmagics(&self, sq: Sq, blockers: BitBoard) -> Option<BitBoard> {
-        self.rook.attacks(sq, blockers)
+        match self.sliders {
+            Sliders::Magic { ref rook, .. } => rook.attacks(sq, blockers),
+            Sliders::Pext { ref rook, .. } => rook.attacks(sq, blockers),
+            Sliders::Black { ref rook, .. } => rook.attacks(sq, blockers),
+        }
     }
 
     pub fn bmagics(&self, sq: Sq, blockers: BitBoard) -> Option<BitBoard> {
-        self.bishop.attacks(sq, blockers)
+        match self.sliders {
+            Sliders::Magic { ref bishop, .. } => bishop.attacks(sq, blockers),
+            Sliders::Pext { ref bishop, .. } => bishop.attacks(sq, blockers),
+            Sliders::Black { ref bishop, .. } => bishop.attacks(sq, blockers),
+        }
     }
 }
 
+// Returns the precomputed ChessMagics shared by the whole process, e.g. for
+// boards that are deserialized without a Ruky to take the magics from.
+pub fn shared_magics() -> Arc<ChessMagics> {
+    static MAGICS: OnceLock<Arc<ChessMagics>> = OnceLock::new();
+    MAGICS
+        .get_or_init(|| {
+            Arc::new(
+                ChessMagics::from_precomputed().expect("Unable to create precomputed ChessMagics."),
+            )
+        })
+        .clone()
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum MagicErr {
     InvalidSquare,
     NumBits,
     NumMagic,
     NotFound,
+    Unsupported,
+    Mismatch,
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -88,6 +177,171 @@ impl AsRef<[Magic]> for MagicAttacks {
     }
 }
 
+// Attack lookups that index the attacks for each square by the blockers in the
+// mask of the square, extracted with pext. Unlike magics, the index is dense,
+// so there is no need to search for magic numbers.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PextAttacks {
+    // The mask for each square.
+    masks: Vec<BitBoard>,
+    // The offset of the attacks for each square in attacks.
+    offsets: Vec<usize>,
+    attacks: Vec<BitBoard>,
+}
+
+impl PextAttacks {
+    #[inline]
+    pub fn attacks(&self, sq: Sq, blockers: BitBoard) -> Option<BitBoard> {
+        let mask = self.masks.get(usize::from(sq))?;
+        let index = pext(blockers.u64(), mask.u64()) as usize;
+        self.attacks
+            .get(self.offsets[usize::from(sq)] + index)
+            .copied()
+    }
+}
+
+// The shifts of fixed-shift magics, which give 9 index bits for bishops and
+// 12 for rooks, the most bits of any square's mask.
+pub const BLACK_BSHIFT: u8 = 64 - 9;
+pub const BLACK_RSHIFT: u8 = 64 - 12;
+
+// Attack lookups with fixed-shift ("black") magics. The index for every square
+// is ((blockers | !mask) * magic) >> shift with the same shift for all
+// squares. Setting the bits outside the mask lets a magic map the blockers to
+// a narrow range of indices, and the tables of all squares share one array in
+// which they may overlap, so the tables are smaller than one table per square
+// with the fixed number of bits. Only long searches give tables smaller than
+// those of search_bmagics and search_rmagics.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BlackMagicAttacks {
+    magics: Vec<u64>,
+    masks: Vec<BitBoard>,
+    // The position of index 0 of each square in attacks, which may be negative
+    // since the smallest index of a square is usually not 0.
+    offsets: Vec<isize>,
+    rshift: u8,
+    attacks: Vec<BitBoard>,
+}
+
+impl BlackMagicAttacks {
+    #[inline]
+    pub fn attacks(&self, sq: Sq, blockers: BitBoard) -> Option<BitBoard> {
+        let s = usize::from(sq);
+        let mask = *self.masks.get(s)?;
+        let index = get_magic_hash(blockers | !mask, self.magics[s], self.rshift);
+        let pos = usize::try_from(self.offsets[s] + index as isize).ok()?;
+        self.attacks.get(pos).copied()
+    }
+
+    pub fn magics(&self) -> &[u64] {
+        &self.magics
+    }
+
+    pub fn offsets(&self) -> &[isize] {
+        &self.offsets
+    }
+
+    // Returns the attack table shared by all squares.
+    pub fn table(&self) -> &[BitBoard] {
+        &self.attacks
+    }
+
+    // Places the table of a square at the first position in attacks where it
+    // agrees with the tables already there, and returns the position.
+    fn place(&mut self, table: &[BitBoard]) -> usize {
+        let attacks = &self.attacks;
+        let fits = |pos: usize| {
+            zip(pos.., table)
+                .all(|(i, t)| t.none() || attacks.get(i).is_none_or(|a| a.none() || a == t))
+        };
+        let pos = (0..=attacks.len()).find(|pos| fits(*pos)).unwrap();
+        if self.attacks.len() < pos + table.len() {
+            self.attacks.resize(pos + table.len(), BitBoard::new());
+        }
+        for (i, t) in zip(pos.., table) {
+            if !t.none() {
+                self.attacks[i] = *t;
+            }
+        }
+        pos
+    }
+}
+
+pub fn pext_battacks() -> Result<PextAttacks, MagicErr> {
+    compute_pext_attacks(&get_full_bmask, &get_battacks)
+}
+
+pub fn pext_rattacks() -> Result<PextAttacks, MagicErr> {
+    compute_pext_attacks(&get_full_rmask, &get_rattacks)
+}
+
+// Computes the pext attacks for all squares. The blockers with index i in the
+// mask are given by permute_mask, which is the inverse of pext.
+fn compute_pext_attacks(
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    attacks_fn: &impl Fn(Sq, BitBoard) -> BitBoard,
+) -> Result<PextAttacks, MagicErr> {
+    if !is_pext_supported() {
+        return Err(MagicErr::Unsupported);
+    }
+    Ok(build_pext_attacks(mask_fn, attacks_fn))
+}
+
+// Builds the pext attack tables, which does not need the pext instruction.
+fn build_pext_attacks(
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    attacks_fn: &impl Fn(Sq, BitBoard) -> BitBoard,
+) -> PextAttacks {
+    let mut masks = Vec::with_capacity(64);
+    let mut offsets = Vec::with_capacity(64);
+    let mut attacks = Vec::new();
+    for s in 0u8..64 {
+        let sq = Sq::from(s);
+        let mask = mask_fn(sq);
+        offsets.push(attacks.len());
+        for i in 0..1u64 << mask.count() {
+            attacks.push(attacks_fn(sq, permute_mask(BitBoard::from(i), mask)));
+        }
+        masks.push(mask);
+    }
+    PextAttacks {
+        masks,
+        offsets,
+        attacks,
+    }
+}
+
+// Returns true if the CPU supports the pext instruction.
+pub fn is_pext_supported() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        std::arch::is_x86_feature_detected!("bmi2")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+fn pext(bits: u64, mask: u64) -> u64 {
+    // Safety: PextAttacks are only created if the CPU supports BMI2.
+    unsafe { bmi2_pext(bits, mask) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+unsafe fn bmi2_pext(bits: u64, mask: u64) -> u64 {
+    std::arch::x86_64::_pext_u64(bits, mask)
+}
+
+// PextAttacks are never created on other architectures.
+#[cfg(not(target_arch = "x86_64"))]
+fn pext(_bits: u64, _mask: u64) -> u64 {
+    unreachable!("pext is only supported on x86_64")
+}
+
 pub fn from_bmagics(mut magics: impl Iterator<Item = u64>) -> Result<MagicAttacks, MagicErr> {
     if magics.size_hint().0 != 64 {
         return Err(MagicErr::NumMagic);
@@ -112,6 +366,106 @@ pub fn compute_rmagics() -> Result<MagicAttacks, MagicErr> {
     find_all_magics(&get_full_rmask, &get_rattacks, rand_iter.by_ref())
 }
 
+pub fn search_bmagics(
+    seed: u64,
+    reduce_bits: u8,
+    attempts: usize,
+) -> Result<MagicAttacks, MagicErr> {
+    search_magics(&get_full_bmask, &get_battacks, seed, reduce_bits, attempts)
+}
+
+pub fn search_rmagics(
+    seed: u64,
+    reduce_bits: u8,
+    attempts: usize,
+) -> Result<MagicAttacks, MagicErr> {
+    search_magics(&get_full_rmask, &get_rattacks, seed, reduce_bits, attempts)
+}
+
+// Loads bishop magics with the given (magic, rshift) pairs, e.g. magics found
+// by search_bmagics, which may use fewer index bits than the mask has.
+pub fn from_bmagics_with_shifts(
+    magics: impl Iterator<Item = (u64, u8)>,
+) -> Result<MagicAttacks, MagicErr> {
+    from_magics_with_shifts(&get_full_bmask, &get_battacks, magics)
+}
+
+pub fn from_rmagics_with_shifts(
+    magics: impl Iterator<Item = (u64, u8)>,
+) -> Result<MagicAttacks, MagicErr> {
+    from_magics_with_shifts(&get_full_rmask, &get_rattacks, magics)
+}
+
+// Loads bishop magics from precomputed attack tables, where the attacks for
+// square i start at offsets[i] in attacks. Only the sizes of the tables are
+// checked, since gen_magic verifies every entry before writing them, so use
+// verify_bmagics for tables from elsewhere.
+pub fn from_btables(
+    magics: &[u64],
+    shifts: &[u8],
+    offsets: &[usize],
+    attacks: &[u64],
+) -> Result<MagicAttacks, MagicErr> {
+    from_tables(&get_full_bmask, magics, shifts, offsets, attacks)
+}
+
+pub fn from_rtables(
+    magics: &[u64],
+    shifts: &[u8],
+    offsets: &[usize],
+    attacks: &[u64],
+) -> Result<MagicAttacks, MagicErr> {
+    from_tables(&get_full_rmask, magics, shifts, offsets, attacks)
+}
+
+// Searches for fixed-shift bishop magics from a seeded random number
+// generator. For each square, the search tries attempts magics, or more if
+// none of them works, and keeps the one with the narrowest range of indices.
+pub fn search_black_bmagics(seed: u64, attempts: usize) -> Result<BlackMagicAttacks, MagicErr> {
+    search_black_magics(&get_full_bmask, &get_battacks, BLACK_BSHIFT, seed, attempts)
+}
+
+pub fn search_black_rmagics(seed: u64, attempts: usize) -> Result<BlackMagicAttacks, MagicErr> {
+    search_black_magics(&get_full_rmask, &get_rattacks, BLACK_RSHIFT, seed, attempts)
+}
+
+// Loads fixed-shift bishop magics from a precomputed shared attack table,
+// where index 0 of square i is at offsets[i] in attacks. Like from_btables,
+// the entries are not verified.
+pub fn from_black_btables(
+    magics: &[u64],
+    offsets: &[isize],
+    attacks: &[u64],
+) -> Result<BlackMagicAttacks, MagicErr> {
+    from_black_tables(&get_full_bmask, BLACK_BSHIFT, magics, offsets, attacks)
+}
+
+pub fn from_black_rtables(
+    magics: &[u64],
+    offsets: &[isize],
+    attacks: &[u64],
+) -> Result<BlackMagicAttacks, MagicErr> {
+    from_black_tables(&get_full_rmask, BLACK_RSHIFT, magics, offsets, attacks)
+}
+
+// Checks that the bishop magics give the attacks of get_battacks for every
+// square and every set of blockers in the mask of the square.
+pub fn verify_bmagics(magics: &impl Magics) -> Result<(), MagicErr> {
+    verify_magics(magics, &get_full_bmask, &get_battacks)
+}
+
+pub fn verify_rmagics(magics: &impl Magics) -> Result<(), MagicErr> {
+    verify_magics(magics, &get_full_rmask, &get_rattacks)
+}
+
+pub fn verify_black_bmagics(magics: &BlackMagicAttacks) -> Result<(), MagicErr> {
+    verify_black_magics(magics, &get_full_bmask, &get_battacks)
+}
+
+pub fn verify_black_rmagics(magics: &BlackMagicAttacks) -> Result<(), MagicErr> {
+    verify_black_magics(magics, &get_full_rmask, &get_rattacks)
+}
+
 // Computes magics for all squares.
 fn find_all_magics(
     mask_fn: &impl Fn(Sq) -> BitBoard,
@@ -120,12 +474,266 @@ fn find_all_magics(
 ) -> Result<MagicAttacks, MagicErr> {
     let mut magics = Vec::<Magic>::with_capacity(64);
     for s in 0u8..64 {
-        let magic = find_magic(s.into(), mask_fn, attacks_fn, magic_iter)?;
+        let sq = Sq::from(s);
+        let index_bits = mask_fn(sq).count();
+        let magic = find_magic(sq, mask_fn, attacks_fn, index_bits, magic_iter)?;
         magics.push(magic);
     }
     Ok(MagicAttacks { magics })
 }
 
+// Computes magics for all squares from a seeded random number generator. For
+// each square, the search first tries up to attempts magics that use
+// reduce_bits fewer index bits than the mask has, which halves the size of the
+// table for every bit, and falls back to the full number of bits.
+fn search_magics(
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    attacks_fn: &impl Fn(Sq, BitBoard) -> BitBoard,
+    seed: u64,
+    reduce_bits: u8,
+    attempts: usize,
+) -> Result<MagicAttacks, MagicErr> {
+    let mut rand_iter = create_seeded_rand_iter(seed);
+    let mut magics = Vec::<Magic>::with_capacity(64);
+    for s in 0u8..64 {
+        let sq = Sq::from(s);
+        let num_bits = mask_fn(sq).count();
+        let reduced = if reduce_bits > 0 && num_bits > reduce_bits {
+            let mut candidates = rand_iter.by_ref().take(attempts);
+            find_magic(
+                sq,
+                mask_fn,
+                attacks_fn,
+                num_bits - reduce_bits,
+                &mut candidates,
+            )
+            .ok()
+        } else {
+            None
+        };
+        let magic = match reduced {
+            Some(magic) => magic,
+            None => find_magic(sq, mask_fn, attacks_fn, num_bits, &mut rand_iter)?,
+        };
+        magics.push(magic);
+    }
+    Ok(MagicAttacks { magics })
+}
+
+fn from_magics_with_shifts(
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    attacks_fn: &impl Fn(Sq, BitBoard) -> BitBoard,
+    magics: impl Iterator<Item = (u64, u8)>,
+) -> Result<MagicAttacks, MagicErr> {
+    let mut found = Vec::<Magic>::with_capacity(64);
+    for (s, (magic, rshift)) in magics.enumerate() {
+        if s >= 64 {
+            return Err(MagicErr::NumMagic);
+        }
+        let index_bits = 64u8.checked_sub(rshift).ok_or(MagicErr::NumBits)?;
+        let magic = find_magic(
+            Sq::from(s),
+            mask_fn,
+            attacks_fn,
+            index_bits,
+            &mut std::iter::once(magic),
+        )?;
+        found.push(magic);
+    }
+    if found.len() != 64 {
+        return Err(MagicErr::NumMagic);
+    }
+    Ok(MagicAttacks { magics: found })
+}
+
+fn from_tables(
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    magics: &[u64],
+    shifts: &[u8],
+    offsets: &[usize],
+    attacks: &[u64],
+) -> Result<MagicAttacks, MagicErr> {
+    if magics.len() != 64 || shifts.len() != 64 || offsets.len() != 64 {
+        return Err(MagicErr::NumMagic);
+    }
+    let mut found = Vec::<Magic>::with_capacity(64);
+    for s in 0..64 {
+        let rshift = shifts[s];
+        let index_bits = 64u8.checked_sub(rshift).ok_or(MagicErr::NumBits)?;
+        if index_bits > 12 {
+            return Err(MagicErr::NumBits);
+        }
+        let table = attacks
+            .get(offsets[s]..offsets[s] + (1usize << index_bits))
+            .ok_or(MagicErr::NumBits)?;
+        found.push(Magic {
+            attacks: table.iter().map(|a| BitBoard::from(*a)).collect(),
+            mask: mask_fn(Sq::from(s)),
+            magic: magics[s],
+            rshift,
+        });
+    }
+    Ok(MagicAttacks { magics: found })
+}
+
+fn verify_magics(
+    magics: &impl Magics,
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    attacks_fn: &impl Fn(Sq, BitBoard) -> BitBoard,
+) -> Result<(), MagicErr> {
+    if magics.as_ref().len() != 64 {
+        return Err(MagicErr::NumMagic);
+    }
+    for s in 0u8..64 {
+        let sq = Sq::from(s);
+        if magics.get(sq).map(|m| m.mask) != Some(mask_fn(sq)) {
+            return Err(MagicErr::Mismatch);
+        }
+    }
+    verify_lookups(
+        &|sq, blockers| magics.attacks(sq, blockers),
+        mask_fn,
+        attacks_fn,
+    )
+}
+
+fn verify_black_magics(
+    magics: &BlackMagicAttacks,
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    attacks_fn: &impl Fn(Sq, BitBoard) -> BitBoard,
+) -> Result<(), MagicErr> {
+    if magics.magics.len() != 64 || magics.offsets.len() != 64 {
+        return Err(MagicErr::NumMagic);
+    }
+    verify_lookups(
+        &|sq, blockers| magics.attacks(sq, blockers),
+        mask_fn,
+        attacks_fn,
+    )
+}
+
+// Checks the lookups for every square and every set of blockers in the mask
+// of the square.
+fn verify_lookups(
+    lookup: &impl Fn(Sq, BitBoard) -> Option<BitBoard>,
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    attacks_fn: &impl Fn(Sq, BitBoard) -> BitBoard,
+) -> Result<(), MagicErr> {
+    for s in 0u8..64 {
+        let sq = Sq::from(s);
+        let mask = mask_fn(sq);
+        for i in 0..1u64 << mask.count() {
+            let blockers = permute_mask(BitBoard::from(i), mask);
+            if lookup(sq, blockers) != Some(attacks_fn(sq, blockers)) {
+                return Err(MagicErr::Mismatch);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn search_black_magics(
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    attacks_fn: &impl Fn(Sq, BitBoard) -> BitBoard,
+    rshift: u8,
+    seed: u64,
+    attempts: usize,
+) -> Result<BlackMagicAttacks, MagicErr> {
+    let mut rand_iter = create_seeded_rand_iter(seed);
+    let mut black = BlackMagicAttacks {
+        magics: Vec::with_capacity(64),
+        masks: Vec::with_capacity(64),
+        offsets: Vec::with_capacity(64),
+        rshift,
+        attacks: Vec::new(),
+    };
+    let mut scratch = vec![BitBoard::new(); 1usize << (64 - rshift)];
+    for s in 0u8..64 {
+        let sq = Sq::from(s);
+        let mask = mask_fn(sq);
+        if mask.count() > 64 - rshift {
+            return Err(MagicErr::NumBits);
+        }
+        let blocking: Vec<BitBoard> = (0..1u64 << mask.count())
+            .map(|i| permute_mask(BitBoard::from(i), mask))
+            .collect();
+        let attacking: Vec<BitBoard> = blocking.iter().map(|b| attacks_fn(sq, *b)).collect();
+
+        // The best magic so far, with the smallest index and the table from
+        // the smallest to the largest index.
+        let mut best: Option<(u64, usize, Vec<BitBoard>)> = None;
+        for (tried, magic) in rand_iter.by_ref().enumerate() {
+            if tried >= attempts && best.is_some() {
+                break;
+            }
+            if ((mask * magic) >> 56).count() < 6 {
+                continue;
+            }
+            let Some((min, max)) = black_range(&blocking, &attacking, mask, magic, &mut scratch)
+            else {
+                continue;
+            };
+            if best
+                .as_ref()
+                .is_none_or(|(_, _, table)| max - min + 1 < table.len())
+            {
+                best = Some((magic, min, scratch[min..=max].to_vec()));
+            }
+        }
+        let (magic, min, table) = best.ok_or(MagicErr::NotFound)?;
+        let pos = black.place(&table);
+        black.magics.push(magic);
+        black.masks.push(mask);
+        black.offsets.push(pos as isize - min as isize);
+    }
+    Ok(black)
+}
+
+// Fills table with the attacks for a fixed-shift magic, where the shift is
+// given by the size of table, and returns the smallest and largest index, or
+// None if two sets of blockers with different attacks collide.
+fn black_range(
+    blocking: &[BitBoard],
+    attacking: &[BitBoard],
+    mask: BitBoard,
+    magic: u64,
+    table: &mut [BitBoard],
+) -> Option<(usize, usize)> {
+    let rshift = 64 - table.len().trailing_zeros() as u8;
+    table.fill(BitBoard::new());
+    let (mut min, mut max) = (usize::MAX, 0);
+    for (blockers, attacks) in zip(blocking, attacking) {
+        let index = get_magic_hash(*blockers | !mask, magic, rshift);
+        if table[index].none() {
+            table[index] = *attacks;
+        } else if table[index] != *attacks {
+            return None;
+        }
+        min = min.min(index);
+        max = max.max(index);
+    }
+    Some((min, max))
+}
+
+fn from_black_tables(
+    mask_fn: &impl Fn(Sq) -> BitBoard,
+    rshift: u8,
+    magics: &[u64],
+    offsets: &[isize],
+    attacks: &[u64],
+) -> Result<BlackMagicAttacks, MagicErr> {
+    if magics.len() != 64 || offsets.len() != 64 {
+        return Err(MagicErr::NumMagic);
+    }
+    Ok(BlackMagicAttacks {
+        magics: magics.to_vec(),
+        masks: (0u8..64).map(|s| mask_fn(Sq::from(s))).collect(),
+        offsets: offsets.to_vec(),
+        rshift,
+        attacks: attacks.iter().map(|a| BitBoard::from(*a)).collect(),
+    })
+}
+
 /// Finds a Magic for a given square.
 ///
 /// # Arguments
@@ -133,17 +741,20 @@ fn find_all_magics(
 /// * `mask_fn`: A function to compute the full mask for a piece given a square.
 /// * `attacks_fn`: A function to compute the attack mask for a piece given a
 ///   square and a given set of blockers.
+/// * `index_bits`: The number of bits in the index of the attack table, which
+///   is at most the number of bits in the mask.
 /// * `magic_iter`: An iterator over magic numbers.
 fn find_magic(
     sq: Sq,
     mask_fn: &impl Fn(Sq) -> BitBoard,
     attacks_fn: &impl Fn(Sq, BitBoard) -> BitBoard,
+    index_bits: u8,
     magic_iter: &mut impl Iterator<Item = u64>,
 ) -> Result<Magic, MagicErr> {
     let mask = mask_fn(sq);
     let num_bits = mask.count();
 
-    if !(5..=12).contains(&num_bits) {
+    if !(5..=12).contains(&num_bits) || index_bits == 0 || index_bits > num_bits {
         return Err(MagicErr::NumBits);
     }
 
@@ -158,14 +769,14 @@ fn find_magic(
         attacking[i] = attacks_fn(sq, blocking[i]);
     }
 
-    let mut attacks = vec![BitBoard::new(); ncombos];
+    let mut attacks = vec![BitBoard::new(); 1usize << index_bits];
 
     'mloop: for magic in magic_iter {
         if ((mask * magic) >> 56).count() < 6 {
             continue;
         }
 
-        let rshift: u8 = 64 - num_bits;
+        let rshift: u8 = 64 - index_bits;
 
         for i in 0..ncombos {
             let magic_hash = get_magic_hash(blocking[i], magic, rshift);
@@ -202,6 +813,19 @@ pub fn create_rand_iter() -> impl Iterator<Item = u64> {
     std::iter::from_fn(move || Some(rng.next_u64() & rng.next_u64() & rng.next_u64()))
 }
 
+// Like create_rand_iter, but the same seed always gives the same numbers. The
+// numbers come from splitmix64 rather than a rand generator, whose stream may
+// change between rand releases.
+pub fn create_seeded_rand_iter(seed: u64) -> impl Iterator<Item = u64> {
+    let mut state = seed;
+    let mut next_u64 = move || {
+        let (next, value) = splitmix64(state);
+        state = next;
+        value
+    };
+    std::iter::from_fn(move || Some(next_u64() & next_u64() & next_u64()))
+}
+
 /// The bits in bit_selector are used to choose a set of bits from mask.
 pub fn permute_mask(bit_selector: BitBoard, mask: BitBoard) -> BitBoard {
     let mut bits = BitBoard::new();
@@ -605,4 +1229,152 @@ mod tests {
         let mask = BitBoard::from(0b111000);
         assert_eq!(permute_mask(bit_selector, mask), BitBoard::from(0b100000));
     }
+
+    // Extracts the bits of bits in mask like pext, one bit at a time.
+    fn soft_pext(bits: u64, mask: u64) -> u64 {
+        let mut extracted = 0;
+        for (i, sq) in BitBoard::from(mask).sq_iter().enumerate() {
+            if bits & (1 << usize::from(sq)) != 0 {
+                extracted |= 1 << i;
+            }
+        }
+        extracted
+    }
+
+    #[test]
+    fn pext_tables_match_magics() {
+        // Checks the tables with a software pext, so it runs on any CPU.
+        let magics = ChessMagics::with_magics().unwrap();
+        let rook = build_pext_attacks(&get_full_rmask, &get_rattacks);
+        let bishop = build_pext_attacks(&get_full_bmask, &get_battacks);
+        let lookup = |attacks: &PextAttacks, sq: Sq, blockers: BitBoard| {
+            let s = usize::from(sq);
+            let index = soft_pext(blockers.u64(), attacks.masks[s].u64()) as usize;
+            attacks.attacks[attacks.offsets[s] + index]
+        };
+
+        let mut rand_iter = create_rand_iter();
+        for s in 0u8..64 {
+            let sq = Sq::from(s);
+            for mask in [get_full_rmask(sq), get_full_bmask(sq)] {
+                for i in 0..1u64 << mask.count() {
+                    let outside = BitBoard::from(rand_iter.next().unwrap()) & !mask;
+                    let blockers = permute_mask(BitBoard::from(i), mask) | outside;
+                    assert_eq!(
+                        Some(lookup(&rook, sq, blockers)),
+                        magics.rmagics(sq, blockers)
+                    );
+                    assert_eq!(
+                        Some(lookup(&bishop, sq, blockers)),
+                        magics.bmagics(sq, blockers)
+                    );
+                }
+            }
+        }
+    }
+
+    // Needs BMI2 at compile time, e.g. RUSTFLAGS="-C target-cpu=native", so
+    // that a passing run always compares the pext instruction with magics.
+    #[test]
+    #[cfg_attr(not(target_feature = "bmi2"), ignore)]
+    fn pext_attacks_match_magics() {
+        assert!(is_pext_supported());
+        let magics = ChessMagics::with_magics().unwrap();
+        let pext = ChessMagics::with_pext().unwrap();
+        assert!(!magics.is_pext());
+        assert!(pext.is_pext());
+
+        let mut rand_iter = create_rand_iter();
+        for s in 0u8..64 {
+            let sq = Sq::from(s);
+            for mask in [get_full_rmask(sq), get_full_bmask(sq)] {
+                for i in 0..1u64 << mask.count() {
+                    // Blockers outside of the mask do not change the attacks.
+                    let outside = BitBoard::from(rand_iter.next().unwrap()) & !mask;
+                    let blockers = permute_mask(BitBoard::from(i), mask) | outside;
+                    assert_eq!(magics.rmagics(sq, blockers), pext.rmagics(sq, blockers));
+                    assert_eq!(magics.bmagics(sq, blockers), pext.bmagics(sq, blockers));
+                    assert_eq!(magics.qmagics(sq, blockers), pext.qmagics(sq, blockers));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_search_round_trips() {
+        let magics = search_bmagics(7, 1, 1000).unwrap();
+        assert_eq!(magics, search_bmagics(7, 1, 1000).unwrap());
+        assert!(verify_bmagics(&magics).is_ok());
+
+        let loaded =
+            from_bmagics_with_shifts(magics.as_ref().iter().map(|m| (m.magic, m.rshift))).unwrap();
+        assert_eq!(loaded, magics);
+
+        let mut offsets = Vec::new();
+        let mut attacks = Vec::new();
+        for m in magics.as_ref() {
+            offsets.push(attacks.len());
+            attacks.extend(m.attacks.iter().map(|a| a.u64()));
+        }
+        let values: Vec<_> = magics.as_ref().iter().map(|m| m.magic).collect();
+        let shifts: Vec<_> = magics.as_ref().iter().map(|m| m.rshift).collect();
+        let tables = from_btables(&values, &shifts, &offsets, &attacks).unwrap();
+        assert_eq!(tables, magics);
+
+        // A wrong entry in the tables is caught by the verification.
+        let index = attacks.iter().position(|a| *a != 0).unwrap();
+        attacks[index] = 0;
+        let tables = from_btables(&values, &shifts, &offsets, &attacks).unwrap();
+        assert!(matches!(verify_bmagics(&tables), Err(MagicErr::Mismatch)));
+    }
+
+    #[test]
+    fn seeded_rand_iter_is_stable() {
+        // The stream must not change, or the same seed gives different magics.
+        let first: Vec<u64> = create_seeded_rand_iter(7).take(2).collect();
+        assert_eq!(first, vec![0x0008_0080_1030_0000, 0x1112_2a00_421e_2000]);
+    }
+
+    #[test]
+    fn black_bmagics() {
+        let magics = search_black_bmagics(7, 10).unwrap();
+        assert_eq!(magics, search_black_bmagics(7, 10).unwrap());
+        assert!(verify_black_bmagics(&magics).is_ok());
+        // The shared table is smaller than one table per square with the fixed
+        // number of bits.
+        assert!(magics.table().len() < 64 << 9);
+
+        let mut attacks: Vec<u64> = magics.table().iter().map(|a| a.u64()).collect();
+        let loaded = from_black_btables(magics.magics(), magics.offsets(), &attacks).unwrap();
+        assert_eq!(loaded, magics);
+
+        // A wrong entry in the table is caught by the verification.
+        attacks[0] = 0;
+        let loaded = from_black_btables(magics.magics(), magics.offsets(), &attacks).unwrap();
+        assert!(matches!(
+            verify_black_bmagics(&loaded),
+            Err(MagicErr::Mismatch)
+        ));
+    }
+
+    #[test]
+    fn shifts_must_match_magics() {
+        // Two entries cannot hold all the attacks of any square.
+        let shifts = BMAGICS.iter().map(|m| (*m, 63));
+        assert!(matches!(
+            from_bmagics_with_shifts(shifts),
+            Err(MagicErr::NotFound)
+        ));
+        assert!(matches!(
+            from_bmagics_with_shifts(
+                from_bmagics(BMAGICS.into_iter())
+                    .unwrap()
+                    .as_ref()
+                    .iter()
+                    .take(63)
+                    .map(|m| (m.magic, m.rshift))
+            ),
+            Err(MagicErr::NumMagic)
+        ));
+    }
 }
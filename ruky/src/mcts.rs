@@ -3,7 +3,7 @@
 use crate::board::Board;
 use crate::err::RukyErr;
 use crate::eval::Eval;
-use crate::search::{Bp, Search, SearchResult, SpSearch, TreeSize};
+use crate::search::{Bp, Search, SearchBudget, SearchResult, SpSearch, TreeSize};
 use crate::tree_search::TreeSearch;
 use std::cmp::max;
 use std::sync::Arc;
@@ -91,6 +91,10 @@ impl<E: Eval> SpSearch for SpMcts<E> {
         self.search_tree.update_root_from_index(best_node.index);
         Ok(result)
     }
+
+    fn reset_board(&mut self, board: &Board) {
+        self.search_tree.reset_board(board);
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -157,6 +161,9 @@ pub struct Mcts<E: Eval> {
     evaluator: Arc<E>,
     search_tree: TreeSearch,
     sims: usize,
+    // If set, the search stops after this time even if not all simulations
+    // are completed.
+    max_time: Option<Duration>,
     use_noise: bool,
     sample_action: bool,
 }
@@ -167,6 +174,7 @@ impl<E: Eval> Mcts<E> {
             evaluator,
             search_tree: TreeSearch::new(),
             sims,
+            max_time: None,
             use_noise: false,
             sample_action: false,
         }
@@ -177,6 +185,7 @@ impl<E: Eval> Mcts<E> {
             evaluator,
             search_tree: TreeSearch::new(),
             sims,
+            max_time: None,
             use_noise: true,
             sample_action: false,
         }
@@ -223,6 +232,12 @@ impl<E: Eval> Search for Mcts<E> {
         let mut nodes_visited = 0;
 
         for _ in 0..self.sims {
+            if self
+                .max_time
+                .is_some_and(|max_time| search_start.elapsed() >= max_time)
+            {
+                break;
+            }
             let mut node_index = root_index;
             let mut current_depth = 0u32;
             while self.search_tree.is_expanded(node_index) {
@@ -264,4 +279,11 @@ impl<E: Eval> Search for Mcts<E> {
         self.search_tree.update_root_from_index(best_node.index);
         Ok(result)
     }
+
+    fn set_budget(&mut self, budget: SearchBudget) {
+        (self.sims, self.max_time) = match budget {
+            SearchBudget::Nodes(nodes) => (nodes, None),
+            SearchBudget::Time(max_time) => (usize::MAX, Some(max_time)),
+        };
+    }
 }
@@ -2,9 +2,9 @@
 
 use crate::err::RukyErr;
 use crate::eval::{Eval, EvalBoards};
-use crate::search::{Bp, Search, SearchResult, SpSearch, TreeSize};
-use crate::tensor_decoder::{dec_boards, N_POSSIBLE_MOVES};
-use crate::tensor_encoder::{enc_boards, get_batch_vec, single_batch_size};
+use crate::search::{Bp, Search, SearchBudget, SearchResult, SpSearch, TreeSize};
+use crate::tensor_decoder::dec_boards;
+use crate::tensor_encoder::{enc_boards, get_batch_vec, single_batch_size, NetShape};
 use crate::tree_search::TreeSearch;
 use crate::Board;
 use crossbeam::channel::{unbounded, Receiver, Sender};
@@ -55,6 +55,9 @@ pub struct ParMcts<E: Eval> {
     decoded_rx: Receiver<DecResult>,
     // The total number of simulations to run.
     sims: usize,
+    // If set, the search stops after this time even if not all simulations
+    // are completed.
+    max_time: Option<Duration>,
     // If true, noise is added to the move priors for the root node.
     use_noise: bool,
     // If true, the MCTS samples from the moves, rather than returning the move
@@ -125,6 +128,7 @@ impl<E: Eval> ParMcts<E> {
             encoded_rx,
             decoded_rx,
             sims,
+            max_time: None,
             use_noise,
             sample_action,
             sample_action_n,
@@ -138,6 +142,7 @@ impl<E: Eval> ParMcts<E> {
         let mut total_evals = 0;
 
         let root_index = self.tree_search.root_index();
+        let shape = NetShape::for_variant(self.tree_search.root_board().variant());
         self.tree_search.sample_action = match self.sample_action_n {
             None => false,
             Some(n) => n < self.tree_search.root_board().num_prev_moves(),
@@ -168,6 +173,12 @@ impl<E: Eval> ParMcts<E> {
         // TODO: increase throughput by doing more rollouts while we wait for evaluator
         // to return.
         while completed_sims < self.sims {
+            if self
+                .max_time
+                .is_some_and(|max_time| search_start.elapsed() >= max_time)
+            {
+                break;
+            }
             let mut batch_count = 0;
             let total_batch_count = min(self.sims - completed_sims, self.batch_size);
 
@@ -206,7 +217,7 @@ impl<E: Eval> ParMcts<E> {
             }
 
             // Create a data vector where board state is encoded.
-            let mut data = get_batch_vec(batch_count);
+            let mut data = get_batch_vec(shape, batch_count);
 
             // Collect the results from the encoded tasks. This blocks until all
             // tasks are encoded.
@@ -216,7 +227,7 @@ impl<E: Eval> ParMcts<E> {
 
             // Copy the encoded data to the input vector.
             for (data_batch, enc_result) in data
-                .chunks_exact_mut(single_batch_size())
+                .chunks_exact_mut(single_batch_size(shape))
                 .zip(enc_results.iter())
             {
                 data_batch.copy_from_slice(enc_result.enc_data.as_ref());
@@ -231,12 +242,15 @@ impl<E: Eval> ParMcts<E> {
             total_evals += 1;
 
             for ((enc_moves, value), enc_result) in zip(
-                mv_data.chunks_exact(N_POSSIBLE_MOVES).zip(value_data),
+                mv_data
+                    .chunks_exact(shape.n_possible_moves())
+                    .zip(value_data),
                 enc_results,
             ) {
                 // Create a decoding tasks.
                 let dec_task = DecTask {
                     node_id: enc_result.node_id,
+                    shape,
                     moves: enc_result.moves,
                     enc_moves: enc_moves.to_vec(),
                     value,
@@ -293,6 +307,13 @@ impl<E: Eval> Search for ParMcts<E> {
         self.tree_search.update_root_from_board(board);
         self.run_search()
     }
+
+    fn set_budget(&mut self, budget: SearchBudget) {
+        (self.sims, self.max_time) = match budget {
+            SearchBudget::Nodes(nodes) => (nodes, None),
+            SearchBudget::Time(max_time) => (usize::MAX, Some(max_time)),
+        };
+    }
 }
 
 impl<E: Eval> SpSearch for ParMcts<E> {
@@ -303,6 +324,10 @@ impl<E: Eval> SpSearch for ParMcts<E> {
     fn reset(&mut self) {
         self.tree_search.reset();
     }
+
+    fn reset_board(&mut self, board: &Board) {
+        self.tree_search.reset_board(board);
+    }
 }
 
 // An enum to represent the different types of work.
@@ -316,6 +341,7 @@ enum Task {
 #[derive(Clone, Debug)]
 struct DecTask {
     node_id: usize,
+    shape: NetShape,
     moves: Vec<Board>,
     enc_moves: Vec<f32>,
     value: f32,
@@ -331,7 +357,7 @@ impl DecTask {
     fn run_task(self) -> DecResult {
         DecResult {
             node_id: self.node_id,
-            eval_boards: dec_boards(self.moves, self.value, self.enc_moves),
+            eval_boards: dec_boards(self.shape, self.moves, self.value, self.enc_moves),
         }
     }
 }
@@ -2,6 +2,7 @@
 // AlphaZero.
 
 use crate::dataset::GamesBatch;
+use crate::tensor_encoder::NetShape;
 use burn::{
     module::Module,
     nn::{
@@ -84,7 +85,7 @@ struct PolicyNet<B: Backend> {
 }
 
 impl<B: Backend> PolicyNet<B> {
-    pub fn new(device: &Device<B>) -> Self {
+    pub fn new(device: &Device<B>, n_move_codes: usize) -> Self {
         Self {
             conv1: Conv2dConfig::new([256, 256], [3, 3])
                 .with_padding(PaddingConfig2d::Same)
@@ -93,7 +94,7 @@ impl<B: Backend> PolicyNet<B> {
                     fan_out_only: true,
                 })
                 .init(device),
-            conv2: Conv2dConfig::new([256, 73], [3, 3])
+            conv2: Conv2dConfig::new([256, n_move_codes], [3, 3])
                 .with_padding(PaddingConfig2d::Same)
                 .with_initializer(Initializer::KaimingNormal {
                     gain: 0.5,
@@ -109,7 +110,7 @@ impl<B: Backend> PolicyNet<B> {
         let x = self.batch_norm.forward(x);
         let x = relu(x);
         let x = self.conv2.forward(x);
-        let x = x.reshape([0, 8, 8, 73]);
+        let x = x.reshape([0, 8, 8, -1]);
         x
     }
 }
@@ -213,9 +214,16 @@ impl<B: Backend> ItemLazy for AzOutput<B> {
 }
 
 impl<B: Backend> AlphaZeroNet<B> {
+    // Creates a network for standard chess.
     pub fn new(device: &Device<B>) -> Self {
+        Self::with_shape(device, NetShape::Standard)
+    }
+
+    // Creates a network with the input and policy sizes of shape. A network
+    // only loads records saved by a network of the same shape.
+    pub fn with_shape(device: &Device<B>, shape: NetShape) -> Self {
         Self {
-            conv: Conv2dConfig::new([119, 256], [3, 3])
+            conv: Conv2dConfig::new([shape.n_planes(), 256], [3, 3])
                 .with_padding(PaddingConfig2d::Same)
                 .with_initializer(Initializer::Normal {
                     mean: 0.0,
@@ -224,7 +232,7 @@ impl<B: Backend> AlphaZeroNet<B> {
                 .init(device),
             batch_norm: BatchNormConfig::new(256).init(device),
             res_blocks: vec![ResBlockNet::new(device); 19],
-            policy_net: PolicyNet::new(device),
+            policy_net: PolicyNet::new(device, shape.n_move_codes()),
             value_net: ValueNet::new(device),
         }
     }
@@ -0,0 +1,454 @@
+/// This module contains a compact binary encoding of a chess position, which
+/// always takes 32 bytes, e.g. to store large numbers of training positions
+/// on disk. The bytes are laid out as:
+///
+/// * bytes 0-7: the occupancy BitBoard, little endian
+/// * bytes 8-23: a 4-bit code for each occupied square, from a1 to h8, two
+///   codes per byte with the low nibble first
+/// * byte 24: bit 0 is set if black moves next, bit 1 is set for Chess960,
+///   and bits 4-7 hold the file of the en-passant target plus one, or 0
+/// * byte 25: the half move clock
+/// * bytes 26-27: the full move counter, little endian
+/// * byte 28: the variant, 0 for standard chess, 1 for Three-check, 2 for King
+///   of the Hill and 3 for Atomic
+/// * byte 29: the checks given in Three-check, white in the low nibble and
+///   black in the high nibble, or 0 in the other variants
+/// * bytes 30-31: reserved, always 0
+///
+/// The piece codes are 0-5 for the white king, queen, rook, bishop, knight and
+/// pawn, 6-11 for the same black pieces, and 12 and 13 for white and black
+/// rooks with castling rights. Since a position has at most 32 pieces, the
+/// codes fit in 16 bytes.
+///
+/// The encoding is lossless for the position, i.e. the unpacked board has the
+/// same FEN as the packed one, but the history of the moves is not kept.
+/// Crazyhouse positions cannot be packed, since the pockets and the promoted
+/// pieces do not fit in the reserved bytes.
+use crate::bitboard::BitBoard;
+use crate::board::{Board, BoardBuilder};
+use crate::piece::{Color, Piece, Piece::*};
+use crate::piece_set::{PieceSet, PiecesErr};
+use crate::sq::Sq;
+use crate::variant::Variant;
+use serde::{Deserialize, Serialize};
+
+pub const PACKED_LEN: usize = 32;
+
+// The maximum number of pieces that fit in the piece codes.
+const MAX_PIECES: u32 = 32;
+
+const PIECES_OFFSET: usize = 8;
+const FLAGS_OFFSET: usize = 24;
+const HALF_MOVE_OFFSET: usize = 25;
+const FULL_MOVE_OFFSET: usize = 26;
+const VARIANT_OFFSET: usize = 28;
+const CHECKS_OFFSET: usize = 29;
+
+const BLACK_FLAG: u8 = 0x1;
+const CHESS960_FLAG: u8 = 0x2;
+
+const WHITE_CASTLE_ROOK: u8 = 12;
+const BLACK_CASTLE_ROOK: u8 = 13;
+
+// A position packed into 32 bytes.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct PackedBoard {
+    bytes: [u8; PACKED_LEN],
+}
+
+impl PackedBoard {
+    #[inline]
+    pub fn bytes(&self) -> &[u8; PACKED_LEN] {
+        &self.bytes
+    }
+
+    // Returns the squares with a piece.
+    #[inline]
+    pub fn occupancy(&self) -> BitBoard {
+        BitBoard::from(u64::from_le_bytes(
+            self.bytes[..PIECES_OFFSET].try_into().unwrap(),
+        ))
+    }
+}
+
+impl From<[u8; PACKED_LEN]> for PackedBoard {
+    fn from(bytes: [u8; PACKED_LEN]) -> Self {
+        Self { bytes }
+    }
+}
+
+// Creates a PackedBoard from a slice, which must have exactly 32 bytes, e.g.
+// when reading positions from a shard.
+impl TryFrom<&[u8]> for PackedBoard {
+    type Error = PackedErr;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, PackedErr> {
+        let bytes = bytes
+            .try_into()
+            .map_err(|_| PackedErr::BadLength(bytes.len()))?;
+        Ok(Self { bytes })
+    }
+}
+
+/// `to_packed` packs the position of a board.
+///
+/// Returns a Result with the PackedBoard, or a PackedErr if the board has more
+/// than 32 pieces, which can only happen for lenient boards, a half move clock
+/// above 255, which can only be set with the builder, or if the variant is
+/// Crazyhouse.
+pub(crate) fn to_packed(board: &Board) -> Result<PackedBoard, PackedErr> {
+    let variant = variant_code(board.variant())?;
+    let (white, black) = (board.white(), board.black());
+    let occupancy = white.all() | black.all();
+    if occupancy.count() as u32 > MAX_PIECES {
+        return Err(PackedErr::TooManyPieces(occupancy.count() as u32));
+    }
+    let half_move =
+        u8::try_from(board.half_moves()).map_err(|_| PackedErr::BadHalfMove(board.half_moves()))?;
+
+    let mut bytes = [0u8; PACKED_LEN];
+    bytes[..PIECES_OFFSET].copy_from_slice(&occupancy.u64().to_le_bytes());
+    for (i, sq) in occupancy.sq_iter().enumerate() {
+        let code = match white.find_type(sq) {
+            Some(piece) => piece_code(piece, white, sq),
+            None => piece_code(black.find_type(sq).unwrap(), black, sq),
+        };
+        bytes[PIECES_OFFSET + i / 2] |= code << (4 * (i % 2));
+    }
+
+    let mut flags = 0u8;
+    if !board.is_white_next() {
+        flags |= BLACK_FLAG;
+    }
+    if board.is_chess960() {
+        flags |= CHESS960_FLAG;
+    }
+    if let Some(passant) = board.passant() {
+        flags |= (passant.capture.rc().1 + 1) << 4;
+    }
+    bytes[FLAGS_OFFSET] = flags;
+    bytes[HALF_MOVE_OFFSET] = half_move;
+    bytes[FULL_MOVE_OFFSET..FULL_MOVE_OFFSET + 2]
+        .copy_from_slice(&board.full_moves().to_le_bytes());
+    bytes[VARIANT_OFFSET] = variant;
+    bytes[CHECKS_OFFSET] =
+        board.checks_given(Color::White) | (board.checks_given(Color::Black) << 4);
+    Ok(PackedBoard { bytes })
+}
+
+/// `from_packed` constructs a Board from a packed position.
+///
+/// # Arguments
+///
+/// * `packed` - The packed position.
+/// * `builder` - A board builder for building the board.
+///
+/// Returns a Result with a Board or a PackedErr if the bytes are not a valid
+/// packed position, or the position is not valid.
+pub(crate) fn from_packed(
+    packed: &PackedBoard,
+    mut builder: BoardBuilder,
+) -> Result<Board, PackedErr> {
+    let bytes = &packed.bytes;
+    let occupancy = packed.occupancy();
+    if occupancy.count() as u32 > MAX_PIECES {
+        return Err(PackedErr::TooManyPieces(occupancy.count() as u32));
+    }
+
+    for (i, sq) in occupancy.sq_iter().enumerate() {
+        let code = (bytes[PIECES_OFFSET + i / 2] >> (4 * (i % 2))) & 0xf;
+        match code {
+            0 => builder.white_king(sq),
+            1 => builder.white_queen(sq),
+            2 => builder.white_rook(sq),
+            3 => builder.white_bishop(sq),
+            4 => builder.white_knight(sq),
+            5 => builder.white_pawn(sq),
+            6 => builder.black_king(sq),
+            7 => builder.black_queen(sq),
+            8 => builder.black_rook(sq),
+            9 => builder.black_bishop(sq),
+            10 => builder.black_knight(sq),
+            11 => builder.black_pawn(sq),
+            WHITE_CASTLE_ROOK => builder.white_rook(sq).white_castle_rook(sq),
+            BLACK_CASTLE_ROOK => builder.black_rook(sq).black_castle_rook(sq),
+            _ => return Err(PackedErr::BadPieceCode(code)),
+        };
+    }
+
+    let flags = bytes[FLAGS_OFFSET];
+    let color = if flags & BLACK_FLAG != 0 {
+        Color::Black
+    } else {
+        Color::White
+    };
+    builder
+        .set_color(color)
+        .set_chess960(flags & CHESS960_FLAG != 0)
+        .set_half_move(bytes[HALF_MOVE_OFFSET].into())
+        .set_full_move(u16::from_le_bytes([
+            bytes[FULL_MOVE_OFFSET],
+            bytes[FULL_MOVE_OFFSET + 1],
+        ]));
+
+    let passant = flags >> 4;
+    if passant > 8 {
+        return Err(PackedErr::BadPassant(passant));
+    }
+    if passant > 0 {
+        // The target square is behind the pawn that just moved.
+        let row = if color.is_white() { 5 } else { 2 };
+        builder.set_passant(Sq::from_rc(row, passant - 1).unwrap());
+    }
+
+    let variant = match bytes[VARIANT_OFFSET] {
+        0 => Variant::Standard,
+        1 => Variant::ThreeCheck,
+        2 => Variant::KingOfTheHill,
+        3 => Variant::Atomic,
+        code => return Err(PackedErr::BadVariant(code)),
+    };
+    let checks = bytes[CHECKS_OFFSET];
+    if variant != Variant::ThreeCheck && checks != 0 {
+        return Err(PackedErr::BadChecks(checks));
+    }
+    builder
+        .set_variant(variant)
+        .set_checks(Color::White, checks & 0xf)
+        .set_checks(Color::Black, checks >> 4);
+
+    Ok(builder.build()?)
+}
+
+// Returns the code of a variant, or an error for Crazyhouse.
+fn variant_code(variant: Variant) -> Result<u8, PackedErr> {
+    match variant {
+        Variant::Standard => Ok(0),
+        Variant::ThreeCheck => Ok(1),
+        Variant::KingOfTheHill => Ok(2),
+        Variant::Atomic => Ok(3),
+        Variant::Crazyhouse => Err(PackedErr::UnsupportedVariant(variant)),
+    }
+}
+
+// Returns the 4-bit code of a piece of pieces on sq.
+fn piece_code<T>(piece: Piece<T>, pieces: &PieceSet, sq: Sq) -> u8 {
+    let is_castle_rook =
+        pieces.king_castle_rook() == Some(sq) || pieces.queen_castle_rook() == Some(sq);
+    let code = match piece {
+        King(_) => 0,
+        Queen(_) => 1,
+        Rook(_) if is_castle_rook => {
+            return match pieces.color() {
+                Color::White => WHITE_CASTLE_ROOK,
+                Color::Black => BLACK_CASTLE_ROOK,
+            }
+        }
+        Rook(_) => 2,
+        Bishop(_) => 3,
+        Knight(_) => 4,
+        Pawn(_) => 5,
+    };
+    match pieces.color() {
+        Color::White => code,
+        Color::Black => code + 6,
+    }
+}
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum PackedErr {
+    #[error("{0} bytes is not a packed position")]
+    BadLength(usize),
+    #[error("{0} pieces do not fit in a packed position")]
+    TooManyPieces(u32),
+    #[error("piece code {0} is not valid")]
+    BadPieceCode(u8),
+    #[error("en-passant file {0} is not valid")]
+    BadPassant(u8),
+    #[error("half move clock {0} does not fit in a packed position")]
+    BadHalfMove(u16),
+    #[error("variant code {0} is not valid")]
+    BadVariant(u8),
+    #[error("checks {0:#x} are not valid for the variant")]
+    BadChecks(u8),
+    #[error("{0:?} positions cannot be packed")]
+    UnsupportedVariant(Variant),
+    #[error("invalid position: {0}")]
+    Pieces(#[from] PiecesErr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magics::ChessMagics;
+    use crate::ruky::Ruky;
+    use crate::sq;
+    use lazy_static::lazy_static;
+    use std::sync::Arc;
+
+    lazy_static! {
+        static ref RUKY: Ruky = Ruky::new();
+        static ref MAGICS: Arc<ChessMagics> = Arc::new(
+            ChessMagics::from_precomputed().expect("Unable to compute magics for unit test.")
+        );
+    }
+
+    #[test]
+    fn packed_round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b Kq e3 0 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4k3/8/8/8/8/8/8/R3K3 w - - 149 1105",
+            "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w BGbg - 0 1",
+            "8/8/8/8/8/8/8/K1k5 w - - 0 1",
+        ] {
+            let board = RUKY.from_fen(fen).unwrap();
+            let packed = board.to_packed().unwrap();
+            assert_eq!(packed.bytes().len(), PACKED_LEN);
+            assert_eq!(packed.bytes()[VARIANT_OFFSET..], [0u8; 4]);
+            let unpacked = RUKY.from_packed(&packed).unwrap();
+            assert_eq!(unpacked.to_fen(), board.to_fen());
+            assert_eq!(unpacked.state_hash(), board.state_hash());
+            assert_eq!(unpacked.is_chess960(), board.is_chess960());
+            let bytes: &[u8] = packed.bytes();
+            assert_eq!(PackedBoard::try_from(bytes).unwrap(), packed);
+        }
+
+        // The castling rights of a Chess960 position that looks standard are
+        // kept.
+        let board = RUKY.chess960_board(518).unwrap();
+        let unpacked = RUKY.from_packed(&board.to_packed().unwrap()).unwrap();
+        assert!(unpacked.is_chess960());
+        assert_eq!(unpacked.to_fen(), board.to_fen());
+    }
+
+    // Returns a board for the variant from a FEN string.
+    fn variant_board(variant: Variant, fen: &str) -> Board {
+        let mut builder = BoardBuilder::from(MAGICS.clone());
+        builder.set_variant(variant);
+        crate::fen::from_fen(fen, builder).unwrap()
+    }
+
+    #[test]
+    fn packed_variants() {
+        for (variant, fen) in [
+            (
+                Variant::ThreeCheck,
+                "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1+3 0 2",
+            ),
+            (Variant::ThreeCheck, "4k3/8/8/8/8/8/8/R3K3 b - - 2+1 0 9"),
+            (Variant::KingOfTheHill, "4k3/8/8/8/8/3K4/8/8 w - - 0 1"),
+            (Variant::KingOfTheHill, "4k3/8/8/3K4/8/8/8/8 b - - 1 1"),
+            (Variant::Atomic, "4k3/8/8/2p1b3/3n4/2P5/8/4K3 w - - 0 1"),
+            // A finished game where the black king exploded.
+            (Variant::Atomic, "3R4/8/8/8/8/8/8/4K3 b - - 0 1"),
+        ] {
+            let board = variant_board(variant, fen);
+            let packed = board.to_packed().unwrap();
+            let unpacked = RUKY.from_packed(&packed).unwrap();
+            assert_eq!(unpacked.variant(), variant);
+            assert_eq!(unpacked.to_fen(), board.to_fen());
+            assert_eq!(unpacked.state_hash(), board.state_hash());
+            assert_eq!(unpacked.game_state(), board.game_state());
+            for color in [Color::White, Color::Black] {
+                assert_eq!(unpacked.checks_given(color), board.checks_given(color));
+            }
+        }
+
+        // Positions that only differ in the variant or the checks given do not
+        // pack the same.
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1";
+        let packed: Vec<PackedBoard> = [Variant::Standard, Variant::ThreeCheck, Variant::Atomic]
+            .into_iter()
+            .map(|variant| variant_board(variant, fen).to_packed().unwrap())
+            .collect();
+        assert_ne!(packed[0], packed[1]);
+        assert_ne!(packed[1], packed[2]);
+        let checks = variant_board(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/R3K3 w - - 2+3 0 1");
+        assert_ne!(checks.to_packed().unwrap(), packed[1]);
+
+        let board = variant_board(
+            Variant::Crazyhouse,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+        );
+        assert_eq!(
+            board.to_packed(),
+            Err(PackedErr::UnsupportedVariant(Variant::Crazyhouse))
+        );
+    }
+
+    #[test]
+    fn bad_packed_positions() {
+        let board = RUKY.new_board();
+        let mut bytes = *board.to_packed().unwrap().bytes();
+        bytes[PIECES_OFFSET] = 0xf;
+        assert_eq!(
+            RUKY.from_packed(&PackedBoard::from(bytes)),
+            Err(PackedErr::BadPieceCode(15))
+        );
+
+        let mut bytes = *board.to_packed().unwrap().bytes();
+        bytes[FLAGS_OFFSET] = 9 << 4;
+        assert_eq!(
+            RUKY.from_packed(&PackedBoard::from(bytes)),
+            Err(PackedErr::BadPassant(9))
+        );
+
+        let mut bytes = *board.to_packed().unwrap().bytes();
+        bytes[VARIANT_OFFSET] = 4;
+        assert_eq!(
+            RUKY.from_packed(&PackedBoard::from(bytes)),
+            Err(PackedErr::BadVariant(4))
+        );
+
+        let mut bytes = *board.to_packed().unwrap().bytes();
+        bytes[CHECKS_OFFSET] = 0x12;
+        assert_eq!(
+            RUKY.from_packed(&PackedBoard::from(bytes)),
+            Err(PackedErr::BadChecks(0x12))
+        );
+
+        // A position without kings.
+        let bytes = [0u8; PACKED_LEN];
+        assert!(matches!(
+            RUKY.from_packed(&PackedBoard::from(bytes)),
+            Err(PackedErr::Pieces(_))
+        ));
+
+        assert_eq!(
+            PackedBoard::try_from(&[0u8; 31][..]),
+            Err(PackedErr::BadLength(31))
+        );
+
+        // Lenient boards may have too many pieces to pack.
+        let mut builder = BoardBuilder::from(MAGICS.clone());
+        builder
+            .set_lenient(true)
+            .white_king(sq::A1)
+            .black_king(sq::H8);
+        for s in 8u8..18 {
+            builder.white_knight(Sq::from(s));
+            builder.white_bishop(Sq::from(s + 10));
+            builder.black_knight(Sq::from(s + 32));
+        }
+        for s in 28u8..36 {
+            builder.white_pawn(Sq::from(s));
+        }
+        let board = builder.build().unwrap();
+        assert_eq!(board.to_packed(), Err(PackedErr::TooManyPieces(40)));
+
+        // The half move clock must fit in a byte, which the builder does not
+        // check.
+        for (half_move, packed) in [(255, Ok(())), (256, Err(PackedErr::BadHalfMove(256)))] {
+            let mut builder = BoardBuilder::from(MAGICS.clone());
+            builder
+                .white_king(sq::A1)
+                .black_king(sq::H8)
+                .set_half_move(half_move);
+            let board = builder.build().unwrap();
+            assert_eq!(board.to_packed().map(|_| ()), packed);
+        }
+    }
+}
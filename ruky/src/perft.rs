@@ -0,0 +1,149 @@
+// This module contains the counters reported by perft, which walks the tree of
+// legal moves to a fixed depth and counts the leaf nodes. The counts can be
+// compared against published results to verify the move generator.
+
+use crate::piece::Piece;
+use crate::piece_move::{PieceMove, PieceMove::*};
+use std::fmt::{Display, Formatter};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign};
+
+// The counts for the leaf nodes of a perft search. Apart from nodes, each count
+// refers to the move that leads to a leaf node, e.g. captures is the number of
+// leaf nodes that are reached by a capture.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PerftCounts {
+    pub nodes: u64,
+    // Includes captures by en passant.
+    pub captures: u64,
+    pub passants: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    // Includes mates.
+    pub checks: u64,
+    pub mates: u64,
+}
+
+impl PerftCounts {
+    // Returns the counts for a single leaf node reached by piece_move.
+    pub(crate) fn leaf(piece_move: Piece<PieceMove>, is_check: bool, is_mate: bool) -> Self {
+        let mv = piece_move.val();
+        Self {
+            nodes: 1,
+            captures: mv.is_capture() as u64,
+            passants: matches!(mv, EnPassant { .. }) as u64,
+            castles: mv.is_castle() as u64,
+            promotions: mv.is_promo() as u64,
+            checks: is_check as u64,
+            mates: is_mate as u64,
+        }
+    }
+}
+
+impl Add for PerftCounts {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            nodes: self.nodes + other.nodes,
+            captures: self.captures + other.captures,
+            passants: self.passants + other.passants,
+            castles: self.castles + other.castles,
+            promotions: self.promotions + other.promotions,
+            checks: self.checks + other.checks,
+            mates: self.mates + other.mates,
+        }
+    }
+}
+
+impl AddAssign for PerftCounts {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Sum for PerftCounts {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, counts| total + counts)
+    }
+}
+
+impl Display for PerftCounts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "nodes={} captures={} passants={} castles={} promotions={} checks={} mates={}",
+            self.nodes,
+            self.captures,
+            self.passants,
+            self.castles,
+            self.promotions,
+            self.checks,
+            self.mates
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::Piece::*;
+    use crate::sq;
+
+    #[test]
+    fn leaf_counts_move_types() {
+        let counts = PerftCounts::leaf(
+            Pawn(PromoCap {
+                from: sq::B7,
+                to: sq::A8,
+                promo: Queen(()),
+                cap: Rook(()),
+            }),
+            true,
+            false,
+        );
+        assert_eq!(
+            counts,
+            PerftCounts {
+                nodes: 1,
+                captures: 1,
+                promotions: 1,
+                checks: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn counts_sum() {
+        let passant = PerftCounts::leaf(
+            Pawn(EnPassant {
+                from: sq::E5,
+                to: sq::D6,
+                passant: sq::D5,
+            }),
+            false,
+            false,
+        );
+        let mate = PerftCounts::leaf(
+            Queen(Simple {
+                from: sq::H5,
+                to: sq::F7,
+            }),
+            true,
+            true,
+        );
+        let total: PerftCounts = [passant, mate, passant].into_iter().sum();
+        assert_eq!(
+            total,
+            PerftCounts {
+                nodes: 3,
+                captures: 2,
+                passants: 2,
+                checks: 1,
+                mates: 1,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,982 @@
+/// This module contains code to read games in Portable Game Notation (PGN),
+/// which is the standard format for chess game records. A PGN file contains
+/// any number of games, and each game consists of:
+///
+/// * a section of tag pairs, e.g. [White "Carlsen, Magnus"]
+/// * the movetext, which is a sequence of moves in SAN with optional move
+///   numbers, comments, numeric annotation glyphs (NAGs) and variations
+/// * the game result, i.e. 1-0, 0-1, 1/2-1/2 or *
+///
+/// For example:
+///
+/// [Event "Example"]
+/// [Result "1-0"]
+///
+/// 1. e4 e5 2. Bc4 {the Italian} Nc6 (2... Nf6) 3. Qh5 Nf6?? 4. Qxf7# 1-0
+///
+/// Games can also be written back to PGN, e.g. to open games played by the
+/// engine in a standard chess GUI.
+///
+/// For more background, see https://www.chessprogramming.org/Portable_Game_Notation.
+use crate::board::Board;
+use crate::piece::Piece;
+use crate::piece_move::PieceMove;
+use crate::ruky::Ruky;
+use crate::variant::Variant;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The maximum length of a line of movetext when writing PGN.
+const MAX_LINE_LEN: usize = 79;
+
+// A move read from the movetext, with its annotations.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PgnMove {
+    pub piece_move: Piece<PieceMove>,
+    // Numeric annotation glyphs, where suffix annotations such as ! or ?? are
+    // converted to their equivalent NAG.
+    pub nags: Vec<u8>,
+    // The comments that follow the move.
+    pub comments: Vec<String>,
+    // The comments that come before the move, which are only read for the
+    // first move of a variation, e.g. ({idea} 1... c5). The comments before the
+    // first move of the main line are kept in PgnGame::comments.
+    #[serde(default)]
+    pub pre_comments: Vec<String>,
+    // Alternatives to this move, where each variation starts from the position
+    // before this move. These are only set if the reader keeps variations.
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(piece_move: Piece<PieceMove>) -> Self {
+        Self {
+            piece_move,
+            nags: Vec::new(),
+            comments: Vec::new(),
+            pre_comments: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+}
+
+// A game read from PGN.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PgnGame {
+    // The tag pairs in the order they appear.
+    pub tags: Vec<(String, String)>,
+    // The position before the first move, which is set from the FEN tag if
+    // present.
+    pub board: Board,
+    // The moves of the main line, which are all legal.
+    pub moves: Vec<PgnMove>,
+    // The comments that come before the first move.
+    pub comments: Vec<String>,
+    // The game result in the movetext, i.e. 1-0, 0-1, 1/2-1/2 or *.
+    pub result: String,
+}
+
+impl PgnGame {
+    // Creates a game without tags or moves starting from board.
+    pub fn new(board: Board) -> Self {
+        Self {
+            tags: Vec::new(),
+            board,
+            moves: Vec::new(),
+            comments: Vec::new(),
+            result: "*".to_string(),
+        }
+    }
+
+    // Sets the value of a tag, replacing the value if the game already has the
+    // tag.
+    pub fn set_tag(&mut self, name: &str, value: &str) -> &mut Self {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old_value)) => *old_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+        self
+    }
+
+    // Returns the value of a tag, or None if the game does not have the tag.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Returns the moves of the main line.
+    pub fn piece_moves(&self) -> Vec<Piece<PieceMove>> {
+        self.moves
+            .iter()
+            .map(|pgn_move| pgn_move.piece_move)
+            .collect()
+    }
+
+    // Returns the board after all the moves of the main line are made.
+    pub fn final_board(&self) -> Board {
+        let mut board = self.board.clone();
+        for pgn_move in self.moves.iter() {
+            board.make_move(pgn_move.piece_move);
+        }
+        board
+    }
+
+    // Returns the game as PGN. Fails if any of the moves is not legal.
+    pub fn to_pgn(&self) -> Result<String, PgnErr> {
+        match self.write_pgn() {
+            (pgn, None) => Ok(pgn),
+            (_, Some(err)) => Err(err),
+        }
+    }
+
+    // Writes the game as PGN. If a move is not legal, the movetext stops
+    // before the move with a comment giving the error, and the error is
+    // returned with the text.
+    fn write_pgn(&self) -> (String, Option<PgnErr>) {
+        let mut pgn = String::new();
+        for (name, value) in self.tags.iter() {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        if !self.tags.is_empty() {
+            pgn.push('\n');
+        }
+
+        let mut tokens: Vec<String> = self.comments.iter().map(|c| comment_token(c)).collect();
+        let err = line_tokens(&self.board, &self.moves, &mut tokens).err();
+        if let Some(err) = err.as_ref() {
+            tokens.push(comment_token(&err.to_string()));
+        }
+        tokens.push(self.result.clone());
+
+        let mut line_len = 0;
+        for token in tokens.iter() {
+            if line_len > 0 && line_len + token.len() + 1 > MAX_LINE_LEN {
+                pgn.push('\n');
+                line_len = 0;
+            }
+            if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            pgn.push_str(token);
+            line_len += token.len();
+        }
+        pgn.push('\n');
+        (pgn, err)
+    }
+}
+
+// Writes the game as PGN. Use PgnGame::to_pgn to find out whether all the
+// moves are legal.
+impl Display for PgnGame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.write_pgn().0)
+    }
+}
+
+// Appends the movetext tokens for the moves of a line starting from board.
+// Stops at the first move that is not legal.
+fn line_tokens(board: &Board, moves: &[PgnMove], tokens: &mut Vec<String>) -> Result<(), PgnErr> {
+    let mut board = board.clone();
+    // Black moves need a move number at the start of a line, or after a comment
+    // or variation.
+    let mut needs_number = true;
+    for pgn_move in moves.iter() {
+        let san = board.to_san(pgn_move.piece_move).ok_or_else(|| {
+            PgnErr::BadMove(board.full_moves(), format!("{:?}", pgn_move.piece_move))
+        })?;
+        tokens.extend(pgn_move.pre_comments.iter().map(|c| comment_token(c)));
+        needs_number |= !pgn_move.pre_comments.is_empty();
+        if board.is_white_next() {
+            tokens.push(format!("{}.", board.full_moves()));
+        } else if needs_number {
+            tokens.push(format!("{}...", board.full_moves()));
+        }
+        tokens.push(san);
+        tokens.extend(pgn_move.nags.iter().map(|nag| format!("${nag}")));
+        tokens.extend(pgn_move.comments.iter().map(|c| comment_token(c)));
+        for variation in pgn_move.variations.iter() {
+            // Variations are written as (1. e4 e5) rather than ( 1. e4 e5 ).
+            let start = tokens.len();
+            let result = line_tokens(&board, variation, tokens);
+            if tokens.len() > start {
+                tokens[start].insert(0, '(');
+                tokens.last_mut().unwrap().push(')');
+            }
+            result?;
+        }
+        needs_number = !pgn_move.comments.is_empty() || !pgn_move.variations.is_empty();
+        board.make_move(pgn_move.piece_move);
+    }
+    Ok(())
+}
+
+// Returns a comment for the movetext, where braces in the comment are removed
+// since comments cannot be nested.
+fn comment_token(comment: &str) -> String {
+    format!("{{{}}}", comment.replace(['{', '}'], ""))
+}
+
+// Returns the date for the PGN Date tag, e.g. 2025.01.31.
+pub fn pgn_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or(0);
+    // Converts days since 1970-01-01 to the civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+// Reads games from PGN text.
+#[derive(Clone, Debug)]
+pub struct PgnReader {
+    ruky: Ruky,
+    keep_variations: bool,
+}
+
+impl PgnReader {
+    pub fn new(ruky: Ruky) -> Self {
+        Self {
+            ruky,
+            keep_variations: false,
+        }
+    }
+
+    // If set, the moves of variations are validated and kept, otherwise
+    // variations are skipped. Variations are skipped by default.
+    pub fn keep_variations(&mut self, keep_variations: bool) -> &mut Self {
+        self.keep_variations = keep_variations;
+        self
+    }
+
+    // Reads all the games in pgn. Returns an error for the first game that
+    // cannot be read.
+    pub fn read_all(&self, pgn: &str) -> Result<Vec<PgnGame>, PgnErr> {
+        self.games(pgn).collect()
+    }
+
+    // Returns an iterator over the games in pgn. A game that cannot be read is
+    // returned as an error, and the iterator moves on to the next game.
+    pub fn games<'a>(&'a self, pgn: &'a str) -> impl Iterator<Item = Result<PgnGame, PgnErr>> + 'a {
+        let mut lexer = Lexer::new(pgn);
+        std::iter::from_fn(move || self.next_game(&mut lexer))
+    }
+
+    // Reads the next game, or returns None if there are no more games.
+    fn next_game(&self, lexer: &mut Lexer) -> Option<Result<PgnGame, PgnErr>> {
+        let tokens = match lexer.next_game()? {
+            Ok(tokens) => tokens,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(self.parse_game(tokens))
+    }
+
+    fn parse_game(&self, tokens: Vec<Token>) -> Result<PgnGame, PgnErr> {
+        let mut tags = Vec::new();
+        let mut iter = tokens.into_iter().peekable();
+        while let Some(Token::Tag(name, value)) = iter.peek() {
+            tags.push((name.clone(), value.clone()));
+            iter.next();
+        }
+
+        let tag = |tag: &str| {
+            tags.iter()
+                .find(|(name, _)| name == tag)
+                .map(|(_, value)| value.as_str())
+        };
+        // Variants without their own rules, e.g. Chess960, are played as
+        // standard chess.
+        let variant = tag("Variant")
+            .and_then(Variant::from_name)
+            .unwrap_or_default();
+        let board = match tag("FEN") {
+            Some(fen) => self
+                .ruky
+                .from_variant_fen(variant, fen)
+                .map_err(|err| PgnErr::BadFen(format!("{fen}: {err}")))?,
+            None => self.ruky.variant_board(variant),
+        };
+
+        let mut comments = Vec::new();
+        while let Some(Token::Comment(comment)) = iter.peek() {
+            comments.push(comment.clone());
+            iter.next();
+        }
+
+        let mut movetext: Vec<Token> = iter.collect();
+        let result = match movetext.last() {
+            Some(Token::Result(result)) => {
+                let result = result.clone();
+                movetext.pop();
+                result
+            }
+            _ => "*".to_string(),
+        };
+
+        let mut tokens = movetext.into_iter();
+        let moves = self.parse_line(&board, &mut tokens, false)?;
+
+        Ok(PgnGame {
+            tags,
+            board,
+            moves,
+            comments,
+            result,
+        })
+    }
+
+    // Parses the moves of a line starting from board, until the end of the
+    // tokens or the end of the variation if in_variation is set.
+    fn parse_line(
+        &self,
+        board: &Board,
+        tokens: &mut impl Iterator<Item = Token>,
+        in_variation: bool,
+    ) -> Result<Vec<PgnMove>, PgnErr> {
+        let mut moves: Vec<PgnMove> = Vec::new();
+        // The comments before the first move of the line.
+        let mut pre_comments = Vec::new();
+        let mut prev_board = board.clone();
+        let mut board = board.clone();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::San(san) => {
+                    let piece_move = board
+                        .parse_san(&san)
+                        .map_err(|err| PgnErr::BadMove(board.full_moves(), err.to_string()))?;
+                    prev_board = board.clone();
+                    board.make_move(piece_move);
+                    let mut pgn_move = PgnMove::new(piece_move);
+                    pgn_move.pre_comments = std::mem::take(&mut pre_comments);
+                    moves.push(pgn_move);
+                }
+                Token::Nag(nag) => {
+                    let last = moves
+                        .last_mut()
+                        .ok_or(PgnErr::Unexpected(format!("${nag}")))?;
+                    last.nags.push(nag);
+                }
+                Token::Comment(comment) => match moves.last_mut() {
+                    Some(last) => last.comments.push(comment),
+                    None => pre_comments.push(comment),
+                },
+                Token::StartVariation => {
+                    let last = moves
+                        .last_mut()
+                        .ok_or(PgnErr::Unexpected("(".to_string()))?;
+                    if self.keep_variations {
+                        let variation = self.parse_line(&prev_board, tokens, true)?;
+                        last.variations.push(variation);
+                    } else {
+                        skip_variation(tokens)?;
+                    }
+                }
+                Token::EndVariation => {
+                    if in_variation {
+                        return Ok(moves);
+                    }
+                    return Err(PgnErr::Unexpected(")".to_string()));
+                }
+                Token::Result(result) => return Err(PgnErr::Unexpected(result)),
+                Token::Tag(name, _) => return Err(PgnErr::Unexpected(format!("[{name}"))),
+            }
+        }
+
+        if in_variation {
+            Err(PgnErr::UnterminatedVariation)
+        } else {
+            Ok(moves)
+        }
+    }
+}
+
+// Skips the tokens of a variation, including nested variations.
+fn skip_variation(tokens: &mut impl Iterator<Item = Token>) -> Result<(), PgnErr> {
+    let mut depth = 1;
+    for token in tokens {
+        match token {
+            Token::StartVariation => depth += 1,
+            Token::EndVariation => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            _ => (),
+        }
+    }
+    Err(PgnErr::UnterminatedVariation)
+}
+
+// The tokens of PGN. Move numbers are dropped by the lexer.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    San(String),
+    Nag(u8),
+    Comment(String),
+    StartVariation,
+    EndVariation,
+    Result(String),
+}
+
+// Splits PGN text into tokens, one game at a time.
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    // Tokens to return before reading more text: a NAG for a move's suffix
+    // annotation, or a tag that was read past the end of the previous game.
+    pending: VecDeque<Token>,
+}
+
+impl Lexer {
+    fn new(pgn: &str) -> Self {
+        Self {
+            chars: pgn.chars().collect(),
+            pos: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    // Returns the tokens of the next game, or None if there are no more games.
+    // A game ends after a result, or when a tag follows the movetext.
+    fn next_game(&mut self) -> Option<Result<Vec<Token>, PgnErr>> {
+        let mut tokens = Vec::new();
+        let mut in_movetext = false;
+        loop {
+            let token = match self.next_token() {
+                Ok(Some(token)) => token,
+                Ok(None) => break,
+                Err(err) => {
+                    // Skip to the next game, which starts with a tag at the
+                    // start of a line.
+                    self.pending.clear();
+                    self.skip_to_next_game();
+                    return Some(Err(err));
+                }
+            };
+            match token {
+                Token::Tag(..) if in_movetext => {
+                    self.pending.push_front(token);
+                    break;
+                }
+                Token::Result(_) => {
+                    tokens.push(token);
+                    break;
+                }
+                Token::Tag(..) => tokens.push(token),
+                _ => {
+                    in_movetext = true;
+                    tokens.push(token);
+                }
+            }
+        }
+        if tokens.is_empty() {
+            None
+        } else {
+            Some(Ok(tokens))
+        }
+    }
+
+    fn skip_to_next_game(&mut self) {
+        while self.pos < self.chars.len() {
+            if self.chars[self.pos] == '[' && (self.pos == 0 || self.chars[self.pos - 1] == '\n') {
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    // Returns the next token, or None at the end of the text.
+    fn next_token(&mut self) -> Result<Option<Token>, PgnErr> {
+        if let Some(token) = self.pending.pop_front() {
+            return Ok(Some(token));
+        }
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Ok(None),
+            };
+            let at_line_start = self.pos == 0 || self.chars[self.pos - 1] == '\n';
+            match c {
+                _ if c.is_whitespace() => self.pos += 1,
+                // Lines starting with % are escaped, and ; starts a comment to the
+                // end of the line.
+                '%' if at_line_start => {
+                    self.read_until('\n');
+                }
+                ';' => {
+                    self.pos += 1;
+                    let comment = self.read_until('\n');
+                    return Ok(Some(Token::Comment(comment.trim().to_string())));
+                }
+                '{' => {
+                    self.pos += 1;
+                    let comment = self.read_until('}');
+                    if self.peek() != Some('}') {
+                        return Err(PgnErr::UnterminatedComment);
+                    }
+                    self.pos += 1;
+                    return Ok(Some(Token::Comment(comment.trim().to_string())));
+                }
+                '[' => return self.read_tag().map(Some),
+                '(' => {
+                    self.pos += 1;
+                    return Ok(Some(Token::StartVariation));
+                }
+                ')' => {
+                    self.pos += 1;
+                    return Ok(Some(Token::EndVariation));
+                }
+                '$' => {
+                    self.pos += 1;
+                    let nag = self.read_symbol();
+                    return nag
+                        .parse::<u8>()
+                        .map(|nag| Some(Token::Nag(nag)))
+                        .map_err(|_| PgnErr::Unexpected(format!("${nag}")));
+                }
+                '*' => {
+                    self.pos += 1;
+                    return Ok(Some(Token::Result("*".to_string())));
+                }
+                _ => {
+                    let symbol = self.read_symbol();
+                    if symbol.is_empty() {
+                        return Err(PgnErr::Unexpected(c.to_string()));
+                    }
+                    if let Some(token) = self.symbol_token(&symbol) {
+                        return Ok(Some(token));
+                    }
+                }
+            }
+        }
+    }
+
+    // Converts a symbol into a token, or returns None for a move number. A
+    // suffix annotation is returned as a NAG after the move.
+    fn symbol_token(&mut self, symbol: &str) -> Option<Token> {
+        if matches!(symbol, "1-0" | "0-1" | "1/2-1/2") {
+            return Some(Token::Result(symbol.to_string()));
+        }
+
+        // Move numbers, e.g. 12. or 12..., which may not be separated from the
+        // move by a space. Note that castling may be written with zeros.
+        let san = if symbol.starts_with("0-0") {
+            symbol
+        } else {
+            symbol
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .trim_start_matches('.')
+        };
+        if san.is_empty() {
+            return None;
+        }
+
+        let suffix_len = san.len() - san.trim_end_matches(['!', '?']).len();
+        let (san, suffix) = san.split_at(san.len() - suffix_len);
+        let nag = match suffix {
+            "!" => Some(1),
+            "?" => Some(2),
+            "!!" => Some(3),
+            "??" => Some(4),
+            "!?" => Some(5),
+            "?!" => Some(6),
+            _ => None,
+        };
+        if let Some(nag) = nag {
+            self.pending.push_back(Token::Nag(nag));
+        }
+        Some(Token::San(san.to_string()))
+    }
+
+    // Reads a symbol, which ends at whitespace or at a character that starts
+    // another token.
+    fn read_symbol(&mut self) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || "{}()[];$".contains(c) {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    // Reads until the end character or the end of the text, and does not consume
+    // the end character.
+    fn read_until(&mut self, end: char) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c == end {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    // Reads a tag pair, e.g. [Event "F/S Return Match"], up to the closing
+    // bracket. Several tags may share a line.
+    fn read_tag(&mut self) -> Result<Token, PgnErr> {
+        self.pos += 1;
+        let start = self.pos;
+        match self.read_tag_pair() {
+            Some(tag) => Ok(tag),
+            None => {
+                self.pos = start;
+                let line = self.read_until('\n');
+                Err(PgnErr::BadTag(line.trim().to_string()))
+            }
+        }
+    }
+
+    // Reads the name, the quoted value and the closing bracket of a tag pair.
+    // A value may not span lines.
+    fn read_tag_pair(&mut self) -> Option<Token> {
+        self.skip_spaces();
+        let name = self.read_symbol();
+        if name.is_empty() {
+            return None;
+        }
+        self.skip_spaces();
+        if self.peek() != Some('"') {
+            return None;
+        }
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek()? {
+                '"' => break,
+                '\n' => return None,
+                '\\' if matches!(self.chars.get(self.pos + 1), Some('"' | '\\')) => {
+                    self.pos += 1;
+                    value.push(self.chars[self.pos]);
+                }
+                c => value.push(c),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        self.skip_spaces();
+        if self.peek() != Some(']') {
+            return None;
+        }
+        self.pos += 1;
+        Some(Token::Tag(name, value))
+    }
+
+    // Skips whitespace within a line.
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace() && c != '\n') {
+            self.pos += 1;
+        }
+    }
+}
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum PgnErr {
+    #[error("tag {0} is not valid")]
+    BadTag(String),
+    #[error("FEN {0} is not valid")]
+    BadFen(String),
+    #[error("bad move in move {0}: {1}")]
+    BadMove(u16, String),
+    #[error("unexpected {0}")]
+    Unexpected(String),
+    #[error("comment is not terminated")]
+    UnterminatedComment,
+    #[error("variation is not terminated")]
+    UnterminatedVariation,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::GameState;
+    use crate::piece::{Color, Piece::*};
+    use crate::piece_move::PieceMove::*;
+    use crate::sq;
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        static ref RUKY: Ruky = Ruky::new();
+    }
+
+    const GAMES: &str = r#"[Event "Example"]
+[Site "?"]
+[White "Player \"One\""]
+[Black "Player Two"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2. Bc4 {the Italian} Nc6 (2... Nf6 3. d3 (3. Nc3)) 3. Qh5
+Nf6?? $18 4. Qxf7# 1-0
+
+[Event "From a position"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 40"]
+
+40.e4 Kd7 41. e5 ; a line comment
+41...Ke6 *
+"#;
+
+    #[test]
+    fn read_games() {
+        let games = PgnReader::new(RUKY.clone()).read_all(GAMES).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let game = &games[0];
+        assert_eq!(game.tags.len(), 5);
+        assert_eq!(game.tag("White"), Some("Player \"One\""));
+        assert_eq!(game.tag("Result"), Some("1-0"));
+        assert_eq!(game.tag("Round"), None);
+        assert_eq!(game.result, "1-0");
+        assert_eq!(game.board, RUKY.new_board());
+        assert_eq!(game.comments, vec!["Opening comment".to_string()]);
+        assert_eq!(game.moves.len(), 7);
+        assert_eq!(game.moves[2].comments, vec!["the Italian".to_string()]);
+        assert!(game.moves[3].variations.is_empty());
+        assert_eq!(game.moves[5].nags, vec![4, 18]);
+        assert_eq!(
+            game.moves[6].piece_move,
+            Queen(Capture {
+                from: sq::H5,
+                to: sq::F7,
+                cap: Pawn(()),
+            })
+        );
+        assert_eq!(
+            game.final_board().game_state(),
+            GameState::Mate(Color::Black)
+        );
+
+        let game = &games[1];
+        assert_eq!(game.result, "*");
+        assert_eq!(game.board.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 w - - 0 40");
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(game.moves[2].comments, vec!["a line comment".to_string()]);
+        assert_eq!(
+            game.final_board().to_fen(),
+            "8/8/4k3/4P3/8/8/8/4K3 w - - 1 42"
+        );
+    }
+
+    #[test]
+    fn keep_variations() {
+        let games = PgnReader::new(RUKY.clone())
+            .keep_variations(true)
+            .read_all(GAMES)
+            .unwrap();
+        let variations = &games[0].moves[3].variations;
+        assert_eq!(variations.len(), 1);
+        assert_eq!(variations[0].len(), 2);
+        assert_eq!(
+            variations[0][0].piece_move,
+            Knight(Simple {
+                from: sq::G8,
+                to: sq::F6,
+            })
+        );
+        assert_eq!(
+            variations[0][1].variations,
+            vec![vec![PgnMove::new(Knight(Simple {
+                from: sq::B1,
+                to: sq::C3,
+            }))]]
+        );
+    }
+
+    #[test]
+    fn comments_before_variations() {
+        let pgn = "1. e4 ({idea} 1. d4 d5) 1... e5 ({a} {b} 1... c5) *\n";
+        let games = PgnReader::new(RUKY.clone())
+            .keep_variations(true)
+            .read_all(pgn)
+            .unwrap();
+        let moves = &games[0].moves;
+        assert_eq!(
+            moves[0].variations[0][0].pre_comments,
+            vec!["idea".to_string()]
+        );
+        assert!(moves[0].variations[0][1].pre_comments.is_empty());
+        assert_eq!(
+            moves[1].variations[0][0].pre_comments,
+            vec!["a".to_string(), "b".to_string()]
+        );
+        assert_eq!(games[0].to_string(), pgn);
+    }
+
+    #[test]
+    fn tags_on_one_line() {
+        let pgn = "[Event \"A [1]\"] [Site \"y\"]\n1. e4 *\n[Event \"B\"][Round \"2\"]\n*";
+        let games = PgnReader::new(RUKY.clone()).read_all(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("A [1]"));
+        assert_eq!(games[0].tag("Site"), Some("y"));
+        assert_eq!(games[1].tag("Event"), Some("B"));
+        assert_eq!(games[1].tag("Round"), Some("2"));
+    }
+
+    #[test]
+    fn suffix_annotations() {
+        let pgn = "1. e4! e5? 2. Nf3!! Nc6?? 3. Bb5!? a6?! $32\n[Event \"B\"]\n*";
+        let games = PgnReader::new(RUKY.clone()).read_all(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+        let nags: Vec<Vec<u8>> = games[0].moves.iter().map(|m| m.nags.clone()).collect();
+        assert_eq!(
+            nags,
+            vec![vec![1], vec![2], vec![3], vec![4], vec![5], vec![6, 32]]
+        );
+        assert_eq!(games[1].tag("Event"), Some("B"));
+    }
+
+    #[test]
+    fn games_without_result() {
+        let pgn = "[Event \"A\"]\n1. e4 e5\n[Event \"B\"]\n1. d4 d5 1/2-1/2\n1. c4";
+        let games = PgnReader::new(RUKY.clone()).read_all(pgn).unwrap();
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].result, "*");
+        assert_eq!(games[0].moves.len(), 2);
+        assert_eq!(games[1].tag("Event"), Some("B"));
+        assert_eq!(games[1].result, "1/2-1/2");
+        assert!(games[2].tags.is_empty());
+        assert_eq!(games[2].moves.len(), 1);
+    }
+
+    #[test]
+    fn castling_with_zeros() {
+        let pgn = "1. e4 e5 2. Nf3 Nf6 3. Bc4 Bc5 4. 0-0 O-O *";
+        let games = PgnReader::new(RUKY.clone()).read_all(pgn).unwrap();
+        let board = games[0].final_board();
+        assert!(!board.has_wk_castle());
+        assert!(!board.has_bk_castle());
+        assert_eq!(games[0].moves.len(), 8);
+    }
+
+    #[test]
+    fn write_game() {
+        let reader = PgnReader::new(RUKY.clone()).keep_variations(true).clone();
+        let games = reader.read_all(GAMES).unwrap();
+        assert_eq!(
+            games[0].to_string(),
+            r#"[Event "Example"]
+[Site "?"]
+[White "Player \"One\""]
+[Black "Player Two"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2. Bc4 {the Italian} 2... Nc6 (2... Nf6 3. d3 (3.
+Nc3)) 3. Qh5 Nf6 $4 $18 4. Qxf7# 1-0
+"#
+        );
+        assert_eq!(
+            games[1].to_pgn().unwrap(),
+            r#"[Event "From a position"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 40"]
+
+40. e4 Kd7 41. e5 {a line comment} 41... Ke6 *
+"#
+        );
+        for game in games.iter() {
+            assert_eq!(reader.read_all(&game.to_string()), Ok(vec![game.clone()]));
+        }
+    }
+
+    #[test]
+    fn write_new_game() {
+        let board = RUKY.from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").unwrap();
+        let mut game = PgnGame::new(board);
+        game.set_tag("White", "A")
+            .set_tag("Black", "B")
+            .set_tag("White", "C");
+        let mut pgn_move = PgnMove::new(King(Simple {
+            from: sq::E8,
+            to: sq::D7,
+        }));
+        pgn_move.comments.push("value={0.5}".to_string());
+        game.moves.push(pgn_move);
+        assert_eq!(
+            game.to_string(),
+            "[White \"C\"]\n[Black \"B\"]\n\n40... Kd7 {value=0.5} *\n"
+        );
+    }
+
+    #[test]
+    fn write_illegal_move() {
+        let mut game = PgnGame::new(RUKY.new_board());
+        game.moves.push(PgnMove::new(Pawn(Simple {
+            from: sq::E2,
+            to: sq::E4,
+        })));
+        game.moves.push(PgnMove::new(Pawn(Simple {
+            from: sq::E4,
+            to: sq::E5,
+        })));
+        assert!(matches!(game.to_pgn(), Err(PgnErr::BadMove(1, _))));
+        let pgn = game.to_string();
+        assert!(pgn.starts_with("1. e4 {bad move in move 1: "));
+        assert!(pgn.ends_with("} *\n"));
+    }
+
+    #[test]
+    fn pgn_dates() {
+        assert_eq!(pgn_date(UNIX_EPOCH), "1970.01.01");
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1_709_208_000);
+        assert_eq!(pgn_date(time), "2024.02.29");
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1_735_689_599);
+        assert_eq!(pgn_date(time), "2024.12.31");
+    }
+
+    #[test]
+    fn errors() {
+        let reader = PgnReader::new(RUKY.clone());
+        assert!(matches!(
+            reader.read_all("1. e4 e5 2. Ke3 *"),
+            Err(PgnErr::BadMove(2, _))
+        ));
+        assert_eq!(
+            reader.read_all("1. e4 {oops"),
+            Err(PgnErr::UnterminatedComment)
+        );
+        assert_eq!(
+            reader.read_all("1. e4 (1. d4 *"),
+            Err(PgnErr::UnterminatedVariation)
+        );
+        assert_eq!(
+            reader.read_all("[Event \"A]\n1. e4 *"),
+            Err(PgnErr::BadTag("Event \"A]".into()))
+        );
+        assert!(matches!(
+            reader.read_all("[FEN \"8/8/8 w - - 0 1\"]\n*"),
+            Err(PgnErr::BadFen(_))
+        ));
+
+        // A bad game does not stop reading the following games.
+        let pgn = "1. e4 e5 2. Ke3 *\n\n[Event \"B\"]\n1. d4 *";
+        let games: Vec<_> = reader.games(pgn).collect();
+        assert_eq!(games.len(), 2);
+        assert!(games[0].is_err());
+        assert_eq!(games[1].as_ref().unwrap().tag("Event"), Some("B"));
+    }
+}
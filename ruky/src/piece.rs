@@ -1,6 +1,6 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum Piece<T> {
     King(T),
     Queen(T),
@@ -47,6 +47,20 @@ impl<T> Piece<T> {
         }
     }
 
+    // Returns the conventional value of the piece in centipawns. The king is
+    // worth more than all other pieces together, so that exchanges that lose
+    // the king are never favourable.
+    pub fn value(&self) -> i32 {
+        match *self {
+            Piece::King(_) => 10_000,
+            Piece::Queen(_) => 900,
+            Piece::Rook(_) => 500,
+            Piece::Bishop(_) => 300,
+            Piece::Knight(_) => 300,
+            Piece::Pawn(_) => 100,
+        }
+    }
+
     // Shorthand for returning the piece with the unit type.
     pub fn kind(&self) -> Piece<()> {
         self.with(())
@@ -69,7 +83,7 @@ impl<T> Piece<T> {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum Color {
     White,
     Black,
@@ -1,11 +1,11 @@
 use crate::bitboard::{BitErr, BitErr::*};
 use crate::piece::Piece;
 use crate::sq::Sq;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::convert::From;
 
 // An enum for representing the different types of moves for all chess pieces.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum PieceMove {
     // Simple represents simple moves that only need a source and destination square to be fully
     // specified.
@@ -52,6 +52,12 @@ pub enum PieceMove {
         promo: Piece<()>,
         cap: Piece<()>,
     },
+
+    // A piece from the pocket dropped on an empty square in Crazyhouse, where
+    // the piece is given by the Piece that wraps the move.
+    Drop {
+        to: Sq,
+    },
 }
 
 impl PieceMove {
@@ -76,7 +82,13 @@ impl PieceMove {
         matches!(*self, PieceMove::Castle { .. })
     }
 
-    // Returns source and destination squares.
+    // Returns true if the move represents a drop.
+    pub fn is_drop(&self) -> bool {
+        matches!(*self, PieceMove::Drop { .. })
+    }
+
+    // Returns source and destination squares. A drop has no source square, so
+    // both squares are the destination square.
     pub fn from_to(&self) -> (Sq, Sq) {
         match *self {
             PieceMove::Simple { from, to } => (from, to),
@@ -87,6 +99,7 @@ impl PieceMove {
             PieceMove::EnPassant { from, to, .. } => (from, to),
             PieceMove::Promo { from, to, .. } => (from, to),
             PieceMove::PromoCap { from, to, .. } => (from, to),
+            PieceMove::Drop { to } => (to, to),
         }
     }
 
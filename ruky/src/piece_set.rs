@@ -3,6 +3,7 @@ use crate::magics::ChessMagics;
 use crate::piece::{Color, Piece, Piece::*};
 use crate::piece_move::{MoveErr, PieceMove, PieceMove::*};
 use crate::sq::{self, Sq};
+use std::cmp::{max, min};
 
 /// PieceSet represents the set of pieces for player, with a bitboard for each
 /// type of piece.
@@ -16,8 +17,11 @@ pub struct PieceSet {
     pawn: BitBoard,
     all_bits: BitBoard,
     color: Color,
-    king_castle: bool,
-    queen_castle: bool,
+    // The files of the rooks that can castle on the king side and on the queen
+    // side. These are the h and a files in standard chess, but can be any file
+    // in Chess960.
+    king_rook: Option<u8>,
+    queen_rook: Option<u8>,
 }
 
 impl PieceSet {
@@ -32,8 +36,8 @@ impl PieceSet {
             pawn: BitBoard::from(0xff00),
             all_bits: BitBoard::from(0xffff),
             color: Color::White,
-            king_castle: true,
-            queen_castle: true,
+            king_rook: Some(7),
+            queen_rook: Some(0),
         }
     }
 
@@ -48,8 +52,8 @@ impl PieceSet {
             pawn: BitBoard::from(0xff << 48),
             all_bits: BitBoard::from(0xffff << 48),
             color: Color::Black,
-            king_castle: true,
-            queen_castle: true,
+            king_rook: Some(7),
+            queen_rook: Some(0),
         }
     }
 
@@ -64,8 +68,8 @@ impl PieceSet {
             pawn: BitBoard::new(),
             all_bits: BitBoard::new(),
             color: Color::White,
-            king_castle: false,
-            queen_castle: false,
+            king_rook: None,
+            queen_rook: None,
         }
     }
 
@@ -111,6 +115,21 @@ impl PieceSet {
         })
     }
 
+    // Returns the least valuable piece on the squares in bits, along with its
+    // square, or None if there are no pieces on those squares.
+    pub fn least_valuable(&self, bits: BitBoard) -> Option<Piece<Sq>> {
+        [
+            Pawn(self.pawn),
+            Knight(self.knight),
+            Bishop(self.bishop),
+            Rook(self.rook),
+            Queen(self.queen),
+            King(self.king),
+        ]
+        .into_iter()
+        .find_map(|pt| (pt.val() & bits).first_bit().map(|sq| pt.with(sq)))
+    }
+
     pub fn attacks(&self, other: &PieceSet, magics: &ChessMagics) -> AttackSquares {
         assert_ne!(self.color, other.color);
 
@@ -185,6 +204,9 @@ impl PieceSet {
     // the other pieces. Returns an error if the move is not valid, e.g. the
     // piece being moved is not found on the source square.
     pub fn apply_move(&mut self, piece_move: Piece<PieceMove>) -> Result<&mut Self, MoveErr> {
+        if let Drop { to } = piece_move.val() {
+            return self.drop_piece(piece_move.kind(), to);
+        }
         match piece_move {
             King(mv) => self.update_king(mv),
             Queen(mv) => self.simple_update(mv, Queen(())),
@@ -195,6 +217,22 @@ impl PieceSet {
         }
     }
 
+    // Adds a piece dropped on square to, which must be empty. Returns an error
+    // for kings, which cannot be dropped.
+    fn drop_piece(&mut self, piece: Piece<()>, to: Sq) -> Result<&mut Self, MoveErr> {
+        let bits = match piece {
+            Queen(_) => &mut self.queen,
+            Rook(_) => &mut self.rook,
+            Bishop(_) => &mut self.bishop,
+            Knight(_) => &mut self.knight,
+            Pawn(_) => &mut self.pawn,
+            King(_) => return Err(MoveErr::BadMove(King(Drop { to }))),
+        };
+        bits.set_bit_or(to)?;
+        self.all_bits.set_bit_or(to)?;
+        Ok(self)
+    }
+
     // Updates the position for the king. Note that this also handles castling.
     // Returns an error if the move is not valid.
     fn update_king(&mut self, mv: PieceMove) -> Result<&mut Self, MoveErr> {
@@ -209,15 +247,21 @@ impl PieceSet {
                 rook_from,
                 rook_to,
             } => {
-                self.king.update_bit(king_from, king_to)?;
-                self.rook.update_bit(rook_from, rook_to)?;
-                self.all_bits.update_bit(king_from, king_to)?;
-                self.all_bits.update_bit(rook_from, rook_to)?
+                // In Chess960 the king or rook may end on the square where the
+                // other one started, so both are removed before adding them back.
+                self.king.clear_bit_or(king_from)?;
+                self.rook.clear_bit_or(rook_from)?;
+                self.all_bits
+                    .clear_bit_or(king_from)?
+                    .clear_bit_or(rook_from)?;
+                self.king.set_bit_or(king_to)?;
+                self.rook.set_bit_or(rook_to)?;
+                self.all_bits.set_bit_or(king_to)?.set_bit_or(rook_to)?
             }
             _ => return Err(MoveErr::BadMove(King(mv))),
         };
-        self.king_castle = false;
-        self.queen_castle = false;
+        self.king_rook = None;
+        self.queen_rook = None;
         Ok(self)
     }
 
@@ -264,19 +308,12 @@ impl PieceSet {
         };
         match mv {
             Simple { from, to } | Capture { from, to, .. } => {
+                piece.update_bit(from, to)?;
+                self.all_bits.update_bit(from, to)?;
                 // TODO: Can this check be removed every time we make a simple update?
                 if piece_type.is_rook() {
-                    // Maybe remove castling right for a specific rook.
-                    match (self.color, from) {
-                        (Color::White, sq::A1) | (Color::Black, sq::A8) => {
-                            self.queen_castle = false
-                        }
-                        (Color::White, sq::H1) | (Color::Black, sq::H8) => self.king_castle = false,
-                        _ => (),
-                    };
+                    self.clear_castle_rook(from);
                 }
-                piece.update_bit(from, to)?;
-                self.all_bits.update_bit(from, to)?
             }
             _ => {
                 let piece_move = match piece_type {
@@ -303,15 +340,7 @@ impl PieceSet {
                     Rook(_) => {
                         // If we are removing a rook that has not moved, then we need to remove the
                         // the castling right for that side.
-                        match (self.color, to) {
-                            (Color::White, sq::A1) | (Color::Black, sq::A8) => {
-                                self.queen_castle = false;
-                            }
-                            (Color::White, sq::H1) | (Color::Black, sq::H8) => {
-                                self.king_castle = false;
-                            }
-                            _ => (),
-                        };
+                        self.clear_castle_rook(to);
                         &mut self.rook
                     }
                     Bishop(_) => &mut self.bishop,
@@ -330,10 +359,37 @@ impl PieceSet {
         Ok(self)
     }
 
+    // Removes all the pieces on the squares in bits, which is used for the
+    // explosions in Atomic chess. Exploded rooks lose their castling rights,
+    // and so does an exploded king.
+    pub fn explode(&mut self, bits: BitBoard) -> &mut Self {
+        for sq in (self.rook & bits).sq_iter() {
+            self.clear_castle_rook(sq);
+        }
+        if (self.king & bits).any() {
+            self.king_rook = None;
+            self.queen_rook = None;
+        }
+        let keep = !bits;
+        for piece in [
+            &mut self.king,
+            &mut self.queen,
+            &mut self.rook,
+            &mut self.bishop,
+            &mut self.knight,
+            &mut self.pawn,
+            &mut self.all_bits,
+        ] {
+            *piece = *piece & keep;
+        }
+        self
+    }
+
     // Returns a pair of optional moves for king and queen side castling if they are
     // valid, which means that the king or rook have not lost the right to
-    // castle, there are no pieces between the king and rook, and the squares
-    // between the king and rook are not being attacked.
+    // castle, there are no pieces between the king and rook, the king is not in
+    // check, and the squares that the king moves through are not being
+    // attacked.
     //
     // @param other The opposing pieces.
     // @param attacked A BitBoard representing all the squares that are attacked by
@@ -346,87 +402,94 @@ impl PieceSet {
     ) -> (Option<Piece<PieceMove>>, Option<Piece<PieceMove>>) {
         assert_ne!(self.color, other.color);
 
-        if !self.king_castle && !self.queen_castle {
+        if self.king_rook.is_none() && self.queen_rook.is_none() {
             return (None, None);
         }
 
-        let mut blocked = self.all() | other.all() | attacked;
+        let mut occupied = self.all() | other.all();
+        let mut attacked = attacked;
         if self.color == Color::Black {
-            blocked >>= 56;
+            occupied >>= 56;
+            attacked >>= 56;
         }
 
         (
-            self.try_king_castle(blocked),
-            self.try_queen_castle(blocked),
+            self.try_castle(self.king_rook, 6, 5, occupied, attacked),
+            self.try_castle(self.queen_rook, 2, 3, occupied, attacked),
         )
     }
 
-    // Computes the move for king side castling, if valid, otherwise returns None.
+    // Computes the castling move for the rook on rook_file, if valid, otherwise
+    // returns None. The king ends on the king_to file and the rook on the
+    // rook_to file, i.e. the g and f files for king side castling, and the c and
+    // d files for queen side castling, which also holds in Chess960.
     //
-    // @param blocked A bitboard represeting all the blocked squares because they
-    // are either occupied or attacked by the other pieces. @return The king
+    // @param occupied A bitboard representing all the occupied squares, shifted
+    // to the first rank. @param attacked A bitboard representing the squares
+    // attacked by the other pieces, shifted to the first rank. @return The
     // castling move if valid, or None.
-    fn try_king_castle(&self, blocked: BitBoard) -> Option<Piece<PieceMove>> {
-        if !self.king_castle {
-            return None;
-        }
+    fn try_castle(
+        &self,
+        rook_file: Option<u8>,
+        king_to: u8,
+        rook_to: u8,
+        occupied: BitBoard,
+        attacked: BitBoard,
+    ) -> Option<Piece<PieceMove>> {
+        let rook_from = rook_file?;
+        let king_from = self.king.first_bit()?.rc().1;
 
-        // The bit pattern representing no pieces between the king and king side rook.
-        let king_bits = BitBoard::from(0b10010000u64);
+        // The squares that the king and rook move through must be empty, except
+        // for the king and rook themselves.
+        let empty_mask = (file_span(king_from, king_to) | file_span(rook_from, rook_to))
+            & !(file_span(king_from, king_from) | file_span(rook_from, rook_from));
 
-        // The bit pattern to mask all squares between the king and king side rook.
-        let king_mask = BitBoard::from(0b11110000u64);
+        // The squares that the king moves through, including the squares where
+        // it starts and ends. Note that the rook may pass through an attacked
+        // square.
+        let safe_mask = file_span(king_from, king_to);
 
-        if (blocked & king_mask) != king_bits {
+        if (occupied & empty_mask).any() || (attacked & safe_mask).any() {
             None
         } else {
-            let (king_from, king_to, rook_from, rook_to) = if self.color.is_white() {
-                (sq::E1, sq::G1, sq::H1, sq::F1)
-            } else {
-                (sq::E8, sq::G8, sq::H8, sq::F8)
-            };
             Some(King(Castle {
-                king_from,
-                king_to,
-                rook_from,
-                rook_to,
+                king_from: self.back_rank_sq(king_from),
+                king_to: self.back_rank_sq(king_to),
+                rook_from: self.back_rank_sq(rook_from),
+                rook_to: self.back_rank_sq(rook_to),
             }))
         }
     }
 
-    // Computes the move for queen side castling, if valid, otherwise returns None.
-    //
-    // @param blocked A bitboard represeting all the blocked squares because they
-    // are either occupied or attacked by the other pieces. @return The king
-    // castling move if valid, or None.
-    fn try_queen_castle(&self, blocked: BitBoard) -> Option<Piece<PieceMove>> {
-        if !self.queen_castle {
-            return None;
+    // Removes the castling right for the rook on sq, if any. This is used when
+    // the rook moves or is captured.
+    fn clear_castle_rook(&mut self, sq: Sq) {
+        let (row, col) = sq.rc();
+        if row != self.back_rank() {
+            return;
         }
+        if self.king_rook == Some(col) {
+            self.king_rook = None;
+        }
+        if self.queen_rook == Some(col) {
+            self.queen_rook = None;
+        }
+    }
 
-        // The bit pattern representing no pieces between the king and queen side rook.
-        let queen_bits = BitBoard::from(0b00010001u64);
-
-        // The bit pattern to mask all squares between the king and queen side rook.
-        let queen_mask = BitBoard::from(0b00011111u64);
-
-        if (blocked & queen_mask) != queen_bits {
-            None
+    // Returns the row of the first rank for the pieces.
+    fn back_rank(&self) -> u8 {
+        if self.color.is_white() {
+            0
         } else {
-            let (king_from, king_to, rook_from, rook_to) = if self.color.is_white() {
-                (sq::E1, sq::C1, sq::A1, sq::D1)
-            } else {
-                (sq::E8, sq::C8, sq::A8, sq::D8)
-            };
-            Some(King(Castle {
-                king_from,
-                king_to,
-                rook_from,
-                rook_to,
-            }))
+            7
         }
     }
 
+    // Returns the square on the first rank for the pieces in the given file.
+    fn back_rank_sq(&self, file: u8) -> Sq {
+        Sq::from_rc(self.back_rank(), file).expect("File should be valid.")
+    }
+
     // Returns an iterator to iterate over each piece as a BitBoard.
     pub fn iter(&self) -> PieceIter<'_> {
         PieceIter::from(self)
@@ -435,14 +498,43 @@ impl PieceSet {
     // Returns true if the pieces have castling rights on the king side.
     #[inline]
     pub fn has_king_castle(&self) -> bool {
-        self.king_castle
+        self.king_rook.is_some()
     }
 
     // Returns true if the pieces have castling rights on the queen side.
     #[inline]
     pub fn has_queen_castle(&self) -> bool {
-        self.queen_castle
+        self.queen_rook.is_some()
+    }
+
+    // Returns true if the castling rights, if any, are for the king on the e file
+    // and the rooks on the a and h files, as in standard chess.
+    pub fn has_std_castle(&self) -> bool {
+        if self.king_rook.is_none() && self.queen_rook.is_none() {
+            return true;
+        }
+        self.king.first_bit().map(|sq| sq.rc().1) == Some(4)
+            && self.king_rook.unwrap_or(7) == 7
+            && self.queen_rook.unwrap_or(0) == 0
     }
+
+    // Returns the square of the rook that can castle on the king side, if any.
+    #[inline]
+    pub fn king_castle_rook(&self) -> Option<Sq> {
+        self.king_rook.map(|file| self.back_rank_sq(file))
+    }
+
+    // Returns the square of the rook that can castle on the queen side, if any.
+    #[inline]
+    pub fn queen_castle_rook(&self) -> Option<Sq> {
+        self.queen_rook.map(|file| self.back_rank_sq(file))
+    }
+}
+
+// Returns the squares on the first rank from file a to file b, inclusive.
+fn file_span(a: u8, b: u8) -> BitBoard {
+    let (low, high) = (min(a, b), max(a, b));
+    BitBoard::from((0xffu64 >> (7 - high)) & (0xffu64 << low))
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -504,6 +596,19 @@ impl<'a> Iterator for PieceIter<'a> {
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub(crate) struct PsBuilder {
     pieces: PieceSet,
+    // Castling with the outermost rook on the king side or queen side of the
+    // king, as set by K and Q in a FEN string.
+    king_castle: bool,
+    queen_castle: bool,
+    // The files of rooks that can castle, as a bit per file, which are used for
+    // Chess960 positions where the castling rook is not the outermost rook.
+    castle_files: u8,
+    // If set, the number of pieces of each type is not limited, since pieces
+    // can be dropped back on the board in Crazyhouse.
+    drops: bool,
+    // If set, the pieces may have no king, since the king of the side to move
+    // has exploded when an Atomic game is over.
+    kingless: bool,
 }
 
 impl PsBuilder {
@@ -511,6 +616,11 @@ impl PsBuilder {
     pub fn new() -> Self {
         PsBuilder {
             pieces: PieceSet::blank(),
+            king_castle: false,
+            queen_castle: false,
+            castle_files: 0,
+            drops: false,
+            kingless: false,
         }
     }
 
@@ -551,15 +661,34 @@ impl PsBuilder {
         self
     }
 
-    // Sets the bishop on the board.
+    // Sets the king castling rights with the outermost rook on the king side.
     pub fn set_king_castle(&mut self, can_castle: bool) -> &mut Self {
-        self.pieces.king_castle = can_castle;
+        self.king_castle = can_castle;
         self
     }
 
-    // Sets the queen castling rights.
+    // Sets the queen castling rights with the outermost rook on the queen side.
     pub fn set_queen_castle(&mut self, can_castle: bool) -> &mut Self {
-        self.pieces.queen_castle = can_castle;
+        self.queen_castle = can_castle;
+        self
+    }
+
+    // Adds the castling right for the rook on the first rank in the given file.
+    // The side of the castling right depends on the side of the king.
+    pub fn add_castle_rook(&mut self, file: u8) -> &mut Self {
+        self.castle_files |= 1 << file;
+        self
+    }
+
+    // Allows more pieces of each type than can be reached by promoting pawns.
+    pub fn set_drops(&mut self, drops: bool) -> &mut Self {
+        self.drops = drops;
+        self
+    }
+
+    // Allows the pieces to have no king.
+    pub fn set_kingless(&mut self, kingless: bool) -> &mut Self {
+        self.kingless = kingless;
         self
     }
 
@@ -570,9 +699,34 @@ impl PsBuilder {
     }
 
     pub fn build(mut self) -> Result<PieceSet, PiecesErr> {
-        if self.pieces.king.count() != 1 {
-            return Err(PiecesErr::NoKing);
+        match self.pieces.king.count() {
+            1 => (),
+            0 if self.kingless => (),
+            _ => return Err(PiecesErr::NoKing),
+        }
+        if !self.drops {
+            self.check_counts()?;
+        }
+        if self.king_castle || self.queen_castle || self.castle_files != 0 {
+            self.set_castle_rooks()?;
+        }
+        self.pieces.all_bits = self.pieces.king
+            | self.pieces.queen
+            | self.pieces.rook
+            | self.pieces.bishop
+            | self.pieces.knight
+            | self.pieces.pawn;
+        let count: u8 = self.pieces.iter().map(|pt| pt.val().count()).sum();
+        if count != self.pieces.all_bits.count() {
+            return Err(PiecesErr::OverlappingPieces);
         }
+
+        Ok(self.pieces)
+    }
+
+    // Checks that there are no more pieces of each type than can be reached by
+    // promoting pawns.
+    fn check_counts(&self) -> Result<(), PiecesErr> {
         if self.pieces.queen.count() > 10 {
             return Err(PiecesErr::TooManyQueens);
         }
@@ -588,30 +742,45 @@ impl PsBuilder {
         if self.pieces.pawn.count() > 8 {
             return Err(PiecesErr::TooManyPawns);
         }
-        if self.pieces.king_castle || self.pieces.queen_castle {
-            let (king_sq, arook, hrook) = if self.pieces.color.is_white() {
-                (sq::E1, sq::A1, sq::H1)
-            } else {
-                (sq::E8, sq::A8, sq::H8)
-            };
-            if !self.pieces.king.has_bit(king_sq) {
-                return Err(PiecesErr::BadCastle);
-            }
-            if self.pieces.king_castle && !self.pieces.rook.has_bit(hrook) {
+        Ok(())
+    }
+
+    // Sets the castling rooks from the castling rights, which requires the king
+    // and the rooks to be on the first rank.
+    fn set_castle_rooks(&mut self) -> Result<(), PiecesErr> {
+        let pieces = &mut self.pieces;
+        let back_rank = pieces.back_rank();
+        let king_sq = pieces.king.first_bit().ok_or(PiecesErr::NoKing)?;
+        let (king_row, king_file) = king_sq.rc();
+        if king_row != back_rank {
+            return Err(PiecesErr::BadCastle);
+        }
+        let rook_files: Vec<u8> = pieces
+            .rook
+            .sq_iter()
+            .filter(|sq| sq.rc().0 == back_rank)
+            .map(|sq| sq.rc().1)
+            .collect();
+
+        if self.king_castle {
+            let file = rook_files.iter().filter(|file| **file > king_file).max();
+            pieces.king_rook = Some(*file.ok_or(PiecesErr::BadCastle)?);
+        }
+        if self.queen_castle {
+            let file = rook_files.iter().filter(|file| **file < king_file).min();
+            pieces.queen_rook = Some(*file.ok_or(PiecesErr::BadCastle)?);
+        }
+        for file in (0..8u8).filter(|file| self.castle_files & (1 << file) != 0) {
+            if !rook_files.contains(&file) || file == king_file {
                 return Err(PiecesErr::BadCastle);
             }
-            if self.pieces.queen_castle && !self.pieces.rook.has_bit(arook) {
-                return Err(PiecesErr::BadCastle);
+            if file > king_file {
+                pieces.king_rook = Some(file);
+            } else {
+                pieces.queen_rook = Some(file);
             }
         }
-        self.pieces.all_bits = self.pieces.king
-            | self.pieces.queen
-            | self.pieces.rook
-            | self.pieces.bishop
-            | self.pieces.knight
-            | self.pieces.pawn;
-
-        Ok(self.pieces)
+        Ok(())
     }
 }
 
@@ -631,6 +800,16 @@ pub enum PiecesErr {
     TooManyPawns,
     #[error("invalid castling rights")]
     BadCastle,
+    #[error("more than one piece on a square")]
+    OverlappingPieces,
+    #[error("pawns on the first or last rank")]
+    PawnsOnBackRank,
+    #[error("the side not to move is in check")]
+    OpponentInCheck,
+    #[error("invalid en-passant square")]
+    InvalidPassant,
+    #[error("full move number must be at least 1")]
+    InvalidFullMove,
 }
 
 #[cfg(test)]
@@ -822,4 +1001,78 @@ mod tests {
             .build()
             .is_ok());
     }
+
+    #[test]
+    fn ps_builder_castling_for_chess960() {
+        let mut ps_builder = PsBuilder::new();
+        let pieces = ps_builder
+            .set_king(sq::B1)
+            .add_rook(sq::A1)
+            .add_rook(sq::D1)
+            .add_rook(sq::H1)
+            .set_king_castle(true)
+            .set_queen_castle(true)
+            .build()
+            .unwrap();
+        assert_eq!(pieces.king_castle_rook(), Some(sq::H1));
+        assert_eq!(pieces.queen_castle_rook(), Some(sq::A1));
+        assert!(!pieces.has_std_castle());
+
+        // The castling rook can be given by its file, even if it is not the
+        // outermost rook.
+        let pieces = ps_builder
+            .set_king_castle(false)
+            .add_castle_rook(3)
+            .build()
+            .unwrap();
+        assert_eq!(pieces.king_castle_rook(), Some(sq::D1));
+        assert_eq!(pieces.queen_castle_rook(), Some(sq::A1));
+
+        let mut ps_builder = PsBuilder::new();
+        assert_eq!(
+            ps_builder
+                .set_king(sq::B1)
+                .add_rook(sq::H1)
+                .add_castle_rook(6)
+                .build(),
+            Err(PiecesErr::BadCastle)
+        );
+
+        let mut ps_builder = PsBuilder::new();
+        assert_eq!(
+            ps_builder
+                .set_color(Color::Black)
+                .set_king(sq::B8)
+                .add_rook(sq::H8)
+                .set_queen_castle(true)
+                .build(),
+            Err(PiecesErr::BadCastle)
+        );
+    }
+
+    #[test]
+    fn chess960_castle_with_overlapping_squares() {
+        let mut ps_builder = PsBuilder::new();
+        let mut pieces = ps_builder
+            .set_king(sq::G1)
+            .add_rook(sq::H1)
+            .set_king_castle(true)
+            .build()
+            .unwrap();
+        let castle = King(Castle {
+            king_from: sq::G1,
+            king_to: sq::G1,
+            rook_from: sq::H1,
+            rook_to: sq::F1,
+        });
+        assert_eq!(
+            pieces.castle(&PieceSet::init_black(), BitBoard::new()),
+            (Some(castle), None)
+        );
+        pieces.apply_move(castle).unwrap();
+        assert_eq!(pieces.king(), BitBoard::from(sq::G1));
+        assert_eq!(pieces.rooks(), BitBoard::from(sq::F1));
+        assert_eq!(pieces.all(), BitBoard::from(&[sq::F1, sq::G1]));
+        assert!(!pieces.has_king_castle());
+    }
 }
@@ -0,0 +1,103 @@
+// This module contains the pocket of a player in Crazyhouse, i.e. the pieces
+// that the player captured, and which the player can drop back on the board as
+// their own pieces instead of making a move.
+
+use crate::piece::{Piece, Piece::*};
+use serde::{Deserialize, Serialize};
+
+// The pieces that can be in a pocket, in the order in which they are written,
+// e.g. in FEN.
+pub const POCKET_PIECES: [Piece<()>; 5] = [Queen(()), Rook(()), Bishop(()), Knight(()), Pawn(())];
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Pocket {
+    // The number of queens, rooks, bishops, knights and pawns in the pocket.
+    counts: [u8; 5],
+}
+
+impl Pocket {
+    // Returns the number of pieces of the given type in the pocket, which is
+    // always 0 for kings.
+    pub fn count<T>(&self, piece: Piece<T>) -> u8 {
+        pocket_index(&piece).map_or(0, |i| self.counts[i])
+    }
+
+    // Adds a piece to the pocket. Kings are never added to a pocket, since
+    // capturing the king ends the game.
+    pub fn add<T>(&mut self, piece: Piece<T>) -> &mut Self {
+        if let Some(i) = pocket_index(&piece) {
+            self.counts[i] += 1;
+        }
+        self
+    }
+
+    // Removes a piece from the pocket. Returns false if there is no such piece
+    // in the pocket, in which case the pocket is not changed.
+    pub fn remove<T>(&mut self, piece: Piece<T>) -> bool {
+        match pocket_index(&piece) {
+            Some(i) if self.counts[i] > 0 => {
+                self.counts[i] -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Returns the total number of pieces in the pocket.
+    pub fn len(&self) -> usize {
+        self.counts.iter().map(|c| *c as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.iter().all(|c| *c == 0)
+    }
+
+    // Returns the types of the pieces in the pocket with their counts, skipping
+    // the types that are not in the pocket.
+    pub fn iter(&self) -> impl Iterator<Item = (Piece<()>, u8)> + '_ {
+        POCKET_PIECES
+            .into_iter()
+            .zip(self.counts)
+            .filter(|(_, count)| *count > 0)
+    }
+}
+
+// Returns the index of piece into the counts of a pocket, or None for kings.
+fn pocket_index<T>(piece: &Piece<T>) -> Option<usize> {
+    match piece {
+        Queen(_) => Some(0),
+        Rook(_) => Some(1),
+        Bishop(_) => Some(2),
+        Knight(_) => Some(3),
+        Pawn(_) => Some(4),
+        King(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_remove() {
+        let mut pocket = Pocket::default();
+        assert!(pocket.is_empty());
+        pocket
+            .add(Pawn(()))
+            .add(Pawn(()))
+            .add(Knight(()))
+            .add(King(()));
+        assert_eq!(pocket.count(Pawn(())), 2);
+        assert_eq!(pocket.count(King(())), 0);
+        assert_eq!(pocket.len(), 3);
+        assert_eq!(
+            pocket.iter().collect::<Vec<_>>(),
+            vec![(Knight(()), 1), (Pawn(()), 2)]
+        );
+
+        assert!(pocket.remove(Knight(())));
+        assert!(!pocket.remove(Knight(())));
+        assert!(!pocket.remove(King(())));
+        assert_eq!(pocket.len(), 2);
+    }
+}
@@ -5,8 +5,10 @@ use crate::random_search::RandomSearch;
 use crate::ruky::Ruky;
 use crate::search::Search;
 use crate::sq::Sq;
+use crate::syzygy::Tablebase;
 use log;
 use std::cell::RefCell;
+use std::path::Path;
 use std::sync::Arc;
 use uzi::eng::Eng;
 use uzi::engtx::EngTx;
@@ -21,6 +23,8 @@ pub struct RandomEng<T: EngTx> {
     ruky: Ruky,
     uzi_out: Arc<T>,
     board: RefCell<Option<Board>>,
+    // The Syzygy tablebases that are probed for positions with few pieces.
+    tablebase: Option<Arc<Tablebase>>,
 }
 
 impl<T: EngTx> RandomEng<T> {
@@ -29,6 +33,7 @@ impl<T: EngTx> RandomEng<T> {
             ruky: Ruky::new(),
             uzi_out,
             board: RefCell::new(None),
+            tablebase: None,
         }
     }
 }
@@ -39,24 +44,26 @@ impl<E: EngTx> Eng for RandomEng<E> {
             PosOpt::StartPos => self.ruky.new_board(),
             PosOpt::Fen(ref fen) => self.ruky.from_fen(fen).map_err(|_| UziErr::Position)?,
         };
-        if pos.moves.is_some() {
-            // Convert the Uzi moves to moves that Ruky understands.
-            let moves: Vec<(u8, u8, Option<Piece<()>>)> = pos
-                .moves
-                .as_ref()
-                .unwrap()
-                .iter()
-                .filter(|pm| !pm.is_null())
-                .map(|pm| {
+        for pm in pos.moves.iter().flatten() {
+            // Null moves pass the turn, and are not legal when in check.
+            if pm.is_null() {
+                board.make_null_move().ok_or(UziErr::Position)?;
+                continue;
+            }
+            // Convert the Uzi move to a move that Ruky understands, where drops
+            // are given to the board as moves to the same square.
+            let rc = match *pm {
+                UziPm::Drop { piece, to } => (u8::from(to), u8::from(to), Some(piece.into())),
+                _ => {
                     let from_to = pm.from_to().unwrap();
                     (
                         u8::from(from_to.0),
                         u8::from(from_to.1),
                         pm.promo().map(|p| p.into()),
                     )
-                })
-                .collect();
-            board = board.next_from_rc(&moves).ok_or(UziErr::Position)?;
+                }
+            };
+            board = board.next_from_rc(&[rc]).ok_or(UziErr::Position)?;
         }
         self.board.borrow_mut().replace(board);
         Ok(())
@@ -70,20 +77,81 @@ impl<E: EngTx> Eng for RandomEng<E> {
         Ok(())
     }
 
+    // Sets the directories of the Syzygy tablebases, where an empty path or
+    // <empty> disables them.
+    fn syzygy_path(&mut self, path: &Path) -> Result<(), UziErr> {
+        if path.as_os_str().is_empty() || path == Path::new("<empty>") {
+            self.tablebase = None;
+            return Ok(());
+        }
+        let tablebase = Tablebase::open(path).map_err(|err| {
+            log::error!("Unable to open the Syzygy tablebases: {}", err);
+            UziErr::BadPath(path.to_path_buf())
+        })?;
+        log::info!(
+            "Found Syzygy tablebases with up to {} pieces",
+            tablebase.max_pieces()
+        );
+        self.tablebase = Some(Arc::new(tablebase));
+        Ok(())
+    }
+
     fn go(&mut self, _go_cmd: &Go) -> Result<(), UziErr> {
         // TODO: Make the errors specific and use the args in the go command.
         let binding = self.board.borrow();
         let board = binding.as_ref().ok_or(UziErr::Position)?;
+        // Play the best move from the tablebases if the position is in them,
+        // and fall back to the random search otherwise.
+        if let Some(best_move) = self.tablebase_move(board) {
+            log::info!("Found best move in the tablebases: {:?}", best_move);
+            self.uzi_out.send_best(to_uzi_pm(board, best_move));
+            return Ok(());
+        }
         let search_result = RandomSearch::new()
             .search_board(board)
             .map_err(|_| UziErr::Position)?;
         let best_move = search_result.best_move();
         log::info!("Calculated best move: {:?}", best_move);
-        self.uzi_out.send_best(best_move.into());
+        self.uzi_out.send_best(to_uzi_pm(board, best_move));
         Ok(())
     }
 }
 
+impl<T: EngTx> RandomEng<T> {
+    // Returns the best move from the tablebases, or None if the position is not
+    // in the tablebases.
+    fn tablebase_move(&self, board: &Board) -> Option<Piece<PieceMove>> {
+        let tablebase = self.tablebase.as_ref()?;
+        let num_pieces = (board.white().all() | board.black().all()).count() as usize;
+        if num_pieces > tablebase.max_pieces() {
+            return None;
+        }
+        match tablebase.best_move(board) {
+            Ok(best) => best.map(|(best_move, _)| best_move),
+            Err(err) => {
+                log::debug!("Unable to probe the tablebases: {}", err);
+                None
+            }
+        }
+    }
+}
+
+// Converts a move to a Uzi move, where castling is written as the king capturing
+// its own rook if the game is Chess960.
+fn to_uzi_pm(board: &Board, piece_move: Piece<PieceMove>) -> UziPm {
+    match piece_move.val() {
+        PieceMove::Castle {
+            king_from,
+            rook_from,
+            ..
+        } if board.is_chess960() => UziPm::Normal {
+            from: king_from.into(),
+            to: rook_from.into(),
+        },
+        _ => piece_move.into(),
+    }
+}
+
 impl From<UziPiece> for Piece<()> {
     fn from(piece: UziPiece) -> Piece<()> {
         match piece {
@@ -125,6 +193,10 @@ impl From<Piece<PieceMove>> for UziPm {
                 from: king_from.into(),
                 to: king_to.into(),
             },
+            PieceMove::Drop { to } => UziPm::Drop {
+                piece: piece_move.into(),
+                to: to.into(),
+            },
             PieceMove::Promo { from, to, promo }
             | PieceMove::PromoCap {
                 from, to, promo, ..
@@ -0,0 +1,442 @@
+/// This module contains code to render a Board as a diagram, either as text
+/// with ASCII or Unicode pieces, e.g. for logs and the terminal, or as an SVG
+/// image with arrows for moves, e.g. for reports on the moves considered by
+/// the search.
+///
+/// For example, the starting position after 1. e4 is rendered as:
+///
+/// 8 r n b q k b n r
+/// 7 p p p p p p p p
+/// 6 . . . . . . . .
+/// 5 . . . . . . . .
+/// 4 . . . .[P]. . .
+/// 3 . . . . . . . .
+/// 2 P P P P[.]P P P
+/// 1 R N B Q K B N R
+///   a b c d e f g h
+/// Black to move
+///
+/// where the squares of the last move are highlighted with brackets.
+use crate::board::Board;
+use crate::piece::{Color, Piece, Piece::*};
+use crate::piece_move::PieceMove;
+use crate::search::SearchResult;
+use crate::sq::Sq;
+use std::fmt::Write;
+
+// The size of a square in SVG pixels.
+const SQ_SIZE: usize = 45;
+// The size of the margin for coordinates in SVG pixels.
+const MARGIN: usize = 20;
+
+// The colors used in SVG.
+const LIGHT_COLOR: &str = "#f0d9b5";
+const DARK_COLOR: &str = "#b58863";
+const HIGHLIGHT_COLOR: &str = "#cdd16a";
+const ARROW_COLOR: &str = "#15781b";
+
+// Renders boards as text or SVG. By default, text is rendered with ASCII
+// pieces and coordinates, but without the side to move or the last move.
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    unicode: bool,
+    coordinates: bool,
+    side_to_move: bool,
+    last_move: bool,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self {
+            unicode: false,
+            coordinates: true,
+            side_to_move: false,
+            last_move: false,
+        }
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // If set, text is rendered with Unicode chess symbols rather than FEN
+    // letters.
+    pub fn unicode(&mut self, unicode: bool) -> &mut Self {
+        self.unicode = unicode;
+        self
+    }
+
+    // If set, the ranks and files are labelled.
+    pub fn coordinates(&mut self, coordinates: bool) -> &mut Self {
+        self.coordinates = coordinates;
+        self
+    }
+
+    // If set, text ends with a line for the side to move.
+    pub fn side_to_move(&mut self, side_to_move: bool) -> &mut Self {
+        self.side_to_move = side_to_move;
+        self
+    }
+
+    // If set, the squares of the last move are highlighted, with brackets in
+    // text and with a different color in SVG.
+    pub fn last_move(&mut self, last_move: bool) -> &mut Self {
+        self.last_move = last_move;
+        self
+    }
+
+    // Renders the board as text, from the point of view of white.
+    pub fn render(&self, board: &Board) -> String {
+        let highlights = self.highlights(board);
+        let mut text = String::new();
+        for row in (0..8).rev() {
+            if self.coordinates {
+                text.push((b'1' + row) as char);
+            }
+            for col in 0..8u8 {
+                let sq = Sq::from_rc(row, col).expect("Square should be valid.");
+                // When both this square and the one to its left are
+                // highlighted, both brackets are kept.
+                let left = col > 0 && highlights.contains(&Sq::from(sq.raw() - 1));
+                let separator = match (left, highlights.contains(&sq)) {
+                    (true, true) => "][",
+                    (false, true) => "[",
+                    (true, false) => "]",
+                    (false, false) => " ",
+                };
+                if col > 0 || self.coordinates || separator != " " {
+                    text.push_str(separator);
+                }
+                text.push(match piece_at(board, sq) {
+                    Some((color, piece)) => self.symbol(color, piece),
+                    None if self.unicode => '·',
+                    None => '.',
+                });
+            }
+            if highlights.contains(&Sq::from_rc(row, 7).expect("Square should be valid.")) {
+                text.push(']');
+            }
+            text.push('\n');
+        }
+        if self.coordinates {
+            text.push_str("  a b c d e f g h\n");
+        }
+        if self.side_to_move {
+            text.push_str(match board.color() {
+                Color::White => "White to move\n",
+                Color::Black => "Black to move\n",
+            });
+        }
+        text
+    }
+
+    // Renders the board as an SVG image from the point of view of white, with
+    // arrows drawn on top of the pieces.
+    pub fn svg(&self, board: &Board, arrows: &[Arrow]) -> String {
+        let margin = if self.coordinates { MARGIN } else { 0 };
+        let size = 8 * SQ_SIZE + margin;
+        let highlights = self.highlights(board);
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" \
+             viewBox=\"0 0 {size} {size}\">"
+        );
+        let _ = writeln!(
+            svg,
+            "<defs><marker id=\"arrowhead\" viewBox=\"0 0 10 10\" refX=\"5\" refY=\"5\" \
+             markerWidth=\"3\" markerHeight=\"3\" orient=\"auto\">\
+             <path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"{ARROW_COLOR}\"/></marker></defs>"
+        );
+
+        for sq in (0..64u8).map(Sq::from) {
+            let (x, y) = sq_origin(sq, margin);
+            let (row, col) = sq.rc();
+            let color = if highlights.contains(&sq) {
+                HIGHLIGHT_COLOR
+            } else if (row + col) % 2 == 0 {
+                DARK_COLOR
+            } else {
+                LIGHT_COLOR
+            };
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{x}\" y=\"{y}\" width=\"{SQ_SIZE}\" height=\"{SQ_SIZE}\" fill=\"{color}\"/>"
+            );
+            if let Some((color, piece)) = piece_at(board, sq) {
+                let _ = writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" \
+                     dominant-baseline=\"central\">{}</text>",
+                    x + SQ_SIZE / 2,
+                    y + SQ_SIZE / 2,
+                    SQ_SIZE * 4 / 5,
+                    unicode_symbol(color, piece)
+                );
+            }
+        }
+
+        if self.coordinates {
+            for i in 0..8 {
+                let _ = writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{}\" font-size=\"14\" text-anchor=\"middle\" \
+                     dominant-baseline=\"central\">{}</text>",
+                    margin / 2,
+                    (7 - i) * SQ_SIZE + SQ_SIZE / 2,
+                    i + 1
+                );
+                let _ = writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{}\" font-size=\"14\" text-anchor=\"middle\" \
+                     dominant-baseline=\"central\">{}</text>",
+                    margin + i * SQ_SIZE + SQ_SIZE / 2,
+                    8 * SQ_SIZE + margin / 2,
+                    (b'a' + i as u8) as char
+                );
+            }
+        }
+
+        for arrow in arrows.iter().filter(|arrow| arrow.from != arrow.to) {
+            let weight = arrow.weight.clamp(0.0, 1.0);
+            let (x1, y1) = sq_center(arrow.from, margin);
+            let (x2, y2) = sq_center(arrow.to, margin);
+            let _ = writeln!(
+                svg,
+                "<line x1=\"{x1}\" y1=\"{y1}\" x2=\"{x2}\" y2=\"{y2}\" stroke=\"{ARROW_COLOR}\" \
+                 stroke-width=\"{:.1}\" stroke-opacity=\"{:.2}\" stroke-linecap=\"round\" \
+                 marker-end=\"url(#arrowhead)\"/>",
+                4.0 + 8.0 * weight,
+                0.3 + 0.6 * weight
+            );
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    // Returns the squares to highlight for the last move, if enabled.
+    fn highlights(&self, board: &Board) -> Vec<Sq> {
+        match board.last_move().filter(|_| self.last_move) {
+            Some(piece_move) => {
+                let (from, to) = piece_move.val().from_to();
+                vec![from, to]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // Returns the text symbol for a piece.
+    fn symbol(&self, color: Color, piece: Piece<()>) -> char {
+        if self.unicode {
+            return unicode_symbol(color, piece);
+        }
+        let letter = match piece {
+            King(_) => 'k',
+            Queen(_) => 'q',
+            Rook(_) => 'r',
+            Bishop(_) => 'b',
+            Knight(_) => 'n',
+            Pawn(_) => 'p',
+        };
+        match color {
+            Color::White => letter.to_ascii_uppercase(),
+            Color::Black => letter,
+        }
+    }
+}
+
+// An arrow between two squares, where weight between 0 and 1 sets how thick
+// and opaque the arrow is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arrow {
+    pub from: Sq,
+    pub to: Sq,
+    pub weight: f32,
+}
+
+impl Arrow {
+    pub fn new(from: Sq, to: Sq, weight: f32) -> Self {
+        Self { from, to, weight }
+    }
+
+    // Returns the arrows for the n moves with the most visits in the search
+    // result, weighted by their share of the visits. If there are no visits,
+    // then the moves are ordered and weighted by their priors.
+    pub fn from_search(result: &SearchResult, n: usize) -> Vec<Arrow> {
+        let total_visits: u32 = result.moves.iter().map(|mp| mp.visits).sum();
+        let weight = |visits: u32, prior: f32| {
+            if total_visits > 0 {
+                visits as f32 / total_visits as f32
+            } else {
+                prior
+            }
+        };
+        let mut arrows: Vec<(f32, Piece<PieceMove>)> = result
+            .moves
+            .iter()
+            .map(|mp| (weight(mp.visits, mp.prior), mp.pm))
+            .collect();
+        arrows.sort_by(|a, b| b.0.total_cmp(&a.0));
+        arrows
+            .into_iter()
+            .take(n)
+            .map(|(weight, pm)| {
+                let (from, to) = pm.val().from_to();
+                Arrow::new(from, to, weight)
+            })
+            .collect()
+    }
+}
+
+// Returns the color and type of the piece on square sq, if any.
+fn piece_at(board: &Board, sq: Sq) -> Option<(Color, Piece<()>)> {
+    match (board.white().find_type(sq), board.black().find_type(sq)) {
+        (Some(piece), _) => Some((Color::White, piece)),
+        (None, Some(piece)) => Some((Color::Black, piece)),
+        (None, None) => None,
+    }
+}
+
+// Returns the Unicode chess symbol for a piece.
+fn unicode_symbol(color: Color, piece: Piece<()>) -> char {
+    match (color, piece) {
+        (Color::White, King(_)) => '♔',
+        (Color::White, Queen(_)) => '♕',
+        (Color::White, Rook(_)) => '♖',
+        (Color::White, Bishop(_)) => '♗',
+        (Color::White, Knight(_)) => '♘',
+        (Color::White, Pawn(_)) => '♙',
+        (Color::Black, King(_)) => '♚',
+        (Color::Black, Queen(_)) => '♛',
+        (Color::Black, Rook(_)) => '♜',
+        (Color::Black, Bishop(_)) => '♝',
+        (Color::Black, Knight(_)) => '♞',
+        (Color::Black, Pawn(_)) => '♟',
+    }
+}
+
+// Returns the top left corner of square sq in SVG, where the margin for
+// coordinates is on the left and at the bottom.
+fn sq_origin(sq: Sq, margin: usize) -> (usize, usize) {
+    let (row, col) = sq.rc();
+    (
+        margin + col as usize * SQ_SIZE,
+        (7 - row as usize) * SQ_SIZE,
+    )
+}
+
+// Returns the center of square sq in SVG.
+fn sq_center(sq: Sq, margin: usize) -> (usize, usize) {
+    let (x, y) = sq_origin(sq, margin);
+    (x + SQ_SIZE / 2, y + SQ_SIZE / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Mp;
+    use crate::Ruky;
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        static ref RUKY: Ruky = Ruky::new();
+    }
+
+    #[test]
+    fn render_ascii() {
+        let board = RUKY.new_board();
+        assert_eq!(
+            Renderer::new().render(&board),
+            "8 r n b q k b n r\n\
+             7 p p p p p p p p\n\
+             6 . . . . . . . .\n\
+             5 . . . . . . . .\n\
+             4 . . . . . . . .\n\
+             3 . . . . . . . .\n\
+             2 P P P P P P P P\n\
+             1 R N B Q K B N R\n  \
+             a b c d e f g h\n"
+        );
+        let text = Renderer::new().coordinates(false).render(&board);
+        assert_eq!(text.lines().next(), Some("r n b q k b n r"));
+        assert_eq!(text.lines().count(), 8);
+    }
+
+    #[test]
+    fn render_unicode_with_last_move() {
+        let board = RUKY
+            .new_board()
+            .next_from_rc(&[(12, 28, None), (54, 38, None), (6, 21, None)])
+            .unwrap();
+        let text = Renderer::new()
+            .unicode(true)
+            .last_move(true)
+            .side_to_move(true)
+            .render(&board);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "8 ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜");
+        assert_eq!(lines[3], "5 · · · · · · ♟ ·");
+        assert_eq!(lines[5], "3 · · · · ·[♘]· ·");
+        assert_eq!(lines[7], "1 ♖ ♘ ♗ ♕ ♔ ♗[·]♖");
+        assert_eq!(lines[9], "Black to move");
+
+        // The last move is highlighted on the first file without coordinates.
+        let board = RUKY.new_board().next_from_rc(&[(8, 16, None)]).unwrap();
+        let text = Renderer::new()
+            .coordinates(false)
+            .last_move(true)
+            .render(&board);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[5], "[P]. . . . . . .");
+        assert_eq!(lines[6], "[.]P P P P P P P");
+
+        // Adjacent squares of a one square king move keep both brackets.
+        let board = RUKY
+            .new_board()
+            .next_from_rc(&[
+                (12, 28, None),
+                (52, 36, None),
+                (5, 26, None),
+                (57, 42, None),
+                (4, 5, None),
+            ])
+            .unwrap();
+        let text = Renderer::new().last_move(true).render(&board);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[7], "1 R N B Q[.][K]N R");
+    }
+
+    #[test]
+    fn svg_with_arrows() {
+        let board = RUKY.new_board();
+        let mut result =
+            SearchResult::with_best(board.clone(), board.next_boards().unwrap()[0].clone());
+        for (board, visits) in board.next_boards().unwrap().into_iter().zip([5, 30, 15]) {
+            result.moves.push(Mp {
+                pm: board.last_move().unwrap(),
+                prior: 0.0,
+                visits,
+            });
+        }
+        let arrows = Arrow::from_search(&result, 2);
+        assert_eq!(arrows.len(), 2);
+        assert_eq!(arrows[0].weight, 0.6);
+        assert_eq!(arrows[1].weight, 0.3);
+
+        let svg = Renderer::new().svg(&board, &arrows);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect").count(), 64);
+        assert_eq!(svg.matches("<line").count(), 2);
+        assert_eq!(svg.matches('♙').count(), 8);
+        assert!(svg.contains(">a</text>"));
+
+        let svg = Renderer::new().coordinates(false).svg(&board, &[]);
+        assert!(svg.contains("width=\"360\""));
+        assert!(!svg.contains("<line"));
+        assert!(!svg.contains(">a</text>"));
+    }
+}
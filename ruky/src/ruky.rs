@@ -1,8 +1,12 @@
 use crate::board::{Board, BoardBuilder};
+use crate::chess960::{from_chess960, random_chess960};
+use crate::epd::{from_epd, Epd, EpdErr};
 use crate::fen::{from_fen, FenErr};
 use crate::magics::ChessMagics;
+use crate::packed::{from_packed, PackedBoard, PackedErr};
 use crate::piece::Piece;
 use crate::piece_move::PieceMove;
+use crate::variant::Variant;
 use std::sync::Arc;
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -29,11 +33,70 @@ impl Ruky {
         BoardBuilder::from(self.magics.clone())
     }
 
+    // Returns the board for the Chess960 starting position with number n, where
+    // 518 is the standard starting position, or None if n is not less than 960.
+    #[inline]
+    pub fn chess960_board(&self, n: u16) -> Option<Board> {
+        from_chess960(n, BoardBuilder::from(self.magics.clone()))
+    }
+
+    // Returns the board for a random Chess960 starting position.
+    pub fn random_chess960_board(&self) -> Board {
+        self.chess960_board(random_chess960())
+            .expect("Random Chess960 number should be valid.")
+    }
+
+    // Returns the board for the starting position of a variant.
+    pub fn variant_board(&self, variant: Variant) -> Board {
+        self.from_variant_fen(variant, &self.new_board().to_fen())
+            .expect("Starting position should be valid.")
+    }
+
+    // Parses a FEN string for a variant. Note that a FEN string with the checks
+    // field of Three-check is always parsed as Three-check.
+    pub fn from_variant_fen(&self, variant: Variant, fen: &str) -> Result<Board, FenErr> {
+        let mut builder = BoardBuilder::from(self.magics.clone());
+        builder.set_variant(variant);
+        from_fen(fen, builder)
+    }
+
     #[inline]
     pub fn from_fen(&self, fen: &str) -> Result<Board, FenErr> {
         from_fen(fen, BoardBuilder::from(self.magics.clone()))
     }
 
+    // Parses a FEN string without rejecting positions that cannot occur in a
+    // game, e.g. with pawns on the back ranks, which is useful for test
+    // positions.
+    pub fn from_fen_lenient(&self, fen: &str) -> Result<Board, FenErr> {
+        let mut builder = BoardBuilder::from(self.magics.clone());
+        builder.set_lenient(true);
+        from_fen(fen, builder)
+    }
+
+    #[inline]
+    pub fn from_packed(&self, packed: &PackedBoard) -> Result<Board, PackedErr> {
+        from_packed(packed, BoardBuilder::from(self.magics.clone()))
+    }
+
+    #[inline]
+    pub fn from_epd(&self, epd: &str) -> Result<Epd, EpdErr> {
+        from_epd(epd, BoardBuilder::from(self.magics.clone()))
+    }
+
+    // Reads a suite of EPD positions, one per line. Empty lines and lines
+    // starting with # are skipped.
+    pub fn read_epds(&self, epds: &str) -> Result<Vec<Epd>, EpdErr> {
+        epds.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+            .map(|(i, line)| {
+                self.from_epd(line)
+                    .map_err(|err| EpdErr::BadLine(i + 1, Box::new(err)))
+            })
+            .collect()
+    }
+
     #[inline]
     pub fn moves_from_fen(&self, fen: &str) -> Result<Option<Vec<Piece<PieceMove>>>, FenErr> {
         self.from_fen(fen).map(|board| board.next_moves())
@@ -43,7 +106,7 @@ impl Ruky {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::board::GameState;
+    use crate::board::{DrawReason, GameState};
     use crate::piece::Piece::*;
     use crate::piece_move::PieceMove::*;
     use crate::sq;
@@ -880,6 +943,6 @@ mod tests {
             .expect("FEN is OK");
         assert!(board.is_terminal());
         assert!(!board.is_check());
-        assert_eq!(board.game_state(), GameState::Draw);
+        assert_eq!(board.game_state(), GameState::Draw(DrawReason::Stalemate));
     }
 }
@@ -0,0 +1,584 @@
+/// This module contains code to write and parse moves in Standard Algebraic
+/// Notation (SAN), which is the notation used in game records and by most
+/// chess tools. A move in SAN consists of:
+///
+/// * the piece letter, which is omitted for pawns
+/// * the file and/or rank of the source square, only if needed to tell apart
+///   two pieces of the same type that can move to the same square
+/// * an x for captures
+/// * the destination square
+/// * the promotion, e.g. =Q
+/// * a + for check or a # for mate
+///
+/// Castling is written as O-O for king side and O-O-O for queen side, and
+/// drops in Crazyhouse as the piece letter, an @ and the destination square.
+///
+/// For example: e4, Nbd7, exd5, R1a3, e8=Q+, O-O-O, Qxf7#, N@f3, P@e6.
+///
+/// For more background, see https://www.chessprogramming.org/Algebraic_Chess_Notation.
+use crate::board::{Board, GameState};
+use crate::piece::{Piece, Piece::*};
+use crate::piece_move::{PieceMove, PieceMove::*};
+use crate::sq::Sq;
+
+/// `to_san` writes a move in SAN.
+///
+/// # Arguments
+///
+/// * `board` - The board before the move is made.
+/// * `piece_move` - The move to write.
+///
+/// Returns the move in SAN, or None if the move is not legal.
+pub(crate) fn to_san(board: &Board, piece_move: Piece<PieceMove>) -> Option<String> {
+    let moves = board.next_moves()?;
+    if !moves.contains(&piece_move) {
+        return None;
+    }
+
+    let mv = piece_move.val();
+    let (from, to) = mv.from_to();
+    let mut san = String::new();
+
+    match mv {
+        Castle { king_to, .. } => {
+            san.push_str(if king_to.rc().1 == 6 { "O-O" } else { "O-O-O" });
+        }
+        Drop { to } => {
+            san.push(piece_char(piece_move));
+            san.push('@');
+            push_sq(to, &mut san);
+        }
+        _ if piece_move.is_pawn() => {
+            if mv.is_capture() {
+                san.push(file_char(from));
+                san.push('x');
+            }
+            push_sq(to, &mut san);
+            if let Some(promo) = mv.promo() {
+                san.push('=');
+                san.push(piece_char(promo));
+            }
+        }
+        _ => {
+            san.push(piece_char(piece_move));
+            push_disambiguation(&moves, piece_move, &mut san);
+            if mv.is_capture() {
+                san.push('x');
+            }
+            push_sq(to, &mut san);
+        }
+    }
+
+    let next = board.next_from_move(piece_move)?;
+    match next.game_state() {
+        GameState::Mate(_) => san.push('#'),
+        _ if next.is_check() => san.push('+'),
+        _ => (),
+    }
+
+    Some(san)
+}
+
+/// `from_san` parses a move in SAN.
+///
+/// # Arguments
+///
+/// * `board` - The board on which the move is made.
+/// * `san` - The move in SAN.
+///
+/// Returns a Result with the matching legal move, or a SanErr if the move is
+/// not valid SAN, or if there is not exactly one legal move that matches it.
+/// The check and mate markers, and annotations such as ! or ?, are ignored.
+/// Captures are matched with or without the x, and pawn drops with or without
+/// the P.
+pub(crate) fn from_san(board: &Board, san: &str) -> Result<Piece<PieceMove>, SanErr> {
+    let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+    if text.is_empty() {
+        return Err(SanErr::Empty);
+    }
+
+    let moves = board
+        .next_moves()
+        .ok_or_else(|| SanErr::NoMatch(san.to_string()))?;
+
+    // Castling, where 0-0 is also accepted.
+    let castle_to = match text {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None,
+    };
+    if let Some(col) = castle_to {
+        return moves
+            .into_iter()
+            .find(|pm| matches!(pm.val(), Castle { king_to, .. } if king_to.rc().1 == col))
+            .ok_or_else(|| SanErr::NoMatch(san.to_string()));
+    }
+
+    // Drops, e.g. N@f3, where pawn drops may also be written as @e4.
+    if let Some((letter, sq)) = text.split_once('@') {
+        let piece = match letter {
+            "" | "P" => Pawn(()),
+            _ => letter
+                .chars()
+                .next()
+                .and_then(promo_piece)
+                .filter(|_| letter.len() == 1)
+                .ok_or_else(|| SanErr::BadFormat(san.to_string()))?,
+        };
+        let to = parse_sq(sq).ok_or_else(|| SanErr::BadFormat(san.to_string()))?;
+        return moves
+            .into_iter()
+            .find(|pm| pm.kind() == piece && pm.val() == Drop { to })
+            .ok_or_else(|| SanErr::NoMatch(san.to_string()));
+    }
+
+    let pattern = SanPattern::parse(text).ok_or_else(|| SanErr::BadFormat(san.to_string()))?;
+    let mut matches = moves.into_iter().filter(|pm| pattern.matches(*pm));
+    match (matches.next(), matches.next()) {
+        (Some(pm), None) => Ok(pm),
+        (Some(_), Some(_)) => Err(SanErr::Ambiguous(san.to_string())),
+        (None, _) => Err(SanErr::NoMatch(san.to_string())),
+    }
+}
+
+// The parts of a move in SAN other than castling.
+struct SanPattern {
+    piece: Piece<()>,
+    from_col: Option<u8>,
+    from_row: Option<u8>,
+    is_capture: bool,
+    to: Sq,
+    promo: Option<Piece<()>>,
+}
+
+impl SanPattern {
+    // Parses a move in SAN without the check and mate markers. Returns None if
+    // the text is not valid SAN.
+    fn parse(text: &str) -> Option<Self> {
+        let mut chars: Vec<char> = text.chars().collect();
+
+        let piece = match chars.first()? {
+            'K' => King(()),
+            'Q' => Queen(()),
+            'R' => Rook(()),
+            'B' => Bishop(()),
+            'N' => Knight(()),
+            _ => Pawn(()),
+        };
+        if !piece.is_pawn() {
+            chars.remove(0);
+        }
+
+        // The promotion, written as =Q or just Q.
+        let mut promo = None;
+        if let Some(promo_piece) = chars.last().and_then(|c| promo_piece(*c)) {
+            if !piece.is_pawn() {
+                return None;
+            }
+            promo = Some(promo_piece);
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        // The destination square.
+        if chars.len() < 2 {
+            return None;
+        }
+        let row = row_index(chars.pop()?)?;
+        let col = col_index(chars.pop()?)?;
+        let to = Sq::from_rc(row, col)?;
+
+        // The optional capture.
+        let is_capture = chars.last() == Some(&'x');
+        if is_capture {
+            chars.pop();
+        }
+
+        // The optional disambiguation.
+        let (mut from_col, mut from_row) = (None, None);
+        for c in chars {
+            if let (Some(col), None, None) = (col_index(c), from_col, from_row) {
+                from_col = Some(col);
+            } else if let (Some(row), None) = (row_index(c), from_row) {
+                from_row = Some(row);
+            } else {
+                return None;
+            }
+        }
+
+        Some(Self {
+            piece,
+            from_col,
+            from_row,
+            is_capture,
+            to,
+            promo,
+        })
+    }
+
+    // Returns true if piece_move matches the pattern.
+    fn matches(&self, piece_move: Piece<PieceMove>) -> bool {
+        let mv = piece_move.val();
+        let (from, to) = mv.from_to();
+        let (from_row, from_col) = from.rc();
+        piece_move.kind() == self.piece
+            && !mv.is_castle()
+            && !mv.is_drop()
+            && to == self.to
+            && mv.promo() == self.promo
+            && (!self.is_capture || mv.is_capture())
+            && self.from_col.is_none_or(|col| col == from_col)
+            && self.from_row.is_none_or(|row| row == from_row)
+    }
+}
+
+// Writes the file and/or rank of the source square if there is another piece of
+// the same type that can move to the same destination square.
+fn push_disambiguation(moves: &[Piece<PieceMove>], piece_move: Piece<PieceMove>, san: &mut String) {
+    let (from, to) = piece_move.val().from_to();
+    let others: Vec<Sq> = moves
+        .iter()
+        .filter(|pm| pm.kind() == piece_move.kind() && !pm.val().is_castle() && !pm.val().is_drop())
+        .map(|pm| pm.val().from_to())
+        .filter(|(other_from, other_to)| *other_to == to && *other_from != from)
+        .map(|(other_from, _)| other_from)
+        .collect();
+
+    if others.is_empty() {
+        return;
+    }
+
+    let (row, col) = from.rc();
+    if others.iter().all(|sq| sq.rc().1 != col) {
+        san.push(file_char(from));
+    } else if others.iter().all(|sq| sq.rc().0 != row) {
+        san.push(rank_char(from));
+    } else {
+        san.push(file_char(from));
+        san.push(rank_char(from));
+    }
+}
+
+fn push_sq(sq: Sq, san: &mut String) {
+    san.push(file_char(sq));
+    san.push(rank_char(sq));
+}
+
+// Parses a square such as e4, or returns None if it is not a valid square.
+fn parse_sq(text: &str) -> Option<Sq> {
+    let mut chars = text.chars();
+    let col = col_index(chars.next()?)?;
+    let row = row_index(chars.next()?)?;
+    if chars.next().is_some() {
+        return None;
+    }
+    Sq::from_rc(row, col)
+}
+
+fn file_char(sq: Sq) -> char {
+    (b'a' + sq.rc().1) as char
+}
+
+fn rank_char(sq: Sq) -> char {
+    (b'1' + sq.rc().0) as char
+}
+
+fn col_index(c: char) -> Option<u8> {
+    ('a'..='h').contains(&c).then(|| c as u8 - b'a')
+}
+
+fn row_index(c: char) -> Option<u8> {
+    ('1'..='8').contains(&c).then(|| c as u8 - b'1')
+}
+
+// Returns the uppercase SAN letter for a piece.
+fn piece_char<T>(piece: Piece<T>) -> char {
+    match piece {
+        King(_) => 'K',
+        Queen(_) => 'Q',
+        Rook(_) => 'R',
+        Bishop(_) => 'B',
+        Knight(_) => 'N',
+        Pawn(_) => 'P',
+    }
+}
+
+// Returns the piece for a promotion letter, or None if the letter is not a
+// valid promotion.
+fn promo_piece(c: char) -> Option<Piece<()>> {
+    match c {
+        'Q' => Some(Queen(())),
+        'R' => Some(Rook(())),
+        'B' => Some(Bishop(())),
+        'N' => Some(Knight(())),
+        _ => None,
+    }
+}
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum SanErr {
+    #[error("empty move")]
+    Empty,
+    #[error("move {0} is not valid SAN")]
+    BadFormat(String),
+    #[error("move {0} does not match any legal move")]
+    NoMatch(String),
+    #[error("move {0} matches more than one legal move")]
+    Ambiguous(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BoardBuilder;
+    use crate::fen::from_fen;
+    use crate::magics::ChessMagics;
+    use crate::sq;
+    use lazy_static::lazy_static;
+    use std::sync::Arc;
+
+    lazy_static! {
+        static ref MAGICS: Arc<ChessMagics> = Arc::new(
+            ChessMagics::from_precomputed().expect("Unable to compute magics for unit test.")
+        );
+    }
+
+    fn board(fen: &str) -> Board {
+        from_fen(fen, BoardBuilder::from(MAGICS.clone())).unwrap()
+    }
+
+    #[test]
+    fn simple_moves() {
+        let board = Board::from(MAGICS.clone());
+        let e4 = Pawn(Simple {
+            from: sq::E2,
+            to: sq::E4,
+        });
+        let nf3 = Knight(Simple {
+            from: sq::G1,
+            to: sq::F3,
+        });
+        assert_eq!(to_san(&board, e4), Some("e4".to_string()));
+        assert_eq!(to_san(&board, nf3), Some("Nf3".to_string()));
+        assert_eq!(from_san(&board, "e4"), Ok(e4));
+        assert_eq!(from_san(&board, "Nf3"), Ok(nf3));
+        assert_eq!(from_san(&board, "Ng1f3"), Ok(nf3));
+    }
+
+    #[test]
+    fn illegal_moves() {
+        let board = Board::from(MAGICS.clone());
+        let e5 = Pawn(Simple {
+            from: sq::E2,
+            to: sq::E5,
+        });
+        assert_eq!(to_san(&board, e5), None);
+        assert_eq!(from_san(&board, "e5"), Err(SanErr::NoMatch("e5".into())));
+        assert_eq!(from_san(&board, "Ke2"), Err(SanErr::NoMatch("Ke2".into())));
+        assert_eq!(from_san(&board, "O-O"), Err(SanErr::NoMatch("O-O".into())));
+        assert_eq!(from_san(&board, ""), Err(SanErr::Empty));
+        assert_eq!(
+            from_san(&board, "Zf3"),
+            Err(SanErr::BadFormat("Zf3".into()))
+        );
+        assert_eq!(
+            from_san(&board, "Nf9"),
+            Err(SanErr::BadFormat("Nf9".into()))
+        );
+    }
+
+    #[test]
+    fn disambiguation() {
+        // Knights on b8 and f6 can both move to d7, rooks on a1 and a5 can both
+        // move to a3, and queens on h4, e4 and h1 can all move to e1.
+        let board = board("1n2k3/4p3/5n2/R7/4Q2Q/8/2K5/R6Q w - - 0 1");
+        let moves = [
+            (
+                Rook(Simple {
+                    from: sq::A1,
+                    to: sq::A3,
+                }),
+                "R1a3",
+            ),
+            (
+                Rook(Simple {
+                    from: sq::A5,
+                    to: sq::A3,
+                }),
+                "R5a3",
+            ),
+            (
+                Queen(Simple {
+                    from: sq::H4,
+                    to: sq::E1,
+                }),
+                "Qh4e1",
+            ),
+            (
+                Queen(Simple {
+                    from: sq::E4,
+                    to: sq::E1,
+                }),
+                "Qee1",
+            ),
+            (
+                Queen(Simple {
+                    from: sq::H1,
+                    to: sq::E1,
+                }),
+                "Q1e1",
+            ),
+            (
+                Queen(Simple {
+                    from: sq::H1,
+                    to: sq::G2,
+                }),
+                "Qhg2",
+            ),
+        ];
+        for (pm, san) in moves {
+            assert_eq!(to_san(&board, pm).as_deref(), Some(san));
+            assert_eq!(from_san(&board, san), Ok(pm));
+        }
+        assert_eq!(
+            from_san(&board, "Ra3"),
+            Err(SanErr::Ambiguous("Ra3".into()))
+        );
+
+        let board = board.next_from_move(moves[0].0).unwrap();
+        let nbd7 = Knight(Simple {
+            from: sq::B8,
+            to: sq::D7,
+        });
+        assert_eq!(to_san(&board, nbd7), Some("Nbd7".to_string()));
+        assert_eq!(from_san(&board, "Nbd7"), Ok(nbd7));
+    }
+
+    #[test]
+    fn pawn_captures_and_promotions() {
+        let board = board("1r2k3/P7/8/3p4/4P3/8/8/4K3 w - - 0 1");
+        let exd5 = Pawn(Capture {
+            from: sq::E4,
+            to: sq::D5,
+            cap: Pawn(()),
+        });
+        let a8q = Pawn(Promo {
+            from: sq::A7,
+            to: sq::A8,
+            promo: Queen(()),
+        });
+        let axb8n = Pawn(PromoCap {
+            from: sq::A7,
+            to: sq::B8,
+            promo: Knight(()),
+            cap: Rook(()),
+        });
+        assert_eq!(to_san(&board, exd5), Some("exd5".to_string()));
+        assert_eq!(to_san(&board, a8q), Some("a8=Q".to_string()));
+        assert_eq!(to_san(&board, axb8n), Some("axb8=N".to_string()));
+        assert_eq!(from_san(&board, "exd5"), Ok(exd5));
+        assert_eq!(from_san(&board, "ed5"), Ok(exd5));
+        assert_eq!(from_san(&board, "a8=Q+"), Ok(a8q));
+        assert_eq!(from_san(&board, "a8Q"), Ok(a8q));
+        assert_eq!(from_san(&board, "axb8=N"), Ok(axb8n));
+        assert_eq!(from_san(&board, "a8"), Err(SanErr::NoMatch("a8".into())));
+        assert_eq!(
+            from_san(&board, "Ka8=Q"),
+            Err(SanErr::BadFormat("Ka8=Q".into()))
+        );
+    }
+
+    #[test]
+    fn castles() {
+        let board = board("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1");
+        let short = King(Castle {
+            king_from: sq::E8,
+            king_to: sq::G8,
+            rook_from: sq::H8,
+            rook_to: sq::F8,
+        });
+        let long = King(Castle {
+            king_from: sq::E8,
+            king_to: sq::C8,
+            rook_from: sq::A8,
+            rook_to: sq::D8,
+        });
+        assert_eq!(to_san(&board, short), Some("O-O".to_string()));
+        assert_eq!(to_san(&board, long), Some("O-O-O".to_string()));
+        assert_eq!(from_san(&board, "O-O"), Ok(short));
+        assert_eq!(from_san(&board, "0-0-0"), Ok(long));
+    }
+
+    #[test]
+    fn check_and_mate() {
+        let board = board("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4");
+        let qxf7 = Queen(Capture {
+            from: sq::F3,
+            to: sq::F7,
+            cap: Pawn(()),
+        });
+        let bxf7 = Bishop(Capture {
+            from: sq::C4,
+            to: sq::F7,
+            cap: Pawn(()),
+        });
+        assert_eq!(to_san(&board, qxf7), Some("Qxf7#".to_string()));
+        assert_eq!(to_san(&board, bxf7), Some("Bxf7+".to_string()));
+        assert_eq!(from_san(&board, "Qxf7#"), Ok(qxf7));
+        assert_eq!(from_san(&board, "Bxf7+!?"), Ok(bxf7));
+    }
+
+    #[test]
+    fn drops() {
+        let board = board("r1bqkbnr/pppp1ppp/2n5/8/8/5N2/PPPP1PPP/RNBQKB1R[Pn] w KQkq - 0 4");
+        let p_e4 = Pawn(Drop { to: sq::E4 });
+        assert_eq!(to_san(&board, p_e4), Some("P@e4".to_string()));
+        assert_eq!(from_san(&board, "P@e4"), Ok(p_e4));
+        assert_eq!(from_san(&board, "@e4"), Ok(p_e4));
+        assert_eq!(
+            from_san(&board, "P@e8"),
+            Err(SanErr::NoMatch("P@e8".to_string()))
+        );
+        assert_eq!(
+            from_san(&board, "N@e4"),
+            Err(SanErr::NoMatch("N@e4".to_string()))
+        );
+        assert_eq!(
+            from_san(&board, "K@e4"),
+            Err(SanErr::BadFormat("K@e4".to_string()))
+        );
+    }
+
+    #[test]
+    fn drops_need_no_disambiguation() {
+        // A knight move to a square where a knight could also be dropped does
+        // not need disambiguation.
+        let next = board("r1bqkbnr/pppp1ppp/2n5/8/8/5N2/PPPP1PPP/RNBQKB1R[N] w KQkq - 0 4");
+        let nd4 = Knight(Simple {
+            from: sq::F3,
+            to: sq::D4,
+        });
+        assert_eq!(to_san(&next, nd4), Some("Nd4".to_string()));
+        assert_eq!(from_san(&next, "Nd4"), Ok(nd4));
+        assert_eq!(
+            to_san(&next, Knight(Drop { to: sq::D4 })),
+            Some("N@d4".to_string())
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let board = board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        for pm in board.next_moves().unwrap() {
+            let san = to_san(&board, pm).unwrap();
+            assert_eq!(from_san(&board, &san), Ok(pm), "{san}");
+            let next = board.next_from_move(pm).unwrap();
+            for next_pm in next.next_moves().unwrap() {
+                let san = to_san(&next, next_pm).unwrap();
+                assert_eq!(from_san(&next, &san), Ok(next_pm), "{san}");
+            }
+        }
+    }
+}
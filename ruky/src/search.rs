@@ -2,6 +2,7 @@ use crate::board::Board;
 use crate::err::RukyErr;
 use crate::piece::Piece;
 use crate::piece_move::PieceMove;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
 // A trait for evaluating a chess board position.
@@ -15,12 +16,28 @@ pub trait Search {
     fn search_game(&mut self, boards: &[Board]) -> Result<SearchResult, RukyErr> {
         self.search_board(boards.last().ok_or(RukyErr::SearchMissingBoard)?)
     }
+
+    // Sets the budget for the following searches. Search agents without a
+    // notion of budget, e.g. a random search, ignore it.
+    fn set_budget(&mut self, _budget: SearchBudget) {}
+}
+
+// The budget for a single search.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SearchBudget {
+    // The number of nodes, i.e. simulations for the MCTS.
+    Nodes(usize),
+    // The maximum time spent searching.
+    Time(Duration),
 }
 
 // A trait for evaluting chess positions during self-play training games.
 pub trait SpSearch {
     fn search(&mut self) -> Result<SearchResult, RukyErr>;
     fn reset(&mut self) {}
+    // Resets the search to start a new game from board, e.g. a different
+    // Chess960 starting position.
+    fn reset_board(&mut self, _board: &Board) {}
 }
 
 // A trait for representing the size of a tree.
@@ -28,7 +45,7 @@ pub trait TreeSize {
     fn total_tree_nodes(&self) -> usize;
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct SearchResult {
     // The position evaluated.
     pub board: Board,
@@ -106,7 +123,7 @@ impl SearchResult {
 }
 
 // Same as Bp, but only captures the move without the board.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Mp {
     pub pm: Piece<PieceMove>,
     pub prior: f32,
@@ -115,7 +132,7 @@ pub struct Mp {
 
 // Packages together a board move with prior probability from the evaluator and
 // the visit count from the MCTS.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Bp {
     pub board: Board,
     pub prior: f32,
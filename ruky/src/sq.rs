@@ -1,3 +1,4 @@
+use serde::de::{self, Deserialize, Deserializer};
 use serde::ser::{Serialize, Serializer};
 use std::convert::From;
 use std::fmt::{self, Debug, Formatter};
@@ -218,6 +219,22 @@ impl Serialize for Sq {
     }
 }
 
+// Implement Deserialize for Sq from a raw number, which must be less than 64.
+impl<'de> Deserialize<'de> for Sq {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let val = u8::deserialize(deserializer)?;
+        if val >= 64 {
+            return Err(de::Error::custom(format!(
+                "square {val} is not less than 64"
+            )));
+        }
+        Ok(Sq { val })
+    }
+}
+
 /// Constants for the squares of the board.
 pub const A1: Sq = Sq { val: 0u8 };
 pub const B1: Sq = Sq { val: 1u8 };
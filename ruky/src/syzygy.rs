@@ -0,0 +1,2366 @@
+/// This module probes Syzygy endgame tablebases, which store the result of
+/// every position with few enough pieces. There are two kinds of tables for
+/// each material, e.g. KQvK, both compressed:
+///
+/// * WDL tables (.rtbw) store whether the position is won, drawn or lost for
+///   the player moving next, where cursed wins and blessed losses are wins and
+///   losses that are drawn by the 50-move rule.
+/// * DTZ tables (.rtbz) store the distance to zeroing, i.e. the number of plies
+///   to the next capture or pawn move, or to mate, when playing the fastest win
+///   or the slowest loss. DTZ tables only store one side to move.
+///
+/// The tables do not store positions with castling rights, and the values of
+/// positions where the best move is a capture, including en-passant, may be
+/// wrong, so probing searches the captures first. The tables are read from
+/// the directories they were found in on the first probe of their material.
+///
+/// For more background, see https://www.chessprogramming.org/Syzygy_Bases and
+/// https://github.com/syzygy1/tb.
+use crate::board::Board;
+use crate::piece::{Color, Piece};
+use crate::piece_move::PieceMove;
+use crate::piece_set::PieceSet;
+use crate::variant::Variant;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Neg;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// The largest number of pieces, including the kings, of a Syzygy table.
+pub const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// The flags in the first byte of a table.
+const SPLIT: u8 = 1;
+const HAS_PAWNS: u8 = 2;
+
+// The flags of an encoding, where the flags other than SINGLE_VALUE are only
+// used by DTZ tables.
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+// The codes of the pieces in a table, where black pieces have the BLACK bit
+// set.
+const PAWN: u8 = 1;
+const KNIGHT: u8 = 2;
+const BISHOP: u8 = 3;
+const ROOK: u8 = 4;
+const QUEEN: u8 = 5;
+const KING: u8 = 6;
+const BLACK: u8 = 8;
+
+// The letters of the pieces in the name of a table, in the order in which they
+// are written.
+const PIECE_CHARS: [(char, u8); 6] = [
+    ('K', KING),
+    ('Q', QUEEN),
+    ('R', ROOK),
+    ('B', BISHOP),
+    ('N', KNIGHT),
+    ('P', PAWN),
+];
+
+// The result of a position for the player moving next.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Wdl {
+    Loss,
+    // A loss that is drawn by the 50-move rule.
+    BlessedLoss,
+    Draw,
+    // A win that is drawn by the 50-move rule.
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    // Returns the result for a value from -2 for a loss to 2 for a win.
+    fn from_value(value: i32) -> Option<Wdl> {
+        match value {
+            -2 => Some(Wdl::Loss),
+            -1 => Some(Wdl::BlessedLoss),
+            0 => Some(Wdl::Draw),
+            1 => Some(Wdl::CursedWin),
+            2 => Some(Wdl::Win),
+            _ => None,
+        }
+    }
+
+    // Returns 1 for wins, -1 for losses and 0 for draws.
+    pub fn signum(&self) -> i32 {
+        match *self {
+            Wdl::Loss | Wdl::BlessedLoss => -1,
+            Wdl::Draw => 0,
+            Wdl::CursedWin | Wdl::Win => 1,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    // Returns the result for the other player.
+    fn neg(self) -> Wdl {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::BlessedLoss => Wdl::CursedWin,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::CursedWin => Wdl::BlessedLoss,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum SyzygyErr {
+    #[error("cannot read {0}")]
+    Io(String),
+    #[error("no Syzygy tables in {0}")]
+    NoTables(String),
+    #[error("{0} has no Syzygy tables")]
+    Variant(Variant),
+    #[error("positions with castling rights are not in the tables")]
+    Castling,
+    #[error("positions with {0} pieces are not in the tables")]
+    TooManyPieces(usize),
+    #[error("table {0} is missing")]
+    MissingTable(String),
+    #[error("table {0} has a bad magic number")]
+    BadMagic(String),
+    #[error("table {0} is corrupt")]
+    Corrupt(String),
+}
+
+// A set of Syzygy tables found in one or more directories.
+#[derive(Debug, Default)]
+pub struct Tablebase {
+    // The paths of the tables by their material, e.g. KQvK.
+    wdl_paths: HashMap<String, PathBuf>,
+    dtz_paths: HashMap<String, PathBuf>,
+    // The largest number of pieces of the WDL tables.
+    max_pieces: usize,
+    // The tables that have been read, by their material.
+    wdl: Mutex<HashMap<String, Arc<Table>>>,
+    dtz: Mutex<HashMap<String, Arc<Table>>>,
+}
+
+impl Tablebase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns a tablebase with the tables in paths, which is a list of
+    // directories separated by ":", or by ";" on Windows, as in SyzygyPath.
+    // Returns an error if none of the directories has any tables.
+    pub fn open(paths: &Path) -> Result<Self, SyzygyErr> {
+        let mut tablebase = Self::new();
+        let mut count = 0;
+        for dir in std::env::split_paths(paths) {
+            if !dir.as_os_str().is_empty() {
+                count += tablebase.add_directory(&dir)?;
+            }
+        }
+        if count == 0 {
+            return Err(SyzygyErr::NoTables(paths.display().to_string()));
+        }
+        Ok(tablebase)
+    }
+
+    // Adds the WDL and DTZ tables in dir, and returns the number of tables that
+    // were found. The tables are not read until they are probed.
+    pub fn add_directory(&mut self, dir: &Path) -> Result<usize, SyzygyErr> {
+        let entries = fs::read_dir(dir)
+            .map_err(|err| SyzygyErr::Io(format!("{}: {}", dir.display(), err)))?;
+        let mut count = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let (Some(name), Some(ext)) = (
+                path.file_stem().and_then(|s| s.to_str()),
+                path.extension().and_then(|s| s.to_str()),
+            ) else {
+                continue;
+            };
+            let Some(material) = Material::parse(name) else {
+                continue;
+            };
+            match ext {
+                "rtbw" => {
+                    self.max_pieces = self.max_pieces.max(material.num_pieces);
+                    self.wdl_paths.insert(name.to_string(), path);
+                }
+                "rtbz" => {
+                    self.dtz_paths.insert(name.to_string(), path);
+                }
+                _ => continue,
+            }
+            count += 1;
+        }
+        if count == 0 {
+            log::warn!("Found no Syzygy tables in {}", dir.display());
+        }
+        Ok(count)
+    }
+
+    // Returns the largest number of pieces, including the kings, of the
+    // positions that can be probed.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    // Returns the result of the position for the player moving next.
+    pub fn probe_wdl(&self, board: &Board) -> Result<Wdl, SyzygyErr> {
+        self.check(board)?;
+        let mut board = board.clone();
+        self.search(&mut board, false).map(|(wdl, _)| wdl)
+    }
+
+    // Returns the distance to zeroing in plies, which is positive if the player
+    // moving next wins and negative if they lose, or 0 for draws. The distance
+    // is 1 if the winning move is a capture or a pawn move, and -1 if the
+    // player moving next is mated. The distance of cursed wins and blessed
+    // losses is above 100. Note that the distance may be off by one ply when
+    // the tables store the number of moves instead of plies.
+    pub fn probe_dtz(&self, board: &Board) -> Result<i32, SyzygyErr> {
+        self.check(board)?;
+        let mut board = board.clone();
+        self.dtz(&mut board)
+    }
+
+    // Returns the move that keeps the best result for the player moving next,
+    // which is the move with the smallest distance to zeroing when winning and
+    // the largest when losing, along with the result. Returns None if there
+    // are no legal moves.
+    pub fn best_move(&self, board: &Board) -> Result<Option<(Piece<PieceMove>, Wdl)>, SyzygyErr> {
+        self.check(board)?;
+        let mut board = board.clone();
+        let mut best: Option<(Piece<PieceMove>, Wdl, i32)> = None;
+        for piece_move in legal_moves(&board) {
+            let zeroing = is_zeroing(piece_move);
+            let undo = board.make_move(piece_move);
+            let result = if board.is_mate() {
+                Ok((Wdl::Win, 1))
+            } else {
+                self.search(&mut board, false).and_then(|(wdl, _)| {
+                    let wdl = -wdl;
+                    if zeroing || wdl == Wdl::Draw {
+                        return Ok((wdl, dtz_before_zeroing(wdl)));
+                    }
+                    let dtz = -self.dtz(&mut board)?;
+                    Ok((wdl, dtz + dtz.signum()))
+                })
+            };
+            board.unmake_move(undo);
+            let (wdl, dtz) = result?;
+            // Both the fastest wins and the slowest losses have the smallest
+            // distance to zeroing.
+            if best.is_none_or(|(_, best_wdl, best_dtz)| (wdl, -dtz) > (best_wdl, -best_dtz)) {
+                best = Some((piece_move, wdl, dtz));
+            }
+        }
+        Ok(best.map(|(piece_move, wdl, _)| (piece_move, wdl)))
+    }
+
+    // Returns an error if the position cannot be probed.
+    fn check(&self, board: &Board) -> Result<(), SyzygyErr> {
+        if !board.variant().is_standard() {
+            return Err(SyzygyErr::Variant(board.variant()));
+        }
+        if board.has_wk_castle()
+            || board.has_wq_castle()
+            || board.has_bk_castle()
+            || board.has_bq_castle()
+        {
+            return Err(SyzygyErr::Castling);
+        }
+        let num_pieces = num_pieces(board);
+        if num_pieces > 2 && num_pieces > self.max_pieces {
+            return Err(SyzygyErr::TooManyPieces(num_pieces));
+        }
+        Ok(())
+    }
+
+    // Returns the result of the position by searching the captures, and also
+    // the pawn moves if pawn_moves is true, before probing the WDL table. The
+    // second value is true if the best move is one of the moves searched, in
+    // which case the value in the DTZ table cannot be used.
+    fn search(&self, board: &mut Board, pawn_moves: bool) -> Result<(Wdl, bool), SyzygyErr> {
+        let moves = legal_moves(board);
+        let total = moves.len();
+        let moves: Vec<_> = moves
+            .into_iter()
+            .filter(|mv| mv.val().is_capture() || (pawn_moves && mv.is_pawn()))
+            .collect();
+        let mut best = Wdl::Loss;
+        for piece_move in moves.iter() {
+            let undo = board.make_move(*piece_move);
+            let result = self.search(board, false);
+            board.unmake_move(undo);
+            let wdl = -result?.0;
+            if wdl > best {
+                best = wdl;
+                if wdl == Wdl::Win {
+                    return Ok((wdl, true));
+                }
+            }
+        }
+        // The value in the table may be wrong when all the moves are captures,
+        // e.g. when the only moves capture en-passant.
+        let no_more_moves = !moves.is_empty() && moves.len() == total;
+        let wdl = if no_more_moves {
+            best
+        } else {
+            self.probe_wdl_table(board)?
+        };
+        if best >= wdl {
+            Ok((best, best > Wdl::Draw || no_more_moves))
+        } else {
+            Ok((wdl, false))
+        }
+    }
+
+    // Returns the distance to zeroing of the position.
+    fn dtz(&self, board: &mut Board) -> Result<i32, SyzygyErr> {
+        let (wdl, zeroing) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Ok(0);
+        }
+        if zeroing {
+            return Ok(dtz_before_zeroing(wdl));
+        }
+        if let Some(dtz) = self.probe_dtz_table(board, wdl)? {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Ok((dtz + if cursed { 100 } else { 0 }) * wdl.signum());
+        }
+        // The DTZ table stores the other side to move, so we find the distance
+        // from the positions after each move.
+        let mut min_dtz = i32::MAX;
+        for piece_move in legal_moves(board) {
+            let zeroing = is_zeroing(piece_move);
+            let undo = board.make_move(piece_move);
+            let result = if zeroing {
+                self.search(board, false)
+                    .map(|(wdl, _)| -dtz_before_zeroing(wdl))
+            } else {
+                self.dtz(board).map(|dtz| -dtz)
+            };
+            let mates = board.is_mate();
+            board.unmake_move(undo);
+            let mut dtz = result?;
+            if dtz == 1 && mates {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+        }
+        Ok(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    // Returns the result of the position stored in the WDL table.
+    fn probe_wdl_table(&self, board: &Board) -> Result<Wdl, SyzygyErr> {
+        if num_pieces(board) == 2 {
+            return Ok(Wdl::Draw);
+        }
+        let table = self.table(Kind::Wdl, board)?;
+        let (_, value) = table
+            .probe(board)
+            .ok_or_else(|| SyzygyErr::Corrupt(table.name.clone()))?;
+        Wdl::from_value(value as i32 - 2).ok_or_else(|| SyzygyErr::Corrupt(table.name.clone()))
+    }
+
+    // Returns the distance to zeroing stored in the DTZ table, or None if the
+    // table does not store the player moving next.
+    fn probe_dtz_table(&self, board: &Board, wdl: Wdl) -> Result<Option<i32>, SyzygyErr> {
+        let table = self.table(Kind::Dtz, board)?;
+        if !table.stores(board) {
+            return Ok(None);
+        }
+        table
+            .probe(board)
+            .and_then(|(file, value)| table.dtz_value(file, value, wdl))
+            .map(Some)
+            .ok_or_else(|| SyzygyErr::Corrupt(table.name.clone()))
+    }
+
+    // Returns the table for the material of the position, reading it on the
+    // first probe.
+    fn table(&self, kind: Kind, board: &Board) -> Result<Arc<Table>, SyzygyErr> {
+        let (paths, tables) = match kind {
+            Kind::Wdl => (&self.wdl_paths, &self.wdl),
+            Kind::Dtz => (&self.dtz_paths, &self.dtz),
+        };
+        let key = material_key(board, false);
+        let mirrored = material_key(board, true);
+        let (name, path) = match (paths.get_key_value(&key), paths.get_key_value(&mirrored)) {
+            (Some(found), _) | (None, Some(found)) => found,
+            (None, None) => return Err(SyzygyErr::MissingTable(kind.file_name(&key))),
+        };
+        let mut tables = tables.lock().unwrap();
+        if let Some(table) = tables.get(name) {
+            return Ok(table.clone());
+        }
+        let data =
+            fs::read(path).map_err(|err| SyzygyErr::Io(format!("{}: {}", path.display(), err)))?;
+        let table = Arc::new(Table::new(name, kind, data)?);
+        tables.insert(name.clone(), table.clone());
+        Ok(table)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    Wdl,
+    Dtz,
+}
+
+impl Kind {
+    fn magic(&self) -> [u8; 4] {
+        match *self {
+            Kind::Wdl => WDL_MAGIC,
+            Kind::Dtz => DTZ_MAGIC,
+        }
+    }
+
+    fn file_name(&self, name: &str) -> String {
+        match *self {
+            Kind::Wdl => format!("{}.rtbw", name),
+            Kind::Dtz => format!("{}.rtbz", name),
+        }
+    }
+}
+
+// The material of a table, which is parsed from its name.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Material {
+    // The number of pieces, indexed by the piece code.
+    counts: [u8; 16],
+    num_pieces: usize,
+    pawns: bool,
+    // True if both players have the same pieces.
+    symmetric: bool,
+    // True if a player has a single piece of a type other than the king, in
+    // which case three pieces are encoded together instead of the two kings.
+    unique: bool,
+    // The number of pawns of the leading color, which is the only color with
+    // pawns or the color with fewer pawns, and of the other color.
+    pawn_counts: [u8; 2],
+}
+
+impl Material {
+    // Parses the name of a table, e.g. KRPvKR, or returns None if it is not
+    // the name of a table.
+    fn parse(name: &str) -> Option<Material> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [0u8; 16];
+        for (pieces, color) in [(white, 0), (black, BLACK)] {
+            for c in pieces.chars() {
+                let (_, code) = PIECE_CHARS.iter().find(|(p, _)| *p == c)?;
+                counts[(code | color) as usize] += 1;
+            }
+            if counts[(KING | color) as usize] != 1 {
+                return None;
+            }
+        }
+        let num_pieces = counts.iter().map(|c| *c as usize).sum();
+        if num_pieces > MAX_PIECES {
+            return None;
+        }
+        let (white_pawns, black_pawns) = (counts[PAWN as usize], counts[(PAWN | BLACK) as usize]);
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        Some(Material {
+            counts,
+            num_pieces,
+            pawns: white_pawns + black_pawns > 0,
+            symmetric: counts[..8] == counts[8..],
+            unique: (PAWN..KING)
+                .any(|code| counts[code as usize] == 1 || counts[(code | BLACK) as usize] == 1),
+            pawn_counts: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+        })
+    }
+}
+
+// The encoding of the positions of a table for one file of the leading pawn
+// and one side to move, and the compressed values of the positions.
+#[derive(Clone, Debug, Default)]
+struct Encoding {
+    // The codes of the pieces in the order in which they are encoded.
+    pieces: Vec<u8>,
+    // The number of pieces in each group of pieces that are encoded together,
+    // and the factor of the index of each group, where the last factor is the
+    // number of positions.
+    group_len: Vec<usize>,
+    group_idx: Vec<u64>,
+    flags: u8,
+    // The value of all the positions if the flags have SINGLE_VALUE.
+    min_sym_len: u8,
+    // The positions are stored in blocks of block_size bytes, and the sparse
+    // index has the block of every span positions.
+    block_size: usize,
+    span: u64,
+    num_blocks: usize,
+    // The lowest Huffman code of each length, left aligned.
+    base64: Vec<u64>,
+    // The number of values of each symbol minus 1.
+    symlen: Vec<u32>,
+    // The offsets in the data of the parts of the encoding.
+    lowest_sym: usize,
+    btree: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_length: usize,
+    block_length_size: usize,
+    blocks: usize,
+    // The offsets into the DTZ map of the values of each result.
+    map_idx: [usize; 4],
+}
+
+// A WDL or DTZ table.
+struct Table {
+    name: String,
+    kind: Kind,
+    material: Material,
+    data: Vec<u8>,
+    // The encodings by the file of the leading pawn, where tables without pawns
+    // only have file a, and by the side to move, where DTZ tables and tables
+    // with symmetric material only have one side.
+    encodings: Vec<Vec<Encoding>>,
+    // The offset of the DTZ map.
+    map: usize,
+}
+
+impl std::fmt::Debug for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Table({}, {:?}, {} bytes)",
+            self.name,
+            self.kind,
+            self.data.len()
+        )
+    }
+}
+
+impl Table {
+    // Parses the data of a table, where name is the material of the table.
+    fn new(name: &str, kind: Kind, data: Vec<u8>) -> Result<Table, SyzygyErr> {
+        let file_name = kind.file_name(name);
+        if data.get(..4) != Some(&kind.magic()[..]) {
+            return Err(SyzygyErr::BadMagic(file_name));
+        }
+        let (mut table, pos) = Table::with_layout(name, kind, data)
+            .ok_or_else(|| SyzygyErr::Corrupt(file_name.clone()))?;
+        table
+            .read_encodings(pos)
+            .ok_or(SyzygyErr::Corrupt(file_name))?;
+        Ok(table)
+    }
+
+    // Parses the pieces and groups of the encodings of a table, and returns the
+    // table with the offset of the rest of the encodings.
+    fn with_layout(name: &str, kind: Kind, data: Vec<u8>) -> Option<(Table, usize)> {
+        let material = Material::parse(name)?;
+        let flags = *data.get(4)?;
+        if (flags & HAS_PAWNS != 0) != material.pawns {
+            return None;
+        }
+        let num_files = if material.pawns { 4 } else { 1 };
+        let num_sides = if kind == Kind::Wdl && !material.symmetric {
+            2
+        } else {
+            1
+        };
+        let both_pawns = material.pawns && material.pawn_counts[1] > 0;
+        let mut pos = 5;
+        let mut encodings = Vec::new();
+        for file in 0..num_files {
+            let order = *data.get(pos)?;
+            let order2 = if both_pawns {
+                *data.get(pos + 1)?
+            } else {
+                0xff
+            };
+            pos += 1 + both_pawns as usize;
+            let mut sides = vec![Encoding::default(); num_sides];
+            for _ in 0..material.num_pieces {
+                let codes = *data.get(pos)?;
+                sides[0].pieces.push(codes & 0xf);
+                if num_sides == 2 {
+                    sides[1].pieces.push(codes >> 4);
+                }
+                pos += 1;
+            }
+            for (side, encoding) in sides.iter_mut().enumerate() {
+                let shift = 4 * side;
+                let order = [(order >> shift) & 0xf, (order2 >> shift) & 0xf];
+                encoding.set_groups(&material, order, file)?;
+            }
+            encodings.push(sides);
+        }
+        pos += pos & 1;
+        let table = Table {
+            name: name.to_string(),
+            kind,
+            material,
+            data,
+            encodings,
+            map: 0,
+        };
+        Some((table, pos))
+    }
+
+    // Parses the rest of the encodings, which starts at pos.
+    fn read_encodings(&mut self, mut pos: usize) -> Option<()> {
+        let data = &self.data;
+        for encoding in self.encodings.iter_mut().flatten() {
+            pos = encoding.read_sizes(data, pos)?;
+        }
+        if self.kind == Kind::Dtz {
+            self.map = pos;
+            for sides in self.encodings.iter_mut() {
+                let encoding = &mut sides[0];
+                if encoding.flags & MAPPED == 0 {
+                    continue;
+                }
+                if encoding.flags & WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        encoding.map_idx[i] = (pos - self.map) / 2 + 1;
+                        pos += 2 * read_u16(data, pos)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        encoding.map_idx[i] = pos - self.map + 1;
+                        pos += *data.get(pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+        for encoding in self.encodings.iter_mut().flatten() {
+            encoding.sparse_index = pos;
+            pos += 6 * encoding.sparse_index_size;
+        }
+        for encoding in self.encodings.iter_mut().flatten() {
+            encoding.block_length = pos;
+            pos += 2 * encoding.block_length_size;
+        }
+        for encoding in self.encodings.iter_mut().flatten() {
+            pos = (pos + 0x3f) & !0x3f;
+            encoding.blocks = pos;
+            pos += encoding.num_blocks * encoding.block_size;
+            // The blocks of an encoding with a single value are empty, and
+            // may be past the end of the data.
+            if encoding.num_blocks > 0 && pos > data.len() {
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    // Returns true if the table stores the player moving next, which is false
+    // for DTZ tables that store the other side.
+    fn stores(&self, board: &Board) -> bool {
+        let (file, side, _) = self.index(board);
+        if self.kind == Kind::Wdl || (self.material.symmetric && !self.material.pawns) {
+            return true;
+        }
+        (self.encodings[file][0].flags & STM) as usize == side
+    }
+
+    // Returns the file of the leading pawn and the value of the position.
+    fn probe(&self, board: &Board) -> Option<(usize, u16)> {
+        let (file, side, idx) = self.index(board);
+        let sides = &self.encodings[file];
+        let encoding = &sides[side % sides.len()];
+        let value = encoding.decompress(&self.data, idx)?;
+        Some((file, value))
+    }
+
+    // Returns the distance to zeroing in plies for a value of the DTZ table.
+    fn dtz_value(&self, file: usize, value: u16, wdl: Wdl) -> Option<i32> {
+        let encoding = &self.encodings[file][0];
+        let mut value = value as usize;
+        if encoding.flags & MAPPED != 0 {
+            let map_idx = match wdl {
+                Wdl::Loss => encoding.map_idx[1],
+                Wdl::BlessedLoss => encoding.map_idx[3],
+                Wdl::CursedWin => encoding.map_idx[2],
+                Wdl::Draw | Wdl::Win => encoding.map_idx[0],
+            };
+            value = if encoding.flags & WIDE != 0 {
+                read_u16(&self.data, self.map + 2 * (map_idx + value))? as usize
+            } else {
+                *self.data.get(self.map + map_idx + value)? as usize
+            };
+        }
+        let in_moves = match wdl {
+            Wdl::Win => encoding.flags & WIN_PLIES == 0,
+            Wdl::Loss => encoding.flags & LOSS_PLIES == 0,
+            _ => true,
+        };
+        let value = value as i32;
+        Some(if in_moves { 2 * value + 1 } else { value + 1 })
+    }
+
+    // Returns the file of the leading pawn, the side to move and the index of
+    // the position, after mirroring the position so that it has the same
+    // material as the table.
+    fn index(&self, board: &Board) -> (usize, usize, u64) {
+        let black_next = board.color() == Color::Black;
+        // The tables store the positions where white has the material of the
+        // first part of the name, and only white moving next if both players
+        // have the same material.
+        let flip =
+            (self.material.symmetric && black_next) || material_key(board, false) != self.name;
+        let side = (flip != black_next) as usize;
+        let mut pieces: Vec<(u8, usize)> = board_pieces(board)
+            .into_iter()
+            .map(|(code, sq)| {
+                if flip {
+                    (code ^ BLACK, sq ^ 56)
+                } else {
+                    (code, sq)
+                }
+            })
+            .collect();
+
+        let mut file = 0;
+        let mut lead = 0;
+        if self.material.pawns {
+            // The leading pawns come first, starting with the pawn closest to
+            // the edge, and on the lowest rank for pawns on the same file.
+            let lead_code = self.encodings[0][0].pieces[0];
+            pieces.sort_by_key(|(code, _)| *code != lead_code);
+            lead = pieces.iter().filter(|(code, _)| *code == lead_code).count();
+            let first = (0..lead)
+                .max_by_key(|i| INDEX.pawns[pieces[*i].1])
+                .unwrap_or(0);
+            pieces.swap(0, first);
+            let f = pieces[0].1 % 8;
+            file = f.min(7 - f);
+        }
+
+        let sides = &self.encodings[file];
+        let encoding = &sides[side % sides.len()];
+        // Reorder the pieces to match the order of the encoding.
+        for i in lead..pieces.len().saturating_sub(1) {
+            if let Some(j) = (i..pieces.len()).find(|j| pieces[*j].0 == encoding.pieces[i]) {
+                pieces.swap(i, j);
+            }
+        }
+        let mut squares: Vec<usize> = pieces.iter().map(|(_, sq)| *sq).collect();
+        if squares[0] % 8 > 3 {
+            squares.iter_mut().for_each(|sq| *sq ^= 7);
+        }
+
+        let mut idx;
+        if self.material.pawns {
+            idx = INDEX.lead_pawn_idx[lead][squares[0]];
+            squares[1..lead].sort_by_key(|sq| INDEX.pawns[*sq]);
+            for (i, sq) in squares.iter().enumerate().take(lead).skip(1) {
+                idx += INDEX.binomial[i][INDEX.pawns[*sq] as usize];
+            }
+        } else {
+            if squares[0] / 8 > 3 {
+                squares.iter_mut().for_each(|sq| *sq ^= 56);
+            }
+            // Mirror along the a1-h8 diagonal if the first piece of the leading
+            // group that is not on the diagonal is above it.
+            for i in 0..encoding.group_len[0] {
+                match off_diagonal(squares[i]) {
+                    0 => continue,
+                    off if off > 0 => squares[i..]
+                        .iter_mut()
+                        .for_each(|sq| *sq = ((*sq >> 3) | (*sq << 3)) & 63),
+                    _ => (),
+                }
+                break;
+            }
+            idx = if self.material.unique {
+                unique_index(&squares)
+            } else {
+                INDEX.kk[INDEX.a1d1d4[squares[0]] as usize][squares[1]]
+            };
+        }
+
+        idx *= encoding.group_idx[0];
+        let mut start = encoding.group_len[0];
+        let mut other_pawns = self.material.pawns && self.material.pawn_counts[1] > 0;
+        for next in 1..encoding.group_len.len() {
+            let len = encoding.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[start + i];
+                // Skip the squares of the previous groups, and the first rank
+                // for the pawns of the other color.
+                let skip = squares[..start].iter().filter(|s| sq > **s).count()
+                    + if other_pawns { 8 } else { 0 };
+                n += INDEX.binomial[i + 1][sq.saturating_sub(skip)];
+            }
+            other_pawns = false;
+            idx += n * encoding.group_idx[next];
+            start += len;
+        }
+        (file, side, idx)
+    }
+}
+
+impl Encoding {
+    // Splits the pieces into groups and computes the factors of the groups,
+    // where order has the position of the leading group and of the pawns of
+    // the other color in the index.
+    fn set_groups(&mut self, material: &Material, order: [u8; 2], file: usize) -> Option<()> {
+        let mut codes = self.pieces.clone();
+        codes.sort_unstable();
+        let mut expected: Vec<u8> = (0..16u8)
+            .flat_map(|code| std::iter::repeat_n(code, material.counts[code as usize] as usize))
+            .collect();
+        expected.sort_unstable();
+        if codes != expected || (material.pawns && self.pieces[0] & 7 != PAWN) {
+            return None;
+        }
+        let mut first_len: i32 = if material.pawns {
+            0
+        } else if material.unique {
+            3
+        } else {
+            2
+        };
+        self.group_len = vec![1];
+        for i in 1..self.pieces.len() {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i - 1] {
+                *self.group_len.last_mut()? += 1;
+            } else {
+                self.group_len.push(1);
+            }
+        }
+        if self.group_len[0] > 5 {
+            return None;
+        }
+
+        // The groups are encoded in the order of the table, where the leading
+        // group and the pawns of the other color may come after other groups.
+        let n = self.group_len.len();
+        let both_pawns = material.pawns && material.pawn_counts[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free = 64 - self.group_len[0] - if both_pawns { self.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        self.group_idx = vec![0; n + 1];
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                self.group_idx[0] = idx;
+                idx *= if material.pawns {
+                    INDEX.lead_pawns_size[self.group_len[0]][file]
+                } else if material.unique {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                self.group_idx[1] = idx;
+                idx *= INDEX.binomial[self.group_len[1]][48 - self.group_len[0]];
+            } else {
+                self.group_idx[next] = idx;
+                idx *= INDEX.binomial[self.group_len[next]][free];
+                free -= self.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_idx[n] = idx;
+        Some(())
+    }
+
+    // Returns the number of positions of the encoding.
+    fn size(&self) -> u64 {
+        self.group_idx.last().copied().unwrap_or(0)
+    }
+
+    // Parses the sizes and the Huffman codes of the encoding at pos, and
+    // returns the offset after them.
+    fn read_sizes(&mut self, data: &[u8], mut pos: usize) -> Option<usize> {
+        self.flags = *data.get(pos)?;
+        if self.flags & SINGLE_VALUE != 0 {
+            self.min_sym_len = *data.get(pos + 1)?;
+            return Some(pos + 2);
+        }
+        let block_log = *data.get(pos + 1)?;
+        let span_log = *data.get(pos + 2)?;
+        if block_log > 30 || span_log > 30 {
+            return None;
+        }
+        self.block_size = 1 << block_log;
+        self.span = 1 << span_log;
+        self.sparse_index_size = self.size().div_ceil(self.span) as usize;
+        let padding = *data.get(pos + 3)? as usize;
+        self.num_blocks = read_u32(data, pos + 4)? as usize;
+        self.block_length_size = self.num_blocks + padding;
+        let max_sym_len = *data.get(pos + 8)? as usize;
+        self.min_sym_len = *data.get(pos + 9)?;
+        let min_sym_len = self.min_sym_len as usize;
+        if max_sym_len < min_sym_len || max_sym_len > 64 || min_sym_len == 0 {
+            return None;
+        }
+        pos += 10;
+
+        // The Huffman codes are canonical, where longer codes have lower values,
+        // so the lowest code of each length is computed from the lowest symbols.
+        self.lowest_sym = pos;
+        let num_lens = max_sym_len - min_sym_len + 1;
+        self.base64 = vec![0; num_lens];
+        for i in (0..num_lens - 1).rev() {
+            let lowest = read_u16(data, pos + 2 * i)? as u64;
+            let next_lowest = read_u16(data, pos + 2 * (i + 1))? as u64;
+            self.base64[i] = self.base64[i + 1]
+                .wrapping_add(lowest)
+                .wrapping_sub(next_lowest)
+                / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base.checked_shl((64 - i - min_sym_len) as u32).unwrap_or(0);
+        }
+        pos += 2 * num_lens;
+
+        // Each symbol is a value or a pair of symbols, from which we compute
+        // the number of values of each symbol.
+        let num_syms = read_u16(data, pos)? as usize;
+        pos += 2;
+        self.btree = pos;
+        data.get(pos..pos + 3 * num_syms)?;
+        self.symlen = vec![0; num_syms];
+        let mut visited = vec![false; num_syms];
+        for sym in 0..num_syms {
+            if !visited[sym] {
+                self.symlen[sym] = self.sym_len(data, sym, &mut visited)?;
+            }
+        }
+        Some(pos + 3 * num_syms + (num_syms & 1))
+    }
+
+    // Returns the number of values of sym minus 1.
+    fn sym_len(&mut self, data: &[u8], sym: usize, visited: &mut [bool]) -> Option<u32> {
+        visited[sym] = true;
+        let (left, right) = self.pair(data, sym);
+        if right == 0xfff {
+            return Some(0);
+        }
+        for child in [left, right] {
+            if child >= visited.len() {
+                return None;
+            }
+            if !visited[child] {
+                self.symlen[child] = self.sym_len(data, child, visited)?;
+            }
+        }
+        Some(self.symlen[left] + self.symlen[right] + 1)
+    }
+
+    // Returns the pair of symbols of sym, where the left symbol is the value
+    // if the right symbol is 0xfff.
+    fn pair(&self, data: &[u8], sym: usize) -> (usize, usize) {
+        let pos = self.btree + 3 * sym;
+        let (b0, b1, b2) = (
+            data[pos] as usize,
+            data[pos + 1] as usize,
+            data[pos + 2] as usize,
+        );
+        (((b1 & 0xf) << 8) | b0, (b2 << 4) | (b1 >> 4))
+    }
+
+    // Returns the value of the position with index idx.
+    fn decompress(&self, data: &[u8], idx: u64) -> Option<u16> {
+        if self.flags & SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as u16);
+        }
+        if idx >= self.size() {
+            return None;
+        }
+        // The sparse index has the block and the offset in the block of the
+        // position in the middle of each span, from which we find the block of
+        // the position.
+        let entry = self.sparse_index + 6 * (idx / self.span) as usize;
+        let mut block = read_u32(data, entry)? as usize;
+        let mut offset = read_u16(data, entry + 4)? as i64;
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+        let block_length = |block: usize| -> Option<i64> {
+            if block >= self.block_length_size {
+                return None;
+            }
+            Some(read_u16(data, self.block_length + 2 * block)? as i64)
+        };
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        // Find the symbol with the position in the block.
+        let min_sym_len = self.min_sym_len as usize;
+        let mut pos = self.blocks + block * self.block_size;
+        let mut buf = read_be(data, pos, 8);
+        pos += 8;
+        let mut buf_size = 64;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf < self.base64[len] {
+                len += 1;
+                if len == self.base64.len() {
+                    return None;
+                }
+            }
+            sym = ((buf - self.base64[len]) >> (64 - len - min_sym_len)) as usize;
+            sym += read_u16(data, self.lowest_sym + 2 * len)? as usize;
+            if sym >= self.symlen.len() {
+                return None;
+            }
+            if offset < self.symlen[sym] as i64 + 1 {
+                break;
+            }
+            offset -= self.symlen[sym] as i64 + 1;
+            let bits = len + min_sym_len;
+            buf = buf.checked_shl(bits as u32).unwrap_or(0);
+            buf_size -= bits;
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= read_be(data, pos, 4) << (64 - buf_size);
+                pos += 4;
+            }
+        }
+
+        // Expand the pairs of the symbol until we reach the value.
+        while self.symlen[sym] != 0 {
+            let (left, right) = self.pair(data, sym);
+            if offset < self.symlen[left] as i64 + 1 {
+                sym = left;
+            } else {
+                offset -= self.symlen[left] as i64 + 1;
+                sym = right;
+            }
+        }
+        Some(self.pair(data, sym).0 as u16)
+    }
+}
+
+// Returns the index of the first three pieces, the first of which is in the
+// triangle a1-d1-d4, and which are below the a1-h8 diagonal if they are not on
+// it.
+fn unique_index(squares: &[usize]) -> u64 {
+    let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+    let adjust1 = (s1 > s0) as u64;
+    let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+    let rank = |sq: usize| (sq / 8) as u64;
+    if off_diagonal(s0) != 0 {
+        (INDEX.a1d1d4[s0] * 63 + (s1 as u64 - adjust1)) * 62 + s2 as u64 - adjust2
+    } else if off_diagonal(s1) != 0 {
+        (6 * 63 + rank(s0) * 28 + INDEX.b1h1h7[s1]) * 62 + s2 as u64 - adjust2
+    } else if off_diagonal(s2) != 0 {
+        6 * 63 * 62 + 4 * 28 * 62 + rank(s0) * 7 * 28 + (rank(s1) - adjust1) * 28 + INDEX.b1h1h7[s2]
+    } else {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + 4 * 7 * 28
+            + rank(s0) * 7 * 6
+            + (rank(s1) - adjust1) * 6
+            + (rank(s2) - adjust2)
+    }
+}
+
+// Returns the distance to zeroing of a position where the best move is a
+// capture or a pawn move.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Loss => -1,
+        Wdl::BlessedLoss => -101,
+        Wdl::Draw => 0,
+        Wdl::CursedWin => 101,
+        Wdl::Win => 1,
+    }
+}
+
+// Returns true if the move resets the 50-move rule.
+fn is_zeroing(piece_move: Piece<PieceMove>) -> bool {
+    piece_move.is_pawn() || piece_move.val().is_capture()
+}
+
+// Returns the legal moves, even if the game is drawn by repetition or by the
+// 50-move rule, which the tables do not consider.
+fn legal_moves(board: &Board) -> Vec<Piece<PieceMove>> {
+    let mut moves = board.captures();
+    moves.extend(board.quiets());
+    moves
+}
+
+fn num_pieces(board: &Board) -> usize {
+    (board.white().all() | board.black().all()).count() as usize
+}
+
+// Returns the codes and squares of the pieces on the board.
+fn board_pieces(board: &Board) -> Vec<(u8, usize)> {
+    let mut pieces = Vec::new();
+    for (set, color) in [(board.white(), 0), (board.black(), BLACK)] {
+        for (code, bits) in piece_bits(set) {
+            pieces.extend(bits.sq_iter().map(|sq| (code | color, sq.as_usize())));
+        }
+    }
+    pieces
+}
+
+fn piece_bits(set: &PieceSet) -> [(u8, crate::bitboard::BitBoard); 6] {
+    [
+        (KING, set.king()),
+        (QUEEN, set.queens()),
+        (ROOK, set.rooks()),
+        (BISHOP, set.bishops()),
+        (KNIGHT, set.knights()),
+        (PAWN, set.pawns()),
+    ]
+}
+
+// Returns the material of the position as in the name of a table, e.g. KRvKN,
+// which has the pieces of black first if mirrored is true.
+fn material_key(board: &Board, mirrored: bool) -> String {
+    let (first, second) = if mirrored {
+        (board.black(), board.white())
+    } else {
+        (board.white(), board.black())
+    };
+    let side = |set: &PieceSet| -> String {
+        piece_bits(set)
+            .iter()
+            .zip(PIECE_CHARS)
+            .map(|((_, bits), (c, _))| c.to_string().repeat(bits.count() as usize))
+            .collect()
+    };
+    format!("{}v{}", side(first), side(second))
+}
+
+// Returns the number of ranks by which sq is above the a1-h8 diagonal, which
+// is negative below the diagonal.
+const fn off_diagonal(sq: usize) -> i32 {
+    (sq / 8) as i32 - (sq % 8) as i32
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+// Reads n big-endian bytes at pos, where the bytes past the end of the data
+// are 0, since the Huffman decoder reads ahead of the last symbol.
+fn read_be(data: &[u8], pos: usize, n: usize) -> u64 {
+    (0..n).fold(0, |acc, i| {
+        (acc << 8) | *data.get(pos + i).unwrap_or(&0) as u64
+    })
+}
+
+// Lookup tables for the indices of the positions.
+struct IndexTables {
+    // The number of ways to choose k of n squares, indexed by [k][n].
+    binomial: [[u64; 64]; MAX_PIECES],
+    // Maps the triangle a1-d1-d4 to 0..10, where the diagonal comes last.
+    a1d1d4: [u64; 64],
+    // Maps the squares below the a1-h8 diagonal to 0..28.
+    b1h1h7: [u64; 64],
+    // Maps the legal positions of two kings to 0..462, where the first king is
+    // in the triangle a1-d1-d4, and indexed by [a1d1d4 of the first king][the
+    // second king].
+    kk: [[u64; 64]; 10],
+    // Maps the squares a2-h7 to 0..48, where the pawn with the largest value
+    // is the leading pawn.
+    pawns: [u64; 64],
+    // The index of the leading pawns, indexed by [number of leading pawns][the
+    // square of the first leading pawn].
+    lead_pawn_idx: [[u64; 64]; 6],
+    // The number of positions of the leading pawns, indexed by [number of
+    // leading pawns][file].
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+// The tables are generated at compile time.
+static INDEX: IndexTables = IndexTables::generate();
+
+impl IndexTables {
+    const fn generate() -> Self {
+        let mut b1h1h7 = [0u64; 64];
+        let mut code = 0;
+        let mut sq = 0;
+        while sq < 64 {
+            if off_diagonal(sq) < 0 {
+                b1h1h7[sq] = code;
+                code += 1;
+            }
+            sq += 1;
+        }
+
+        // The squares below the diagonal come first, and then the squares on
+        // the diagonal.
+        let mut a1d1d4 = [0u64; 64];
+        code = 0;
+        let mut pass = 0;
+        while pass < 2 {
+            sq = 0;
+            while sq < 64 {
+                let off = off_diagonal(sq);
+                if sq % 8 <= 3 && sq / 8 <= 3 && ((pass == 0 && off < 0) || (pass == 1 && off == 0))
+                {
+                    a1d1d4[sq] = code;
+                    code += 1;
+                }
+                sq += 1;
+            }
+            pass += 1;
+        }
+
+        // The positions with both kings on the diagonal come last.
+        let mut kk = [[0u64; 64]; 10];
+        code = 0;
+        pass = 0;
+        while pass < 2 {
+            let mut idx = 0;
+            while idx < 10 {
+                let mut s1 = 0;
+                while s1 < 64 {
+                    let in_triangle = s1 % 8 <= 3 && s1 / 8 <= 3 && off_diagonal(s1) <= 0;
+                    if in_triangle && a1d1d4[s1] == idx {
+                        let mut s2 = 0;
+                        while s2 < 64 {
+                            let (dr, df) = (
+                                (s1 / 8) as i32 - (s2 / 8) as i32,
+                                (s1 % 8) as i32 - (s2 % 8) as i32,
+                            );
+                            let both = off_diagonal(s1) == 0 && off_diagonal(s2) == 0;
+                            let legal = (dr > 1 || dr < -1 || df > 1 || df < -1)
+                                && !(off_diagonal(s1) == 0 && off_diagonal(s2) > 0);
+                            if legal && both == (pass == 1) {
+                                kk[idx as usize][s2] = code;
+                                code += 1;
+                            }
+                            s2 += 1;
+                        }
+                    }
+                    s1 += 1;
+                }
+                idx += 1;
+            }
+            pass += 1;
+        }
+
+        let mut binomial = [[0u64; 64]; MAX_PIECES];
+        binomial[0][0] = 1;
+        let mut n = 1;
+        while n < 64 {
+            let mut k = 0;
+            while k < MAX_PIECES && k <= n {
+                binomial[k][n] = (if k > 0 { binomial[k - 1][n - 1] } else { 0 })
+                    + (if k < n { binomial[k][n - 1] } else { 0 });
+                k += 1;
+            }
+            n += 1;
+        }
+
+        // The pawns on the edge files and on the lowest ranks have the largest
+        // values.
+        let mut pawns = [0u64; 64];
+        let mut lead_pawn_idx = [[0u64; 64]; 6];
+        let mut lead_pawns_size = [[0u64; 4]; 6];
+        let mut available = 48;
+        let mut lead = 1;
+        while lead <= 5 {
+            let mut file = 0;
+            while file < 4 {
+                let mut idx = 0;
+                let mut rank = 1;
+                while rank <= 6 {
+                    let sq = rank * 8 + file;
+                    if lead == 1 {
+                        available -= 1;
+                        pawns[sq] = available;
+                        available -= 1;
+                        pawns[sq ^ 7] = available;
+                    }
+                    lead_pawn_idx[lead][sq] = idx;
+                    idx += binomial[lead - 1][pawns[sq] as usize];
+                    rank += 1;
+                }
+                lead_pawns_size[lead][file] = idx;
+                file += 1;
+            }
+            lead += 1;
+        }
+
+        Self {
+            binomial,
+            a1d1d4,
+            b1h1h7,
+            kk,
+            pawns,
+            lead_pawn_idx,
+            lead_pawns_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BoardBuilder;
+    use crate::ruky::Ruky;
+    use crate::sq::Sq;
+    use lazy_static::lazy_static;
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    lazy_static! {
+        static ref RUKY: Ruky = Ruky::new();
+        static ref SPEC_KK: Vec<[u64; 64]> = spec_kk();
+    }
+
+    #[test]
+    fn material_names() {
+        let material = Material::parse("KRPvKR").unwrap();
+        assert_eq!(material.num_pieces, 5);
+        assert!(material.pawns && material.unique && !material.symmetric);
+        assert_eq!(material.pawn_counts, [1, 0]);
+        assert_eq!(Material::parse("KPPvKP").unwrap().pawn_counts, [1, 2]);
+        let material = Material::parse("KNNvKNN").unwrap();
+        assert!(material.symmetric && !material.unique && !material.pawns);
+        assert_eq!(Material::parse("KKvK"), None);
+        assert_eq!(Material::parse("KRv"), None);
+        assert_eq!(Material::parse("KXvK"), None);
+        assert_eq!(Material::parse("KQQQQvKQQ"), None);
+
+        let board = RUKY.from_fen("8/8/8/8/8/2n5/k7/1R2K3 w - - 0 1").unwrap();
+        assert_eq!(material_key(&board, false), "KRvKN");
+        assert_eq!(material_key(&board, true), "KNvKR");
+    }
+
+    #[test]
+    fn index_tables() {
+        assert_eq!(INDEX.binomial[2][5], 10);
+        assert_eq!(INDEX.binomial[0][63], 1);
+        assert_eq!(INDEX.a1d1d4[1], 0);
+        assert_eq!(INDEX.a1d1d4[0], 6);
+        assert_eq!(INDEX.a1d1d4[27], 9);
+        assert_eq!(INDEX.b1h1h7[55], 27);
+        assert_eq!(INDEX.kk.iter().flatten().max(), Some(&461));
+        assert_eq!(INDEX.pawns[8], 47);
+        assert_eq!(INDEX.pawns[15], 46);
+        assert_eq!(INDEX.lead_pawns_size[1], [6; 4]);
+    }
+
+    #[test]
+    fn probe_errors() {
+        let mut tablebase = Tablebase::new();
+        let board = RUKY.from_fen("8/8/8/8/8/8/1k6/4K3 w - - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&board), Ok(Wdl::Draw));
+        assert_eq!(tablebase.probe_dtz(&board), Ok(0));
+
+        let board = RUKY.from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&board), Err(SyzygyErr::Castling));
+        let board = RUKY.from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(
+            tablebase.probe_wdl(&board),
+            Err(SyzygyErr::TooManyPieces(3))
+        );
+        tablebase.max_pieces = 3;
+        assert_eq!(
+            tablebase.probe_dtz(&board),
+            Err(SyzygyErr::MissingTable("KRvK.rtbw".to_string()))
+        );
+
+        let board = RUKY
+            .from_variant_fen(Variant::Atomic, "8/8/8/8/8/8/1k6/4K3 w - - 0 1")
+            .unwrap();
+        assert_eq!(
+            tablebase.best_move(&board),
+            Err(SyzygyErr::Variant(Variant::Atomic))
+        );
+    }
+
+    #[test]
+    fn open_tables() {
+        let dir = std::env::temp_dir().join(format!("ruky-syzygy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("KRvK.rtbw"), [0u8; 16]).unwrap();
+        fs::write(dir.join("KRvK.txt"), "").unwrap();
+        let tablebase = Tablebase::open(&dir).unwrap();
+        assert_eq!(tablebase.max_pieces(), 3);
+        let board = RUKY.from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(
+            tablebase.probe_wdl(&board),
+            Err(SyzygyErr::BadMagic("KRvK.rtbw".to_string()))
+        );
+
+        fs::remove_file(dir.join("KRvK.rtbw")).unwrap();
+        assert_eq!(
+            Tablebase::open(&dir).map(|_| ()),
+            Err(SyzygyErr::NoTables(dir.display().to_string()))
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(Tablebase::open(&dir), Err(SyzygyErr::Io(_))));
+    }
+
+    #[test]
+    fn index_matches_spec() {
+        // The indices computed by hand with the published encoding.
+        let board = RUKY.from_fen("7Q/8/8/8/8/k7/8/1K6 w - - 0 1").unwrap();
+        assert_eq!(spec_index("KQvK", &board), (0, 0, 991));
+        let board = RUKY.from_fen("8/8/8/8/8/2k5/8/K6Q w - - 0 1").unwrap();
+        assert_eq!(spec_index("KQvK", &board), (0, 0, 30414));
+        let board = RUKY.from_fen("7k/8/8/8/8/8/4P3/K7 b - - 0 1").unwrap();
+        assert_eq!(spec_index("KPvK", &board), (3, 1, 20454));
+        // The pawn, king and king on d2, h1 and a8 after the mirror index to
+        // 0, 7 and 56 - 2 on both sides, but for white moving next the fixture
+        // encodes the white king before the pawn.
+        let board = RUKY.from_fen("7k/8/8/8/8/8/4P3/K7 w - - 0 1").unwrap();
+        assert_eq!(spec_index("KPvK", &board), (3, 0, 7 + 63 * 6 * 54));
+        assert_eq!(spec_size("KQvK"), 31332);
+        assert_eq!(spec_size("KNNvK"), 462 * 1891);
+        assert_eq!(spec_size("KPvK"), 6 * 63 * 62);
+        assert_eq!(spec_size("KPvKP"), 6 * 47 * 62 * 61);
+
+        for name in ["KQvK", "KNNvK", "KPvK", "KPvKP"] {
+            let table = layout(name, Kind::Wdl);
+            for encoding in table.encodings.iter().flatten() {
+                assert_eq!(encoding.size(), spec_size(name));
+            }
+        }
+        for (name, fen) in [
+            ("KQvK", "7Q/8/8/8/8/k7/8/1K6 w - - 0 1"),
+            ("KQvK", "8/8/8/8/8/2k5/8/K6Q w - - 0 1"),
+            ("KRvK", "8/8/8/3k4/8/8/8/R3K3 b - - 0 1"),
+            ("KNNvK", "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1"),
+            ("KPvK", "7k/8/8/8/8/8/4P3/K7 b - - 0 1"),
+            ("KPvK", "8/8/8/8/8/K7/4p3/4k3 w - - 0 1"),
+            ("KPvKP", "8/8/8/3p4/8/8/4P3/K6k w - - 0 1"),
+            ("KPvKP", "8/8/8/3p4/8/8/4P3/K6k b - - 0 1"),
+            ("KPvKP", "8/1p6/8/8/7k/4K3/6P1/8 w - - 0 1"),
+        ] {
+            let board = RUKY.from_fen(fen).unwrap();
+            assert_eq!(
+                layout(name, Kind::Wdl).index(&board),
+                spec_index(name, &board),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    // The tables with three pieces that are used by the integration tests are
+    // written by write_fixtures, which solves every position of the tables.
+    // The positions are indexed by spec_index rather than by Table::index, so
+    // that the reader is checked against an independent encoder, and the
+    // tables are compressed with pairs of symbols, codes of different lengths
+    // and DTZ maps like the published tables. This is a slow test, which
+    // should be run with --release.
+    #[ignore]
+    #[test]
+    fn write_fixtures() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/syzygy");
+        fs::create_dir_all(&dir).unwrap();
+        // The tables come after the tables that their captures and promotions
+        // lead to. The DTZ maps of KRvK have distances in plies, and those of
+        // KPvK have two bytes per distance.
+        for (name, with_dtz, flags) in [
+            ("KQvK", true, 0),
+            ("KRvK", true, WIN_PLIES | LOSS_PLIES),
+            ("KBvK", false, 0),
+            ("KNvK", false, 0),
+            ("KPvK", true, WIDE),
+            ("KNNvK", false, 0),
+        ] {
+            let tablebase = Tablebase::open(&dir).unwrap();
+            let solved = Solved::new(name, &tablebase);
+            let (wdl, dtz) = solved.values(flags);
+            fs::write(
+                dir.join(format!("{}.rtbw", name)),
+                write_table(&layout(name, Kind::Wdl), &wdl),
+            )
+            .unwrap();
+            if with_dtz {
+                let data = write_table(&layout(name, Kind::Dtz), &dtz);
+                fs::write(dir.join(format!("{}.rtbz", name)), data).unwrap();
+            }
+
+            // Check the result and the index of every position against the
+            // tables.
+            let tablebase = Tablebase::open(&dir).unwrap();
+            let wdl_layout = layout(name, Kind::Wdl);
+            for_each_position(name, |board| {
+                assert_eq!(
+                    wdl_layout.index(board),
+                    spec_index(name, board),
+                    "{}",
+                    board
+                );
+                let state = solved.states[&spec_index(name, board)];
+                assert_eq!(
+                    tablebase.probe_wdl(board),
+                    Ok(solved.wdl[state]),
+                    "{}",
+                    board
+                );
+                if with_dtz {
+                    let dtz = solved.dtz[state] * solved.wdl[state].signum();
+                    assert_eq!(tablebase.probe_dtz(board), Ok(dtz), "{}", board);
+                }
+            });
+        }
+    }
+
+    // Checks the index of every position of KPvKP, which has pawns of both
+    // colors and encodes other groups before the leading group. This is a
+    // slow test, which should be run with --release.
+    #[ignore]
+    #[test]
+    fn index_both_pawns() {
+        let table = layout("KPvKP", Kind::Wdl);
+        for_each_position("KPvKP", |board| {
+            assert_eq!(table.index(board), spec_index("KPvKP", board), "{}", board);
+        });
+    }
+
+    // The fixtures are compressed with pairs of symbols and codes of different
+    // lengths, the DTZ maps have one or two bytes per distance, and some groups
+    // come after others, so the integration tests read all of them.
+    #[test]
+    fn fixture_features() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/syzygy");
+        let read = |name: &str, kind: Kind| {
+            let data = fs::read(dir.join(kind.file_name(name))).unwrap();
+            Table::new(name, kind, data).unwrap()
+        };
+        for (name, kind) in [
+            ("KQvK", Kind::Wdl),
+            ("KQvK", Kind::Dtz),
+            ("KRvK", Kind::Dtz),
+            ("KPvK", Kind::Wdl),
+            ("KPvK", Kind::Dtz),
+            ("KNNvK", Kind::Wdl),
+        ] {
+            let table = read(name, kind);
+            let encodings = table.encodings.iter().flatten();
+            for encoding in encodings.filter(|e| e.flags & SINGLE_VALUE == 0) {
+                assert!(encoding.symlen.iter().any(|len| *len > 0), "{}", name);
+                assert!(encoding.base64.len() > 1, "{}", name);
+            }
+        }
+
+        let flags = |name: &str| read(name, Kind::Dtz).encodings[0][0].flags;
+        let plies = WIN_PLIES | LOSS_PLIES;
+        assert_eq!(flags("KQvK") & (MAPPED | WIDE | plies), MAPPED);
+        assert_eq!(flags("KRvK") & (MAPPED | WIDE | plies), MAPPED | plies);
+        assert_eq!(flags("KPvK") & (MAPPED | WIDE), MAPPED | WIDE);
+        let group_idx = |name: &str, kind: Kind, side: usize| {
+            read(name, kind).encodings[0][side].group_idx.clone()
+        };
+        assert_eq!(group_idx("KNNvK", Kind::Wdl, 0), vec![1, 462, 462 * 1891]);
+        assert_eq!(group_idx("KNNvK", Kind::Wdl, 1), vec![1891, 1, 462 * 1891]);
+        assert_eq!(
+            group_idx("KPvK", Kind::Dtz, 0),
+            vec![63, 1, 63 * 6, 63 * 6 * 62]
+        );
+    }
+
+    // The values of a table by file, side and index, with the flags and the
+    // DTZ maps of each file.
+    struct Values {
+        values: Vec<Vec<Vec<u16>>>,
+        flags: Vec<u8>,
+        maps: Vec<[Vec<u16>; 4]>,
+    }
+
+    // The result of a move from a position of the table being solved, which
+    // leads either to another position of the table, or to a position of
+    // another table with the given result.
+    #[derive(Clone, Copy)]
+    enum Next {
+        State(usize, bool),
+        Known(Wdl),
+    }
+
+    // The results and the distances to zeroing of every position of a table,
+    // where the positions are identified by their file, side and index.
+    struct Solved {
+        name: String,
+        states: HashMap<(usize, usize, u64), usize>,
+        wdl: Vec<Wdl>,
+        dtz: Vec<i32>,
+    }
+
+    impl Solved {
+        fn new(name: &str, tablebase: &Tablebase) -> Self {
+            let mut states = HashMap::new();
+            for_each_position(name, |board| {
+                let next = states.len();
+                states.entry(spec_index(name, board)).or_insert(next);
+            });
+
+            // Find the moves from one position of each state.
+            let mut moves: Vec<Option<(Vec<Next>, bool)>> = vec![None; states.len()];
+            for_each_position(name, |board| {
+                let state = states[&spec_index(name, board)];
+                if moves[state].is_some() {
+                    return;
+                }
+                let mut board = board.clone();
+                let mut next = Vec::new();
+                for piece_move in legal_moves(&board) {
+                    let zeroing = is_zeroing(piece_move);
+                    let undo = board.make_move(piece_move);
+                    if material_key(&board, false) == name || material_key(&board, true) == name {
+                        next.push(Next::State(states[&spec_index(name, &board)], zeroing));
+                    } else {
+                        next.push(Next::Known(tablebase.probe_wdl(&board).unwrap()));
+                    }
+                    board.unmake_move(undo);
+                }
+                moves[state] = Some((next, board.is_check()));
+            });
+            let moves: Vec<(Vec<Next>, bool)> = moves.into_iter().map(Option::unwrap).collect();
+            let mated: Vec<bool> = moves
+                .iter()
+                .map(|(next, check)| next.is_empty() && *check)
+                .collect();
+
+            // The results of the moves for the player making them.
+            let results = |wdl: &[Option<Wdl>], next: &[Next]| -> Vec<Option<Wdl>> {
+                next.iter()
+                    .map(|next| match *next {
+                        Next::State(state, _) => wdl[state].map(|wdl| -wdl),
+                        Next::Known(wdl) => Some(-wdl),
+                    })
+                    .collect()
+            };
+            let mut wdl: Vec<Option<Wdl>> = moves
+                .iter()
+                .zip(mated.iter())
+                .map(|((next, _), mated)| match (next.is_empty(), mated) {
+                    (true, true) => Some(Wdl::Loss),
+                    (true, false) => Some(Wdl::Draw),
+                    _ => None,
+                })
+                .collect();
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for state in 0..wdl.len() {
+                    if wdl[state].is_some() {
+                        continue;
+                    }
+                    let results = results(&wdl, &moves[state].0);
+                    if results.contains(&Some(Wdl::Win)) {
+                        wdl[state] = Some(Wdl::Win);
+                    } else if results.iter().all(|r| *r == Some(Wdl::Loss)) {
+                        wdl[state] = Some(Wdl::Loss);
+                    } else {
+                        continue;
+                    }
+                    changed = true;
+                }
+            }
+            let wdl: Vec<Wdl> = wdl
+                .into_iter()
+                .map(|wdl| wdl.unwrap_or(Wdl::Draw))
+                .collect();
+
+            // The distance to zeroing of a win is the smallest distance of the
+            // moves to a loss, and the distance of a loss is the largest
+            // distance of the moves, where zeroing moves and mates count as 1.
+            let mut dtz = vec![0; wdl.len()];
+            let mut round = 1;
+            while (0..wdl.len()).any(|state| wdl[state] != Wdl::Draw && dtz[state] == 0) {
+                assert!(round <= 100, "{} has cursed wins", name);
+                let distances = |dtz: &[i32], state: usize| -> Vec<Option<i32>> {
+                    moves[state]
+                        .0
+                        .iter()
+                        .filter(|next| match **next {
+                            Next::State(next, _) => wdl[next] == -wdl[state],
+                            Next::Known(next) => next == -wdl[state],
+                        })
+                        .map(|next| match *next {
+                            Next::State(next, zeroing) if !zeroing && !mated[next] => {
+                                Some(dtz[next] + 1).filter(|_| dtz[next] > 0)
+                            }
+                            _ => Some(1),
+                        })
+                        .collect()
+                };
+                let mut next_dtz = dtz.clone();
+                for state in 0..wdl.len() {
+                    if dtz[state] != 0 {
+                        continue;
+                    }
+                    let distances = distances(&dtz, state);
+                    let done = match wdl[state] {
+                        Wdl::Win => distances.contains(&Some(round)),
+                        Wdl::Loss if mated[state] => round == 1,
+                        Wdl::Loss => {
+                            distances.iter().all(Option::is_some)
+                                && distances.iter().flatten().max() == Some(&round)
+                        }
+                        _ => false,
+                    };
+                    if done {
+                        next_dtz[state] = round;
+                    }
+                }
+                dtz = next_dtz;
+                round += 1;
+            }
+
+            Solved {
+                name: name.to_string(),
+                states,
+                wdl,
+                dtz,
+            }
+        }
+
+        // Returns the values of the WDL and the DTZ table, where the values of
+        // the indices without positions are the most common value. The DTZ
+        // table stores the index of the distance in the map of the result, with
+        // the given DTZ flags, where the distances of a result are in plies if
+        // the flags have it or if any of them is even, and in moves otherwise.
+        fn values(&self, dtz_flags: u8) -> (Values, Values) {
+            let mut wdl = values_for(&self.name, Kind::Wdl);
+            let mut dtz = values_for(&self.name, Kind::Dtz);
+            let num_files = dtz.len();
+            let mut flags = vec![MAPPED | dtz_flags; num_files];
+            let mut maps: Vec<[Vec<u16>; 4]> = vec![Default::default(); num_files];
+            // The maps of wins and losses, and the flags for distances in plies.
+            let results = [(Wdl::Win, 0, WIN_PLIES), (Wdl::Loss, 1, LOSS_PLIES)];
+            let stored = |state: usize, flags: u8| {
+                let (_, map, plies) = results.iter().find(|r| r.0 == self.wdl[state])?;
+                let dtz = self.dtz[state] - 1;
+                let dtz = if flags & plies != 0 { dtz } else { dtz / 2 };
+                Some((*map, dtz as u16))
+            };
+            for (file, flags) in flags.iter_mut().enumerate() {
+                let states = self
+                    .states
+                    .iter()
+                    .filter(|((f, side, _), _)| *f == file && *side == 0);
+                for (result, _, plies) in results {
+                    if states
+                        .clone()
+                        .any(|(_, s)| self.wdl[*s] == result && self.dtz[*s] % 2 == 0)
+                    {
+                        *flags |= plies;
+                    }
+                }
+                for (_, state) in states {
+                    if let Some((map, value)) = stored(*state, *flags) {
+                        maps[file][map].push(value);
+                    }
+                }
+                for map in maps[file].iter_mut() {
+                    map.sort_unstable();
+                    map.dedup();
+                }
+            }
+
+            for ((file, side, idx), state) in self.states.iter() {
+                let value = (self.wdl[*state] as i32 - Wdl::Draw as i32 + 2) as u16;
+                wdl[*file][*side][*idx as usize] = Some(value);
+                if *side != 0 {
+                    continue;
+                }
+                if let Some((map, value)) = stored(*state, flags[*file]) {
+                    let value = maps[*file][map].binary_search(&value).unwrap();
+                    dtz[*file][0][*idx as usize] = Some(value as u16);
+                }
+            }
+            let wdl = Values {
+                flags: vec![0; wdl.len()],
+                maps: vec![Default::default(); wdl.len()],
+                values: fill(wdl),
+            };
+            let dtz = Values {
+                values: fill(dtz),
+                flags,
+                maps,
+            };
+            (wdl, dtz)
+        }
+    }
+
+    // Returns the values of a fixture by file and side, which are all unknown.
+    fn values_for(name: &str, kind: Kind) -> Vec<Vec<Vec<Option<u16>>>> {
+        let material = Material::parse(name).unwrap();
+        let num_files = if material.pawns { 4 } else { 1 };
+        let num_sides = if kind == Kind::Wdl && !material.symmetric {
+            2
+        } else {
+            1
+        };
+        vec![vec![vec![None; spec_size(name) as usize]; num_sides]; num_files]
+    }
+
+    fn fill(values: Vec<Vec<Vec<Option<u16>>>>) -> Vec<Vec<Vec<u16>>> {
+        values
+            .into_iter()
+            .map(|sides| {
+                sides
+                    .into_iter()
+                    .map(|values| {
+                        let mut counts = HashMap::new();
+                        values
+                            .iter()
+                            .flatten()
+                            .for_each(|v| *counts.entry(*v).or_insert(0) += 1);
+                        let common = counts
+                            .into_iter()
+                            .max_by_key(|(v, c)| (*c, *v))
+                            .map_or(0, |(v, _)| v);
+                        values.into_iter().map(|v| v.unwrap_or(common)).collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Returns the pieces of a table where black only has the king, or of KPvKP,
+    // in the order in which the fixtures encode them, where the pawns lead
+    // and the kings come first otherwise.
+    fn fixture_pieces(name: &str) -> Vec<u8> {
+        if name == "KPvKP" {
+            return vec![PAWN, PAWN | BLACK, KING, KING | BLACK];
+        }
+        let (white, black) = name.split_once('v').unwrap();
+        assert_eq!(black, "K");
+        let extra = white[1..]
+            .chars()
+            .map(|c| PIECE_CHARS.iter().find(|(p, _)| *p == c).unwrap().1);
+        if white.contains('P') {
+            assert_eq!(white, "KP");
+            vec![PAWN, KING, KING | BLACK]
+        } else {
+            [KING, KING | BLACK].into_iter().chain(extra).collect()
+        }
+    }
+
+    // Returns a table with the encodings of the fixtures, without values.
+    fn layout(name: &str, kind: Kind) -> Table {
+        Table::with_layout(name, kind, header(name, kind))
+            .unwrap()
+            .0
+    }
+
+    fn header(name: &str, kind: Kind) -> Vec<u8> {
+        let material = Material::parse(name).unwrap();
+        let mut data = kind.magic().to_vec();
+        data.push(SPLIT | if material.pawns { HAS_PAWNS } else { 0 });
+        for _ in 0..if material.pawns { 4 } else { 1 } {
+            data.extend(fixture_order(name));
+            data.extend(fixture_pieces(name).iter().map(|code| code | (code << 4)));
+        }
+        if data.len() % 2 == 1 {
+            data.push(0);
+        }
+        data
+    }
+
+    // Returns the order bytes of each file of a fixture, where the nibble of
+    // each side is the position of the leading group in the index, and the
+    // second byte has the position of the pawns of the other color. Like the
+    // published tables, some fixtures encode the leading group after others.
+    fn fixture_order(name: &str) -> Vec<u8> {
+        match name {
+            "KNNvK" => vec![0x10],
+            "KPvK" => vec![0x01],
+            "KPvKP" => vec![0x02, 0x00],
+            _ => vec![0x00],
+        }
+    }
+
+    // Calls f with every legal position with the pieces of a table, where
+    // pieces of the same type are only placed in one order.
+    fn for_each_position(name: &str, mut f: impl FnMut(&Board)) {
+        let pieces = fixture_pieces(name);
+        for n in 0..1usize << (6 * pieces.len()) {
+            let squares: Vec<usize> = (0..pieces.len()).map(|i| (n >> (6 * i)) & 63).collect();
+            let placed = (0..pieces.len()).all(|j| {
+                (0..j).all(|i| squares[i] != squares[j])
+                    && (j == 0 || pieces[j - 1] != pieces[j] || squares[j - 1] < squares[j])
+                    && (pieces[j] & 7 != PAWN || (8..56).contains(&squares[j]))
+            });
+            if !placed {
+                continue;
+            }
+            for color in [Color::White, Color::Black] {
+                let mut builder = RUKY.board_builder();
+                for (code, sq) in pieces.iter().zip(squares.iter()) {
+                    add_piece(&mut builder, *code, *sq);
+                }
+                if let Ok(board) = builder.set_color(color).build() {
+                    f(&board);
+                }
+            }
+        }
+    }
+
+    // The squares of the triangle a1-d1-d4 in the order of the published
+    // encoding, where the squares on the diagonal come last.
+    const TRIANGLE: [u64; 64] = [
+        6, 0, 1, 2, 2, 1, 0, 6, //
+        0, 7, 3, 4, 4, 3, 7, 0, //
+        1, 3, 8, 5, 5, 8, 3, 1, //
+        2, 4, 5, 9, 9, 5, 4, 2, //
+        2, 4, 5, 9, 9, 5, 4, 2, //
+        1, 3, 8, 5, 5, 8, 3, 1, //
+        0, 7, 3, 4, 4, 3, 7, 0, //
+        6, 0, 1, 2, 2, 1, 0, 6, //
+    ];
+
+    // Returns the file of the leading pawn, the side to move and the index of a
+    // position of a fixture, as computed by encode_piece and encode_pawn of the
+    // original probing code. It uses neither Table::index nor INDEX, so that
+    // the fixtures that it writes check the reader.
+    fn spec_index(name: &str, board: &Board) -> (usize, usize, u64) {
+        let black_next = board.color() == Color::Black;
+        // Tables with the same material for both players only store white
+        // moving next.
+        let (white, black) = name.split_once('v').unwrap();
+        let flip = (white == black && black_next) || material_key(board, false) != name;
+        let side = (flip != black_next) as usize;
+        let codes = fixture_pieces(name);
+        let mut pieces = board_pieces(board);
+        let mut pos: Vec<usize> = codes
+            .iter()
+            .map(|code| {
+                let code = if flip { code ^ BLACK } else { *code };
+                let i = pieces.iter().position(|(c, _)| *c == code).unwrap();
+                let sq = pieces.swap_remove(i).1;
+                if flip {
+                    sq ^ 56
+                } else {
+                    sq
+                }
+            })
+            .collect();
+
+        if pos[0] & 0x04 != 0 {
+            pos.iter_mut().for_each(|sq| *sq ^= 0x07);
+        }
+        let (lead_len, lead_size) = spec_lead(name);
+        let file = if name.contains('P') { pos[0] % 8 } else { 0 };
+        let idx = if name.contains('P') {
+            // A single leading pawn is indexed by its rank.
+            (pos[0] / 8 - 1) as u64
+        } else {
+            if pos[0] & 0x20 != 0 {
+                pos.iter_mut().for_each(|sq| *sq ^= 0x38);
+            }
+            let above = |sq: usize| sq / 8 > sq % 8;
+            let on_diag = |sq: usize| sq / 8 == sq % 8;
+            if let Some(i) = pos.iter().position(|sq| !on_diag(*sq)) {
+                if i < lead_len && above(pos[i]) {
+                    pos.iter_mut().for_each(|sq| *sq = (*sq % 8) * 8 + *sq / 8);
+                }
+            }
+            let lower = |sq: usize| (0..sq).filter(|s| s % 8 > s / 8).count() as u64;
+            let diag = |sq: usize| (sq / 8) as u64;
+            if lead_len == 3 {
+                let i = (pos[1] > pos[0]) as u64;
+                let j = (pos[2] > pos[0]) as u64 + (pos[2] > pos[1]) as u64;
+                let (p1, p2) = (pos[1] as u64, pos[2] as u64);
+                if !on_diag(pos[0]) {
+                    TRIANGLE[pos[0]] * 63 * 62 + (p1 - i) * 62 + (p2 - j)
+                } else if !on_diag(pos[1]) {
+                    6 * 63 * 62 + diag(pos[0]) * 28 * 62 + lower(pos[1]) * 62 + p2 - j
+                } else if !on_diag(pos[2]) {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + diag(pos[0]) * 7 * 28
+                        + (diag(pos[1]) - i) * 28
+                        + lower(pos[2])
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + diag(pos[0]) * 7 * 6
+                        + (diag(pos[1]) - i) * 6
+                        + (diag(pos[2]) - j)
+                }
+            } else {
+                SPEC_KK[TRIANGLE[pos[0]] as usize][pos[1]]
+            }
+        };
+
+        // The other groups of pieces of the same type follow the leading group,
+        // where the pawns of the other color come first, on the 48 squares of
+        // the second to the seventh rank.
+        let both_pawns = codes.contains(&(PAWN | BLACK));
+        let mut groups = vec![(idx, lead_size)];
+        let mut i = lead_len;
+        while i < pos.len() {
+            let len = (i..pos.len()).take_while(|j| codes[*j] == codes[i]).count();
+            let other_pawns = both_pawns && i == lead_len;
+            pos[i..i + len].sort_unstable();
+            let mut group_idx = 0;
+            for m in i..i + len {
+                let below = pos[..i].iter().filter(|sq| pos[m] > **sq).count();
+                let below = below + if other_pawns { 8 } else { 0 };
+                group_idx += binomial(pos[m] - below, m - i + 1);
+            }
+            let squares = if other_pawns { 48 } else { 64 };
+            groups.push((group_idx, binomial(squares - i, len)));
+            i += len;
+        }
+
+        // The groups are multiplied in the order of the table, where the
+        // leading group and the pawns of the other color are at the positions
+        // of the order bytes, and the other groups fill the other positions.
+        let order = fixture_order(name);
+        let nibble = |byte: u8| (byte >> (4 * side)) & 0xf;
+        let (first, second) = (nibble(order[0]), order.get(1).map_or(0xf, |o| nibble(*o)));
+        let mut rest = 1 + both_pawns as usize..groups.len();
+        let (mut idx, mut factor) = (0, 1);
+        for k in 0..groups.len() as u8 {
+            let group = match k {
+                _ if k == first => 0,
+                _ if k == second => 1,
+                _ => rest.next().unwrap(),
+            };
+            idx += groups[group].0 * factor;
+            factor *= groups[group].1;
+        }
+        (file, side, idx)
+    }
+
+    // Returns the number of pieces in the leading group of a fixture and the
+    // number of their positions, which are a single pawn on one file, three
+    // unique pieces, or the two kings.
+    fn spec_lead(name: &str) -> (usize, u64) {
+        if name.contains('P') {
+            (1, 6)
+        } else if Material::parse(name).unwrap().unique {
+            (3, 31332)
+        } else {
+            (2, 462)
+        }
+    }
+
+    // Returns the number of indices of each file and side of a fixture.
+    fn spec_size(name: &str) -> u64 {
+        let codes = fixture_pieces(name);
+        let (lead_len, mut size) = spec_lead(name);
+        let mut i = lead_len;
+        while i < codes.len() {
+            let len = (i..codes.len())
+                .take_while(|j| codes[*j] == codes[i])
+                .count();
+            let other_pawns = codes[i] == PAWN | BLACK && i == lead_len;
+            size *= binomial(if other_pawns { 48 } else { 64 } - i, len);
+            i += len;
+        }
+        size
+    }
+
+    // The legal positions of two kings, where the first is in the triangle
+    // a1-d1-d4 and the second is not above the diagonal if the first is on it,
+    // and where the positions with both kings on the diagonal come last.
+    fn spec_kk() -> Vec<[u64; 64]> {
+        let on_diag = |sq: usize| sq / 8 == sq % 8;
+        let mut kk = vec![[0; 64]; 10];
+        let mut both_on_diag = Vec::new();
+        let mut code = 0;
+        for idx in 0..10 {
+            let s1 = (0..64)
+                .find(|sq| sq % 8 <= 3 && sq / 8 <= sq % 8 && TRIANGLE[*sq] == idx)
+                .unwrap();
+            for (s2, kk_code) in kk[idx as usize].iter_mut().enumerate() {
+                let near = (s1 / 8).abs_diff(s2 / 8) <= 1 && (s1 % 8).abs_diff(s2 % 8) <= 1;
+                if near || (on_diag(s1) && s2 / 8 > s2 % 8) {
+                    continue;
+                }
+                if on_diag(s1) && on_diag(s2) {
+                    both_on_diag.push((idx, s2));
+                } else {
+                    *kk_code = code;
+                    code += 1;
+                }
+            }
+        }
+        for (idx, s2) in both_on_diag {
+            kk[idx as usize][s2] = code;
+            code += 1;
+        }
+        assert_eq!(code, 462);
+        kk
+    }
+
+    fn binomial(n: usize, k: usize) -> u64 {
+        if k > n {
+            return 0;
+        }
+        (0..k).fold(1, |acc, i| acc * (n - i) as u64 / (i as u64 + 1))
+    }
+
+    fn add_piece(builder: &mut BoardBuilder, code: u8, sq: usize) {
+        let sq = Sq::from(sq);
+        match code {
+            KING => builder.white_king(sq),
+            QUEEN => builder.white_queen(sq),
+            ROOK => builder.white_rook(sq),
+            BISHOP => builder.white_bishop(sq),
+            KNIGHT => builder.white_knight(sq),
+            PAWN => builder.white_pawn(sq),
+            _ if code == PAWN | BLACK => builder.black_pawn(sq),
+            _ => builder.black_king(sq),
+        };
+    }
+
+    // Writes a table with the given values, compressed like the published
+    // tables: the most frequent pairs of adjacent symbols are replaced by new
+    // symbols, the symbols have canonical Huffman codes, where longer codes
+    // have lower values, and DTZ tables have the maps of the values. Checks
+    // that the table reads back the same values.
+    fn write_table(layout: &Table, values: &Values) -> Vec<u8> {
+        const BLOCK_LOG: u8 = 10;
+        const SPAN_LOG: u8 = 10;
+        let (block_size, span) = (1usize << BLOCK_LOG, 1usize << SPAN_LOG);
+        let mut data = header(&layout.name, layout.kind);
+        let (mut sparse_index, mut block_lengths, mut blocks) =
+            (Vec::new(), Vec::new(), Vec::new());
+        for (sides, flags) in values.values.iter().zip(values.flags.iter()) {
+            for values in sides {
+                let mut distinct = values.clone();
+                distinct.sort_unstable();
+                distinct.dedup();
+                if distinct.len() == 1 {
+                    data.extend([flags | SINGLE_VALUE, distinct[0] as u8]);
+                    continue;
+                }
+
+                let (syms, sym_values, seq) = pair_symbols(values);
+                let mut freqs = vec![0; syms.len()];
+                seq.iter().for_each(|sym| freqs[*sym] += 1);
+                let lens = code_lengths(&freqs);
+                let (min_len, max_len) = (*lens.iter().min().unwrap(), *lens.iter().max().unwrap());
+                // The symbols are numbered from the longest codes to the
+                // shortest, and the lowest code of each length follows the
+                // codes of the next length.
+                let mut order: Vec<usize> = (0..syms.len()).collect();
+                order.sort_by_key(|sym| (Reverse(lens[*sym]), *sym));
+                let mut number = vec![0; syms.len()];
+                order
+                    .iter()
+                    .enumerate()
+                    .for_each(|(n, sym)| number[*sym] = n);
+                let num_lens = max_len - min_len + 1;
+                let count = |i: usize| lens.iter().filter(|len| **len == min_len + i).count();
+                let (mut lowest, mut base) = (vec![0; num_lens], vec![0u64; num_lens]);
+                for i in (0..num_lens - 1).rev() {
+                    lowest[i] = lowest[i + 1] + count(i + 1);
+                    assert_eq!((base[i + 1] + count(i + 1) as u64) % 2, 0);
+                    base[i] = (base[i + 1] + count(i + 1) as u64) / 2;
+                }
+                let code = |sym: usize| {
+                    let i = lens[sym] - min_len;
+                    (base[i] + (number[sym] - lowest[i]) as u64, lens[sym])
+                };
+
+                // Each block has whole symbols, and at most 2^15 values.
+                let mut block_starts = vec![0];
+                let mut encoding_blocks = Vec::new();
+                let mut block = vec![0u8; block_size];
+                let (mut bits, mut count) = (0, 0);
+                for sym in seq.iter() {
+                    let (code, len) = code(*sym);
+                    if bits + len > 8 * block_size || count + sym_values[*sym] > 1 << 15 {
+                        block_lengths.extend(((count - 1) as u16).to_le_bytes());
+                        encoding_blocks.extend(block);
+                        block_starts.push(block_starts.last().unwrap() + count);
+                        block = vec![0u8; block_size];
+                        (bits, count) = (0, 0);
+                    }
+                    for bit in 0..len {
+                        if code >> (len - 1 - bit) & 1 == 1 {
+                            block[(bits + bit) / 8] |= 0x80 >> ((bits + bit) % 8);
+                        }
+                    }
+                    bits += len;
+                    count += sym_values[*sym];
+                }
+                block_lengths.extend(((count - 1) as u16).to_le_bytes());
+                encoding_blocks.extend(block);
+                blocks.push(encoding_blocks);
+                for k in 0..values.len().div_ceil(span) {
+                    let target = k * span + span / 2;
+                    let block = block_starts.partition_point(|start| *start <= target) - 1;
+                    let offset = u16::try_from(target - block_starts[block]).unwrap();
+                    sparse_index.extend((block as u32).to_le_bytes());
+                    sparse_index.extend(offset.to_le_bytes());
+                }
+
+                data.extend([*flags, BLOCK_LOG, SPAN_LOG, 0]);
+                data.extend((block_starts.len() as u32).to_le_bytes());
+                data.extend([max_len as u8, min_len as u8]);
+                data.extend(lowest.iter().flat_map(|sym| (*sym as u16).to_le_bytes()));
+                data.extend((syms.len() as u16).to_le_bytes());
+                for sym in order {
+                    let (left, right) = match syms[sym] {
+                        (value, 0xfff) => (value, 0xfff),
+                        (left, right) => (number[left], number[right]),
+                    };
+                    data.extend([
+                        left as u8,
+                        ((left >> 8) & 0xf | (right & 0xf) << 4) as u8,
+                        (right >> 4) as u8,
+                    ]);
+                }
+                if syms.len() % 2 == 1 {
+                    data.push(0);
+                }
+            }
+        }
+        if layout.kind == Kind::Dtz {
+            for (flags, maps) in values.flags.iter().zip(values.maps.iter()) {
+                if flags & MAPPED == 0 {
+                    continue;
+                }
+                if flags & WIDE != 0 {
+                    data.resize(data.len() + data.len() % 2, 0);
+                    for map in maps {
+                        data.extend((map.len() as u16).to_le_bytes());
+                        data.extend(map.iter().flat_map(|value| value.to_le_bytes()));
+                    }
+                } else {
+                    for map in maps {
+                        data.push(map.len() as u8);
+                        data.extend(map.iter().map(|value| *value as u8));
+                    }
+                }
+            }
+            data.resize(data.len() + data.len() % 2, 0);
+        }
+        data.extend(sparse_index);
+        data.extend(block_lengths);
+        for encoding_blocks in blocks {
+            data.resize((data.len() + 0x3f) & !0x3f, 0);
+            data.extend(encoding_blocks);
+        }
+
+        let table = Table::new(&layout.name, layout.kind, data.clone()).unwrap();
+        for (encodings, values) in table.encodings.iter().zip(values.values.iter()) {
+            for (encoding, values) in encodings.iter().zip(values) {
+                for (idx, value) in values.iter().enumerate() {
+                    assert_eq!(encoding.decompress(&table.data, idx as u64), Some(*value));
+                }
+            }
+        }
+        data
+    }
+
+    // Returns the symbols of the values, where a symbol is either a value and
+    // 0xfff or a pair of symbols, with the number of values of each symbol,
+    // and the values as a sequence of symbols. The most frequent pairs of
+    // adjacent symbols are replaced by a new symbol, as long as the pairs are
+    // frequent and the symbols have at most 256 values.
+    fn pair_symbols(values: &[u16]) -> (Vec<(usize, usize)>, Vec<usize>, Vec<usize>) {
+        let mut distinct = values.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let mut syms: Vec<(usize, usize)> = distinct.iter().map(|v| (*v as usize, 0xfff)).collect();
+        let mut sym_values = vec![1; syms.len()];
+        let mut seq: Vec<usize> = values
+            .iter()
+            .map(|v| distinct.binary_search(v).unwrap())
+            .collect();
+        for _ in 0..64 {
+            let mut counts = HashMap::new();
+            for pair in seq.windows(2) {
+                if sym_values[pair[0]] + sym_values[pair[1]] <= 256 {
+                    *counts.entry((pair[0], pair[1])).or_insert(0) += 1;
+                }
+            }
+            let Some((pair, count)) = counts
+                .into_iter()
+                .max_by_key(|(pair, count)| (*count, Reverse(*pair)))
+            else {
+                break;
+            };
+            if count < 16 {
+                break;
+            }
+            let sym = syms.len();
+            syms.push(pair);
+            sym_values.push(sym_values[pair.0] + sym_values[pair.1]);
+            let mut next = Vec::with_capacity(seq.len());
+            let mut i = 0;
+            while i < seq.len() {
+                if i + 1 < seq.len() && (seq[i], seq[i + 1]) == pair {
+                    next.push(sym);
+                    i += 2;
+                } else {
+                    next.push(seq[i]);
+                    i += 1;
+                }
+            }
+            seq = next;
+        }
+        (syms, sym_values, seq)
+    }
+
+    // Returns the lengths of the Huffman codes of symbols with the given
+    // frequencies, where unused symbols count as used once. The frequencies are
+    // flattened until the codes have at most 32 bits, which the reader needs.
+    fn code_lengths(freqs: &[u64]) -> Vec<usize> {
+        let mut freqs: Vec<u64> = freqs.iter().map(|freq| (*freq).max(1)).collect();
+        loop {
+            let mut heap: BinaryHeap<Reverse<(u64, usize)>> = freqs
+                .iter()
+                .enumerate()
+                .map(|(sym, freq)| Reverse((*freq, sym)))
+                .collect();
+            let mut parent = vec![usize::MAX; freqs.len()];
+            while let (Some(Reverse((f1, a))), Some(Reverse((f2, b)))) = (heap.pop(), heap.pop()) {
+                let node = parent.len();
+                parent.push(usize::MAX);
+                (parent[a], parent[b]) = (node, node);
+                heap.push(Reverse((f1 + f2, node)));
+            }
+            let lens: Vec<usize> = (0..freqs.len())
+                .map(|mut node| {
+                    let mut len = 0;
+                    while parent[node] != usize::MAX {
+                        node = parent[node];
+                        len += 1;
+                    }
+                    len
+                })
+                .collect();
+            if lens.iter().all(|len| *len <= 32) {
+                return lens;
+            }
+            freqs.iter_mut().for_each(|freq| *freq = *freq / 2 + 1);
+        }
+    }
+}
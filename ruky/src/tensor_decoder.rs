@@ -6,13 +6,19 @@ use crate::ecmv::EcMove;
 use crate::err::RukyErr;
 use crate::piece::Piece;
 use crate::piece_move::PieceMove;
+use crate::tensor_encoder::NetShape;
 use burn::prelude::{Backend, Tensor};
 use std::marker::PhantomData;
 
 // dec_boards returns a DecBoards by decoding the moves in |enc_moves| given
-// legal |moves|.
-pub fn dec_boards(moves: Vec<Board>, value: f32, enc_moves: Vec<f32>) -> DecBoards {
-    assert_eq!(enc_moves.len(), N_POSSIBLE_MOVES);
+// legal |moves|, where |enc_moves| is the policy of a network of |shape|.
+pub fn dec_boards(
+    shape: NetShape,
+    moves: Vec<Board>,
+    value: f32,
+    enc_moves: Vec<f32>,
+) -> DecBoards {
+    assert_eq!(enc_moves.len(), shape.n_possible_moves());
 
     let mut total = 0.0;
     let mut board_probs = Vec::<(Board, f32)>::new();
@@ -88,7 +94,7 @@ impl<B: Backend> TensorDecoder<B> for AzDecoder<B> {
         let mv_data = mv_tensor_data
             .as_slice::<f32>()
             .map_err(|_| RukyErr::InputIsNotValid)?;
-        if mv_data.len() != N_POSSIBLE_MOVES {
+        if mv_data.len() != NetShape::for_variant(board.variant()).n_possible_moves() {
             return Err(RukyErr::MoveTensorDim);
         }
 
@@ -123,7 +129,7 @@ impl<B: Backend> TensorDecoder<B> for AzDecoder<B> {
         let mv_data = mv_tensor_data
             .as_slice::<f32>()
             .map_err(|_| RukyErr::InputIsNotValid)?;
-        if mv_data.len() != N_POSSIBLE_MOVES {
+        if mv_data.len() != NetShape::for_variant(board.variant()).n_possible_moves() {
             return Err(RukyErr::MoveTensorDim);
         }
 
@@ -157,5 +163,3 @@ fn get_value<B: Backend>(tensor: &Tensor<B, 2>) -> Result<f32, RukyErr> {
     }
     Ok(eval_data[0])
 }
-
-pub(crate) const N_POSSIBLE_MOVES: usize = 73 * 8 * 8;
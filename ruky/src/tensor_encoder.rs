@@ -1,36 +1,87 @@
 // This module contains components for encoding boards and moves to tensors.
 
 use crate::board::Board;
-use crate::ecmv::EcMove;
+use crate::ecmv::{EcMove, N_MOVE_CODES, N_STD_MOVE_CODES};
+use crate::piece::Color;
 use crate::piece_set::PieceSet;
+use crate::pocket::POCKET_PIECES;
 use crate::search::{Bp, Mp};
+use crate::variant::Variant;
 use burn::prelude::{Backend, Device, Tensor, TensorData};
 use std::iter::zip;
 use std::ops::Range;
 
+// NetShape is the shape of the input and the policy of the network for a
+// variant. Standard chess keeps the AlphaZero shape of 119 input planes and 73
+// move codes, so networks trained on standard chess keep loading. The other
+// variants add the planes for the pockets, the variant and the checks given,
+// and the move codes for drops in Crazyhouse.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum NetShape {
+    #[default]
+    Standard,
+    Variants,
+}
+
+impl NetShape {
+    pub fn for_variant(variant: Variant) -> Self {
+        match variant {
+            Variant::Standard => NetShape::Standard,
+            _ => NetShape::Variants,
+        }
+    }
+
+    // Returns the number of input planes.
+    pub const fn n_planes(&self) -> usize {
+        match *self {
+            NetShape::Standard => N_STD_PLANES,
+            NetShape::Variants => N_PLANES,
+        }
+    }
+
+    // Returns the number of move codes, i.e. the number of policy planes.
+    pub const fn n_move_codes(&self) -> usize {
+        match *self {
+            NetShape::Standard => N_STD_MOVE_CODES,
+            NetShape::Variants => N_MOVE_CODES,
+        }
+    }
+
+    // Returns the number of values in the policy for one board position.
+    pub const fn n_possible_moves(&self) -> usize {
+        self.n_move_codes() * BOARD_SIZE
+    }
+}
+
 // Creates a vector of floats for writing the encoded data for |batch_size|
 // board positions.
-pub fn get_batch_vec(batch_size: impl Into<usize>) -> Vec<f32> {
-    vec![0.0; batch_size.into() * N_PLANES * BOARD_SIZE]
+pub fn get_batch_vec(shape: NetShape, batch_size: impl Into<usize>) -> Vec<f32> {
+    vec![0.0; batch_size.into() * single_batch_size(shape)]
 }
 
 // Returns a pair representing the first and last index in a range for a given
 // |batch|.
-pub fn get_batch_range(batch: impl Into<usize>) -> Range<usize> {
-    let first = batch.into() * N_PLANES * BOARD_SIZE;
-    let last = first + N_PLANES * BOARD_SIZE;
+pub fn get_batch_range(shape: NetShape, batch: impl Into<usize>) -> Range<usize> {
+    let first = batch.into() * single_batch_size(shape);
+    let last = first + single_batch_size(shape);
     first..last
 }
 
-pub const fn single_batch_size() -> usize {
-    N_PLANES * BOARD_SIZE
+pub const fn single_batch_size(shape: NetShape) -> usize {
+    shape.n_planes() * BOARD_SIZE
 }
 
-// Encodes a single board as a vector of floats.
+// Encodes a single board as a vector of floats, using the shape for the variant
+// of the board.
 pub fn enc_board(board: &Board) -> Vec<f32> {
-    let mut data = vec![0.0; N_PLANES * BOARD_SIZE];
+    let shape = NetShape::for_variant(board.variant());
+    let mut data = vec![0.0; single_batch_size(shape)];
 
     enc_pieces_and_rep(board, &mut data);
+    if shape == NetShape::Variants {
+        enc_pockets(board, &mut data[POCKETS]);
+        enc_variant(board, &mut data[VARIANT]);
+    }
 
     let state_features = get_state_features(&board);
     for (val, chunk) in zip(
@@ -45,10 +96,14 @@ pub fn enc_board(board: &Board) -> Vec<f32> {
 
 // Encodes a slice of boards as a vector of floats. Panics if the slice is
 // empty. Encodes at most eight boards. It assumes that the first board is the
-// one being evaluated.
+// one being evaluated, and uses the shape for its variant.
 pub fn enc_boards(boards: &[Board]) -> Vec<f32> {
     assert!(!boards.is_empty());
-    let mut data = vec![0.0; N_PLANES * BOARD_SIZE];
+    let board = boards
+        .first()
+        .expect("boards should have at least one board.");
+    let shape = NetShape::for_variant(board.variant());
+    let mut data = vec![0.0; single_batch_size(shape)];
 
     for (board, chunk) in zip(
         boards.into_iter().take(8),
@@ -57,9 +112,10 @@ pub fn enc_boards(boards: &[Board]) -> Vec<f32> {
         enc_pieces_and_rep(board, chunk);
     }
 
-    let board = boards
-        .first()
-        .expect("boards should have at least one board.");
+    if shape == NetShape::Variants {
+        enc_pockets(board, &mut data[POCKETS]);
+        enc_variant(board, &mut data[VARIANT]);
+    }
     let state_features = get_state_features(&board);
 
     for (val, chunk) in zip(
@@ -106,13 +162,17 @@ pub trait TensorEncoder<B: Backend> {
 }
 
 // AzEncoder represents the AlphaZero encoder, i.e. it encodes the board state
-// for input into the AlphaZero network. For a given board position, it outputs
-// a 119 x 8 x 8 tensor. 6 planes are used to represent one set of the pieces, 6
-// for the other set of pieces, and 2 planes for repetition count in current
-// position for each player. Hence, the current board position uses 14 total
-// planes, but this is repeated for 8 total time steps. If there are no previous
-// positions, the planes are set to zeros. The last 7 planes are used to
-// represent
+// for input into the AlphaZero network. For a given board position in a
+// variant, it outputs a 136 x 8 x 8 tensor. 6 planes are used to represent one
+// set of the pieces, 6 for the other set of pieces, and 2 planes for repetition
+// count in current position for each player. Hence, the current board position
+// uses 14 total planes, but this is repeated for 8 total time steps. If there
+// are no previous positions, the planes are set to zeros. The next 10 planes
+// hold the number of queens, rooks, bishops, knights and pawns in the pockets
+// in Crazyhouse, first for the current player. The next 5 planes are a one-hot
+// encoding of the variant, and the 2 planes after them hold the number of
+// checks given in Three-check, first by the current player. The last 7 planes
+// are used to represent
 // - 1 for the current color
 // - 1 for the total move count
 // - 1 for king castling for the current player
@@ -120,65 +180,115 @@ pub trait TensorEncoder<B: Backend> {
 // - 1 for king castling for the other player
 // - 1 for queen castling for the other player
 // - 1 for the progress count (i.e. 50 move rule)
+// Standard chess leaves out the planes for the pockets, the variant and the
+// checks, which gives a 119 x 8 x 8 tensor. See NetShape.
 #[derive(Clone, Debug)]
 pub struct AzEncoder<B: Backend> {
     device: Device<B>,
+    // The shape of the network, used for the moves and the batches, which are
+    // encoded without a board.
+    shape: NetShape,
 }
 
 impl<B: Backend> AzEncoder<B> {
+    // Creates an encoder for a network for standard chess.
     pub fn new(device: Device<B>) -> Self {
-        Self { device }
+        Self::with_shape(device, NetShape::Standard)
+    }
+
+    pub fn with_shape(device: Device<B>, shape: NetShape) -> Self {
+        Self { device, shape }
     }
 }
 
 impl<B: Backend> TensorEncoder<B> for AzEncoder<B> {
-    // Outputs a Tensor with dimensions (1, 119, 8, 8).
+    // Outputs a Tensor with dimensions (1, 136, 8, 8), or (1, 119, 8, 8) in
+    // standard chess.
     fn encode_board(&self, board: &Board) -> Tensor<B, 4> {
         let data = enc_board(board);
-        let tensor_data = TensorData::new(data, [1, N_PLANES, N_ROWS, N_COLS]);
+        let n_planes = NetShape::for_variant(board.variant()).n_planes();
+        let tensor_data = TensorData::new(data, [1, n_planes, N_ROWS, N_COLS]);
         Tensor::from_data(tensor_data, &self.device)
     }
 
     fn encode_boards(&self, boards: &[Board]) -> Tensor<B, 4> {
         let data = enc_boards(boards);
-        let tensor_data = TensorData::new(data, [1, N_PLANES, N_ROWS, N_COLS]);
+        let n_planes = NetShape::for_variant(boards[0].variant()).n_planes();
+        let tensor_data = TensorData::new(data, [1, n_planes, N_ROWS, N_COLS]);
         Tensor::from_data(tensor_data, &self.device)
     }
 
     // Encodes the move probabilities in mps as a tensor.
     fn encode_mps(&self, mps: &[Mp]) -> Tensor<B, 4> {
         assert!(!mps.is_empty());
-        let mut data = vec![0.0; N_MOVE_TYPES * BOARD_SIZE];
+        let mut data = vec![0.0; self.shape.n_possible_moves()];
         let total_visits = mps.iter().fold(0, |acc, mp| acc + mp.visits) as f32;
         for mp in mps {
             let ec_move = EcMove::from(mp.pm);
             let index = ec_move.index();
             data[index] = mp.visits as f32 / total_visits;
         }
-        let tensor_data = TensorData::new(data, [1, N_MOVE_TYPES, N_ROWS, N_COLS]);
+        let tensor_data = TensorData::new(data, [1, self.shape.n_move_codes(), N_ROWS, N_COLS]);
         Tensor::from_data(tensor_data, &self.device)
     }
 
     // Encodes the move probabilities in bps as a tensor.
     fn encode_bps(&self, bps: &[Bp]) -> Tensor<B, 4> {
         assert!(!bps.is_empty());
-        let mut data = vec![0.0; N_MOVE_TYPES * BOARD_SIZE];
+        let mut data = vec![0.0; self.shape.n_possible_moves()];
         let total_visits = bps.iter().fold(0, |acc, bp| acc + bp.visits) as f32;
         for bp in bps {
             let ec_move = EcMove::from(bp.last_move());
             let index = ec_move.index();
             data[index] = bp.visits as f32 / total_visits;
         }
-        let tensor_data = TensorData::new(data, [1, N_MOVE_TYPES, N_ROWS, N_COLS]);
+        let tensor_data = TensorData::new(data, [1, self.shape.n_move_codes(), N_ROWS, N_COLS]);
         Tensor::from_data(tensor_data, &self.device)
     }
 
     fn encode_batch_data(&self, batch_size: usize, data: Vec<f32>) -> Tensor<B, 4> {
-        let tensor_data = TensorData::new(data, [batch_size, N_PLANES, N_ROWS, N_COLS]);
+        let tensor_data =
+            TensorData::new(data, [batch_size, self.shape.n_planes(), N_ROWS, N_COLS]);
         Tensor::from_data(tensor_data, &self.device)
     }
 }
 
+// Encodes the number of pieces of each type in the pockets in Crazyhouse, first
+// for the player to move next. The planes are zero in other variants.
+fn enc_pockets(board: &Board, data: &mut [f32]) {
+    assert!(data.len() == N_POCKET_PLANES * BOARD_SIZE);
+    let (next_to_play, after_to_play) = if board.is_white_next() {
+        (Color::White, Color::Black)
+    } else {
+        (Color::Black, Color::White)
+    };
+    let counts = [next_to_play, after_to_play]
+        .into_iter()
+        .flat_map(|color| POCKET_PIECES.map(|piece| board.pocket(color).count(piece)));
+    for (count, chunk) in zip(counts, data.chunks_exact_mut(BOARD_SIZE)) {
+        chunk.fill(count.into());
+    }
+}
+
+// Encodes the variant as one-hot planes followed by the number of checks given
+// in Three-check, first by the player to move next. The check planes are zero
+// in other variants.
+fn enc_variant(board: &Board, data: &mut [f32]) {
+    assert!(data.len() == (N_VARIANT_PLANES + 2) * BOARD_SIZE);
+    let (variant, checks) = data.split_at_mut(N_VARIANT_PLANES * BOARD_SIZE);
+    let first = board.variant() as usize * BOARD_SIZE;
+    variant[first..first + BOARD_SIZE].fill(1.0);
+
+    let (next_to_play, after_to_play) = if board.is_white_next() {
+        (Color::White, Color::Black)
+    } else {
+        (Color::Black, Color::White)
+    };
+    let (next_checks, after_checks) = checks.split_at_mut(BOARD_SIZE);
+    next_checks.fill(board.checks_given(next_to_play).into());
+    after_checks.fill(board.checks_given(after_to_play).into());
+}
+
 fn get_state_features(board: &Board) -> [f32; 7] {
     [
         board.is_white_next().into(),
@@ -204,5 +314,13 @@ const BOARD_SIZE: usize = 64;
 const N_PIECE_TYPES: usize = 6;
 const N_ROWS: usize = 8;
 const N_COLS: usize = 8;
-const N_PLANES: usize = 119;
-const N_MOVE_TYPES: usize = 6;
+const N_POCKET_PLANES: usize = 2 * POCKET_PIECES.len();
+// One plane for each Variant.
+const N_VARIANT_PLANES: usize = 5;
+const N_PLANES: usize = 8 * 14 + N_POCKET_PLANES + N_VARIANT_PLANES + 2 + 7;
+const N_STD_PLANES: usize = 8 * 14 + 7;
+// The planes for the pockets, which come after the planes for the 8 positions.
+const POCKETS: Range<usize> = 8 * 14 * BOARD_SIZE..(8 * 14 + N_POCKET_PLANES) * BOARD_SIZE;
+// The planes for the variant and the checks given, which come after the
+// pockets.
+const VARIANT: Range<usize> = POCKETS.end..POCKETS.end + (N_VARIANT_PLANES + 2) * BOARD_SIZE;
@@ -4,6 +4,7 @@ use crate::dataset::{GamesBatcher, GamesDataset};
 use crate::err::RukyErr;
 use crate::game::{GameResult, GameWinner, MatchGamesBuilder, TrainingGameBuilder};
 use crate::nn::{AlphaZeroNet, AlphaZeroNetRecord};
+use crate::tensor_encoder::NetShape;
 use crate::Board;
 use burn::{
     backend::Autodiff,
@@ -65,6 +66,9 @@ pub struct Trainer<B: Backend> {
     min_win_rate: f32,
     // The number of games to play between newly trained and older network.
     match_games: usize,
+    // If true, self-play games start from random Chess960 positions to increase
+    // the diversity of openings.
+    chess960: bool,
 }
 
 impl<B: Backend> Trainer<B> {
@@ -83,6 +87,7 @@ impl<B: Backend> Trainer<B> {
             .batch_size(self.inference_batch_size)
             .num_workers(self.num_workers)
             .net(net)
+            .chess960(self.chess960)
             .build()?;
 
         let mut game_results = Vec::new();
@@ -141,7 +146,7 @@ impl<B: Backend> Trainer<B> {
         // current best trained model. This could be an older model trained in a
         // previously, or it could be one from one of the current training
         // sessions.
-        let model = AlphaZeroNet::<Autodiff<B>>::new(&self.device);
+        let model = AlphaZeroNet::<Autodiff<B>>::with_shape(&self.device, self.shape());
 
         // TODO: configure learner to log metrics and to use a learning rate
         // scheduler.
@@ -171,7 +176,7 @@ impl<B: Backend> Trainer<B> {
             )
             .expect("Model just saved - should exist.");
 
-        let model = AlphaZeroNet::<B>::new(&self.device).load_record(record);
+        let model = AlphaZeroNet::<B>::with_shape(&self.device, self.shape()).load_record(record);
         Ok(Arc::new(model))
     }
 
@@ -219,10 +224,15 @@ impl<B: Backend> Trainer<B> {
         Ok(net)
     }
 
+    // Returns the shape of the network for the variant played by the trainer.
+    fn shape(&self) -> NetShape {
+        NetShape::for_variant(self.board.variant())
+    }
+
     pub fn run_training(&self) -> Result<(), RukyErr> {
         // TODO: an option to be able to begin training with an already trained
         // model.
-        let mut net = Arc::new(AlphaZeroNet::new(&self.device));
+        let mut net = Arc::new(AlphaZeroNet::with_shape(&self.device, self.shape()));
         for i in 0..self.num_sessions {
             let (old_net, game_results) = self.play_self(net)?;
             let new_net = self.train_net(game_results, i)?;
@@ -273,6 +283,8 @@ pub struct TrainerBuilder<B: Backend> {
     min_win_rate: f32,
     // The number of games to play between newly trained and older network.
     match_games: usize,
+    // If true, self-play games start from random Chess960 positions.
+    chess960: bool,
 }
 
 impl<B: Backend> TrainerBuilder<B> {
@@ -301,6 +313,7 @@ impl<B: Backend> TrainerBuilder<B> {
             num_epochs: None,
             min_win_rate: 0.55,
             match_games: 50,
+            chess960: false,
         }
     }
 
@@ -390,6 +403,11 @@ impl<B: Backend> TrainerBuilder<B> {
         self
     }
 
+    pub fn chess960(mut self, chess960: bool) -> Self {
+        self.chess960 = chess960;
+        self
+    }
+
     pub fn build(self) -> Result<Trainer<B>, RukyErr> {
         if self.board.is_none() || self.device.is_none() || self.num_games.is_none() {
             return Err(RukyErr::PreconditionErr);
@@ -415,6 +433,7 @@ impl<B: Backend> TrainerBuilder<B> {
             num_epochs: self.num_epochs.unwrap_or(100),
             min_win_rate: self.min_win_rate,
             match_games: self.match_games,
+            chess960: self.chess960,
         })
     }
 }
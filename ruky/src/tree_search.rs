@@ -53,6 +53,14 @@ impl TreeSearch {
         self.sample_action = false;
     }
 
+    // Same as reset, but the new root is board instead of the initial board.
+    pub fn reset_board(&mut self, board: &Board) {
+        self.children.clear();
+        self.children.push(Node::from(board));
+        self.root = 0;
+        self.sample_action = false;
+    }
+
     pub fn rollout(&mut self) -> Result<RolloutType, RukyErr> {
         let mut node_index = self.root_index();
         let mut depth = 0u32;
@@ -110,8 +118,12 @@ impl TreeSearch {
         self.children[node_index].is_leaf
     }
 
+    // Returns true if the game ends at the node. Below the root, a draw that
+    // can be claimed also ends the game, since the player to move would claim
+    // it. The root is still searched, e.g. when the engine plays on instead.
     pub fn is_terminal(&self, node_index: usize) -> bool {
-        self.children[node_index].is_terminal()
+        let node = &self.children[node_index];
+        node.is_terminal() || (node_index != self.root && node.board.claimable_draw().is_some())
     }
 
     pub fn is_expanded(&self, node_index: usize) -> bool {
@@ -135,8 +147,8 @@ impl TreeSearch {
     }
 
     pub fn terminate(&mut self, node_index: usize) {
+        assert!(self.is_terminal(node_index));
         let node = &mut self.children[node_index];
-        assert!(node.is_terminal());
         node.init_value = match node.board.is_mate() {
             true => 1.0,
             false => 0.0,
@@ -500,3 +512,103 @@ const DIR_EXPLORE_FRAC: f32 = 0.25;
 
 // The maximum number of boards to collect for encoding.
 const MAX_ENC_BOARDS: usize = 8;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruky::Ruky;
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        static ref RUKY: Ruky = Ruky::new();
+    }
+
+    // The value of a terminal node is from the perspective of the player who
+    // made the move leading to it, so a mate favours the player who mated, and
+    // the parent, where the mated player is to move, gets the opposite value.
+    #[test]
+    fn terminal_value_after_mate() {
+        let board = RUKY.from_fen("4k3/8/4K3/8/8/8/8/7R w - - 0 1").unwrap();
+        let mated = RUKY.from_fen("4k2R/8/4K3/8/8/8/8/8 b - - 1 1").unwrap();
+        assert!(mated.is_mate());
+        let mut tree_search = TreeSearch::from(board);
+        tree_search.expand(
+            0,
+            EvalBoards {
+                board_probs: vec![(mated, 1.0)],
+                value: 0.0,
+            },
+        );
+
+        let rollout = tree_search.rollout().unwrap();
+        assert!(matches!(
+            rollout,
+            RolloutType::Terminal {
+                node_id: 1,
+                depth: 1
+            }
+        ));
+        assert_eq!(tree_search.children[1].value, 1.0);
+        assert_eq!(tree_search.root_node().value, -1.0);
+    }
+
+    #[test]
+    fn terminal_value_after_stalemate() {
+        let board = RUKY.from_fen("k7/8/1K6/8/8/8/8/1R6 w - - 0 1").unwrap();
+        let stalemate = RUKY.from_fen("k7/1R6/1K6/8/8/8/8/8 b - - 1 1").unwrap();
+        assert!(stalemate.is_terminal() && !stalemate.is_mate());
+        let mut tree_search = TreeSearch::from(board);
+        tree_search.expand(
+            0,
+            EvalBoards {
+                board_probs: vec![(stalemate, 1.0)],
+                value: 0.0,
+            },
+        );
+
+        let rollout = tree_search.rollout().unwrap();
+        assert!(matches!(
+            rollout,
+            RolloutType::Terminal {
+                node_id: 1,
+                depth: 1
+            }
+        ));
+        assert_eq!(tree_search.children[1].value, 0.0);
+        assert_eq!(tree_search.root_node().value, 0.0);
+    }
+
+    // A draw that can be claimed ends the game below the root, but a root with
+    // a draw that can be claimed is still searched.
+    #[test]
+    fn terminal_value_after_threefold_repetition() {
+        let mut board = RUKY.new_board();
+        for san in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"] {
+            board.make_move(board.parse_san(san).unwrap());
+        }
+        let mut repeated = board.clone();
+        repeated.make_move(board.parse_san("Ng8").unwrap());
+        assert!(!repeated.is_terminal() && repeated.claimable_draw().is_some());
+        let mut tree_search = TreeSearch::from(board);
+        tree_search.expand(
+            0,
+            EvalBoards {
+                board_probs: vec![(repeated.clone(), 1.0)],
+                value: 0.5,
+            },
+        );
+
+        let rollout = tree_search.rollout().unwrap();
+        assert!(matches!(
+            rollout,
+            RolloutType::Terminal {
+                node_id: 1,
+                depth: 1
+            }
+        ));
+        assert_eq!(tree_search.children[1].value, 0.0);
+
+        let mut tree_search = TreeSearch::from(repeated);
+        assert!(!tree_search.rollout().unwrap().is_terminal());
+    }
+}
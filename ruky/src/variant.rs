@@ -0,0 +1,114 @@
+/// This module contains the chess variants that a Board can play. The rules of
+/// each variant are implemented by the Board, which checks the variant where
+/// the rules differ from standard chess:
+///
+/// * Three-check: a player also wins by giving check for the third time. The
+///   number of checks is part of the position, and is written in FEN as the
+///   checks remaining for each player after the en-passant square, e.g.
+///   [rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1].
+/// * King of the Hill: a player also wins by moving their king to one of the
+///   four center squares.
+/// * Atomic: captures explode, which removes the capturing piece and every
+///   piece other than pawns next to the capture square. A player wins by
+///   exploding the king of the other player, kings cannot capture, and a king
+///   next to the other king cannot be in check.
+/// * Crazyhouse: a captured piece goes to the pocket of the player who captured
+///   it, and instead of moving, a player may drop a piece from their pocket on
+///   any empty square, except pawns on the first and last rank. A promoted piece
+///   goes back to the pocket as a pawn when it is captured. The pockets are
+///   written in FEN after the pieces, and promoted pieces are marked with a ~,
+///   e.g. [r1bqkbnr/pppp1ppp/2n5/4Q~3/8/8/PPPP1PPP/RNB1KBNR[Pp] b KQkq - 0 4].
+///
+/// In every variant, a player who is mated also loses, and a game that is won
+/// by the rules of a variant ends with GameState::Mate for the losing player.
+///
+/// For more background, see https://lichess.org/variant.
+use crate::bitboard::{BitBoard, CENTER};
+use serde::{Deserialize, Serialize};
+
+// The squares that win the game in King of the Hill.
+pub const HILL: BitBoard = CENTER;
+
+// The number of checks that wins the game in Three-check.
+pub const WINNING_CHECKS: u8 = 3;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Variant {
+    #[default]
+    Standard,
+    ThreeCheck,
+    KingOfTheHill,
+    Atomic,
+    Crazyhouse,
+}
+
+impl Variant {
+    // Returns the name of the variant as used in the Variant tag of PGN.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Variant::Standard => "Standard",
+            Variant::ThreeCheck => "Three-check",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::Atomic => "Atomic",
+            Variant::Crazyhouse => "Crazyhouse",
+        }
+    }
+
+    // Returns the variant with the given name, ignoring case, spaces and
+    // dashes, so that the names used in PGN and by UCI_Variant are both
+    // accepted, e.g. "Three-check" and "3check". Returns None for unknown
+    // variants.
+    pub fn from_name(name: &str) -> Option<Variant> {
+        let name: String = name
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+            .collect::<String>()
+            .to_lowercase();
+        match name.as_str() {
+            "standard" | "chess" | "normal" => Some(Variant::Standard),
+            "threecheck" | "3check" => Some(Variant::ThreeCheck),
+            "kingofthehill" | "koth" => Some(Variant::KingOfTheHill),
+            "atomic" => Some(Variant::Atomic),
+            "crazyhouse" | "zh" => Some(Variant::Crazyhouse),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn is_standard(&self) -> bool {
+        matches!(*self, Variant::Standard)
+    }
+}
+
+impl std::fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sq;
+
+    #[test]
+    fn variant_names() {
+        for variant in [
+            Variant::Standard,
+            Variant::ThreeCheck,
+            Variant::KingOfTheHill,
+            Variant::Atomic,
+            Variant::Crazyhouse,
+        ] {
+            assert_eq!(Variant::from_name(variant.name()), Some(variant));
+        }
+        assert_eq!(Variant::from_name("3check"), Some(Variant::ThreeCheck));
+        assert_eq!(
+            Variant::from_name("kingofthehill"),
+            Some(Variant::KingOfTheHill)
+        );
+        assert_eq!(Variant::from_name("zh"), Some(Variant::Crazyhouse));
+        assert_eq!(Variant::from_name("horde"), None);
+        assert_eq!(HILL, BitBoard::from(&[sq::D4, sq::E4, sq::D5, sq::E5]));
+    }
+}
@@ -0,0 +1,323 @@
+// This module contains the Zobrist keys used to hash board positions. The keys
+// are generated at compile time from a fixed seed, which means that a position
+// hashes to the same value across runs, processes and machines, and hence the
+// hash can be used to key transposition tables, opening books and datasets.
+
+use crate::piece::{Color, Piece, Piece::*};
+use crate::piece_move::{PieceMove, PieceMove::*};
+use crate::piece_set::PieceSet;
+use crate::pocket::{Pocket, POCKET_PIECES};
+use crate::sq::Sq;
+
+// The Zobrist keys for all the position features that we hash: a key for each
+// piece on each square, a key for the side to move, a key for each castling
+// right, a key for each en-passant file, a key for each number of checks
+// given in Three-check, and a key for each number of pieces of each type in a
+// pocket in Crazyhouse.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ZobristKeys {
+    // Indexed by [color][piece][square].
+    pieces: [[[u64; 64]; 6]; 2],
+    // Included in the hash when black is moving next.
+    black: u64,
+    // Indexed by [color][0 for king side, 1 for queen side].
+    castle: [[u64; 2]; 2],
+    // Indexed by the file of the en-passant capture square.
+    passant: [u64; 8],
+    // Indexed by [color][number of checks given - 1].
+    checks: [[u64; 3]; 2],
+    // Indexed by [color][piece][number of pieces in the pocket - 1], where the
+    // pieces are in the order of POCKET_PIECES.
+    pocket: [[[u64; MAX_POCKET]; 5]; 2],
+}
+
+// The number of pieces of one type in a pocket with distinct keys. Larger
+// counts share the key of the largest count.
+const MAX_POCKET: usize = 16;
+
+// The global set of keys. Note that changing SEED changes the hash of every
+// position, which invalidates any data keyed by the hash.
+pub static ZOBRIST: ZobristKeys = ZobristKeys::generate(SEED);
+
+const SEED: u64 = 0x5275_6b79_5a6f_6272;
+
+impl ZobristKeys {
+    // Generates all the keys from a seed using the splitmix64 generator.
+    const fn generate(seed: u64) -> Self {
+        let mut state = seed;
+        let mut pieces = [[[0u64; 64]; 6]; 2];
+        let mut c = 0;
+        while c < 2 {
+            let mut p = 0;
+            while p < 6 {
+                let mut s = 0;
+                while s < 64 {
+                    let (next, key) = splitmix64(state);
+                    state = next;
+                    pieces[c][p][s] = key;
+                    s += 1;
+                }
+                p += 1;
+            }
+            c += 1;
+        }
+
+        let (next, black) = splitmix64(state);
+        state = next;
+
+        let mut castle = [[0u64; 2]; 2];
+        let mut c = 0;
+        while c < 2 {
+            let mut side = 0;
+            while side < 2 {
+                let (next, key) = splitmix64(state);
+                state = next;
+                castle[c][side] = key;
+                side += 1;
+            }
+            c += 1;
+        }
+
+        let mut passant = [0u64; 8];
+        let mut f = 0;
+        while f < 8 {
+            let (next, key) = splitmix64(state);
+            state = next;
+            passant[f] = key;
+            f += 1;
+        }
+
+        // The keys for checks and pockets are generated last, so that the other
+        // keys are the same as before they were added.
+        let mut checks = [[0u64; 3]; 2];
+        let mut c = 0;
+        while c < 2 {
+            let mut n = 0;
+            while n < 3 {
+                let (next, key) = splitmix64(state);
+                state = next;
+                checks[c][n] = key;
+                n += 1;
+            }
+            c += 1;
+        }
+
+        let mut pocket = [[[0u64; MAX_POCKET]; 5]; 2];
+        let mut c = 0;
+        while c < 2 {
+            let mut p = 0;
+            while p < 5 {
+                let mut n = 0;
+                while n < MAX_POCKET {
+                    let (next, key) = splitmix64(state);
+                    state = next;
+                    pocket[c][p][n] = key;
+                    n += 1;
+                }
+                p += 1;
+            }
+            c += 1;
+        }
+
+        Self {
+            pieces,
+            black,
+            castle,
+            passant,
+            checks,
+            pocket,
+        }
+    }
+
+    // Returns the key for a piece of a given color on square sq.
+    #[inline]
+    pub fn piece<T>(&self, color: Color, piece: Piece<T>, sq: Sq) -> u64 {
+        self.pieces[color_index(color)][piece_index(&piece)][sq.as_usize()]
+    }
+
+    // Returns the key that is included in the hash when black moves next.
+    #[inline]
+    pub fn black_to_move(&self) -> u64 {
+        self.black
+    }
+
+    // Returns the key for the castling right of a given color and side.
+    #[inline]
+    pub fn castle(&self, color: Color, king_side: bool) -> u64 {
+        self.castle[color_index(color)][if king_side { 0 } else { 1 }]
+    }
+
+    // Returns the key for an en-passant capture on the file of square sq.
+    #[inline]
+    pub fn passant(&self, sq: Sq) -> u64 {
+        self.passant[sq.rc().1 as usize]
+    }
+
+    // Returns the key for the number of checks given by a color in Three-check,
+    // or 0 if no checks were given.
+    #[inline]
+    pub fn checks(&self, color: Color, count: u8) -> u64 {
+        match count {
+            0 => 0,
+            n => self.checks[color_index(color)][(n.min(3) - 1) as usize],
+        }
+    }
+
+    // Returns the combined key for the pieces in the pocket of a color in
+    // Crazyhouse, which is 0 for an empty pocket.
+    pub fn pocket(&self, color: Color, pocket: &Pocket) -> u64 {
+        let mut key = 0;
+        for (p, piece) in POCKET_PIECES.iter().enumerate() {
+            let count = pocket.count(*piece) as usize;
+            if count > 0 {
+                key ^= self.pocket[color_index(color)][p][count.min(MAX_POCKET) - 1];
+            }
+        }
+        key
+    }
+
+    // Returns the combined key for the castling rights of a set of pieces.
+    pub fn castling(&self, pieces: &PieceSet) -> u64 {
+        let mut key = 0;
+        if pieces.has_king_castle() {
+            key ^= self.castle(pieces.color(), true);
+        }
+        if pieces.has_queen_castle() {
+            key ^= self.castle(pieces.color(), false);
+        }
+        key
+    }
+
+    // Returns the combined key for all the pieces in a set, including the
+    // castling rights.
+    pub fn pieces(&self, pieces: &PieceSet) -> u64 {
+        let color = pieces.color();
+        let mut key = self.castling(pieces);
+        for piece in pieces.iter() {
+            for sq in piece.val().sq_iter() {
+                key ^= self.piece(color, piece, sq);
+            }
+        }
+        key
+    }
+
+    // Returns the key that needs to be toggled to update the hash for the pieces
+    // of the player making piece_move. It does not include captured pieces.
+    pub fn move_key(&self, color: Color, piece_move: Piece<PieceMove>) -> u64 {
+        match piece_move.val() {
+            Simple { from, to } | Capture { from, to, .. } | EnPassant { from, to, .. } => {
+                self.piece(color, piece_move, from) ^ self.piece(color, piece_move, to)
+            }
+            Castle {
+                king_from,
+                king_to,
+                rook_from,
+                rook_to,
+            } => {
+                self.piece(color, King(()), king_from)
+                    ^ self.piece(color, King(()), king_to)
+                    ^ self.piece(color, Rook(()), rook_from)
+                    ^ self.piece(color, Rook(()), rook_to)
+            }
+            Promo { from, to, promo }
+            | PromoCap {
+                from, to, promo, ..
+            } => self.piece(color, Pawn(()), from) ^ self.piece(color, promo, to),
+            Drop { to } => self.piece(color, piece_move, to),
+        }
+    }
+
+    // Returns the key that needs to be toggled to remove the piece captured by
+    // mv, where color is the color of the captured piece. Returns 0 if mv is not
+    // a capture.
+    pub fn capture_key(&self, color: Color, mv: PieceMove) -> u64 {
+        match mv {
+            Capture { to, cap, .. } | PromoCap { to, cap, .. } => self.piece(color, cap, to),
+            EnPassant { passant, .. } => self.piece(color, Pawn(()), passant),
+            _ => 0,
+        }
+    }
+}
+
+#[inline]
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+#[inline]
+fn piece_index<T>(piece: &Piece<T>) -> usize {
+    match piece {
+        King(_) => 0,
+        Queen(_) => 1,
+        Rook(_) => 2,
+        Bishop(_) => 3,
+        Knight(_) => 4,
+        Pawn(_) => 5,
+    }
+}
+
+// A step of the splitmix64 generator. Returns the pair (next state, output).
+pub(crate) const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, z ^ (z >> 31))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sq;
+    use std::collections::HashSet;
+
+    #[test]
+    fn keys_are_unique() {
+        let mut keys = HashSet::new();
+        for color in ZOBRIST.pieces {
+            for piece in color {
+                for key in piece {
+                    assert!(keys.insert(key));
+                }
+            }
+        }
+        assert!(keys.insert(ZOBRIST.black));
+        for color in ZOBRIST.castle {
+            for key in color {
+                assert!(keys.insert(key));
+            }
+        }
+        for key in ZOBRIST.passant {
+            assert!(keys.insert(key));
+        }
+        assert!(!keys.contains(&0));
+    }
+
+    #[test]
+    fn keys_are_stable() {
+        // These values should never change, since hashes may be persisted.
+        assert_eq!(
+            ZOBRIST.piece(Color::White, King(()), sq::A1),
+            0xceda5352bf571a3f
+        );
+        assert_eq!(ZOBRIST.black_to_move(), 0x26ac27c21666b815);
+    }
+
+    #[test]
+    fn pieces_key_includes_castling() {
+        let white = PieceSet::init_white();
+        let no_castling = white.iter().fold(0, |key, piece| {
+            piece
+                .val()
+                .sq_iter()
+                .fold(key, |key, sq| key ^ ZOBRIST.piece(Color::White, piece, sq))
+        });
+        assert_eq!(
+            ZOBRIST.pieces(&white),
+            no_castling ^ ZOBRIST.castle(Color::White, true) ^ ZOBRIST.castle(Color::White, false)
+        );
+    }
+}
@@ -1,4 +1,9 @@
-use ruky::magics::{compute_bmagics, compute_rmagics, from_bmagics, from_rmagics};
+use ruky::bitboard::BitBoard;
+use ruky::magics::{
+    compute_bmagics, compute_rmagics, create_rand_iter, from_black_rtables, from_bmagics,
+    from_rmagics, search_black_bmagics, search_black_rmagics, verify_black_bmagics,
+    verify_black_rmagics, ChessMagics,
+};
 use std::thread::spawn;
 
 // This is a slow test.
@@ -30,3 +35,34 @@ fn test_magics() {
     assert_eq!(b, fb);
     assert_eq!(r, fr);
 }
+
+// This is a slow test.
+#[ignore]
+#[test]
+fn test_black_magics() {
+    let t = spawn(|| search_black_bmagics(7, 100));
+    let r = search_black_rmagics(7, 100);
+
+    let b = t.join().unwrap().unwrap();
+    let r = r.unwrap();
+    assert!(verify_black_bmagics(&b).is_ok());
+    assert!(verify_black_rmagics(&r).is_ok());
+    assert!(r.table().len() < 64 << 12);
+
+    let attacks: Vec<u64> = r.table().iter().map(|a| a.u64()).collect();
+    let fr = from_black_rtables(r.magics(), r.offsets(), &attacks).unwrap();
+    assert_eq!(r, fr);
+
+    let black = ChessMagics::from_black_magics(r, b);
+    let magics = ChessMagics::with_magics().unwrap();
+    let mut rand_iter = create_rand_iter();
+    for s in 0u8..64 {
+        for _ in 0..1000 {
+            let blockers = BitBoard::from(rand_iter.next().unwrap());
+            assert_eq!(
+                black.qmagics(s.into(), blockers),
+                magics.qmagics(s.into(), blockers)
+            );
+        }
+    }
+}
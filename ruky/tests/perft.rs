@@ -0,0 +1,160 @@
+use lazy_static::lazy_static;
+use ruky::{perft::PerftCounts, Ruky};
+
+// The expected counts are the published results for these positions, e.g. from
+// https://www.chessprogramming.org/Perft_Results.
+
+lazy_static! {
+    static ref RUKY: Ruky = Ruky::new();
+}
+
+const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+const POSITION3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+const POSITION4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+const POSITION4_MIRRORED: &str = "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
+const POSITION5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+const POSITION6: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+fn assert_nodes(fen: &str, expected: &[u64]) {
+    let board = RUKY.from_fen(fen).expect("Unable to parse FEN.");
+    for (depth, nodes) in expected.iter().enumerate() {
+        assert_eq!(
+            board.perft(depth as u8 + 1),
+            *nodes,
+            "{} at depth {}",
+            fen,
+            depth + 1
+        );
+    }
+}
+
+fn assert_counts(fen: &str, depth: u8, expected: PerftCounts) {
+    let board = RUKY.from_fen(fen).expect("Unable to parse FEN.");
+    assert_eq!(
+        board.perft_counts(depth),
+        expected,
+        "{} at depth {}",
+        fen,
+        depth
+    );
+}
+
+#[test]
+fn perft_start() {
+    assert_nodes(START, &[20, 400, 8902, 197281]);
+    assert_counts(
+        START,
+        4,
+        PerftCounts {
+            nodes: 197281,
+            captures: 1576,
+            checks: 469,
+            mates: 8,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn perft_kiwipete() {
+    assert_nodes(KIWIPETE, &[48, 2039, 97862]);
+    assert_counts(
+        KIWIPETE,
+        3,
+        PerftCounts {
+            nodes: 97862,
+            captures: 17102,
+            passants: 45,
+            castles: 3162,
+            promotions: 0,
+            checks: 993,
+            mates: 1,
+        },
+    );
+}
+
+#[test]
+fn perft_position3() {
+    assert_nodes(POSITION3, &[14, 191, 2812, 43238, 674624]);
+    assert_counts(
+        POSITION3,
+        4,
+        PerftCounts {
+            nodes: 43238,
+            captures: 3348,
+            passants: 123,
+            checks: 1680,
+            mates: 17,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn perft_position4() {
+    let expected = PerftCounts {
+        nodes: 9467,
+        captures: 1021,
+        passants: 4,
+        castles: 0,
+        promotions: 120,
+        checks: 38,
+        mates: 22,
+    };
+    assert_nodes(POSITION4, &[6, 264, 9467, 422333]);
+    assert_counts(POSITION4, 3, expected);
+    assert_nodes(POSITION4_MIRRORED, &[6, 264, 9467, 422333]);
+    assert_counts(POSITION4_MIRRORED, 3, expected);
+}
+
+#[test]
+fn perft_position5() {
+    assert_nodes(POSITION5, &[44, 1486, 62379]);
+}
+
+#[test]
+fn perft_position6() {
+    assert_nodes(POSITION6, &[46, 2079, 89890]);
+}
+
+#[test]
+fn divide_sums_to_perft() {
+    let board = RUKY.from_fen(KIWIPETE).expect("Unable to parse FEN.");
+    let moves = board.divide(3);
+    assert_eq!(moves.len(), 48);
+    let total: PerftCounts = moves.into_iter().map(|(_, counts)| counts).sum();
+    assert_eq!(total, board.perft_counts(3));
+}
+
+// Chess960 positions from https://www.chessprogramming.org/Chess960_Perft_Results,
+// with the castling rights in Shredder-FEN.
+const CHESS960: [(&str, [u64; 4]); 5] = [
+    (
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        [21, 528, 12189, 326672],
+    ),
+    (
+        "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+        [20, 479, 10471, 273318],
+    ),
+    (
+        "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+        [22, 593, 13440, 382958],
+    ),
+    (
+        "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+        [28, 1120, 31058, 1171749],
+    ),
+    (
+        "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+        [29, 899, 26578, 824055],
+    ),
+];
+
+#[test]
+fn perft_chess960() {
+    for (fen, expected) in CHESS960 {
+        assert_nodes(fen, &expected);
+    }
+}
@@ -0,0 +1,206 @@
+use lazy_static::lazy_static;
+use ruky::random_eng::RandomEng;
+use ruky::syzygy::{Tablebase, Wdl};
+use ruky::Ruky;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use uzi::eng::Eng;
+use uzi::engcmd::Info;
+use uzi::engtx::EngTx;
+use uzi::err::UziErr;
+use uzi::guicmd::{Go, Pos};
+use uzi::pm::Pm;
+
+// The tables in tests/fixtures/syzygy have the positions with three pieces and
+// KNNvK, and are written by the ignored syzygy::tests::write_fixtures unit
+// test, which solves every position of the tables and indexes them with its
+// own implementation of the published encoding, independent of the reader. It
+// compresses them with pairs of symbols, codes of different lengths and DTZ
+// maps like the published tables. The tests check them against known results
+// of the endings.
+
+lazy_static! {
+    static ref RUKY: Ruky = Ruky::new();
+    static ref TABLEBASE: Tablebase = Tablebase::open(&fixtures()).unwrap();
+}
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/syzygy")
+}
+
+fn assert_probe(fen: &str, wdl: Wdl, dtz: i32) {
+    let board = RUKY.from_fen(fen).expect("Unable to parse FEN.");
+    assert_eq!(TABLEBASE.probe_wdl(&board), Ok(wdl), "{}", fen);
+    assert_eq!(TABLEBASE.probe_dtz(&board), Ok(dtz), "{}", fen);
+}
+
+#[test]
+fn open_fixtures() {
+    assert_eq!(TABLEBASE.max_pieces(), 4);
+    let mut tablebase = Tablebase::new();
+    assert_eq!(tablebase.add_directory(&fixtures()), Ok(9));
+}
+
+#[test]
+fn probe_rook() {
+    assert_probe("4k3/8/4K3/8/8/8/8/7R w - - 0 1", Wdl::Win, 1);
+    assert_probe("R3k3/8/4K3/8/8/8/8/8 b - - 0 1", Wdl::Loss, -1);
+    // The rook is captured.
+    assert_probe("8/8/8/8/8/8/1k6/1R2K3 b - - 0 1", Wdl::Draw, 0);
+    // Stalemate.
+    assert_probe("k7/1R6/1K6/8/8/8/8/8 b - - 0 1", Wdl::Draw, 0);
+    // Black has the rook.
+    assert_probe("7r/8/8/8/8/4k3/8/4K3 b - - 0 1", Wdl::Win, 1);
+    assert_probe("7r/8/8/8/8/4k3/8/4K3 w - - 0 1", Wdl::Loss, -4);
+}
+
+#[test]
+fn probe_minor_pieces() {
+    assert_probe("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", Wdl::Draw, 0);
+    assert_probe("4k3/8/8/8/8/8/8/1n2K3 b - - 0 1", Wdl::Draw, 0);
+}
+
+#[test]
+fn probe_two_knights() {
+    // The fixtures have no DTZ table for KNNvK.
+    for (fen, wdl) in [
+        ("k7/3N4/1K6/3N4/8/8/8/8 w - - 0 1", Wdl::Win),
+        // Stalemate.
+        ("k7/3N4/1K6/3N4/8/8/8/8 b - - 0 1", Wdl::Draw),
+        ("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", Wdl::Draw),
+        ("4K3/8/8/8/8/8/8/1n2kn2 b - - 0 1", Wdl::Draw),
+    ] {
+        let board = RUKY.from_fen(fen).expect("Unable to parse FEN.");
+        assert_eq!(TABLEBASE.probe_wdl(&board), Ok(wdl), "{}", fen);
+    }
+}
+
+#[test]
+fn probe_queen() {
+    assert_probe("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1", Wdl::Win, 1);
+    // Stalemate.
+    assert_probe("k7/8/1QK5/8/8/8/8/8 b - - 0 1", Wdl::Draw, 0);
+    assert_probe("8/8/8/3k4/8/8/8/Q3K3 b - - 0 1", Wdl::Loss, -18);
+}
+
+#[test]
+fn probe_pawn() {
+    assert_probe("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", Wdl::Win, 1);
+    assert_probe("8/4P3/8/8/8/8/k7/4K3 b - - 0 1", Wdl::Loss, -2);
+    // The king holds the rook pawn.
+    assert_probe("k7/8/8/8/8/8/P7/K7 w - - 0 1", Wdl::Draw, 0);
+    // The pawn is captured.
+    assert_probe("8/8/8/8/8/3k4/3P4/7K b - - 0 1", Wdl::Draw, 0);
+    // Black has the pawn.
+    assert_probe("4k3/8/8/8/8/K7/4p3/8 b - - 0 1", Wdl::Win, 1);
+}
+
+#[test]
+fn longest_wins() {
+    // The longest wins with white to move are mate in 10 with the queen and
+    // mate in 16 with the rook, as found by the first retrograde analyses of
+    // these endings. Only the other king can zero the counter, so the distance
+    // to zeroing of a win is the distance to mate.
+    for (piece, plies) in [('Q', 19), ('R', 31)] {
+        let mut longest = 0;
+        // The white king is on the triangle a1-d1-d4 by symmetry.
+        for wk in [0, 1, 2, 3, 9, 10, 11, 18, 19, 27] {
+            for bk in 0..64 {
+                for sq in 0..64 {
+                    let fen = fen_of(&[('K', wk), ('k', bk), (piece, sq)]);
+                    let Ok(board) = RUKY.from_fen(&fen) else {
+                        continue;
+                    };
+                    longest = longest.max(TABLEBASE.probe_dtz(&board).unwrap());
+                }
+            }
+        }
+        assert_eq!(longest, plies, "{}", piece);
+    }
+}
+
+// Returns the FEN of a position with white to move and the given pieces, where
+// the squares are numbered from a1 to h8.
+fn fen_of(pieces: &[(char, usize)]) -> String {
+    let ranks: Vec<String> = (0..8)
+        .rev()
+        .map(|rank| {
+            let mut row = String::new();
+            let mut empty = 0;
+            for file in 0..8 {
+                match pieces.iter().find(|(_, sq)| *sq == rank * 8 + file) {
+                    Some((piece, _)) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        row.push(*piece);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            row
+        })
+        .collect();
+    format!("{} w - - 0 1", ranks.join("/"))
+}
+
+#[test]
+fn best_moves_reach_zeroing() {
+    for fen in [
+        "8/8/8/3k4/8/8/8/Q3K3 w - - 0 1",
+        "8/8/8/4k3/8/8/8/R3K3 w - - 0 1",
+        "8/8/8/4k3/8/8/4P3/4K3 b - - 0 1",
+    ] {
+        let mut board = RUKY.from_fen(fen).expect("Unable to parse FEN.");
+        let mut wdl = TABLEBASE.probe_wdl(&board).unwrap();
+        let mut dtz = TABLEBASE.probe_dtz(&board).unwrap();
+        while dtz.abs() > 1 {
+            let (best_move, best_wdl) = TABLEBASE.best_move(&board).unwrap().unwrap();
+            assert_eq!(best_wdl, wdl, "{}", board);
+            board.make_move(best_move);
+            let next_dtz = TABLEBASE.probe_dtz(&board).unwrap();
+            assert_eq!(next_dtz, -(dtz - dtz.signum()), "{}", board);
+            wdl = -wdl;
+            dtz = next_dtz;
+        }
+    }
+}
+
+// Records the best moves sent by the engine.
+#[derive(Debug, Default)]
+struct BestMoves(Mutex<Vec<Pm>>);
+
+impl EngTx for BestMoves {
+    fn send_best(&self, best: Pm) {
+        self.0.lock().unwrap().push(best);
+    }
+
+    fn send_ponder(&self, _best: Pm, _ponder: Pm) {}
+
+    fn send_info(&self, _info: Info) {}
+}
+
+#[test]
+fn random_eng_plays_tablebase_moves() {
+    let best_moves = Arc::new(BestMoves::default());
+    let mut eng = RandomEng::new(best_moves.clone());
+    let missing = fixtures().join("missing");
+    assert_eq!(eng.syzygy_path(&missing), Err(UziErr::BadPath(missing)));
+    assert_eq!(eng.syzygy_path(&fixtures()), Ok(()));
+
+    eng.position(&Pos::with_fen("4k3/8/4K3/8/8/8/8/7R w - - 0 1"))
+        .unwrap();
+    eng.go(&Go::new()).unwrap();
+    assert_eq!(
+        best_moves.0.lock().unwrap().last().map(|pm| pm.to_string()),
+        Some("h1h8".to_string())
+    );
+
+    assert_eq!(eng.syzygy_path(Path::new("<empty>")), Ok(()));
+    eng.go(&Go::new()).unwrap();
+    assert_eq!(best_moves.0.lock().unwrap().len(), 2);
+}
@@ -26,6 +26,7 @@ pub struct Config {
     pub opponent: Option<Opponent>,
     pub about: Option<StrType>,
     pub shredder_bases: Option<PathBuf>,
+    pub syzygy_path: Option<PathBuf>,
     pub pos_value: Option<PosValueOpt>,
 }
 
@@ -124,6 +125,17 @@ impl Iterator for ConfigIter<'_> {
                         .unwrap();
                     return Some(HasOpt::ShredderBasesPath(StrType(path)));
                 }
+                UziOpt::SyzygyPath if self.conf.syzygy_path.is_some() => {
+                    let path = self
+                        .conf
+                        .syzygy_path
+                        .clone()
+                        .unwrap()
+                        .into_os_string()
+                        .into_string()
+                        .unwrap();
+                    return Some(HasOpt::SyzygyPath(StrType(path)));
+                }
                 UziOpt::SetPosVal if self.conf.pos_value.is_some() => {
                     return Some(HasOpt::SetPosVal(
                         self.conf.pos_value.clone().unwrap().into(),
@@ -173,6 +185,7 @@ mod tests {
         conf.own_book = Some(true);
         conf.show_curr_line = Some(true);
         conf.about = Some(about.clone());
+        conf.syzygy_path = Some(PathBuf::from_str("<empty>").unwrap());
 
         let mut iter = conf.iter();
 
@@ -180,6 +193,10 @@ mod tests {
         assert_eq!(iter.next(), Some(HasOpt::OwnBook(CheckType(true))));
         assert_eq!(iter.next(), Some(HasOpt::ShowCurrLine(CheckType(true))));
         assert_eq!(iter.next(), Some(HasOpt::About(about)));
+        assert_eq!(
+            iter.next(),
+            Some(HasOpt::SyzygyPath(StrType("<empty>".into())))
+        );
         assert_eq!(iter.next(), None);
     }
 }
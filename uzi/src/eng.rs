@@ -77,6 +77,11 @@ pub trait Eng {
         Err(UziErr::NotImplemented)
     }
 
+    fn syzygy_path(&mut self, path: &Path) -> Result<(), UziErr> {
+        log::info!("Eng::syzygy_path is not implemented");
+        Err(UziErr::NotImplemented)
+    }
+
     fn opponent(&mut self, opponent: &Opponent) -> Result<(), UziErr> {
         log::info!("Eng::opponent is not implemented");
         Err(UziErr::NotImplemented)
@@ -252,6 +257,11 @@ impl<E: Eng, O: EngOutTx> EngController<E, O> {
                     self.eng.shredder_bases(x)
                 })
             }
+            SetOpt::SyzygyPath(path_buf) => {
+                set_opt_val(path_buf, self.conf.syzygy_path.is_some(), |x| {
+                    self.eng.syzygy_path(x)
+                })
+            }
             SetOpt::Opp(opp) => {
                 set_opt_val(opp, self.conf.opponent.is_some(), |x| self.eng.opponent(x))
             }
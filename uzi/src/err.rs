@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 // En enum to represent all errors in the library.
 #[derive(Debug, Clone, PartialEq)]
 pub enum UziErr {
@@ -5,6 +7,7 @@ pub enum UziErr {
     BadMillis(String, String),
     BadNumber(String),
     BadOpponent,
+    BadPath(PathBuf),
     BadPlayerType,
     BadPositionVal,
     BadTitle,
@@ -42,6 +42,9 @@ pub enum HasOpt {
     // UCI_ShredderbasesPath: Tells the GUI that the engine supports the
     // Shredder end-game databa.
     ShredderBasesPath(StrType),
+    // SyzygyPath: Tells the GUI that the engine supports the Syzygy endgame
+    // tablebases.
+    SyzygyPath(StrType),
     // UCI_Opponent: Tells the GUI how to configure the Opponent.
     Opp(StrType),
     // UCI_SetPositionValue: Tells the GUI that the engine supports evaluating
@@ -67,6 +70,7 @@ impl Display for HasOpt {
             HasOpt::Elo(t) => write!(formatter, "{} {}", ELO, t),
             HasOpt::AnalysisMode(t) => write!(formatter, "{} {}", ANALYSIS_MODE, t),
             HasOpt::ShredderBasesPath(t) => write!(formatter, "{} {}", SHREDDER_BASES_PATH, t),
+            HasOpt::SyzygyPath(t) => write!(formatter, "{} {}", SYZYGY_PATH, t),
             HasOpt::Opp(t) => write!(formatter, "{} {}", OPPONENT, t),
             HasOpt::SetPosVal(t) => write!(formatter, "{} {}", SET_POSITION_VALUE, t),
             HasOpt::About(t) => write!(formatter, "{} {}", ABOUT, t),
@@ -110,6 +114,10 @@ pub enum SetOpt {
     // UCI_ShredderbasesPath: Sets the path to a folder containing the Shredder
     // endgame databases.
     ShredderBasesPath(PathBuf),
+    // SyzygyPath: Sets the path to the folders containing the Syzygy endgame
+    // tablebases. Multiple directories can be concatenated with ":", or with
+    // ";" on Windows.
+    SyzygyPath(PathBuf),
     // UCI_Opponent: Tells the engine the name, title, elo and if the engine is
     // playing a human or computer to the engine. The format of the string is:
     // - [GM|IM|FM|WGM|WIM|none] [<elo>|none] [computer|human] <name>, e.g.:
@@ -168,6 +176,7 @@ fn parse_value(opt: UziOpt, cmd: &[&str]) -> Result<SetOpt, UziErr> {
         UziOpt::ShredderBasesPath => {
             Ok(SetOpt::ShredderBasesPath(PathBuf::from_str(word).unwrap()))
         }
+        UziOpt::SyzygyPath => Ok(SetOpt::SyzygyPath(PathBuf::from_str(word).unwrap())),
         UziOpt::Opponent => Ok(SetOpt::Opp(Opponent::try_from(cmd)?)),
         UziOpt::SetPosVal => Ok(SetOpt::SetPosVal(PosValueOpt::try_from(cmd)?)),
     }
@@ -215,6 +224,7 @@ pub enum UziOpt {
     Opponent,
     About,
     ShredderBasesPath,
+    SyzygyPath,
     SetPosVal,
 }
 
@@ -237,6 +247,7 @@ impl FromStr for UziOpt {
             ANALYSIS_MODE => Ok(UziOpt::AnalysisMode),
             OPPONENT => Ok(UziOpt::Opponent),
             SHREDDER_BASES_PATH => Ok(UziOpt::ShredderBasesPath),
+            SYZYGY_PATH => Ok(UziOpt::SyzygyPath),
             SET_POSITION_VALUE => Ok(UziOpt::SetPosVal),
             _ => Err(UziErr::UnknownOpt),
         }
@@ -275,7 +286,8 @@ impl Iterator for UziOptIter {
                 UziOpt::AnalysisMode => Some(UziOpt::Opponent),
                 UziOpt::Opponent => Some(UziOpt::About),
                 UziOpt::About => Some(UziOpt::ShredderBasesPath),
-                UziOpt::ShredderBasesPath => Some(UziOpt::SetPosVal),
+                UziOpt::ShredderBasesPath => Some(UziOpt::SyzygyPath),
+                UziOpt::SyzygyPath => Some(UziOpt::SetPosVal),
                 UziOpt::SetPosVal => None,
             },
         };
@@ -502,6 +514,7 @@ const ELO: &str = "UCI_Elo";
 const ANALYSIS_MODE: &str = "UCI_AnalysisMode";
 const OPPONENT: &str = "UCI_Opponent";
 const SHREDDER_BASES_PATH: &str = "UCI_ShredderbasesPath";
+const SYZYGY_PATH: &str = "SyzygyPath";
 const SET_POSITION_VALUE: &str = "UCI_SetPositionValue";
 
 #[cfg(test)]
@@ -547,6 +560,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn set_opt_try_from_syzygy_path() {
+        let opts = ["setoption", "name", SYZYGY_PATH, "value", "/tb/wdl:/tb/dtz"];
+        assert_eq!(
+            SetOpt::try_from(&opts[..]),
+            Ok(SetOpt::SyzygyPath(
+                PathBuf::from_str("/tb/wdl:/tb/dtz").unwrap()
+            ))
+        );
+    }
+
     #[test]
     fn set_opt_try_from_nalimov_cache() {
         let opts = ["setoption", "name", NALIMOV_CACHE, "value", "256000"];
@@ -17,6 +17,10 @@ pub enum Pm {
 
     // A pawn promotion move.
     Promo { from: Sq, to: Sq, promo: Piece },
+
+    // A piece drop in Crazyhouse, written as the uppercase piece, @ and the
+    // destination square, e.g. P@e4.
+    Drop { piece: Piece, to: Sq },
 }
 
 impl Pm {
@@ -24,6 +28,10 @@ impl Pm {
         matches!(*self, Pm::Null)
     }
 
+    pub fn is_drop(&self) -> bool {
+        matches!(*self, Pm::Drop { .. })
+    }
+
     pub fn promo(&self) -> Option<Piece> {
         match *self {
             Pm::Promo { promo, .. } => Some(promo),
@@ -46,6 +54,9 @@ impl Display for Pm {
             Pm::Null => formatter.write_str("0000"),
             Pm::Normal { from, to } => write!(formatter, "{}{}", from, to),
             Pm::Promo { from, to, promo } => write!(formatter, "{}{}{}", from, to, promo),
+            Pm::Drop { piece, to } => {
+                write!(formatter, "{}@{}", piece.to_char().to_ascii_uppercase(), to)
+            }
         }
     }
 }
@@ -55,6 +66,10 @@ impl TryFrom<&[u8]> for Pm {
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
         match bytes.len() {
+            4 if bytes[1] == b'@' => Ok(Pm::Drop {
+                piece: Piece::try_from(bytes[0].to_ascii_lowercase())?,
+                to: Sq::try_from(&bytes[2..])?,
+            }),
             4 if bytes[0] != b'0' => Ok(Pm::Normal {
                 from: Sq::try_from(&bytes[..2])?,
                 to: Sq::try_from(&bytes[2..])?,
@@ -113,4 +128,19 @@ mod tests {
         assert_eq!(Pm::try_from(&promo_move[..]), Ok(pm));
         assert_eq!(Pm::from_str("a7a8q"), Ok(pm));
     }
+
+    #[test]
+    fn pm_from_drop_move() {
+        let pm = Pm::Drop {
+            piece: Piece::Pawn,
+            to: Sq::from((3, 4)),
+        };
+
+        assert_eq!(Pm::from_str("P@e4"), Ok(pm));
+        assert_eq!(Pm::from_str("p@e4"), Ok(pm));
+        assert_eq!(pm.to_string(), "P@e4");
+        assert_eq!(pm.from_to(), None);
+        assert_eq!(Pm::from_str("K@e4").map(|pm| pm.is_drop()), Ok(true));
+        assert!(Pm::from_str("X@e4").is_err());
+    }
 }